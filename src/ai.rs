@@ -0,0 +1,349 @@
+use crate::board::{GameState, LineMove, Move, MoveOutcome, BOARD_SIZE};
+use crate::piece::{PieceType, Color};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+const MATE_SCORE: i32 = 100_000;
+pub const MAX_PLAYING_DEPTH: u32 = 4; // Deepest search a local game against the computer may ask for
+const EVAL_BAR_SCALE: f32 = 400.0; // Centipawns for the bar to reach about three quarters
+const EVAL_BAR_MARGIN: f32 = 0.02; // The losing side always keeps a sliver of the bar
+
+fn piece_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Pawn => 100,
+        PieceType::Knight => 320,
+        PieceType::Bishop => 330,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King => 0,
+    }
+}
+
+// Piece-square tables in centipawns, laid out like the board from White's side (row 0 is the
+// eighth rank). Black reads them upside down
+type SquareTable = [[i32; BOARD_SIZE]; BOARD_SIZE];
+
+const PAWN_TABLE: SquareTable = [
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+    [ 50,  50,  50,  50,  50,  50,  50,  50],
+    [ 10,  10,  20,  30,  30,  20,  10,  10],
+    [  5,   5,  10,  25,  25,  10,   5,   5],
+    [  0,   0,   0,  20,  20,   0,   0,   0],
+    [  5,  -5, -10,   0,   0, -10,  -5,   5],
+    [  5,  10,  10, -20, -20,  10,  10,   5],
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+];
+
+const KNIGHT_TABLE: SquareTable = [
+    [-50, -40, -30, -30, -30, -30, -40, -50],
+    [-40, -20,   0,   0,   0,   0, -20, -40],
+    [-30,   0,  10,  15,  15,  10,   0, -30],
+    [-30,   5,  15,  20,  20,  15,   5, -30],
+    [-30,   0,  15,  20,  20,  15,   0, -30],
+    [-30,   5,  10,  15,  15,  10,   5, -30],
+    [-40, -20,   0,   5,   5,   0, -20, -40],
+    [-50, -40, -30, -30, -30, -30, -40, -50],
+];
+
+const BISHOP_TABLE: SquareTable = [
+    [-20, -10, -10, -10, -10, -10, -10, -20],
+    [-10,   0,   0,   0,   0,   0,   0, -10],
+    [-10,   0,   5,  10,  10,   5,   0, -10],
+    [-10,   5,   5,  10,  10,   5,   5, -10],
+    [-10,   0,  10,  10,  10,  10,   0, -10],
+    [-10,  10,  10,  10,  10,  10,  10, -10],
+    [-10,   5,   0,   0,   0,   0,   5, -10],
+    [-20, -10, -10, -10, -10, -10, -10, -20],
+];
+
+const ROOK_TABLE: SquareTable = [
+    [  0,   0,   0,   0,   0,   0,   0,   0],
+    [  5,  10,  10,  10,  10,  10,  10,   5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [ -5,   0,   0,   0,   0,   0,   0,  -5],
+    [  0,   0,   0,   5,   5,   0,   0,   0],
+];
+
+const QUEEN_TABLE: SquareTable = [
+    [-20, -10, -10,  -5,  -5, -10, -10, -20],
+    [-10,   0,   0,   0,   0,   0,   0, -10],
+    [-10,   0,   5,   5,   5,   5,   0, -10],
+    [ -5,   0,   5,   5,   5,   5,   0,  -5],
+    [  0,   0,   5,   5,   5,   5,   0,  -5],
+    [-10,   5,   5,   5,   5,   5,   0, -10],
+    [-10,   0,   5,   0,   0,   0,   0, -10],
+    [-20, -10, -10,  -5,  -5, -10, -10, -20],
+];
+
+// Middlegame king: stay castled behind the pawns
+const KING_TABLE: SquareTable = [
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-20, -30, -30, -40, -40, -30, -30, -20],
+    [-10, -20, -20, -20, -20, -20, -20, -10],
+    [ 20,  20,   0,   0,   0,   0,  20,  20],
+    [ 20,  30,  10,   0,   0,  10,  30,  20],
+];
+
+fn square_bonus(piece_type: PieceType, color: Color, rank: usize, file: usize) -> i32 {
+    let table = match piece_type {
+        PieceType::Pawn => &PAWN_TABLE,
+        PieceType::Knight => &KNIGHT_TABLE,
+        PieceType::Bishop => &BISHOP_TABLE,
+        PieceType::Rook => &ROOK_TABLE,
+        PieceType::Queen => &QUEEN_TABLE,
+        PieceType::King => &KING_TABLE,
+    };
+    let rank = if color == Color::White { rank } else { BOARD_SIZE - 1 - rank };
+    table[rank][file]
+}
+
+// Material plus piece placement, from the point of view of the side to move
+fn evaluate(state: &GameState) -> i32 {
+    let mut score = 0;
+
+    for rank in 0..BOARD_SIZE {
+        for file in 0..BOARD_SIZE {
+            if let Some(piece) = state.board[rank][file] {
+                let value = piece_value(piece.piece_type) + square_bonus(piece.piece_type, piece.color, rank, file);
+                score += if piece.color == Color::White { value } else { -value };
+            }
+        }
+    }
+
+    if state.current_turn == Color::White { score } else { -score }
+}
+
+// Leaf score for mate search: only mates count, so material never hides a slower forced mate
+fn no_material(_state: &GameState) -> i32 {
+    0
+}
+
+// The strongest piece this game lets a pawn promote to, which is what best_move assumes it becomes
+pub fn promotion_piece(state: &GameState) -> Option<PieceType> {
+    state.promotion_options.iter().copied().max_by_key(|&piece_type| piece_value(piece_type))
+}
+
+// Play a move on a copy of the state, promoting to the strongest piece allowed
+fn play(state: &GameState, from: (usize, usize), to: (usize, usize)) -> Option<GameState> {
+    let mut next = state.clone_for_search();
+
+    match next.apply_move_checked(from, to) {
+        Ok(MoveOutcome::Moved) => Some(next),
+        Ok(MoveOutcome::PromotionRequired) => {
+            let piece_type = promotion_piece(state)?;
+            next.promote_pawn(piece_type).then_some(next)
+        }
+        Err(_) => None,
+    }
+}
+
+// Every position one legal move on. A pawn reaching the last rank gives one per piece it may become,
+// so the searches see underpromotions too
+fn successors(state: &GameState) -> Vec<(LineMove, GameState)> {
+    let mut search_state = state.clone_for_search();
+    let mut positions = Vec::new();
+    
+    for (from, to) in search_state.get_all_legal_moves() {
+        let mut next = state.clone_for_search();
+        match next.apply_move_checked(from, to) {
+            Ok(MoveOutcome::Moved) => positions.push(((from, to, None), next)),
+            Ok(MoveOutcome::PromotionRequired) => {
+                for &piece_type in &state.promotion_options {
+                    let mut promoted = next.clone_for_search();
+                    promoted.promotion_pending = next.promotion_pending.clone();
+                    if promoted.promote_pawn(piece_type) {
+                        positions.push(((from, to, Some(piece_type)), promoted));
+                    }
+                }
+            }
+            Err(_) => {}
+        }
+    }
+    
+    positions
+}
+
+fn negamax(state: &GameState, depth: u32, mut alpha: i32, beta: i32, leaf: fn(&GameState) -> i32) -> i32 {
+    let positions = successors(state);
+
+    if positions.is_empty() {
+        // Prefer faster mates by scoring them higher at greater remaining depth
+        return if state.is_in_check(state.current_turn) { -MATE_SCORE - depth as i32 } else { 0 };
+    }
+
+    if depth == 0 {
+        return leaf(state);
+    }
+
+    let mut best = -MATE_SCORE * 2;
+    for (_, next) in positions {
+        let score = -negamax(&next, depth - 1, -beta, -alpha, leaf);
+        best = best.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+
+    best
+}
+
+// Search score in centipawns from White's point of view, for showing rather than choosing moves
+pub fn white_score(state: &GameState, depth: u32) -> i32 {
+    let score = negamax(state, depth, -MATE_SCORE * 2, MATE_SCORE * 2, evaluate);
+    if state.current_turn == Color::White { score } else { -score }
+}
+
+// The evaluation bar's score, kept until the position changes. Keyed on the Zobrist hash, which covers
+// castling rights and the en passant square as well as the pieces and the side to move
+#[derive(Debug, Default)]
+pub struct EvalCache {
+    entry: Option<(u64, i32)>, // Hash and white_score of the last position searched
+}
+
+impl EvalCache {
+    pub fn score(&self) -> Option<i32> {
+        self.entry.map(|(_, score)| score)
+    }
+    
+    // Search the position unless it's the one already scored. True when the score was recomputed
+    pub fn refresh(&mut self, state: &GameState, depth: u32) -> bool {
+        let hash = state.zobrist_hash();
+        if self.entry.is_some_and(|(cached, _)| cached == hash) {
+            return false;
+        }
+        self.entry = Some((hash, white_score(state, depth)));
+        true
+    }
+}
+
+// Moves until mate behind a white_score searched to this depth, negative when Black mates.
+// None when the score is material rather than a mate
+pub fn mate_in(white_score: i32, depth: u32) -> Option<i32> {
+    if white_score.abs() < MATE_SCORE {
+        return None;
+    }
+
+    let plies = depth as i32 - (white_score.abs() - MATE_SCORE);
+    let moves = (plies + 1) / 2;
+    Some(if white_score > 0 { moves } else { -moves })
+}
+
+// White's share of an evaluation bar: 0.5 when level, leaning towards 1.0 as White gets ahead.
+// Saturates smoothly so a few pawns read as a clear edge without a queen pinning it to the end
+pub fn eval_bar_fraction(white_score: i32) -> f32 {
+    let fraction = 0.5 + 0.5 * (white_score as f32 / EVAL_BAR_SCALE).tanh();
+    fraction.clamp(EVAL_BAR_MARGIN, 1.0 - EVAL_BAR_MARGIN)
+}
+
+// Minimax search with alpha-beta pruning over the legal moves of the side to move.
+// Equally good moves are chosen between at random, so the same position doesn't always get the same reply
+pub fn best_move(state: &GameState, depth: u32) -> Option<Move> {
+    best_move_seeded(state, depth, rand::random())
+}
+
+// Same as best_move, but the choice between equally good moves is fixed by the seed
+pub fn best_move_seeded(state: &GameState, depth: u32, seed: u64) -> Option<Move> {
+    let mut search_state = state.clone_for_search();
+    let moves = search_state.get_all_legal_moves();
+
+    let mut best_moves = Vec::new();
+    let mut alpha = -MATE_SCORE * 2;
+    let beta = MATE_SCORE * 2;
+
+    for (from, to) in moves {
+        if let Some(next) = play(state, from, to) {
+            // Search one point below the best so far, so a move that only ties it still gets an exact score
+            let score = -negamax(&next, depth.saturating_sub(1), -beta, -(alpha - 1), evaluate);
+            if best_moves.is_empty() || score > alpha {
+                alpha = score;
+                best_moves.clear();
+                best_moves.push((from, to));
+            } else if score == alpha {
+                best_moves.push((from, to));
+            }
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    best_moves.choose(&mut rng).copied()
+}
+
+// A best_move search on its own thread, so a window keeps drawing while the computer thinks
+pub struct BackgroundSearch {
+    receiver: Receiver<Option<Move>>,
+    fen: String, // The position searched, to tell whether the answer still applies
+}
+
+impl BackgroundSearch {
+    pub fn start(state: &GameState, depth: u32) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let position = state.clone_for_search();
+        thread::spawn(move || {
+            // Nobody is listening any more if the search was dropped, e.g. for a new game
+            let _ = sender.send(best_move(&position, depth));
+        });
+        
+        Self { receiver, fen: state.to_fen() }
+    }
+    
+    // The search's answer once it has finished, without waiting for it. Some(None) when it found no
+    // move, or its thread died
+    pub fn try_result(&self) -> Option<Option<Move>> {
+        match self.receiver.try_recv() {
+            Ok(best) => Some(best),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(None),
+        }
+    }
+    
+    pub fn is_for(&self, state: &GameState) -> bool {
+        self.fen == state.to_fen()
+    }
+}
+
+// Shortest forced mate for the side to move in at most max_moves of its own moves, as the whole
+// line with the defender's longest resistance. None when there is no such mate
+pub fn find_mate(state: &GameState, max_moves: u32) -> Option<Vec<LineMove>> {
+    (1..=max_moves).find_map(|moves| mating_line(state, moves * 2 - 1))
+}
+
+// A line that mates within depth plies, the attacker's move first
+fn mating_line(state: &GameState, depth: u32) -> Option<Vec<LineMove>> {
+    for (attack, next) in successors(state) {
+        let score = -negamax(&next, depth - 1, -MATE_SCORE * 2, MATE_SCORE * 2, no_material);
+        if score < MATE_SCORE {
+            continue;
+        }
+        
+        let mut line = vec![attack];
+        if next.is_checkmate() {
+            return Some(line);
+        }
+        
+        // Defend as long as possible, then take the attacker's quickest mate from there
+        let mut longest: Option<(i32, GameState, LineMove)> = None;
+        for (reply, after) in successors(&next) {
+            let defender_score = -negamax(&after, depth - 2, -MATE_SCORE * 2, MATE_SCORE * 2, no_material);
+            if longest.as_ref().is_none_or(|(best, _, _)| defender_score > *best) {
+                longest = Some((defender_score, after, reply));
+            }
+        }
+        
+        let (_, after, reply) = longest?;
+        line.push(reply);
+        line.extend(find_mate(&after, (depth - 1) / 2)?);
+        return Some(line);
+    }
+    
+    None
+}
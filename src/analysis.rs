@@ -0,0 +1,110 @@
+use crate::ai;
+use crate::board::{self, GameState, MoveOutcome, PgnError, RecordedMove};
+use crate::piece::{Color, PieceType};
+
+const ANALYSIS_DEPTH: u32 = 3; // Plies searched from each position, shallow enough for a whole game in seconds
+const BLUNDER_THRESHOLD: i32 = 200; // Centipawns a move may lose against the best one before it's flagged
+
+// One move of an analysed game
+#[derive(Debug, Clone)]
+pub struct AnalyzedMove {
+    pub fullmove_number: u32,
+    pub color: Color,
+    pub san: String,
+    pub white_score: i32, // After the move, in centipawns from White's point of view
+    pub mate_in: Option<i32>, // Moves to mate after the move, negative when Black mates
+    pub best: Option<String>, // The search's choice in SAN, only kept for blunders
+}
+
+impl AnalyzedMove {
+    pub fn is_blunder(&self) -> bool {
+        self.best.is_some()
+    }
+
+    // The [%eval] value lichess and most PGN viewers read: pawns, or "#N" for a mate
+    pub fn eval_text(&self) -> String {
+        match self.mate_in {
+            Some(moves) => format!("#{}", moves),
+            None => format!("{:.2}", self.white_score as f64 / 100.0),
+        }
+    }
+}
+
+// Score every move of a game with a shallow search. A move is compared with the best move of the
+// same position searched to the same depth, so a blunder is a real loss rather than a horizon effect
+pub fn analyze(game: &GameState) -> Vec<AnalyzedMove> {
+    let mut position = game.logged_start_position();
+    let mut analyzed = Vec::new();
+
+    for recorded in &game.move_log {
+        let best_score = ai::white_score(&position, ANALYSIS_DEPTH);
+        let color = position.current_turn;
+        let fullmove_number = position.fullmove_number;
+        let san = position.move_to_san(recorded.from, recorded.to, recorded.promotion);
+        let best_move = ai::best_move_seeded(&position, ANALYSIS_DEPTH, 0);
+        let best_san = best_move.map(|(from, to)| position.move_to_san(from, to, None));
+
+        if !play(&mut position, recorded) {
+            break;
+        }
+
+        let white_score = ai::white_score(&position, ANALYSIS_DEPTH - 1);
+        let loss = match color {
+            Color::White => best_score - white_score,
+            Color::Black => white_score - best_score,
+        };
+
+        analyzed.push(AnalyzedMove {
+            fullmove_number,
+            color,
+            san,
+            white_score,
+            mate_in: ai::mate_in(white_score, ANALYSIS_DEPTH - 1),
+            best: if loss >= BLUNDER_THRESHOLD { best_san } else { None },
+        });
+    }
+
+    analyzed
+}
+
+// The game in the PGN again, with an [%eval] comment after every move and blunders marked "??"
+// along with the move the search preferred. The original tag pairs are kept
+pub fn analyze_pgn(pgn: &str) -> Result<String, PgnError> {
+    let game = GameState::from_pgn(pgn)?;
+    let mut annotated = String::new();
+
+    for tag in pgn.lines().map(str::trim).filter(|line| line.starts_with('[')) {
+        annotated.push_str(tag);
+        annotated.push('\n');
+    }
+    annotated.push('\n');
+
+    // Every move is followed by a comment, so Black's moves need their own numbers too
+    let mut tokens = Vec::new();
+    for analyzed in analyze(&game) {
+        tokens.push(match analyzed.color {
+            Color::White => format!("{}.", analyzed.fullmove_number),
+            Color::Black => format!("{}...", analyzed.fullmove_number),
+        });
+        tokens.push(match &analyzed.best {
+            Some(_) => format!("{}??", analyzed.san),
+            None => analyzed.san.clone(),
+        });
+        tokens.push(match &analyzed.best {
+            Some(best) => format!("{{[%eval {}] Blunder, best was {}}}", analyzed.eval_text(), best),
+            None => format!("{{[%eval {}]}}", analyzed.eval_text()),
+        });
+    }
+    tokens.push(board::pgn_tag(pgn, "Result").unwrap_or_else(|| "*".to_string()));
+
+    annotated.push_str(&board::pgn_movetext(&tokens));
+    Ok(annotated)
+}
+
+fn play(position: &mut GameState, recorded: &RecordedMove) -> bool {
+    match position.apply_move_checked(recorded.from, recorded.to) {
+        Ok(MoveOutcome::PromotionRequired) => position.promote_pawn(recorded.promotion.unwrap_or(PieceType::Queen)),
+        Ok(MoveOutcome::Moved) => true,
+        Err(_) => false,
+    }
+}
@@ -1,9 +1,12 @@
 use ggez::{Context, ContextBuilder, GameResult};
 use ggez::event::{self, EventHandler};
 use ggez::input::mouse::MouseButton;
+use ggez::input::keyboard::{KeyCode, KeyInput};
 use ggez::conf::{WindowSetup, WindowMode};
 
 use chess::gui::ChessGui;
+use chess::config::DEFAULT_CONFIG_FILE;
+use std::path::PathBuf;
 
 struct ChessGame {
     gui: ChessGui,
@@ -36,6 +39,21 @@ impl EventHandler for ChessGame {
         Ok(())
     }
     
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult<()> {
+        self.gui.handle_mouse_up(button, x, y)?;
+        Ok(())
+    }
+    
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> GameResult<()> {
+        self.gui.handle_key_press(character)
+    }
+    
     fn mouse_motion_event(
         &mut self,
         _ctx: &mut Context,
@@ -46,20 +64,39 @@ impl EventHandler for ChessGame {
     ) -> GameResult<()> {
         self.gui.handle_mouse_move(x, y)
     }
+    
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, _repeated: bool) -> GameResult<()> {
+        // Escape quits like ggez's default, once there's no dialog or selection to deal with
+        if input.keycode == Some(KeyCode::Escape) && self.gui.handle_escape() {
+            ctx.request_quit();
+        }
+        Ok(())
+    }
 }
 
 fn main() -> GameResult {
     println!("Starting Local Chess Game");
     
+    if !chess::gui::display_available() {
+        println!("No display found, so the game window can't be opened. The main binary's --server mode runs without one");
+        std::process::exit(1);
+    }
+    
     let resource_dir = std::path::PathBuf::from("./assets");
     
     let (mut ctx, event_loop) = ContextBuilder::new("chess_local", "Rust Chess - Local")
         .window_setup(WindowSetup::default().title("Rust Chess - Local Game"))
         .window_mode(WindowMode::default().dimensions(600.0, 750.0))
         .add_resource_path(resource_dir)
-        .build()?;
+        .build()
+        .map_err(|e| {
+            println!("Could not open the game window: {}", e);
+            e
+        })?;
 
-    let game = ChessGame::new(&mut ctx)?;
+    let mut game = ChessGame::new(&mut ctx)?;
+    game.gui.load_config(PathBuf::from(DEFAULT_CONFIG_FILE));
+    game.gui.init_display_scale(&mut ctx, None)?;
     
     println!("Local game initialized, running event loop");
     event::run(ctx, event_loop, game)
@@ -1,11 +1,22 @@
 use crate::piece::{Piece, PieceType, Color};
-use crate::zobrist::{ZOBRIST, WHITE, BLACK};
+use serde::{Serialize, Deserialize};
+use crate::zobrist::{ZOBRIST, ZobristKeys};
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 
 pub const BOARD_SIZE: usize = 8;
 pub type Square = Option<Piece>;
 pub type Board = [[Square; BOARD_SIZE]; BOARD_SIZE];
+pub type Position = (usize, usize); // (rank, file), rank 0 being the 8th rank
+pub type Move = (Position, Position); // From and to squares
+pub type LineMove = (Position, Position, Option<PieceType>); // A move with the piece a pawn promotes to
+pub const SQUARE_COUNT: usize = BOARD_SIZE * BOARD_SIZE;
 
+// What a pawn may become unless a variant says otherwise
+pub const STANDARD_PROMOTIONS: [PieceType; 4] = [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight];
+
+#[derive(Debug, Clone)]
 pub struct PromotionState {
     pub position: (usize, usize),
     pub color: Color,
@@ -16,6 +27,267 @@ pub struct PromotionPending {
     pub color: Color,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveOutcome {
+    Moved,
+    PromotionRequired, // Call promote_pawn to complete the move
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    PromotionPending,
+    OutOfBounds,
+    NoPieceAtSource,
+    WrongTurn,
+    IllegalDestination,
+    LeavesKingInCheck,
+    InvalidNotation,
+    NoMatchingMove,
+    AmbiguousMove,
+    MissingPromotion,
+}
+
+impl std::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let message = match self {
+            MoveError::PromotionPending => "a pawn promotion must be completed first",
+            MoveError::OutOfBounds => "square is off the board",
+            MoveError::NoPieceAtSource => "no piece on the source square",
+            MoveError::WrongTurn => "it is not this side's turn",
+            MoveError::IllegalDestination => "the piece cannot move to that square",
+            MoveError::LeavesKingInCheck => "the move would leave the king in check",
+            MoveError::InvalidNotation => "not a move in algebraic notation",
+            MoveError::NoMatchingMove => "no legal move matches that notation",
+            MoveError::AmbiguousMove => "more than one legal move matches, name the file or rank it leaves",
+            MoveError::MissingPromotion => "a pawn reaching the last rank must name its promotion piece",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    WrongFieldCount(usize),
+    InvalidPlacement(String),
+    InvalidTurn(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidClock(String),
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FenError::WrongFieldCount(count) => write!(f, "expected 4 or 6 fields, found {}", count),
+            FenError::InvalidPlacement(field) => write!(f, "invalid piece placement \"{}\"", field),
+            FenError::InvalidTurn(field) => write!(f, "invalid side to move \"{}\"", field),
+            FenError::InvalidCastling(field) => write!(f, "invalid castling rights \"{}\"", field),
+            FenError::InvalidEnPassant(field) => write!(f, "invalid en passant square \"{}\"", field),
+            FenError::InvalidClock(field) => write!(f, "invalid move counter \"{}\"", field),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnError {
+    InvalidFen(FenError),
+    IllegalMove(String, MoveError), // As written, after its move number, e.g. "12... Qxf2"
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PgnError::InvalidFen(e) => write!(f, "invalid FEN tag: {}", e),
+            PgnError::IllegalMove(san, e) => write!(f, "illegal move {}: {}", san, e),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    pub fn win_for(color: Color) -> Self {
+        match color {
+            Color::White => GameResult::WhiteWins,
+            Color::Black => GameResult::BlackWins,
+        }
+    }
+    
+    // The score as a PGN Result tag writes it, e.g. "1-0"
+    pub fn from_pgn(text: &str) -> Option<Self> {
+        [GameResult::WhiteWins, GameResult::BlackWins, GameResult::Draw]
+            .into_iter()
+            .find(|result| result.to_string() == text)
+    }
+    
+    // Points scored as (white, black)
+    pub fn points(&self) -> (f32, f32) {
+        match self {
+            GameResult::WhiteWins => (1.0, 0.0),
+            GameResult::BlackWins => (0.0, 1.0),
+            GameResult::Draw => (0.5, 0.5),
+        }
+    }
+}
+
+impl std::fmt::Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let score = match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        };
+        write!(f, "{}", score)
+    }
+}
+
+// Why a game ended, displayed the way a PGN Termination tag spells it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TerminationReason {
+    Checkmate,
+    Resignation,
+    Timeout,
+    Forfeit,
+    DrawAgreed,
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    InsufficientMaterial,
+}
+
+impl TerminationReason {
+    // The reason as a PGN Termination tag spells it, e.g. "Time forfeit"
+    pub fn from_pgn(text: &str) -> Option<Self> {
+        [
+            TerminationReason::Checkmate,
+            TerminationReason::Resignation,
+            TerminationReason::Timeout,
+            TerminationReason::Forfeit,
+            TerminationReason::DrawAgreed,
+            TerminationReason::Stalemate,
+            TerminationReason::ThreefoldRepetition,
+            TerminationReason::FiftyMoveRule,
+            TerminationReason::InsufficientMaterial,
+        ]
+        .into_iter()
+        .find(|reason| reason.to_string() == text)
+    }
+    
+    pub fn is_draw(&self) -> bool {
+        matches!(self,
+            TerminationReason::DrawAgreed |
+            TerminationReason::Stalemate |
+            TerminationReason::ThreefoldRepetition |
+            TerminationReason::FiftyMoveRule |
+            TerminationReason::InsufficientMaterial)
+    }
+}
+
+impl std::fmt::Display for TerminationReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let text = match self {
+            TerminationReason::Checkmate => "Checkmate",
+            TerminationReason::Resignation => "Resignation",
+            TerminationReason::Timeout => "Time forfeit",
+            TerminationReason::Forfeit => "Forfeit",
+            TerminationReason::DrawAgreed => "Draw agreed",
+            TerminationReason::Stalemate => "Stalemate",
+            TerminationReason::ThreefoldRepetition => "Threefold repetition",
+            TerminationReason::FiftyMoveRule => "Fifty-move rule",
+            TerminationReason::InsufficientMaterial => "Insufficient material",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+// A position as GameState::save writes it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedPosition {
+    pub fen: String,
+    pub hash: u64, // Zobrist hash when saved, recomputed on load
+}
+
+// Where the game stands for the side to move, from a single pass over the rules
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GamePhaseStatus {
+    pub in_check: bool,
+    pub terminal: Option<TerminationReason>, // Only endings the board itself can decide
+    pub winner: Option<Color>,
+}
+
+// A named checkmate, for teaching hints. Classified heuristically from the final position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatePattern {
+    BackRank,  // A rook or queen on the back rank, the king walled in by its own pieces
+    Smothered, // A knight, the king surrounded by its own pieces
+    Ladder,    // Two heavy pieces on neighbouring lines drive the king to the edge
+}
+
+impl std::fmt::Display for MatePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            MatePattern::BackRank => "Back-rank mate",
+            MatePattern::Smothered => "Smothered mate",
+            MatePattern::Ladder => "Ladder mate",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// A move as it was played, kept in GameState::move_log for exporting the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedMove {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub piece: PieceType,
+    pub captured: Option<PieceType>,
+    pub promotion: Option<PieceType>, // Filled in by promote_pawn
+    pub castling: bool,
+    pub en_passant: bool,
+}
+
+// What make_move hands back for unmake_move: the pieces the move touched and the state that can't
+// be worked out backwards from the position after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveUndo {
+    from: Position,
+    to: Position,
+    moved: Piece,                        // As it stood before the move, has_moved included
+    captured: Option<(Position, Piece)>, // En passant takes its pawn from beside `to`
+    castling_rights: [bool; 4],          // White kingside, white queenside, black kingside, black queenside
+    en_passant_target: Option<Position>,
+    halfmove_clock: u32,
+    hash: u64,
+    game_over: bool,
+}
+
+impl MoveUndo {
+    // Pawn moves and captures, after which no earlier position can come back
+    fn irreversible(&self) -> bool {
+        self.moved.piece_type == PieceType::Pawn || self.captured.is_some()
+    }
+}
+
+// Moves and null moves share one stack, so each can only be taken back in the order it was made
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UndoEntry {
+    Move(MoveUndo),
+    NullMove { en_passant_target: Option<(usize, usize)> }, // The target the null move cleared
+}
+
+// Clone copies everything, repetition history included; clone_for_search is the cheap copy for lookahead
+#[derive(Debug, Clone)]
 pub struct GameState {
     pub board: Board,
     pub current_turn: Color,
@@ -27,22 +299,32 @@ pub struct GameState {
     pub halfmove_clock: u32,
     pub fullmove_number: u32,
     pub promotion_pending: Option<PromotionState>,
+    pub promotion_options: Vec<PieceType>, // Pieces promote_pawn accepts, in the order the dialog lists them
     
     pub position_history: HashMap<u64, u32>, // Maps hash to occurrence count
-    pub current_hash: u64,                  // Current position hash
+    current_hash: u64,                      // Current position hash, kept in step by every move
     
-    move_cache: HashMap<u64, Vec<((usize, usize), (usize, usize))>>, // Maps position hash to legal moves
+    move_cache: HashMap<u64, Vec<Move>>, // Maps position hash to legal moves
+    undo_stack: Vec<UndoEntry>,
+    cleared_histories: Vec<HashMap<u64, u32>>, // The history each irreversible move on undo_stack cleared
+    pub move_log: Vec<RecordedMove>, // One entry per move on the undo stack, so unmake_move takes both back
+    pub captured: Vec<Piece>, // Pieces taken so far in the order they fell, en passant included
+    ending: Option<(TerminationReason, GameResult)>, // Set by set_ending for endings the board can't see
     pub game_over: bool,
 }
 
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GameState {
     pub fn new() -> Self {
         let mut board = [[None; BOARD_SIZE]; BOARD_SIZE];
         
-        for file in 0..BOARD_SIZE {
-            board[1][file] = Some(Piece::new(PieceType::Pawn, Color::Black));
-            board[6][file] = Some(Piece::new(PieceType::Pawn, Color::White));
-        }
+        board[1] = [Some(Piece::new(PieceType::Pawn, Color::Black)); BOARD_SIZE];
+        board[6] = [Some(Piece::new(PieceType::Pawn, Color::White)); BOARD_SIZE];
         
         board[0][0] = Some(Piece::new(PieceType::Rook, Color::Black));
         board[0][1] = Some(Piece::new(PieceType::Knight, Color::Black));
@@ -73,9 +355,15 @@ impl GameState {
             halfmove_clock: 0,
             fullmove_number: 1,
             promotion_pending: None,
+            promotion_options: STANDARD_PROMOTIONS.to_vec(),
             position_history: HashMap::new(),
             current_hash: 0, // Will be calculated below
             move_cache: HashMap::new(),
+            undo_stack: Vec::new(),
+            cleared_histories: Vec::new(),
+            move_log: Vec::new(),
+            captured: Vec::new(),
+            ending: None,
             game_over: false,
         };
         
@@ -86,243 +374,662 @@ impl GameState {
         state
     }
     
-    fn update_position_history(&mut self) {
-        *self.position_history.entry(self.current_hash).or_insert(0) += 1;
-    }
-    
-    pub fn make_move(&mut self, from: (usize, usize), to: (usize, usize)) -> bool {
-        if self.promotion_pending.is_some() {
-            return false;
+    // Set up a position from Forsyth-Edwards Notation. The move counters may be left off, as
+    // puzzle collections often do, and then start at 0 and 1
+    pub fn from_fen(fen: &str) -> Result<GameState, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 4 && fields.len() != 6 {
+            return Err(FenError::WrongFieldCount(fields.len()));
         }
         
-        let (from_rank, from_file) = from;
-        let (to_rank, to_file) = to;
+        let placement_error = || FenError::InvalidPlacement(fields[0].to_string());
+        let rows: Vec<&str> = fields[0].split('/').collect();
+        if rows.len() != BOARD_SIZE {
+            return Err(placement_error());
+        }
         
-        if let Some(mut piece) = self.board[from_rank][from_file] {
-            if piece.color != self.current_turn {
-                return false;
+        // Rank 0 is the eighth rank, which FEN lists first
+        let mut board = [[None; BOARD_SIZE]; BOARD_SIZE];
+        for (rank, row) in rows.iter().enumerate() {
+            let mut file = 0;
+            for c in row.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    file += empty as usize;
+                } else {
+                    let piece = Piece::from_fen_char(c).ok_or_else(placement_error)?;
+                    if file >= BOARD_SIZE {
+                        return Err(placement_error());
+                    }
+                    board[rank][file] = Some(piece);
+                    file += 1;
+                }
             }
-            
-            if self.would_be_in_check_after_move(from, to) {
-                return false;
+            if file != BOARD_SIZE {
+                return Err(placement_error());
             }
-            
-            self.clear_move_cache();
-            
-            let old_en_passant = self.en_passant_target;
-            if let Some((_rank, file)) = old_en_passant {
-                self.current_hash ^= ZOBRIST.en_passant_keys[file];
+        }
+        
+        for color in [Color::White, Color::Black] {
+            let kings = board.iter().flatten().flatten()
+                .filter(|piece| piece.piece_type == PieceType::King && piece.color == color)
+                .count();
+            if kings != 1 {
+                return Err(placement_error());
             }
-            
-            self.en_passant_target = None;
-            
-            if piece.piece_type == PieceType::Pawn && old_en_passant == Some(to) && 
-               from_file != to_file && self.board[to_rank][to_file].is_none() {
-                // Check that the pawn is on the correct rank for en passant
-                let correct_en_passant_rank = match piece.color {
-                    Color::White => 3, // 5th rank (index 3)
-                    Color::Black => 4, // 4th rank (index 4)
+        }
+        
+        let current_turn = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            other => return Err(FenError::InvalidTurn(other.to_string())),
+        };
+        
+        // Each right needs its king and rook still at home
+        let castling_error = || FenError::InvalidCastling(fields[2].to_string());
+        let mut rights = [false; 4]; // White kingside, white queenside, black kingside, black queenside
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let (index, rank, rook_file) = match c {
+                    'K' => (0, 7, 7),
+                    'Q' => (1, 7, 0),
+                    'k' => (2, 0, 7),
+                    'q' => (3, 0, 0),
+                    _ => return Err(castling_error()),
                 };
-                
-                if from_rank == correct_en_passant_rank {
-                    let captured_pawn_rank = from_rank;
-                    let captured_pawn_file = to_file;
-                    
-                    self.update_hash_for_move(&piece, from, to);
-                    
-                    let captured_color = if piece.color == Color::White { BLACK } else { WHITE };
-                    let captured_square = captured_pawn_rank * 8 + captured_pawn_file;
-                    self.current_hash ^= ZOBRIST.piece_keys[captured_color][0][captured_square]; // Remove captured pawn
-                    
-                    piece.has_moved = true;
-                    self.board[to_rank][to_file] = Some(piece);
-                    self.board[from_rank][from_file] = None;
-                    
-                    self.board[captured_pawn_rank][captured_pawn_file] = None;
-                    
-                    self.halfmove_clock = 0;
-                    
-                    self.switch_turn();
-                    
-                    self.update_position_history();
-                    
-                    return true;
+                let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+                let king_home = board[rank][4] == Some(Piece::new(PieceType::King, color));
+                let rook_home = board[rank][rook_file] == Some(Piece::new(PieceType::Rook, color));
+                if rights[index] || !king_home || !rook_home {
+                    return Err(castling_error());
                 }
+                rights[index] = true;
             }
-            
-            self.halfmove_clock += 1;
-            
-            if piece.piece_type == PieceType::Pawn && 
-               ((from_rank as isize - to_rank as isize).abs() == 2) {
-                let direction = if piece.color == Color::White { -1 } else { 1 };
-                let en_passant_rank = (from_rank as isize + direction) as usize;
-                
-                self.en_passant_target = Some((en_passant_rank, from_file));
-                
-                self.current_hash ^= ZOBRIST.en_passant_keys[from_file];
-            }
-            
-            if piece.piece_type == PieceType::Pawn {
-                self.halfmove_clock = 0;
+        }
+        
+        // The target sits behind a pawn that just made a double step
+        let en_passant_target = if fields[3] == "-" {
+            None
+        } else {
+            let expected_rank = if current_turn == Color::White { 2 } else { 5 };
+            match square_from_uci(fields[3]) {
+                Some(square) if square.0 == expected_rank => Some(square),
+                _ => return Err(FenError::InvalidEnPassant(fields[3].to_string())),
             }
-            
-            if piece.piece_type == PieceType::King {
-                if from_file + 2 == to_file && from_rank == to_rank {
-                    if !self.can_castle_kingside(piece.color) {
-                        return false;
-                    }
-                    
-                    let mid_square = (from_rank, from_file + 1);
-                    if self.would_be_in_check_after_move(from, mid_square) {
-                        return false;
-                    }
-                    
-                    self.update_hash_for_move(&piece, from, to);
-                    
-                    piece.has_moved = true;
-                    self.board[to_rank][to_file] = Some(piece);
-                    self.board[from_rank][from_file] = None;
-                    
-                    let rook_file = 7; // h-file
-                    let rook_to_file = 5; // f-file
-                    
-                    if let Some(mut rook) = self.board[from_rank][rook_file] {
-                        if rook.piece_type == PieceType::Rook && rook.color == piece.color {
-                            self.update_hash_for_move(&rook, (from_rank, rook_file), (from_rank, rook_to_file));
-                            
-                            rook.has_moved = true;
-                            self.board[from_rank][rook_to_file] = Some(rook);
-                            self.board[from_rank][rook_file] = None;
-                        }
-                    }
-                    
-                    self.update_castling_flags(piece.color);
-                    
-                    self.switch_turn();
-                    
-                    self.update_position_history();
-                    
-                    return true;
-                }
-                
-                if from_file as isize - 2 == to_file as isize && from_rank == to_rank {
-                    if !self.can_castle_queenside(piece.color) {
-                        return false;
-                    }
-                    
-                    let mid_square = (from_rank, from_file - 1);
-                    if self.would_be_in_check_after_move(from, mid_square) {
-                        return false;
-                    }
-                    
-                    self.update_hash_for_move(&piece, from, to);
-                    
+        };
+        
+        let (halfmove_clock, fullmove_number) = if fields.len() == 6 {
+            let halfmove = fields[4].parse::<u32>().map_err(|_| FenError::InvalidClock(fields[4].to_string()))?;
+            let fullmove = fields[5].parse::<u32>().ok()
+                .filter(|&number| number > 0)
+                .ok_or_else(|| FenError::InvalidClock(fields[5].to_string()))?;
+            (halfmove, fullmove)
+        } else {
+            (0, 1)
+        };
+        
+        Ok(Self::from_setup(board, current_turn, rights, en_passant_target, halfmove_clock, fullmove_number))
+    }
+    
+    // Shared by from_fen and GameStateBuilder once the position has been checked
+    fn from_setup(
+        mut board: Board,
+        current_turn: Color,
+        rights: [bool; 4],
+        en_passant_target: Option<(usize, usize)>,
+        halfmove_clock: u32,
+        fullmove_number: u32,
+    ) -> Self {
+        // A pawn off its starting rank has moved, like it would have in a played game
+        for (rank, row) in board.iter_mut().enumerate() {
+            for piece in row.iter_mut().flatten() {
+                let start_rank = if piece.color == Color::White { 6 } else { 1 };
+                if piece.piece_type == PieceType::Pawn && rank != start_rank {
                     piece.has_moved = true;
-                    self.board[to_rank][to_file] = Some(piece);
-                    self.board[from_rank][from_file] = None;
-                    
-                    let rook_file = 0; // a-file
-                    let rook_to_file = 3; // d-file
-                    
-                    if let Some(mut rook) = self.board[from_rank][rook_file] {
-                        if rook.piece_type == PieceType::Rook && rook.color == piece.color {
-                            self.update_hash_for_move(&rook, (from_rank, rook_file), (from_rank, rook_to_file));
-                            
-                            rook.has_moved = true;
-                            self.board[from_rank][rook_to_file] = Some(rook);
-                            self.board[from_rank][rook_file] = None;
-                        }
-                    }
-                    
-                    self.update_castling_flags(piece.color);
-                    
-                    self.switch_turn();
-                    
-                    self.update_position_history();
-                    
-                    return true;
                 }
             }
-            
-            if piece.piece_type == PieceType::King {
-                self.update_castling_flags(piece.color);
-            } else if piece.piece_type == PieceType::Rook {
-                if from_rank == 7 && from_file == 0 && piece.color == Color::White && self.white_can_castle_queenside {
-                    self.current_hash ^= ZOBRIST.castling_keys[1]; // Toggle white queenside castling
-                    self.white_can_castle_queenside = false;
-                } else if from_rank == 7 && from_file == 7 && piece.color == Color::White && self.white_can_castle_kingside {
-                    self.current_hash ^= ZOBRIST.castling_keys[0]; // Toggle white kingside castling
-                    self.white_can_castle_kingside = false;
-                } else if from_rank == 0 && from_file == 0 && piece.color == Color::Black && self.black_can_castle_queenside {
-                    self.current_hash ^= ZOBRIST.castling_keys[3]; // Toggle black queenside castling
-                    self.black_can_castle_queenside = false;
-                } else if from_rank == 0 && from_file == 7 && piece.color == Color::Black && self.black_can_castle_kingside {
-                    self.current_hash ^= ZOBRIST.castling_keys[2]; // Toggle black kingside castling
-                    self.black_can_castle_kingside = false;
+        }
+        
+        // Castling moves are generated from has_moved, so pieces that lost their rights count as moved
+        for (rank, color, kingside, queenside) in [(7, Color::White, rights[0], rights[1]), (0, Color::Black, rights[2], rights[3])] {
+            for (file, has_right) in [(4, kingside || queenside), (7, kingside), (0, queenside)] {
+                if let Some(piece) = &mut board[rank][file] {
+                    if piece.color == color && !has_right &&
+                       (piece.piece_type == PieceType::King || piece.piece_type == PieceType::Rook) {
+                        piece.has_moved = true;
+                    }
                 }
             }
-            
-            let is_capture = self.board[to_rank][to_file].is_some();
-            if is_capture {
-                self.halfmove_clock = 0;
+        }
+        
+        let mut state = Self {
+            board,
+            current_turn,
+            white_can_castle_kingside: rights[0],
+            white_can_castle_queenside: rights[1],
+            black_can_castle_kingside: rights[2],
+            black_can_castle_queenside: rights[3],
+            en_passant_target,
+            halfmove_clock,
+            fullmove_number,
+            promotion_pending: None,
+            promotion_options: STANDARD_PROMOTIONS.to_vec(),
+            position_history: HashMap::new(),
+            current_hash: 0, // Will be calculated below
+            move_cache: HashMap::new(),
+            undo_stack: Vec::new(),
+            cleared_histories: Vec::new(),
+            move_log: Vec::new(),
+            captured: Vec::new(),
+            ending: None,
+            game_over: false,
+        };
+        
+        state.current_hash = state.calculate_zobrist_hash();
+        
+        state.position_history.insert(state.current_hash, 1);
+        
+        state
+    }
+    
+    fn update_position_history(&mut self) {
+        *self.position_history.entry(self.current_hash).or_insert(0) += 1;
+    }
+    
+    // None if the move is illegal. Otherwise the undo takes it back again with unmake_move, also
+    // once a promotion it left pending has been chosen
+    pub fn make_move(&mut self, from: (usize, usize), to: (usize, usize)) -> Option<MoveUndo> {
+        self.apply_move_checked(from, to).ok()?;
+        match self.undo_stack.last() {
+            Some(&UndoEntry::Move(undo)) => Some(undo),
+            _ => None,
+        }
+    }
+    
+    pub fn apply_move_checked(&mut self, from: (usize, usize), to: (usize, usize)) -> Result<MoveOutcome, MoveError> {
+        if self.promotion_pending.is_some() {
+            return Err(MoveError::PromotionPending);
+        }
+        
+        let (from_rank, from_file) = from;
+        let (to_rank, to_file) = to;
+        
+        // Reject malformed input outright rather than trusting the move generators
+        if from_rank >= BOARD_SIZE || from_file >= BOARD_SIZE || to_rank >= BOARD_SIZE || to_file >= BOARD_SIZE {
+            return Err(MoveError::OutOfBounds);
+        }
+        
+        if from == to {
+            return Err(MoveError::IllegalDestination);
+        }
+        
+        let piece = match self.board[from_rank][from_file] {
+            Some(piece) => piece,
+            None => return Err(MoveError::NoPieceAtSource),
+        };
+        
+        if piece.color != self.current_turn {
+            return Err(MoveError::WrongTurn);
+        }
+        
+        // Never capture a friendly piece
+        if let Some(target) = self.board[to_rank][to_file] {
+            if target.color == piece.color {
+                return Err(MoveError::IllegalDestination);
             }
+        }
+        
+        if !self.candidate_moves(from, &piece).contains(&to) {
+            return Err(MoveError::IllegalDestination);
+        }
+        
+        if self.would_be_in_check_after_move(from, to) {
+            return Err(MoveError::LeavesKingInCheck);
+        }
+        
+        // candidate_moves only offers a castle castling_allowed has passed
+        self.execute_move(from, to);
+        
+        if self.promotion_pending.is_some() {
+            Ok(MoveOutcome::PromotionRequired)
+        } else {
+            Ok(MoveOutcome::Moved)
+        }
+    }
+    
+    // The move and everything recorded about it: undo stack, move log, captures and repetition history
+    fn execute_move(&mut self, from: (usize, usize), to: (usize, usize)) -> Option<MoveUndo> {
+        let piece = self.board[from.0][from.1]?;
+        self.clear_move_cache();
+        
+        let en_passant = self.is_en_passant(from, to);
+        let castling = self.is_castle(from, to);
+        let undo = self.move_pieces(from, to);
+        
+        self.undo_stack.push(UndoEntry::Move(undo));
+        self.move_log.push(RecordedMove {
+            from,
+            to,
+            piece: piece.piece_type,
+            captured: undo.captured.map(|(_, taken)| taken.piece_type),
+            promotion: None,
+            castling,
+            en_passant,
+        });
+        if let Some((_, taken)) = undo.captured {
+            self.captured.push(Piece::new(taken.piece_type, taken.color));
+        }
+        
+        // No earlier position can recur after an irreversible move, so the history starts again from
+        // the new position. The old one is kept for unmake_move
+        if undo.irreversible() {
+            self.cleared_histories.push(std::mem::take(&mut self.position_history));
+        }
+        
+        // A pawn waiting for its promotion piece is counted once promote_pawn finishes the move
+        if self.promotion_pending.is_none() {
+            self.update_position_history();
+        }
+        
+        Some(undo)
+    }
+    
+    // Just the move on the board, hash, castling rights, en passant target, clocks and turn, with
+    // nothing recorded, so the legality check can try a move and put it back with restore_position
+    fn move_pieces(&mut self, from: (usize, usize), to: (usize, usize)) -> MoveUndo {
+        let (from_rank, from_file) = from;
+        let (to_rank, to_file) = to;
+        
+        let mut piece = self.board[from_rank][from_file].expect("move_pieces needs a piece on the from square");
+        let en_passant = self.is_en_passant(from, to);
+        let captured_square = if en_passant { (from_rank, to_file) } else { to };
+        let undo = MoveUndo {
+            from,
+            to,
+            moved: piece,
+            captured: self.board[captured_square.0][captured_square.1].map(|taken| (captured_square, taken)),
+            castling_rights: self.castling_rights(),
+            en_passant_target: self.en_passant_target,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.current_hash,
+            game_over: self.game_over,
+        };
+        
+        // Pawn moves and captures (including en passant) reset the fifty-move clock, everything else counts
+        if undo.irreversible() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        
+        let old_en_passant = self.en_passant_target;
+        if let Some((_rank, file)) = old_en_passant {
+            self.current_hash ^= ZOBRIST.en_passant_keys[file];
+        }
+        
+        self.en_passant_target = None;
+        
+        if en_passant {
+            let captured_pawn_rank = from_rank;
+            let captured_pawn_file = to_file;
             
             self.update_hash_for_move(&piece, from, to);
             
-            piece.has_moved = true;
+            let captured_color = ZobristKeys::get_color_index(piece.color.opposite());
+            let pawn_index = ZobristKeys::get_piece_index(PieceType::Pawn);
+            let captured_square = square_index(captured_pawn_rank, captured_pawn_file);
+            self.current_hash ^= ZOBRIST.piece_keys[captured_color][pawn_index][captured_square]; // Remove captured pawn
             
+            piece.has_moved = true;
             self.board[to_rank][to_file] = Some(piece);
             self.board[from_rank][from_file] = None;
             
-            if piece.piece_type == PieceType::Pawn {
-                let promotion_rank = match piece.color {
-                    Color::White => 0, // White pawns promote on the 8th rank (index 0)
-                    Color::Black => 7, // Black pawns promote on the 1st rank (index 7)
-                };
-                
-                if to_rank == promotion_rank {
-                    self.promotion_pending = Some(PromotionState {
-                        position: (to_rank, to_file),
-                        color: piece.color,
-                    });
-                    
-                    return true;
-                }
-            }
+            self.board[captured_pawn_rank][captured_pawn_file] = None;
             
             self.switch_turn();
             
-            self.update_position_history();
-            
-            return true;
+            return undo;
         }
         
-        false
+        if piece.piece_type == PieceType::Pawn && 
+           ((from_rank as isize - to_rank as isize).abs() == 2) {
+            let direction = if piece.color == Color::White { -1 } else { 1 };
+            let en_passant_rank = (from_rank as isize + direction) as usize;
+            
+            self.en_passant_target = Some((en_passant_rank, from_file));
+            
+            self.current_hash ^= ZOBRIST.en_passant_keys[from_file];
+        }
+        
+        if piece.piece_type == PieceType::King {
+            if from_file + 2 == to_file && from_rank == to_rank {
+                self.update_hash_for_move(&piece, from, to);
+                
+                piece.has_moved = true;
+                self.board[to_rank][to_file] = Some(piece);
+                self.board[from_rank][from_file] = None;
+                
+                let rook_file = 7; // h-file
+                let rook_to_file = 5; // f-file
+                
+                if let Some(mut rook) = self.board[from_rank][rook_file] {
+                    if rook.piece_type == PieceType::Rook && rook.color == piece.color {
+                        self.update_hash_for_move(&rook, (from_rank, rook_file), (from_rank, rook_to_file));
+                        
+                        rook.has_moved = true;
+                        self.board[from_rank][rook_to_file] = Some(rook);
+                        self.board[from_rank][rook_file] = None;
+                    }
+                }
+                
+                self.update_castling_flags(piece.color);
+                
+                self.switch_turn();
+                
+                return undo;
+            }
+            
+            if from_file as isize - 2 == to_file as isize && from_rank == to_rank {
+                self.update_hash_for_move(&piece, from, to);
+                
+                piece.has_moved = true;
+                self.board[to_rank][to_file] = Some(piece);
+                self.board[from_rank][from_file] = None;
+                
+                let rook_file = 0; // a-file
+                let rook_to_file = 3; // d-file
+                
+                if let Some(mut rook) = self.board[from_rank][rook_file] {
+                    if rook.piece_type == PieceType::Rook && rook.color == piece.color {
+                        self.update_hash_for_move(&rook, (from_rank, rook_file), (from_rank, rook_to_file));
+                        
+                        rook.has_moved = true;
+                        self.board[from_rank][rook_to_file] = Some(rook);
+                        self.board[from_rank][rook_file] = None;
+                    }
+                }
+                
+                self.update_castling_flags(piece.color);
+                
+                self.switch_turn();
+                
+                return undo;
+            }
+        }
+        
+        if piece.piece_type == PieceType::King {
+            self.update_castling_flags(piece.color);
+        }
+        
+        // A rook leaving its corner loses its castling right, and so does one captured there,
+        // including by a pawn that promotes on the capture
+        self.clear_castling_right_at(from);
+        self.clear_castling_right_at(to);
+        
+        self.update_hash_for_move(&piece, from, to);
+        
+        piece.has_moved = true;
+        
+        self.board[to_rank][to_file] = Some(piece);
+        self.board[from_rank][from_file] = None;
+        
+        if piece.piece_type == PieceType::Pawn {
+            let promotion_rank = match piece.color {
+                Color::White => 0, // White pawns promote on the 8th rank (index 0)
+                Color::Black => 7, // Black pawns promote on the 1st rank (index 7)
+            };
+            
+            if to_rank == promotion_rank {
+                self.promotion_pending = Some(PromotionState {
+                    position: (to_rank, to_file),
+                    color: piece.color,
+                });
+                
+                return undo;
+            }
+        }
+        
+        self.switch_turn();
+        
+        undo
+    }
+    
+    // Move classification, without applying the move
+    pub fn is_capture(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        if let Some(piece) = self.board[from.0][from.1] {
+            match self.board[to.0][to.1] {
+                Some(target) => target.color != piece.color,
+                None => self.is_en_passant(from, to),
+            }
+        } else {
+            false
+        }
+    }
+    
+    pub fn is_castle(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        matches!(self.board[from.0][from.1], Some(piece) if piece.piece_type == PieceType::King) &&
+            from.0 == to.0 &&
+            (from.1 as isize - to.1 as isize).abs() == 2
+    }
+    
+    pub fn is_en_passant(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        if let Some(piece) = self.board[from.0][from.1] {
+            // The capturing pawn must stand on its 5th rank
+            let correct_en_passant_rank = match piece.color {
+                Color::White => 3, // 5th rank (index 3)
+                Color::Black => 4, // 4th rank (index 4)
+            };
+            
+            piece.piece_type == PieceType::Pawn &&
+                self.en_passant_target == Some(to) &&
+                from.0 == correct_en_passant_rank &&
+                from.1 != to.1 &&
+                self.board[to.0][to.1].is_none()
+        } else {
+            false
+        }
+    }
+    
+    // Work out the move between two consecutive snapshots, for clients that only receive boards.
+    // Castling reports the king's move and a promotion the piece the pawn became. Anything that
+    // isn't a single move gives None
+    pub fn diff(prev: &Board, next: &Board) -> Option<(Position, Position, Option<PieceType>)> {
+        let kind = |square: &Square| square.map(|piece| (piece.piece_type, piece.color));
+        
+        let mut vacated = Vec::new();
+        let mut arrived = Vec::new();
+        for rank in 0..BOARD_SIZE {
+            for file in 0..BOARD_SIZE {
+                let (before, after) = (kind(&prev[rank][file]), kind(&next[rank][file]));
+                if before == after {
+                    continue;
+                }
+                match (before, after) {
+                    (Some(piece), None) => vacated.push(((rank, file), piece)),
+                    (_, Some(piece)) => arrived.push(((rank, file), piece)),
+                    (None, None) => {}
+                }
+            }
+        }
+        
+        // One piece lands, or a king and rook when castling; en passant empties two squares for one landing
+        let (to, (moved_type, moved_color)) = match arrived.as_slice() {
+            [single] => *single,
+            [first, second] if vacated.len() == 2 => {
+                *[first, second].into_iter().find(|(_, (piece_type, _))| *piece_type == PieceType::King)?
+            }
+            _ => return None,
+        };
+        if vacated.is_empty() || vacated.len() > 2 {
+            return None;
+        }
+        
+        let own: Vec<_> = vacated.iter().filter(|(_, (_, color))| *color == moved_color).collect();
+        let (from, (from_type, _)) = match own.as_slice() {
+            [single] => **single,
+            _ => **own.iter().find(|(_, (piece_type, _))| *piece_type == moved_type)?,
+        };
+        
+        let promotion = if from_type == PieceType::Pawn && moved_type != PieceType::Pawn {
+            Some(moved_type)
+        } else if from_type != moved_type {
+            return None;
+        } else {
+            None
+        };
+        
+        Some((from, to, promotion))
+    }
+    
+    // Take back the move make_move returned `undo` for, including one whose promotion is still
+    // waiting for its piece. Refused unless it is the last thing made, null moves included
+    pub fn unmake_move(&mut self, undo: MoveUndo) -> bool {
+        if self.undo_stack.last() != Some(&UndoEntry::Move(undo)) {
+            return false;
+        }
+        self.undo_stack.pop();
+        if self.move_log.pop().is_some_and(|recorded| recorded.captured.is_some()) {
+            self.captured.pop();
+        }
+        
+        // A completed move counted its position in the history, a pending promotion hasn't yet
+        if self.promotion_pending.is_none() {
+            if let Some(count) = self.position_history.get_mut(&self.current_hash) {
+                *count -= 1;
+                if *count == 0 {
+                    self.position_history.remove(&self.current_hash);
+                }
+            }
+        }
+        if undo.irreversible() {
+            if let Some(history) = self.cleared_histories.pop() {
+                self.position_history = history;
+            }
+        }
+        
+        self.restore_position(&undo);
+        
+        true
+    }
+    
+    // Take back the last move for a caller that didn't keep its MoveUndo, like a takeback agreed over
+    // the network. Refused while a null move made after it hasn't been taken back
+    pub fn take_back(&mut self) -> bool {
+        match self.undo_stack.last() {
+            Some(&UndoEntry::Move(undo)) => self.unmake_move(undo),
+            _ => false,
+        }
+    }
+    
+    // move_pieces in reverse. The promoted piece, if any, is simply replaced by the pawn
+    fn restore_position(&mut self, undo: &MoveUndo) {
+        let (from, to) = (undo.from, undo.to);
+        
+        // A completed move passed the turn, one waiting for its promotion piece hasn't yet
+        if self.current_turn != undo.moved.color && undo.moved.color == Color::Black {
+            self.fullmove_number -= 1;
+        }
+        self.current_turn = undo.moved.color;
+        self.promotion_pending = None;
+        
+        self.board[to.0][to.1] = None;
+        if undo.moved.piece_type == PieceType::King && from.0 == to.0 && from.1.abs_diff(to.1) == 2 {
+            // Castling needs an unmoved rook, so it goes back to its corner unmoved
+            let (rook_file, rook_to_file) = if to.1 > from.1 { (7, 5) } else { (0, 3) };
+            if let Some(mut rook) = self.board[from.0][rook_to_file].take() {
+                rook.has_moved = false;
+                self.board[from.0][rook_file] = Some(rook);
+            }
+        }
+        self.board[from.0][from.1] = Some(undo.moved);
+        if let Some((square, taken)) = undo.captured {
+            self.board[square.0][square.1] = Some(taken);
+        }
+        
+        [
+            self.white_can_castle_kingside,
+            self.white_can_castle_queenside,
+            self.black_can_castle_kingside,
+            self.black_can_castle_queenside,
+        ] = undo.castling_rights;
+        self.en_passant_target = undo.en_passant_target;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.current_hash = undo.hash;
+        self.game_over = undo.game_over;
+    }
+    
+    // White kingside, white queenside, black kingside, black queenside
+    fn castling_rights(&self) -> [bool; 4] {
+        [
+            self.white_can_castle_kingside,
+            self.white_can_castle_queenside,
+            self.black_can_castle_kingside,
+            self.black_can_castle_queenside,
+        ]
+    }
+    
+    // Pass the turn without moving, for null-move pruning in a search. Refused while in check, where
+    // passing would leave the king en prise, and while a promotion waits for its piece
+    pub fn make_null_move(&mut self) -> bool {
+        if self.promotion_pending.is_some() || self.is_in_check(self.current_turn) {
+            return false;
+        }
+        
+        let en_passant_target = self.en_passant_target.take();
+        if let Some((_rank, file)) = en_passant_target {
+            self.current_hash ^= ZOBRIST.en_passant_keys[file];
+        }
+        self.undo_stack.push(UndoEntry::NullMove { en_passant_target });
+        
+        self.switch_turn();
+        
+        true
+    }
+    
+    // Refused unless a null move was the last thing made
+    pub fn unmake_null_move(&mut self) -> bool {
+        let en_passant_target = match self.undo_stack.last() {
+            Some(UndoEntry::NullMove { en_passant_target }) => *en_passant_target,
+            _ => return false,
+        };
+        self.undo_stack.pop();
+        
+        // switch_turn in reverse
+        self.current_hash ^= ZOBRIST.side_to_move_key;
+        self.current_turn = self.current_turn.opposite();
+        if self.current_turn == Color::Black {
+            self.fullmove_number -= 1;
+        }
+        
+        if let Some((_rank, file)) = en_passant_target {
+            self.current_hash ^= ZOBRIST.en_passant_keys[file];
+        }
+        self.en_passant_target = en_passant_target;
+        
+        true
     }
     
+    // A piece outside promotion_options is refused and the promotion stays pending
     pub fn promote_pawn(&mut self, piece_type: PieceType) -> bool {
+        if !self.promotion_options.contains(&piece_type) {
+            return false;
+        }
+        
         if let Some(promotion) = self.promotion_pending.take() {
             let (rank, file) = promotion.position;
             let color = promotion.color;
-            let square = rank * 8 + file;
+            let square = square_index(rank, file);
             
-            let color_index = match color {
-                Color::White => WHITE,
-                Color::Black => BLACK,
-            };
-            self.current_hash ^= ZOBRIST.piece_keys[color_index][0][square]; // Remove pawn
+            let color_index = ZobristKeys::get_color_index(color);
+            let pawn_index = ZobristKeys::get_piece_index(PieceType::Pawn);
+            self.current_hash ^= ZOBRIST.piece_keys[color_index][pawn_index][square]; // Remove pawn
             
-            let piece_index = match piece_type {
-                PieceType::Pawn => 0,
-                PieceType::Knight => 1,
-                PieceType::Bishop => 2,
-                PieceType::Rook => 3,
-                PieceType::Queen => 4,
-                PieceType::King => 5,
-            };
+            let piece_index = ZobristKeys::get_piece_index(piece_type);
             self.current_hash ^= ZOBRIST.piece_keys[color_index][piece_index][square]; // Add new piece
             
             self.board[rank][file] = Some(Piece::new(piece_type, color));
+            if let Some(last) = self.move_log.last_mut() {
+                last.promotion = Some(piece_type);
+            }
             
             self.switch_turn();
             
@@ -371,6 +1078,21 @@ impl GameState {
         }
     }
     
+    // Each castling right belongs to one rook's starting corner
+    fn clear_castling_right_at(&mut self, square: (usize, usize)) {
+        for (index, corner, right) in [
+            (0, (7, 7), &mut self.white_can_castle_kingside),
+            (1, (7, 0), &mut self.white_can_castle_queenside),
+            (2, (0, 7), &mut self.black_can_castle_kingside),
+            (3, (0, 0), &mut self.black_can_castle_queenside),
+        ] {
+            if square == corner && *right {
+                self.current_hash ^= ZOBRIST.castling_keys[index];
+                *right = false;
+            }
+        }
+    }
+    
     fn can_castle_kingside(&self, color: Color) -> bool {
         let can_castle = match color {
             Color::White => self.white_can_castle_kingside,
@@ -451,80 +1173,207 @@ impl GameState {
         true
     }
     
-    pub fn is_in_check(&self, color: Color) -> bool {
-        let mut king_pos = None;
+    // The one place castling legality is decided: the right hasn't been lost, king and rook are unmoved
+    // with nothing between them, and the king doesn't start on, pass through or land on an attacked square
+    fn castling_allowed(&mut self, from: (usize, usize), to: (usize, usize)) -> bool {
+        let color = match self.board[from.0][from.1] {
+            Some(piece) => piece.color,
+            None => return false,
+        };
         
-        for rank in 0..BOARD_SIZE {
-            for file in 0..BOARD_SIZE {
-                if let Some(piece) = self.board[rank][file] {
-                    if piece.piece_type == PieceType::King && piece.color == color {
-                        king_pos = Some((rank, file));
-                        break;
-                    }
-                }
+        let kingside = to.1 > from.1;
+        let rights_and_path = if kingside {
+            self.can_castle_kingside(color)
+        } else {
+            self.can_castle_queenside(color)
+        };
+        if !rights_and_path {
+            return false;
+        }
+        
+        // can_castle_* already ruled out starting in check; try the king on every square it crosses
+        let crossed: Vec<usize> = if kingside {
+            (from.1 + 1..=to.1).collect()
+        } else {
+            (to.1..from.1).collect()
+        };
+        crossed.into_iter().all(|file| !self.would_be_in_check_after_move(from, (from.0, file)))
+    }
+    
+    fn find_king(&self, color: Color) -> Option<(usize, usize)> {
+        king_square(&self.board, color)
+    }
+    
+    // The piece's moves before the check test. The en passant target lasts just the one ply after
+    // the double push, so a pawn only gets that capture while it's set
+    fn candidate_moves(&mut self, from: (usize, usize), piece: &Piece) -> Vec<(usize, usize)> {
+        let mut moves = piece.get_possible_moves(from, &self.board, self.en_passant_target);
+        
+        // Castling depends on the game's rights and on attacks, so pieces don't generate it themselves
+        if piece.piece_type == PieceType::King {
+            let castles = [(from.0, from.1 + 2), (from.0, from.1.saturating_sub(2))];
+            moves.extend(castles.into_iter().filter(|&to| {
+                to.1 < BOARD_SIZE && self.is_castle(from, to) && self.castling_allowed(from, to)
+            }));
+        }
+        
+        moves
+    }
+    
+    pub fn verify_hash(&self) -> bool {
+        self.current_hash == self.calculate_zobrist_hash()
+    }
+    
+    // Bring everything derived from the board back in line after the board was set from outside
+    // (a snapshot from the server, say), dropping what the pieces can't support. True if anything
+    // had to change
+    pub fn validate_and_repair(&mut self) -> bool {
+        let mut repaired = false;
+        
+        // A pending promotion needs that side's pawn on its last rank
+        if let Some(promotion) = &self.promotion_pending {
+            let (rank, file) = promotion.position;
+            let last_rank = if promotion.color == Color::White { 0 } else { BOARD_SIZE - 1 };
+            let pawn = self.board[rank][file].map(|piece| (piece.piece_type, piece.color));
+            if rank != last_rank || pawn != Some((PieceType::Pawn, promotion.color)) {
+                self.promotion_pending = None;
+                repaired = true;
             }
-            if king_pos.is_some() {
-                break;
+        }
+        
+        // Castling rights need the king and rook still at home
+        for (rank, color, rook_file, right) in [
+            (7, Color::White, 7, &mut self.white_can_castle_kingside),
+            (7, Color::White, 0, &mut self.white_can_castle_queenside),
+            (0, Color::Black, 7, &mut self.black_can_castle_kingside),
+            (0, Color::Black, 0, &mut self.black_can_castle_queenside),
+        ] {
+            let at_home = |file: usize, piece_type: PieceType| {
+                self.board[rank][file].map(|piece| (piece.piece_type, piece.color)) == Some((piece_type, color))
+            };
+            if *right && !(at_home(4, PieceType::King) && at_home(rook_file, PieceType::Rook)) {
+                *right = false;
+                repaired = true;
             }
         }
         
-        if king_pos.is_none() {
-            return false;
+        // An en passant target is an empty square behind the opponent's pawn that just double-stepped
+        if let Some((rank, file)) = self.en_passant_target {
+            let (expected_rank, pawn_rank) = if self.current_turn == Color::White { (2, 3) } else { (5, 4) };
+            let pawn = self.board[pawn_rank][file].map(|piece| (piece.piece_type, piece.color));
+            if rank != expected_rank || self.board[rank][file].is_some() ||
+               pawn != Some((PieceType::Pawn, self.current_turn.opposite())) {
+                self.en_passant_target = None;
+                repaired = true;
+            }
         }
         
-        let (king_rank, king_file) = king_pos.unwrap();
+        let hash = self.calculate_zobrist_hash();
+        if hash != self.current_hash {
+            self.current_hash = hash;
+            self.clear_move_cache();
+            repaired = true;
+        }
         
-        for rank in 0..BOARD_SIZE {
-            for file in 0..BOARD_SIZE {
-                if let Some(piece) = self.board[rank][file] {
-                    if piece.color != color {
-                        let moves = piece.get_possible_moves((rank, file), &self.board);
-                        
-                        if moves.contains(&(king_rank, king_file)) {
-                            return true;
-                        }
-                    }
-                }
-            }
+        // The current position has occurred at least once
+        if !self.position_history.contains_key(&self.current_hash) {
+            self.position_history.insert(self.current_hash, 1);
+            repaired = true;
         }
         
-        false
+        repaired
     }
     
-    pub fn would_be_in_check_after_move(&self, from: (usize, usize), to: (usize, usize)) -> bool {
-        let mut temp_board = self.clone();
+    // Write the position to a save file, with its hash alongside
+    pub fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+        let saved = SavedPosition { fen: self.to_fen(), hash: self.current_hash };
+        fs::write(path, serde_json::to_string_pretty(&saved)?)
+    }
+    
+    // Read a position written by save. The stored hash is never trusted, since the Zobrist keys are
+    // drawn again each run and the file may have been edited, so validate_and_repair recomputes it
+    pub fn load(path: &Path) -> Result<GameState, std::io::Error> {
+        let saved: SavedPosition = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let mut state = GameState::from_fen(&saved.fen)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
         
-        let (from_rank, from_file) = from;
-        let (to_rank, to_file) = to;
+        state.position_history.clear();
+        state.current_hash = saved.hash;
+        state.validate_and_repair();
+        Ok(state)
+    }
+    
+    pub fn is_in_check(&self, color: Color) -> bool {
+        king_attacked(&self.board, color)
+    }
+    
+    // Pieces of `color` that can't leave their ray to the king, each paired with the square of its pinner
+    pub fn pinned_pieces(&self, color: Color) -> Vec<Move> {
+        let mut pins = Vec::new();
         
-        let piece = match temp_board.board[from_rank][from_file] {
-            Some(piece) => piece,
-            None => return false, // No piece to move
+        let (king_rank, king_file) = match self.find_king(color) {
+            Some(position) => position,
+            None => return pins,
         };
         
-        // Check for en passant capture
-        if piece.piece_type == PieceType::Pawn && 
-           temp_board.en_passant_target == Some(to) && 
-           from_file != to_file &&
-           temp_board.board[to_rank][to_file].is_none() {
-            // Check that the pawn is on the correct rank for en passant
-            let correct_en_passant_rank = match piece.color {
-                Color::White => 3, // 5th rank (index 3)
-                Color::Black => 4, // 4th rank (index 4)
-            };
+        let directions = [
+            (-1, 0), (0, 1), (1, 0), (0, -1),   // Rook rays
+            (-1, -1), (-1, 1), (1, -1), (1, 1), // Bishop rays
+        ];
+        
+        for (rank_dir, file_dir) in directions {
+            let mut blocker = None;
+            let mut rank = king_rank as isize;
+            let mut file = king_file as isize;
             
-            if from_rank == correct_en_passant_rank {
-                // Remove the captured pawn in the simulation
-                let captured_pawn_rank = from_rank;
-                let captured_pawn_file = to_file;
-                temp_board.board[captured_pawn_rank][captured_pawn_file] = None;
+            loop {
+                rank += rank_dir;
+                file += file_dir;
+                
+                if rank < 0 || rank >= BOARD_SIZE as isize || file < 0 || file >= BOARD_SIZE as isize {
+                    break;
+                }
+                
+                let square = (rank as usize, file as usize);
+                if let Some(piece) = self.board[square.0][square.1] {
+                    if piece.color == color {
+                        // A second friendly piece on the ray shields the first
+                        if blocker.is_some() {
+                            break;
+                        }
+                        blocker = Some(square);
+                    } else {
+                        let slides_on_ray = piece.piece_type.slide_directions().contains(&(rank_dir, file_dir));
+                        
+                        if let Some(pinned) = blocker {
+                            if slides_on_ray {
+                                pins.push((pinned, square));
+                            }
+                        }
+                        break;
+                    }
+                }
             }
         }
         
-        temp_board.board[to_rank][to_file] = temp_board.board[from_rank][from_file];
-        temp_board.board[from_rank][from_file] = None;
+        pins
+    }
+    
+    // Plays the move and takes it straight back, so the legality check of every generated move
+    // copies nothing
+    pub fn would_be_in_check_after_move(&mut self, from: (usize, usize), to: (usize, usize)) -> bool {
+        let color = match self.board[from.0][from.1] {
+            Some(piece) => piece.color,
+            None => return false, // No piece to move
+        };
+        
+        let promotion_pending = self.promotion_pending.take();
+        let undo = self.move_pieces(from, to);
+        let in_check = king_attacked(&self.board, color);
+        self.restore_position(&undo);
+        self.promotion_pending = promotion_pending;
         
-        temp_board.is_in_check(piece.color)
+        in_check
     }
     
     pub fn is_checkmate(&self) -> bool {
@@ -537,45 +1386,186 @@ impl GameState {
         !self.has_legal_moves()
     }
     
-    pub fn is_stalemate(&self) -> bool {
+    // Which well-known mate the position is, if it's checkmate and fits one. Only a single checker
+    // is classified; double checks are left unnamed
+    pub fn mate_pattern(&self) -> Option<MatePattern> {
+        if !self.is_checkmate() {
+            return None;
+        }
+        
         let color = self.current_turn;
+        let king = self.find_king(color)?;
+        let checker = match self.checkers(color).as_slice() {
+            [only] => *only,
+            _ => return None,
+        };
+        let checker_type = self.board[checker.0][checker.1]?.piece_type;
+        let is_heavy = |piece_type| matches!(piece_type, PieceType::Rook | PieceType::Queen);
         
-        if self.is_in_check(color) {
-            return false;
+        let neighbours: Vec<(usize, usize)> = (-1i32..=1)
+            .flat_map(|rank_step| (-1i32..=1).map(move |file_step| (rank_step, file_step)))
+            .filter(|&step| step != (0, 0))
+            .map(|(rank_step, file_step)| (king.0 as i32 + rank_step, king.1 as i32 + file_step))
+            .filter(|&(rank, file)| rank >= 0 && rank < BOARD_SIZE as i32 && file >= 0 && file < BOARD_SIZE as i32)
+            .map(|(rank, file)| (rank as usize, file as usize))
+            .collect();
+        let own_piece_on = |square: &(usize, usize)| {
+            matches!(self.board[square.0][square.1], Some(piece) if piece.color == color)
+        };
+        
+        if checker_type == PieceType::Knight && neighbours.iter().all(own_piece_on) {
+            return Some(MatePattern::Smothered);
         }
         
-        !self.has_legal_moves()
-    }
-    
-    fn has_legal_moves(&self) -> bool {
-        let mut clone = self.clone();
-        clone.move_cache = self.move_cache.clone();
+        if !is_heavy(checker_type) {
+            return None;
+        }
         
-        let moves = clone.get_all_legal_moves();
+        // Rank 0 is the 8th rank, Black's back rank
+        let back_rank = match color {
+            Color::White => BOARD_SIZE - 1,
+            Color::Black => 0,
+        };
+        if king.0 == back_rank && checker.0 == back_rank &&
+           neighbours.iter().filter(|square| square.0 != back_rank).all(own_piece_on) {
+            return Some(MatePattern::BackRank);
+        }
         
-        !moves.is_empty()
-    }
-    
-    pub fn is_threefold_repetition(&self) -> bool {
-        if let Some(count) = self.position_history.get(&self.current_hash) {
-            return *count >= 3;
+        // The checker holds the edge line the king is on, a second heavy piece the line next to it
+        let edge = BOARD_SIZE - 1;
+        let on_edge_rank = (king.0 == 0 || king.0 == edge) && checker.0 == king.0;
+        let on_edge_file = (king.1 == 0 || king.1 == edge) && checker.1 == king.1;
+        let inner_rank = if king.0 == 0 { 1 } else { edge - 1 };
+        let inner_file = if king.1 == 0 { 1 } else { edge - 1 };
+        
+        for rank in 0..BOARD_SIZE {
+            for file in 0..BOARD_SIZE {
+                if let Some(piece) = self.board[rank][file] {
+                    if piece.color != color && is_heavy(piece.piece_type) && (rank, file) != checker &&
+                       ((on_edge_rank && rank == inner_rank) || (on_edge_file && file == inner_file)) {
+                        return Some(MatePattern::Ladder);
+                    }
+                }
+            }
         }
         
-        false
-    }
-    
-    pub fn is_fifty_move_rule(&self) -> bool {
-        self.halfmove_clock >= 100 // 50 moves from each player = 100 halfmoves
+        None
     }
     
-    pub fn is_insufficient_material(&self) -> bool {
-        let mut piece_counts = HashMap::new();
+    // Squares of the pieces giving check to `color`'s king
+    fn checkers(&self, color: Color) -> Vec<(usize, usize)> {
+        let king = match self.find_king(color) {
+            Some(position) => position,
+            None => return Vec::new(),
+        };
         
+        let mut checkers = Vec::new();
         for rank in 0..BOARD_SIZE {
             for file in 0..BOARD_SIZE {
                 if let Some(piece) = self.board[rank][file] {
-                    let key = (piece.piece_type, piece.color);
-                    *piece_counts.entry(key).or_insert(0) += 1;
+                    if piece.color != color && piece.get_possible_moves((rank, file), &self.board, None).contains(&king) {
+                        checkers.push((rank, file));
+                    }
+                }
+            }
+        }
+        
+        checkers
+    }
+    
+    // The side that delivered checkmate, if the game ended that way
+    pub fn winner(&self) -> Option<Color> {
+        if self.is_checkmate() {
+            Some(self.current_turn.opposite())
+        } else {
+            None
+        }
+    }
+    
+    pub fn is_stalemate(&self) -> bool {
+        let color = self.current_turn;
+        
+        if self.is_in_check(color) {
+            return false;
+        }
+        
+        !self.has_legal_moves()
+    }
+    
+    // Record an ending the board can't decide, such as a resignation, a flag fall or an agreed draw,
+    // so the PGN export can say how the game ended and who won
+    pub fn set_ending(&mut self, reason: TerminationReason, result: GameResult) {
+        self.ending = Some((reason, result));
+        self.game_over = true;
+    }
+    
+    // How the game ended: what set_ending recorded, otherwise what the board itself decides
+    pub fn ending(&self) -> Option<(TerminationReason, GameResult)> {
+        if self.ending.is_some() {
+            return self.ending;
+        }
+        
+        let status = self.status();
+        status.terminal.map(|reason| {
+            let result = status.winner.map_or(GameResult::Draw, GameResult::win_for);
+            (reason, result)
+        })
+    }
+    
+    // Generates the legal moves once, where is_checkmate and is_stalemate each do it again
+    pub fn status(&self) -> GamePhaseStatus {
+        let in_check = self.is_in_check(self.current_turn);
+        
+        let terminal = if !self.has_legal_moves() {
+            Some(if in_check { TerminationReason::Checkmate } else { TerminationReason::Stalemate })
+        } else if self.is_threefold_repetition() {
+            Some(TerminationReason::ThreefoldRepetition)
+        } else if self.is_fifty_move_rule() {
+            Some(TerminationReason::FiftyMoveRule)
+        } else if self.is_insufficient_material() {
+            Some(TerminationReason::InsufficientMaterial)
+        } else {
+            None
+        };
+        
+        let winner = if terminal == Some(TerminationReason::Checkmate) {
+            Some(self.current_turn.opposite())
+        } else {
+            None
+        };
+        
+        GamePhaseStatus { in_check, terminal, winner }
+    }
+    
+    fn has_legal_moves(&self) -> bool {
+        let mut clone = self.clone_for_search();
+        clone.move_cache = self.move_cache.clone();
+        
+        let moves = clone.get_all_legal_moves();
+        
+        !moves.is_empty()
+    }
+    
+    pub fn repetition_count(&self) -> u32 {
+        self.position_history.get(&self.current_hash).copied().unwrap_or(0)
+    }
+    
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetition_count() >= 3
+    }
+    
+    pub fn is_fifty_move_rule(&self) -> bool {
+        self.halfmove_clock >= 100 // 50 moves from each player = 100 halfmoves
+    }
+    
+    pub fn is_insufficient_material(&self) -> bool {
+        let mut piece_counts = HashMap::new();
+        
+        for rank in 0..BOARD_SIZE {
+            for file in 0..BOARD_SIZE {
+                if let Some(piece) = self.board[rank][file] {
+                    let key = (piece.piece_type, piece.color);
+                    *piece_counts.entry(key).or_insert(0) += 1;
                 }
             }
         }
@@ -632,17 +1622,10 @@ impl GameState {
         self.is_insufficient_material()
     }
     
-    fn clone(&self) -> Self {
-        let mut new_board = [[None; BOARD_SIZE]; BOARD_SIZE];
-        
-        for rank in 0..BOARD_SIZE {
-            for file in 0..BOARD_SIZE {
-                new_board[rank][file] = self.board[rank][file];
-            }
-        }
-        
+    // Just the position, for trying moves out: no history, cache, undo stack or pending promotion
+    pub(crate) fn clone_for_search(&self) -> Self {
         Self {
-            board: new_board,
+            board: self.board,
             current_turn: self.current_turn,
             white_can_castle_kingside: self.white_can_castle_kingside,
             white_can_castle_queenside: self.white_can_castle_queenside,
@@ -652,14 +1635,21 @@ impl GameState {
             halfmove_clock: self.halfmove_clock,
             fullmove_number: self.fullmove_number,
             promotion_pending: None, // Don't need to copy this for simulation
+            promotion_options: self.promotion_options.clone(),
             position_history: HashMap::new(), // Don't need to copy history for simulation
             current_hash: self.current_hash, // Copy the hash
             move_cache: HashMap::new(), // Don't need to copy move cache for simulation
+            undo_stack: Vec::new(), // Simulations never take moves back past the copy
+            cleared_histories: Vec::new(),
+            move_log: Vec::new(),
+            captured: Vec::new(),
+            ending: None,
             game_over: self.game_over,
         }
     }
     
-    fn calculate_zobrist_hash(&self) -> u64 {
+    // The hash worked out from scratch, which zobrist_hash must always agree with
+    pub fn calculate_zobrist_hash(&self) -> u64 {
         use crate::zobrist::ZOBRIST;
         
         let mut hash = 0u64;
@@ -667,19 +1657,9 @@ impl GameState {
         for rank in 0..BOARD_SIZE {
             for file in 0..BOARD_SIZE {
                 if let Some(piece) = self.board[rank][file] {
-                    let square = rank * 8 + file;
-                    let color_index = match piece.color {
-                        Color::White => WHITE,
-                        Color::Black => BLACK,
-                    };
-                    let piece_index = match piece.piece_type {
-                        PieceType::Pawn => 0,
-                        PieceType::Knight => 1,
-                        PieceType::Bishop => 2,
-                        PieceType::Rook => 3,
-                        PieceType::Queen => 4,
-                        PieceType::King => 5,
-                    };
+                    let square = square_index(rank, file);
+                    let color_index = ZobristKeys::get_color_index(piece.color);
+                    let piece_index = ZobristKeys::get_piece_index(piece.piece_type);
                     
                     hash ^= ZOBRIST.piece_keys[color_index][piece_index][square];
                 }
@@ -713,44 +1693,36 @@ impl GameState {
     fn update_hash_for_move(&mut self, piece: &Piece, from: (usize, usize), to: (usize, usize)) {
         let (from_rank, from_file) = from;
         let (to_rank, to_file) = to;
-        let from_square = from_rank * 8 + from_file;
-        let to_square = to_rank * 8 + to_file;
+        let from_square = square_index(from_rank, from_file);
+        let to_square = square_index(to_rank, to_file);
         
-        let color_index = match piece.color {
-            Color::White => WHITE,
-            Color::Black => BLACK,
-        };
-        let piece_index = match piece.piece_type {
-            PieceType::Pawn => 0,
-            PieceType::Knight => 1,
-            PieceType::Bishop => 2,
-            PieceType::Rook => 3,
-            PieceType::Queen => 4,
-            PieceType::King => 5,
-        };
+        let color_index = ZobristKeys::get_color_index(piece.color);
+        let piece_index = ZobristKeys::get_piece_index(piece.piece_type);
         
         self.current_hash ^= ZOBRIST.piece_keys[color_index][piece_index][from_square];
         
         if let Some(captured) = self.board[to_rank][to_file] {
-            let cap_color_index = match captured.color {
-                Color::White => WHITE,
-                Color::Black => BLACK,
-            };
-            let cap_piece_index = match captured.piece_type {
-                PieceType::Pawn => 0,
-                PieceType::Knight => 1,
-                PieceType::Bishop => 2,
-                PieceType::Rook => 3,
-                PieceType::Queen => 4,
-                PieceType::King => 5,
-            };
+            let cap_color_index = ZobristKeys::get_color_index(captured.color);
+            let cap_piece_index = ZobristKeys::get_piece_index(captured.piece_type);
             self.current_hash ^= ZOBRIST.piece_keys[cap_color_index][cap_piece_index][to_square];
         }
         
         self.current_hash ^= ZOBRIST.piece_keys[color_index][piece_index][to_square];
     }
     
-    pub fn get_all_legal_moves(&mut self) -> Vec<((usize, usize), (usize, usize))> {
+    pub fn legal_move_count(&mut self) -> usize {
+        self.get_all_legal_moves().len()
+    }
+    
+    // The side to move's only legal move, if it has exactly one
+    pub fn forced_move(&mut self) -> Option<Move> {
+        match self.get_all_legal_moves().as_slice() {
+            [only] => Some(*only),
+            _ => None,
+        }
+    }
+    
+    pub fn get_all_legal_moves(&mut self) -> Vec<Move> {
         if let Some(moves) = self.move_cache.get(&self.current_hash) {
             return moves.clone();
         }
@@ -762,7 +1734,7 @@ impl GameState {
             for from_file in 0..BOARD_SIZE {
                 if let Some(piece) = self.board[from_rank][from_file] {
                     if piece.color == current_color {
-                        let moves = piece.get_possible_moves((from_rank, from_file), &self.board);
+                        let moves = self.candidate_moves((from_rank, from_file), &piece);
                         
                         for to_pos in moves {
                             if !self.would_be_in_check_after_move((from_rank, from_file), to_pos) {
@@ -779,6 +1751,572 @@ impl GameState {
         legal_moves
     }
     
+    pub fn legal_destinations(&mut self, from: (usize, usize)) -> Vec<(usize, usize)> {
+        self.get_all_legal_moves()
+            .into_iter()
+            .filter(|&(move_from, _)| move_from == from)
+            .map(|(_, to)| to)
+            .collect()
+    }
+    
+    // Where the piece on `from` could go if it were its side's turn, e.g. to show a player their
+    // options while the opponent is still to move. En passant only ever belongs to the side to move
+    pub fn get_legal_moves_from(&self, from: (usize, usize)) -> Vec<(usize, usize)> {
+        let piece = match self.board[from.0][from.1] {
+            Some(piece) => piece,
+            None => return Vec::new(),
+        };
+        
+        let mut preview = self.clone_for_search();
+        if piece.color != preview.current_turn {
+            preview.current_turn = piece.color;
+            preview.en_passant_target = None;
+        }
+        preview.legal_destinations(from)
+    }
+    
+    // Same squares as legal_destinations, one bit each (see square_bit), so a UI can test
+    // every square cheaply without holding on to a Vec
+    pub fn legal_destinations_bitboard(&mut self, from: (usize, usize)) -> u64 {
+        self.get_all_legal_moves()
+            .iter()
+            .filter(|&&(move_from, _)| move_from == from)
+            .fold(0, |bits, &(_, to)| bits | square_bit(to))
+    }
+    
+    // Legal moves in UCI long algebraic form ("e2e4", "e7e8q"), promotions expanded per piece in
+    // promotion_options
+    pub fn legal_moves_uci(&mut self) -> Vec<String> {
+        let mut uci_moves = Vec::new();
+        
+        for (from, to) in self.get_all_legal_moves() {
+            let base = format!("{}{}", square_to_uci(from), square_to_uci(to));
+            
+            if self.is_promotion_move(from, to) {
+                for &piece_type in &self.promotion_options {
+                    let suffix = Piece::new(piece_type, Color::Black).to_fen_char();
+                    uci_moves.push(format!("{}{}", base, suffix));
+                }
+            } else {
+                uci_moves.push(base);
+            }
+        }
+        
+        uci_moves
+    }
+    
+    // Leaf nodes of the legal move tree `depth` plies deep, with a promotion counted once per piece
+    // in promotion_options. Compare against published counts to check the move generator
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        
+        let mut nodes = 0;
+        for (from, to) in self.get_all_legal_moves() {
+            if self.is_promotion_move(from, to) {
+                for piece_type in self.promotion_options.clone() {
+                    nodes += self.perft_after(from, to, Some(piece_type), depth - 1);
+                }
+            } else {
+                nodes += self.perft_after(from, to, None, depth - 1);
+            }
+        }
+        
+        nodes
+    }
+    
+    // perft split by first move, in UCI form, so a wrong total can be traced to the move that causes it
+    pub fn perft_divide(&mut self, depth: u32) -> Vec<(String, u64)> {
+        if depth == 0 {
+            return Vec::new();
+        }
+        
+        let mut counts = Vec::new();
+        for (from, to) in self.get_all_legal_moves() {
+            let base = format!("{}{}", square_to_uci(from), square_to_uci(to));
+            
+            if self.is_promotion_move(from, to) {
+                for piece_type in self.promotion_options.clone() {
+                    let nodes = self.perft_after(from, to, Some(piece_type), depth - 1);
+                    counts.push((format!("{}{}", base, Piece::new(piece_type, Color::Black).to_fen_char()), nodes));
+                }
+            } else {
+                counts.push((base, self.perft_after(from, to, None, depth - 1)));
+            }
+        }
+        
+        counts
+    }
+    
+    // Play one move, count below it and take it back
+    fn perft_after(&mut self, from: (usize, usize), to: (usize, usize), promotion: Option<PieceType>, depth: u32) -> u64 {
+        let undo = match self.make_move(from, to) {
+            Some(undo) => undo,
+            None => return 0,
+        };
+        
+        let nodes = match promotion {
+            Some(piece_type) if !self.promote_pawn(piece_type) => 0,
+            _ => self.perft(depth),
+        };
+        self.unmake_move(undo);
+        
+        nodes
+    }
+    
+    pub fn apply_uci_move(&mut self, uci: &str) -> bool {
+        let uci = uci.trim();
+        if !uci.is_ascii() || (uci.len() != 4 && uci.len() != 5) {
+            return false;
+        }
+        
+        let (from, to) = match (square_from_uci(&uci[0..2]), square_from_uci(&uci[2..4])) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return false,
+        };
+        
+        let promotion = match uci.chars().nth(4) {
+            Some('q') => Some(PieceType::Queen),
+            Some('r') => Some(PieceType::Rook),
+            Some('b') => Some(PieceType::Bishop),
+            Some('n') => Some(PieceType::Knight),
+            Some(_) => return false,
+            None => None,
+        };
+        
+        // The suffix must be present exactly when the move promotes, and name a piece this game allows.
+        // Checked before anything moves, so a refused move leaves the position as it was
+        if self.is_promotion_move(from, to) != promotion.is_some() {
+            return false;
+        }
+        if promotion.is_some_and(|piece_type| !self.promotion_options.contains(&piece_type)) {
+            return false;
+        }
+        
+        match self.apply_move_checked(from, to) {
+            Ok(MoveOutcome::PromotionRequired) => self.promote_pawn(promotion.unwrap_or(PieceType::Queen)),
+            Ok(MoveOutcome::Moved) => true,
+            Err(_) => false,
+        }
+    }
+    
+    // Standard algebraic notation for a legal move in this position, e.g. "Nbd7", "exd5", "O-O" or
+    // "e8=Q+". Call it before playing the move
+    pub fn move_to_san(&mut self, from: (usize, usize), to: (usize, usize), promotion: Option<PieceType>) -> String {
+        let piece = match self.board[from.0][from.1] {
+            Some(piece) => piece,
+            None => return format!("{}{}", square_to_uci(from), square_to_uci(to)),
+        };
+        
+        let mut san = String::new();
+        if self.is_castle(from, to) {
+            san.push_str(if to.1 > from.1 { "O-O" } else { "O-O-O" });
+        } else {
+            let capture = self.is_capture(from, to);
+            let from_square = square_to_uci(from);
+            
+            if piece.piece_type == PieceType::Pawn {
+                if capture {
+                    san.push_str(&from_square[..1]);
+                }
+            } else {
+                san.push(piece.to_fen_char().to_ascii_uppercase());
+                
+                // Name the file, else the rank, else both, when another piece of the same kind can go there too
+                let rivals: Vec<(usize, usize)> = self.get_all_legal_moves()
+                    .into_iter()
+                    .filter(|&(other, dest)| {
+                        dest == to && other != from &&
+                            self.board[other.0][other.1].map(|p| p.piece_type) == Some(piece.piece_type)
+                    })
+                    .map(|(other, _)| other)
+                    .collect();
+                if !rivals.is_empty() {
+                    if rivals.iter().all(|other| other.1 != from.1) {
+                        san.push_str(&from_square[..1]);
+                    } else if rivals.iter().all(|other| other.0 != from.0) {
+                        san.push_str(&from_square[1..]);
+                    } else {
+                        san.push_str(&from_square);
+                    }
+                }
+            }
+            
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&square_to_uci(to));
+            
+            if self.is_promotion_move(from, to) {
+                let promoted = Piece::new(promotion.unwrap_or(PieceType::Queen), Color::White);
+                san.push('=');
+                san.push(promoted.to_fen_char());
+            }
+        }
+        
+        let mut after = self.clone_for_search();
+        let played = match after.apply_move_checked(from, to) {
+            Ok(MoveOutcome::PromotionRequired) => after.promote_pawn(promotion.unwrap_or(PieceType::Queen)),
+            Ok(MoveOutcome::Moved) => true,
+            Err(_) => false,
+        };
+        if played && after.is_in_check(after.current_turn) {
+            san.push(if after.is_checkmate() { '#' } else { '+' });
+        }
+        
+        san
+    }
+    
+    // A line of moves from this position as numbered SAN, e.g. "1. e4 e5 2. Nf3" or "12... Qxf2#".
+    // Pawns promote to the piece given, a queen if there's none, and the line stops at the first
+    // move that isn't legal
+    pub fn line_to_san(&self, moves: &[LineMove]) -> String {
+        let mut position = self.clone_for_search();
+        let mut parts = Vec::new();
+        
+        for (i, &(from, to, promotion)) in moves.iter().enumerate() {
+            let san = position.move_to_san(from, to, promotion);
+            let numbered = match position.current_turn {
+                Color::White => format!("{}. {}", position.fullmove_number, san),
+                Color::Black if i == 0 => format!("{}... {}", position.fullmove_number, san),
+                Color::Black => san,
+            };
+            
+            let played = match position.apply_move_checked(from, to) {
+                Ok(MoveOutcome::PromotionRequired) => position.promote_pawn(promotion.unwrap_or(PieceType::Queen)),
+                Ok(MoveOutcome::Moved) => true,
+                Err(_) => false,
+            };
+            if !played {
+                break;
+            }
+            parts.push(numbered);
+        }
+        
+        parts.join(" ")
+    }
+    
+    // The game so far as PGN, for pasting into analysis tools. A game that hasn't ended is written as "*",
+    // and one that has gets a Termination tag saying how
+    pub fn to_pgn(&self) -> String {
+        let position = self.logged_start_position();
+        let mut pgn = String::new();
+        
+        let ending = self.ending();
+        let result = match ending {
+            Some((_, result)) => result.to_string(),
+            None => "*".to_string(),
+        };
+        pgn.push_str(&format!("[Result \"{}\"]\n", result));
+        if let Some((reason, _)) = ending {
+            pgn.push_str(&format!("[Termination \"{}\"]\n", reason));
+        }
+        
+        // Games that didn't start from the initial position need it spelled out
+        let start_fen = position.to_fen();
+        if start_fen != GameState::new().to_fen() {
+            pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", start_fen));
+        }
+        pgn.push('\n');
+        
+        let mut tokens = Vec::new();
+        let black_first = position.current_turn == Color::Black;
+        for (i, san) in self.move_log_san().into_iter().enumerate() {
+            let ply = i + black_first as usize;
+            let number = position.fullmove_number + (ply / 2) as u32;
+            if ply.is_multiple_of(2) {
+                tokens.push(format!("{}.", number));
+            } else if i == 0 {
+                tokens.push(format!("{}...", number));
+            }
+            tokens.push(san);
+        }
+        tokens.push(result);
+        pgn.push_str(&pgn_movetext(&tokens));
+        
+        pgn
+    }
+    
+    // A game read from PGN: the [FEN] tag's position if there is one, with the movetext played on it.
+    // Comments, variations, NAGs and move numbers are skipped, and reading stops at the first result
+    pub fn from_pgn(pgn: &str) -> Result<GameState, PgnError> {
+        let mut state = match pgn_tag(pgn, "FEN") {
+            Some(fen) => GameState::from_fen(&fen).map_err(PgnError::InvalidFen)?,
+            None => GameState::new(),
+        };
+        
+        for san in pgn_move_tokens(pgn) {
+            if let Err(e) = state.make_move_san(&san) {
+                let number = match state.current_turn {
+                    Color::White => format!("{}.", state.fullmove_number),
+                    Color::Black => format!("{}...", state.fullmove_number),
+                };
+                return Err(PgnError::IllegalMove(format!("{} {}", number, san), e));
+            }
+        }
+        
+        // Endings the moves don't show, like a resignation, only survive through the tags
+        let termination = pgn_tag(pgn, "Termination").and_then(|text| TerminationReason::from_pgn(&text));
+        let result = pgn_tag(pgn, "Result").and_then(|text| GameResult::from_pgn(&text));
+        if let (Some(reason), Some(result)) = (termination, result) {
+            if state.status().terminal.is_none() {
+                state.set_ending(reason, result);
+            }
+        }
+        
+        Ok(state)
+    }
+    
+    // Play a move written in standard algebraic notation, e.g. "e4", "Nbd7", "exd5", "O-O" or "e8=Q+".
+    // It's resolved against the legal moves, so notation that fits none of them, or more than one, is
+    // refused. Check marks and annotations like "!?" are ignored, and castling may be written with zeros
+    pub fn make_move_san(&mut self, san: &str) -> Result<(), MoveError> {
+        if self.promotion_pending.is_some() {
+            return Err(MoveError::PromotionPending);
+        }
+        
+        let text = san_without_suffixes(san.trim()).replace('0', "O");
+        let legal_moves = self.get_all_legal_moves();
+        
+        let (candidates, promotion) = if text == "O-O" || text == "O-O-O" {
+            let kingside = text == "O-O";
+            let castles = legal_moves.into_iter()
+                .filter(|&(from, to)| self.is_castle(from, to) && (to.1 > from.1) == kingside)
+                .collect::<Vec<_>>();
+            (castles, None)
+        } else {
+            let (body, promotion) = match text.split_once('=') {
+                Some((body, piece)) => match piece.chars().next().and_then(san_piece_type) {
+                    Some(piece_type) if piece.len() == 1 && piece_type != PieceType::King => (body, Some(piece_type)),
+                    _ => return Err(MoveError::InvalidNotation),
+                },
+                None => (text.as_str(), None),
+            };
+            
+            // A leading capital names the piece; without one it's a pawn
+            let (piece_type, rest) = match body.chars().next().and_then(san_piece_type) {
+                Some(piece_type) => (piece_type, body[1..].replace('x', "")),
+                None => (PieceType::Pawn, body.replace('x', "")),
+            };
+            if rest.len() < 2 || rest.len() > 4 || !rest.is_ascii() {
+                return Err(MoveError::InvalidNotation);
+            }
+            
+            let (hint, destination) = rest.split_at(rest.len() - 2);
+            let to = square_from_uci(destination).ok_or(MoveError::InvalidNotation)?;
+            let mut from_file = None;
+            let mut from_rank = None;
+            for c in hint.chars() {
+                match c {
+                    'a'..='h' if from_file.is_none() => from_file = Some(c as usize - 'a' as usize),
+                    '1'..='8' if from_rank.is_none() => from_rank = Some(BOARD_SIZE - (c as usize - '0' as usize)),
+                    _ => return Err(MoveError::InvalidNotation),
+                }
+            }
+            
+            // A pawn's file is only written when it captures, so otherwise it stays on its file
+            if piece_type == PieceType::Pawn && from_file.is_none() {
+                from_file = Some(to.1);
+            }
+            
+            let matching = legal_moves.into_iter()
+                .filter(|&(from, dest)| {
+                    dest == to &&
+                        matches!(self.board[from.0][from.1], Some(piece) if piece.piece_type == piece_type) &&
+                        from_file.unwrap_or(from.1) == from.1 &&
+                        from_rank.unwrap_or(from.0) == from.0
+                })
+                .collect::<Vec<_>>();
+            (matching, promotion)
+        };
+        
+        let (from, to) = match candidates.as_slice() {
+            [] => return Err(MoveError::NoMatchingMove),
+            [only] => *only,
+            _ => return Err(MoveError::AmbiguousMove),
+        };
+        
+        match (self.is_promotion_move(from, to), promotion) {
+            (true, None) => return Err(MoveError::MissingPromotion),
+            (true, Some(piece_type)) if !self.promotion_options.contains(&piece_type) => return Err(MoveError::NoMatchingMove),
+            (false, Some(_)) => return Err(MoveError::NoMatchingMove),
+            _ => {}
+        }
+        
+        match self.apply_move_checked(from, to)? {
+            MoveOutcome::PromotionRequired => {
+                self.promote_pawn(promotion.unwrap_or(PieceType::Queen));
+                Ok(())
+            }
+            MoveOutcome::Moved => Ok(()),
+        }
+    }
+    
+    // The moves in move_log as UCI, which apply_uci_move plays back
+    pub fn move_log_uci(&self) -> Vec<String> {
+        self.move_log.iter()
+            .map(|recorded| {
+                let mut uci = format!("{}{}", square_to_uci(recorded.from), square_to_uci(recorded.to));
+                if let Some(piece_type) = recorded.promotion {
+                    uci.push(Piece::new(piece_type, Color::Black).to_fen_char());
+                }
+                uci
+            })
+            .collect()
+    }
+    
+    // The moves in move_log as SAN, replayed from logged_start_position. A pawn still waiting for
+    // its piece is written as a queen promotion and ends the list
+    pub fn move_log_san(&self) -> Vec<String> {
+        let mut position = self.logged_start_position();
+        let mut sans = Vec::new();
+        
+        for recorded in &self.move_log {
+            sans.push(position.move_to_san(recorded.from, recorded.to, recorded.promotion));
+            
+            let played = match position.apply_move_checked(recorded.from, recorded.to) {
+                Ok(MoveOutcome::PromotionRequired) => match recorded.promotion {
+                    Some(piece) => position.promote_pawn(piece),
+                    None => false,
+                },
+                Ok(MoveOutcome::Moved) => true,
+                Err(_) => false,
+            };
+            if !played {
+                break;
+            }
+        }
+        
+        sans
+    }
+    
+    // The position before the first move in move_log, found by taking every move back on a copy
+    pub fn logged_start_position(&self) -> GameState {
+        if self.move_log.is_empty() {
+            return self.clone_for_search();
+        }
+        
+        let mut position = self.clone();
+        while position.take_back() || position.unmake_null_move() {}
+        Self::from_setup(position.board, position.current_turn, position.castling_rights(),
+                         position.en_passant_target, position.halfmove_clock, position.fullmove_number)
+    }
+    
+    pub fn is_promotion_move(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        matches!(self.board[from.0][from.1], Some(piece) if piece.piece_type == PieceType::Pawn) &&
+            (to.0 == 0 || to.0 == BOARD_SIZE - 1)
+    }
+    
+    // Zobrist hash of the position, for transposition tables and caches keyed on positions.
+    // The keys are drawn at random when the process starts, so hashes from different runs
+    // (or different processes) can't be compared with each other
+    pub fn zobrist_hash(&self) -> u64 {
+        self.current_hash
+    }
+    
+    // Read-only stats for coaching displays
+    pub fn moves_since_capture(&self) -> u32 {
+        // Plies since the last capture, which pawn moves don't reset the way they do the halfmove clock.
+        // Before the first logged move only that clock is known, so the count starts from it
+        if let Some(plies) = self.move_log.iter().rev().position(|played| played.captured.is_some()) {
+            return plies as u32;
+        }
+        let start_clock = self.undo_stack.iter().find_map(|entry| match entry {
+            UndoEntry::Move(undo) => Some(undo.halfmove_clock),
+            UndoEntry::NullMove { .. } => None,
+        });
+        start_clock.unwrap_or(self.halfmove_clock) + self.move_log.len() as u32
+    }
+    
+    pub fn total_moves(&self) -> u32 {
+        self.fullmove_number
+    }
+    
+    // Material in pawns: N/B 3, R 5, Q 9, kings don't count
+    pub fn material_count(&self, color: Color) -> u32 {
+        self.board.iter()
+            .flatten()
+            .flatten()
+            .filter(|piece| piece.color == color)
+            .map(|piece| match piece.piece_type {
+                PieceType::Pawn => 1,
+                PieceType::Knight | PieceType::Bishop => 3,
+                PieceType::Rook => 5,
+                PieceType::Queen => 9,
+                PieceType::King => 0,
+            })
+            .sum()
+    }
+    
+    // First field of the FEN only, so it ignores turn, castling and move counters
+    pub fn piece_placement_fen(&self) -> String {
+        let mut placement = String::new();
+        
+        // Rank 0 is the eighth rank, which FEN lists first
+        for (rank, row) in self.board.iter().enumerate() {
+            let mut empty = 0;
+            for square in row {
+                match square {
+                    Some(piece) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(piece.to_fen_char());
+                    }
+                    None => empty += 1,
+                }
+            }
+            
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank < BOARD_SIZE - 1 {
+                placement.push('/');
+            }
+        }
+        
+        placement
+    }
+    
+    // All six FEN fields, readable again by from_fen
+    pub fn to_fen(&self) -> String {
+        let turn = match self.current_turn {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+        
+        let mut castling = String::new();
+        for (has_right, c) in [(self.white_can_castle_kingside, 'K'), (self.white_can_castle_queenside, 'Q'),
+                               (self.black_can_castle_kingside, 'k'), (self.black_can_castle_queenside, 'q')] {
+            if has_right {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        
+        let en_passant = self.en_passant_target.map_or_else(|| "-".to_string(), square_to_uci);
+        
+        format!("{} {} {} {} {} {}", self.piece_placement_fen(), turn, castling, en_passant,
+                self.halfmove_clock, self.fullmove_number)
+    }
+    
+    // The board as `color` sits at it: their own pieces end up on the high rank indices, the way a
+    // frontend draws the bottom of the screen. White's view is the board itself
+    pub fn perspective_board(&self, color: Color) -> Board {
+        let mut view = [[None; BOARD_SIZE]; BOARD_SIZE];
+        for (rank, row) in self.board.iter().enumerate() {
+            for (file, square) in row.iter().enumerate() {
+                let (view_rank, view_file) = perspective_square((rank, file), color);
+                view[view_rank][view_file] = *square;
+            }
+        }
+        view
+    }
+    
     fn clear_move_cache(&mut self) {
         self.move_cache.clear();
     }
@@ -786,4 +2324,263 @@ impl GameState {
     pub fn is_game_over(&self) -> bool {
         self.game_over
     }
-} 
+}
+
+// Positions are equal when the repetition-relevant fields match; clocks, history and caches are ignored.
+// Squares compare by piece type and color only, the castling rights already say what has_moved would
+impl PartialEq for GameState {
+    fn eq(&self, other: &Self) -> bool {
+        let placement = |board: &Board| board.map(|row| row.map(|square| square.map(|piece| (piece.piece_type, piece.color))));
+        placement(&self.board) == placement(&other.board) &&
+            self.current_turn == other.current_turn &&
+            self.white_can_castle_kingside == other.white_can_castle_kingside &&
+            self.white_can_castle_queenside == other.white_can_castle_queenside &&
+            self.black_can_castle_kingside == other.black_can_castle_kingside &&
+            self.black_can_castle_queenside == other.black_can_castle_queenside &&
+            self.en_passant_target == other.en_passant_target
+    }
+}
+
+impl Eq for GameState {}
+
+// Hand-built positions without writing FEN: start from an empty board and place pieces by square.
+// Bad square names panic, since these are meant for literals in tests and tools
+pub struct GameStateBuilder {
+    board: Board,
+    current_turn: Color,
+    rights: [bool; 4], // White kingside, white queenside, black kingside, black queenside
+    en_passant_target: Option<(usize, usize)>,
+}
+
+impl GameStateBuilder {
+    pub fn new() -> Self {
+        Self {
+            board: [[None; BOARD_SIZE]; BOARD_SIZE],
+            current_turn: Color::White,
+            rights: [false; 4],
+            en_passant_target: None,
+        }
+    }
+    
+    pub fn place(mut self, square: &str, piece_type: PieceType, color: Color) -> Self {
+        let (rank, file) = Self::parse_square(square);
+        self.board[rank][file] = Some(Piece::new(piece_type, color));
+        self
+    }
+    
+    pub fn turn(mut self, color: Color) -> Self {
+        self.current_turn = color;
+        self
+    }
+    
+    // Rights as FEN writes them, e.g. "KQkq" or "-"
+    pub fn castling(mut self, rights: &str) -> Self {
+        self.rights = [false; 4];
+        for c in rights.chars().filter(|&c| c != '-') {
+            let index = match c {
+                'K' => 0,
+                'Q' => 1,
+                'k' => 2,
+                'q' => 3,
+                _ => panic!("invalid castling right '{}'", c),
+            };
+            self.rights[index] = true;
+        }
+        self
+    }
+    
+    pub fn en_passant(mut self, square: &str) -> Self {
+        self.en_passant_target = Some(Self::parse_square(square));
+        self
+    }
+    
+    // Rights whose king or rook isn't at home are dropped rather than rejected
+    pub fn build(self) -> GameState {
+        let mut rights = self.rights;
+        for (index, rank, rook_file, color) in [(0, 7, 7, Color::White), (1, 7, 0, Color::White), (2, 0, 7, Color::Black), (3, 0, 0, Color::Black)] {
+            let king_home = self.board[rank][4] == Some(Piece::new(PieceType::King, color));
+            let rook_home = self.board[rank][rook_file] == Some(Piece::new(PieceType::Rook, color));
+            rights[index] = rights[index] && king_home && rook_home;
+        }
+        
+        GameState::from_setup(self.board, self.current_turn, rights, self.en_passant_target, 0, 1)
+    }
+    
+    fn parse_square(square: &str) -> (usize, usize) {
+        square_from_uci(square).unwrap_or_else(|| panic!("invalid square \"{}\"", square))
+    }
+}
+
+impl Default for GameStateBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Where `color`'s king stands, if it's on the board at all
+fn king_square(board: &Board, color: Color) -> Option<(usize, usize)> {
+    for (rank, row) in board.iter().enumerate() {
+        for (file, square) in row.iter().enumerate() {
+            if let Some(piece) = square {
+                if piece.piece_type == PieceType::King && piece.color == color {
+                    return Some((rank, file));
+                }
+            }
+        }
+    }
+    
+    None
+}
+
+// Whether any piece of the other side attacks `color`'s king. Needs only the board, so a move can be
+// tried on a copy of it without the rest of the GameState
+fn king_attacked(board: &Board, color: Color) -> bool {
+    let (king_rank, king_file) = match king_square(board, color) {
+        Some(position) => position,
+        None => return false,
+    };
+    
+    for (rank, row) in board.iter().enumerate() {
+        for (file, square) in row.iter().enumerate() {
+            if let Some(piece) = square {
+                if piece.color != color {
+                    let moves = piece.get_possible_moves((rank, file), board, None);
+                    
+                    if moves.contains(&(king_rank, king_file)) {
+                        return true;
+                    }
+                }
+            }
+        }
+    }
+    
+    false
+}
+
+// Squares are numbered rank by rank from the top-left, as in the Zobrist keys
+pub fn square_index(rank: usize, file: usize) -> usize {
+    rank * BOARD_SIZE + file
+}
+
+// Bitboards are u64, so they only cover boards of up to 64 squares
+pub fn square_bit(square: (usize, usize)) -> u64 {
+    1u64 << square_index(square.0, square.1)
+}
+
+// Movetext tokens joined into lines under 80 characters, as PGN's export format wants
+pub fn pgn_movetext(tokens: &[String]) -> String {
+    let mut text = String::new();
+    let mut line_length = 0;
+    for token in tokens {
+        if line_length > 0 && line_length + 1 + token.len() > 79 {
+            text.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            text.push(' ');
+            line_length += 1;
+        }
+        line_length += token.len();
+        text.push_str(token);
+    }
+    text.push('\n');
+    
+    text
+}
+
+// The value of a PGN tag pair such as [Result "1-0"], if the text has one
+pub fn pgn_tag(pgn: &str, name: &str) -> Option<String> {
+    let prefix = format!("[{} \"", name);
+    pgn.lines()
+        .map(str::trim)
+        .find_map(|line| line.strip_prefix(&prefix)?.strip_suffix("\"]"))
+        .map(str::to_string)
+}
+
+// The moves of a PGN's movetext, up to its result. Tag pairs, {comments}, ; comments, (variations),
+// $NAGs and move numbers are all dropped
+fn pgn_move_tokens(pgn: &str) -> Vec<String> {
+    let mut movetext = String::new();
+    let mut in_comment = false;
+    let mut variation_depth: usize = 0;
+    
+    for line in pgn.lines() {
+        if !in_comment && variation_depth == 0 && line.trim_start().starts_with('[') {
+            continue;
+        }
+        for c in line.chars() {
+            match c {
+                '}' if in_comment => in_comment = false,
+                _ if in_comment => {}
+                '{' => in_comment = true,
+                ';' => break,
+                '(' => variation_depth += 1,
+                ')' => variation_depth = variation_depth.saturating_sub(1),
+                _ if variation_depth > 0 => {}
+                _ => movetext.push(c),
+            }
+        }
+        movetext.push(' ');
+    }
+    
+    let mut moves = Vec::new();
+    for token in movetext.split_whitespace() {
+        if matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*") {
+            break;
+        }
+        
+        // Move numbers may be glued to their move, like "12.e4", but castling with zeros has no dot
+        let digits = token.len() - token.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let san = match token[digits..].strip_prefix('.') {
+            Some(rest) => rest.trim_start_matches('.'),
+            None => token,
+        };
+        if !token.starts_with('$') && !san.is_empty() {
+            moves.push(san.to_string());
+        }
+    }
+    
+    moves
+}
+
+// The piece a SAN capital stands for; lowercase letters are files
+fn san_piece_type(c: char) -> Option<PieceType> {
+    match c {
+        'K' | 'Q' | 'R' | 'B' | 'N' => Piece::from_fen_char(c).map(|piece| piece.piece_type),
+        _ => None,
+    }
+}
+
+fn san_without_suffixes(san: &str) -> &str {
+    san.trim_end_matches(['+', '#', '!', '?'])
+}
+
+// Where a square lands on perspective_board(color). Flipping both axes is its own inverse, so the
+// same call maps a square of that view back onto the board
+pub fn perspective_square(square: (usize, usize), color: Color) -> (usize, usize) {
+    match color {
+        Color::White => square,
+        Color::Black => (BOARD_SIZE - 1 - square.0, BOARD_SIZE - 1 - square.1),
+    }
+}
+
+// Rank 0 of the board array is the eighth rank
+pub fn square_to_uci(square: (usize, usize)) -> String {
+    let (rank, file) = square;
+    format!("{}{}", (b'a' + file as u8) as char, BOARD_SIZE - rank)
+}
+
+pub fn square_from_uci(text: &str) -> Option<(usize, usize)> {
+    let mut chars = text.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?;
+    if chars.next().is_some() || !file.is_ascii_lowercase() || !rank.is_ascii_digit() {
+        return None;
+    }
+    
+    let (rank, file) = (rank as usize - '0' as usize, file as usize - 'a' as usize);
+    if !(1..=BOARD_SIZE).contains(&rank) || file >= BOARD_SIZE {
+        return None;
+    }
+    
+    Some((BOARD_SIZE - rank, file))
+}
@@ -0,0 +1,167 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ai;
+use crate::board::{GameState, MoveOutcome};
+use crate::network::{ChessClient, ChessNetError, GameStatus, NetworkMessage};
+use crate::piece::{Piece, PieceType, Color};
+
+pub const HOUSE_BOT_NAME: &str = "House Bot";
+const HOUSE_BOT_DEPTH: u32 = 2;
+const LOBBY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const LOBBY_REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+const READ_TIMEOUT: Duration = Duration::from_millis(50); // Longest a read waits before the loop runs again
+
+// Keeps joining waiting games on the server and plays them with the AI. The heartbeat interval must
+// match the server's, or the bot is dropped as unresponsive
+pub fn run_house_bot(server_address: &str, heartbeat_interval: Duration) {
+    let mut lobby = None;
+    loop {
+        // One lobby connection is kept between polls, and replaced once it goes off to a game or fails
+        if lobby.is_none() {
+            match connect_to_lobby(server_address, heartbeat_interval) {
+                Ok(client) => lobby = Some(client),
+                Err(e) => println!("House bot: lobby error: {}", e),
+            }
+        }
+
+        if let Some(client) = &mut lobby {
+            match join_waiting_game(client) {
+                Ok(true) => {
+                    if let Err(e) = play_game(lobby.take().unwrap()) {
+                        println!("House bot: game ended with error: {}", e);
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    println!("House bot: lobby error: {}", e);
+                    lobby = None;
+                }
+            }
+        }
+
+        thread::sleep(LOBBY_POLL_INTERVAL);
+    }
+}
+
+fn connect_to_lobby(server_address: &str, heartbeat_interval: Duration) -> Result<ChessClient, ChessNetError> {
+    let mut client = ChessClient::new(server_address)?;
+    client.set_read_timeout(Some(READ_TIMEOUT))?;
+    client.set_heartbeat_interval(heartbeat_interval);
+    client.player_name = HOUSE_BOT_NAME.to_string();
+    Ok(client)
+}
+
+// Ask for the game list and join the first game waiting for an opponent. True once the join is sent
+fn join_waiting_game(client: &mut ChessClient) -> Result<bool, ChessNetError> {
+    client.send_message(NetworkMessage::RequestGameList)?;
+
+    let started = Instant::now();
+    while started.elapsed() < LOBBY_REPLY_TIMEOUT {
+        if let Some(NetworkMessage::GameList { available_games }) = client.receive_message()? {
+            let waiting = available_games.iter().find(|game| {
+                game.status == GameStatus::Waiting && game.host_name != HOUSE_BOT_NAME
+            });
+
+            if let Some(game) = waiting {
+                println!("House bot: joining game {} hosted by {}", game.game_id, game.host_name);
+                client.send_message(NetworkMessage::JoinGame {
+                    game_id: game.game_id.clone(),
+                    player_name: HOUSE_BOT_NAME.to_string(),
+                    player_color_tag: None,
+                })?;
+                return Ok(true);
+            }
+
+            return Ok(false);
+        }
+    }
+
+    Ok(false)
+}
+
+fn play_game(mut client: ChessClient) -> Result<(), ChessNetError> {
+    let mut game_state = GameState::new();
+    let mut color = None;
+    let mut awaiting_echo = false;
+
+    loop {
+        match client.receive_message()? {
+            Some(NetworkMessage::GameStart { is_white, .. }) => {
+                color = Some(Color::from_bool(is_white));
+                game_state = GameState::new();
+                awaiting_echo = false;
+                client.send_ready()?;
+            }
+            // Every applied move is broadcast, including our own, so the local state follows the server
+            Some(NetworkMessage::Move { from, to, promotion, .. }) => {
+                let from = (from.0 as usize, from.1 as usize);
+                let to = (to.0 as usize, to.1 as usize);
+
+                match game_state.apply_move_checked(from, to) {
+                    Ok(MoveOutcome::PromotionRequired) => {
+                        let piece_type = match promotion {
+                            Some('R') => PieceType::Rook,
+                            Some('B') => PieceType::Bishop,
+                            Some('N') => PieceType::Knight,
+                            _ => PieceType::Queen,
+                        };
+                        game_state.promote_pawn(piece_type);
+                    }
+                    Ok(_) => {}
+                    // The GameState that follows puts the board right
+                    Err(e) => println!("House bot: couldn't follow the move {:?} -> {:?} ({})", from, to, e),
+                }
+                awaiting_echo = false;
+            }
+            // The server's position is the one that counts, so the game is rebuilt from its move list
+            Some(NetworkMessage::GameState { moves, .. }) => match replay_moves(&moves) {
+                Some(server_game) => game_state = server_game,
+                None => println!("House bot: couldn't replay the server's moves {:?}", moves),
+            },
+            Some(NetworkMessage::MoveTakenBack { color }) => {
+                if !game_state.take_back() {
+                    println!("House bot: no move to take back for {:?}", color);
+                }
+                awaiting_echo = false;
+            }
+            // The real board follows, so wait for it before choosing again
+            Some(NetworkMessage::MoveRejected { reason, .. }) => {
+                println!("House bot: move rejected ({})", reason);
+                awaiting_echo = false;
+                continue;
+            }
+            Some(NetworkMessage::GameEnd { reason, .. }) => {
+                println!("House bot: game over ({})", reason);
+                return Ok(());
+            }
+            // Someone else took the seat first
+            Some(NetworkMessage::JoinRejected { reason, .. }) => {
+                println!("House bot: join rejected ({})", reason);
+                return Ok(());
+            }
+            Some(NetworkMessage::DrawOffered) => {
+                client.decline_draw()?;
+            }
+            _ => {}
+        }
+
+        if color == Some(game_state.current_turn) && !awaiting_echo && !game_state.is_game_over() {
+            if let Some((from, to)) = ai::best_move(&game_state, HOUSE_BOT_DEPTH) {
+                let promotion = if game_state.is_promotion_move(from, to) {
+                    ai::promotion_piece(&game_state).map(|piece_type| Piece::new(piece_type, Color::White).to_fen_char())
+                } else {
+                    None
+                };
+                client.send_move((from.0 as u8, from.1 as u8), (to.0 as u8, to.1 as u8), promotion)?;
+                awaiting_echo = true;
+            }
+        }
+    }
+}
+
+// The game a server's move list describes, or None if one of the moves doesn't apply
+fn replay_moves(moves: &[String]) -> Option<GameState> {
+    let mut game_state = GameState::new();
+    moves.iter().all(|uci| game_state.apply_uci_move(uci)).then_some(game_state)
+}
@@ -0,0 +1,83 @@
+use serde::{Serialize, Deserialize};
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_CONFIG_FILE: &str = "chess_config.json"; // Relative to where the game is started, like the server's games/ folder
+
+// How pieces are moved with the mouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum InputMode {
+    ClickClick, // Click a piece, then click its destination
+    Drag,       // Press on a piece and release it on its destination
+    #[default]
+    Both,       // A quick click selects, a press-drag moves
+}
+
+impl InputMode {
+    pub fn next(self) -> Self {
+        match self {
+            InputMode::ClickClick => InputMode::Drag,
+            InputMode::Drag => InputMode::Both,
+            InputMode::Both => InputMode::ClickClick,
+        }
+    }
+    
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "click" => Some(InputMode::ClickClick),
+            "drag" => Some(InputMode::Drag),
+            "both" => Some(InputMode::Both),
+            _ => None,
+        }
+    }
+    
+    // Whether clicking a destination plays the selected piece's move. Pure drag mode only moves on release
+    pub fn moves_on_click(self) -> bool {
+        self != InputMode::Drag
+    }
+    
+    // Whether pressing on a piece starts a drag. In click-click mode a drag is just a click-select
+    pub fn tracks_drags(self) -> bool {
+        self != InputMode::ClickClick
+    }
+    
+    // Whether letting go of a drag counts as a click instead of a drop
+    pub fn release_is_click(self, moved: bool, back_on_origin: bool) -> bool {
+        self == InputMode::Both && (!moved || back_on_origin)
+    }
+}
+
+// Client settings kept between launches
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ClientConfig {
+    #[serde(default)]
+    pub tutorial_seen: bool, // The first-launch hints have been dismissed
+    #[serde(default)]
+    pub input_mode: InputMode, // Changed with F2
+}
+
+impl ClientConfig {
+    // No file yet just means a first launch, and a damaged one is treated the same way
+    pub fn load(path: &Path) -> Self {
+        match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                println!("Ignoring unreadable config {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn should_show_tutorial(&self) -> bool {
+        !self.tutorial_seen
+    }
+}
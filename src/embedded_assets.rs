@@ -1,13 +1,20 @@
-use ggez::graphics::Image;
+use ggez::graphics::{Image, Text, Color as GgezColor};
 use ggez::{Context, GameResult};
 use std::collections::HashMap;
 use std::io::Cursor;
 use ggez::graphics::DrawParam;
 
-use crate::piece::{PieceType, Color};
+use crate::piece::{Piece, PieceType, Color};
+
+// Glyph size before the caller's DrawParam scale is applied, roughly matching the piece images
+const TEXT_PIECE_SIZE: f32 = 100.0;
+
+// Fraction of a square the piece should cover
+const PIECE_FILL: f32 = 0.96;
 
 pub struct EmbeddedAssets {
     piece_images: HashMap<(PieceType, Color), Image>,
+    text_pieces: bool,
 }
 
 impl EmbeddedAssets {
@@ -44,9 +51,22 @@ impl EmbeddedAssets {
         
         Ok(Self {
             piece_images,
+            text_pieces: false,
         })
     }
     
+    // Render pieces as unicode glyphs instead of loading the embedded images
+    pub fn text_pieces() -> Self {
+        Self {
+            piece_images: HashMap::new(),
+            text_pieces: true,
+        }
+    }
+    
+    pub fn uses_text_pieces(&self) -> bool {
+        self.text_pieces
+    }
+    
     fn load_piece_image(
         ctx: &mut Context,
         piece_images: &mut HashMap<(PieceType, Color), Image>,
@@ -64,6 +84,16 @@ impl EmbeddedAssets {
         self.piece_images.get(&(piece_type, color)).expect("Missing piece image")
     }
     
+    // Draw scale that makes a piece fill a square of the given size
+    pub fn piece_scale(&self, square_size: f32) -> f32 {
+        let piece_size = if self.text_pieces {
+            TEXT_PIECE_SIZE
+        } else {
+            self.piece_images.values().next().map_or(TEXT_PIECE_SIZE, |image| image.width() as f32)
+        };
+        square_size * PIECE_FILL / piece_size
+    }
+    
     pub fn draw_piece(
         &self,
         canvas: &mut ggez::graphics::Canvas,
@@ -71,6 +101,13 @@ impl EmbeddedAssets {
         color: Color,
         param: DrawParam,
     ) {
+        if self.text_pieces {
+            let mut text = Text::new(Piece::new(piece_type, color).to_char());
+            text.set_scale(TEXT_PIECE_SIZE);
+            canvas.draw(&text, param.color(GgezColor::BLACK));
+            return;
+        }
+        
         let image = self.get_piece_image(piece_type, color);
         canvas.draw(image, param);
     }
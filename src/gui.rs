@@ -3,29 +3,35 @@ use ggez::graphics::{self, Canvas, Color as GgezColor, DrawParam, Rect, Text};
 use ggez::input::mouse::MouseButton;
 use ggez::mint::{Point2, Vector2};
 
-use crate::board::{GameState, BOARD_SIZE, PromotionState};
+use crate::ai;
+use crate::board::{perspective_square, square_bit, Board, GameState, Move, RecordedMove, BOARD_SIZE, PromotionState};
 use crate::piece::{PieceType, Color, Piece};
 use crate::embedded_assets::EmbeddedAssets;
-use crate::network::{ChessClient, ClientRole, GameInfo, GameStatus, NetworkMessage};
-use std::io::Write;
+use crate::protocol_log::ProtocolRecorder;
+use crate::config::ClientConfig;
+pub use crate::config::InputMode;
+use crate::network::{ChessClient, ChessNetError, GameInfo, NetworkMessage, PlayerColorTag, ReconnectState, TerminationReason, TimeControl, TimeMode, DEFAULT_HEARTBEAT_INTERVAL, REMATCH_WINDOW, WIRE_BOARD_SIZE};
+use std::collections::VecDeque;
 use std::time::{Duration, Instant};
+use std::path::PathBuf;
 
-const SQUARE_SIZE: f32 = 60.0;
-const BOARD_OFFSET_X: f32 = 50.0;
-const BOARD_OFFSET_Y: f32 = 50.0;
+pub const SQUARE_SIZE: f32 = 60.0;
+pub const BOARD_OFFSET_X: f32 = 50.0;
+pub const BOARD_OFFSET_Y: f32 = 50.0;
 
 const LIGHT_SQUARE: GgezColor = GgezColor::new(0.9, 0.9, 0.8, 1.0);
 const DARK_SQUARE: GgezColor = GgezColor::new(0.5, 0.5, 0.4, 1.0);
 const SELECTED_SQUARE: GgezColor = GgezColor::new(0.7, 0.9, 0.7, 1.0);
 const POSSIBLE_MOVE: GgezColor = GgezColor::new(0.7, 0.7, 0.9, 0.7);
+const LAST_MOVE_SQUARE: GgezColor = GgezColor::new(0.95, 0.85, 0.2, 0.5); // Blended over the square, see tint
 const PROMOTION_BG: GgezColor = GgezColor::new(0.3, 0.3, 0.3, 0.9);
 const BUTTON_BG: GgezColor = GgezColor::new(0.3, 0.3, 0.6, 1.0);
 const BUTTON_HOVER: GgezColor = GgezColor::new(0.4, 0.4, 0.7, 1.0);
+const BUTTON_DISABLED: GgezColor = GgezColor::new(0.3, 0.3, 0.3, 1.0);
+const BUTTON_DISABLED_TEXT: GgezColor = GgezColor::new(0.6, 0.6, 0.6, 1.0);
 const DIALOG_BG: GgezColor = GgezColor::new(0.2, 0.2, 0.2, 0.9);
 const ACCEPT_BUTTON_BG: GgezColor = GgezColor::new(0.3, 0.6, 0.3, 1.0);
-const ACCEPT_BUTTON_HOVER: GgezColor = GgezColor::new(0.4, 0.7, 0.4, 1.0);
 const DECLINE_BUTTON_BG: GgezColor = GgezColor::new(0.6, 0.3, 0.3, 1.0);
-const DECLINE_BUTTON_HOVER: GgezColor = GgezColor::new(0.7, 0.4, 0.4, 1.0);
 const SPECTATOR_PANEL_BG: GgezColor = GgezColor::new(0.2, 0.2, 0.3, 0.9);
 const CHAT_BG: GgezColor = GgezColor::new(0.2, 0.2, 0.2, 0.9);
 const CHAT_INPUT_BG: GgezColor = GgezColor::new(0.3, 0.3, 0.3, 1.0);
@@ -44,11 +50,46 @@ const SPECTATOR_PANEL_WIDTH: f32 = 200.0;
 const SPECTATOR_PANEL_HEIGHT: f32 = 300.0;
 const CHAT_HEIGHT: f32 = 200.0;
 const MAX_CHAT_MESSAGES: usize = 10;
+const MAX_CHAT_INPUT_LENGTH: usize = 200; // Characters, the input stops accepting more at the cap
+const CHAT_SEND_INTERVAL: Duration = Duration::from_secs(1); // Minimum time between sent messages
+
+const MAX_NETWORK_LOG_LINES: usize = 50;
+const NETWORK_LOG_HEIGHT: f32 = 160.0;
+const NETWORK_LOG_LINE_HEIGHT: f32 = 14.0;
+// Move history panel in the right sidebar, below the spectator panel when that's showing
+const HISTORY_PANEL_WIDTH: f32 = 200.0;
+const HISTORY_PANEL_BOTTOM: f32 = 740.0; // Just inside the default 750-high window
+const HISTORY_HEADER_HEIGHT: f32 = 25.0;
+const HISTORY_LINE_HEIGHT: f32 = 18.0;
+const CAPTURED_PIECE_SIZE: f32 = 20.0; // Icons in the captured-pieces rows below the clocks
+
+const MOVE_ANIMATION_DURATION: Duration = Duration::from_millis(200);
+const DRAG_THRESHOLD: f32 = 5.0; // Pointer travel before a press counts as a drag rather than a click
+const BOARD_EDGE_TOLERANCE: f32 = 2.0; // Pixels past the board's outer edge that still count as the edge square
+const SQUARE_SNAP_EPSILON: f32 = 1e-3; // Fraction of a square within which a position snaps onto a boundary
+
+// First-launch hints, centred in the default 780x750 window
+const TUTORIAL_WIDTH: f32 = 640.0;
+const TUTORIAL_HEIGHT: f32 = 230.0;
+const TUTORIAL_X: f32 = (780.0 - TUTORIAL_WIDTH) / 2.0;
+const TUTORIAL_Y: f32 = (750.0 - TUTORIAL_HEIGHT) / 2.0;
+const TUTORIAL_LINES: [&str; 5] = [
+    "Welcome to Rust Chess!",
+    "Click a piece to see where it can go, then click a highlighted square to move it.",
+    "Use the buttons on the right to connect to a server and create or join a game.",
+    "Spectators can chat with the players in the panel beside the board.",
+    "Press C for coordinates, E for the evaluation bar and F12 for the network log.",
+];
+
+const EVAL_BAR_WIDTH: f32 = 16.0;
+const EVAL_BAR_GAP: f32 = 12.0; // Between the bar and the board's left edge
+const EVAL_BAR_DEPTH: u32 = 2;
 
 pub struct Button {
     rect: Rect,
     text: String,
     hovered: bool,
+    enabled: bool,
 }
 
 impl Button {
@@ -57,6 +98,7 @@ impl Button {
             rect: Rect::new(x, y, width, height),
             text: text.to_string(),
             hovered: false,
+            enabled: true,
         }
     }
     
@@ -68,8 +110,19 @@ impl Button {
         self.hovered = hovered;
     }
     
+    // A disabled button is drawn greyed out; callers still decide what a click does
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+    
     fn draw(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
-        let color = if self.hovered { BUTTON_HOVER } else { BUTTON_BG };
+        let color = if !self.enabled {
+            BUTTON_DISABLED
+        } else if self.hovered {
+            BUTTON_HOVER
+        } else {
+            BUTTON_BG
+        };
         
         let mesh = graphics::Mesh::new_rectangle(
             ctx,
@@ -101,7 +154,7 @@ impl Button {
             DrawParam::default()
                 .dest(dest)
                 .offset(Point2 { x: 0.5, y: 0.5 }) // Center the text
-                .color(GgezColor::WHITE)
+                .color(if self.enabled { GgezColor::WHITE } else { BUTTON_DISABLED_TEXT })
         );
         
         Ok(())
@@ -114,18 +167,87 @@ pub struct MoveInfo {
     pub promotion: Option<char>,
 }
 
+pub struct MoveAnimation {
+    piece: Piece,
+    from: (usize, usize),
+    to: (usize, usize),
+    started: Instant,
+}
+
+// What Escape does, most pressing first. Only an otherwise idle board lets it quit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeAction {
+    LeaveChatInput, // The chat box loses the focus, the typed text stays
+    KeepDialog,     // A promotion, draw or rematch dialog needs an answer first
+    DropSelection,
+    Quit,
+}
+
+impl EscapeAction {
+    pub fn decide(chat_input_active: bool, dialog_open: bool, has_selection: bool) -> Self {
+        if chat_input_active {
+            EscapeAction::LeaveChatInput
+        } else if dialog_open {
+            EscapeAction::KeepDialog
+        } else if has_selection {
+            EscapeAction::DropSelection
+        } else {
+            EscapeAction::Quit
+        }
+    }
+}
+
+// Lets a paused window still poll the network every so often, measured on the game's own clock so the
+// event loop never has to sleep
+#[derive(Debug, Default)]
+pub struct UpdateThrottle {
+    last_update: Option<Duration>, // Time since start, e.g. ctx.time.time_since_start()
+}
+
+impl UpdateThrottle {
+    pub fn should_update(&mut self, paused: bool, now: Duration, interval: Duration) -> bool {
+        let due = !paused || self.last_update.is_none_or(|last| now.saturating_sub(last) >= interval);
+        if due {
+            self.last_update = Some(now);
+        }
+        due
+    }
+}
+
+// With auto-queen on, a pawn that just reached the last rank becomes a queen without the dialog, unless
+// the player held the modifier to choose. True when it promoted
+pub fn auto_promote(game_state: &mut GameState, auto_queen: bool, force_dialog: bool) -> bool {
+    auto_queen && !force_dialog && game_state.promotion_pending.is_some() && game_state.promote_pawn(PieceType::Queen)
+}
+
+struct DragState {
+    from: (usize, usize),
+    origin: Point2<f32>,
+    position: Point2<f32>,
+    reselected: bool, // The piece was already selected when it was pressed
+}
+
+impl DragState {
+    fn has_moved(&self) -> bool {
+        let dx = self.position.x - self.origin.x;
+        let dy = self.position.y - self.origin.y;
+        (dx * dx + dy * dy).sqrt() > DRAG_THRESHOLD
+    }
+}
+
 pub struct SpectatorPanel {
     rect: Rect,
     chat_rect: Rect,
     chat_input_rect: Rect,
     send_button: Button,
-    chat_messages: Vec<(String, String, bool)>, // sender, message, is_spectator
+    chat_messages: Vec<(String, String, bool, Option<PlayerColorTag>)>, // sender, message, is_spectator, color tag
     chat_input: String,
+    last_chat_sent: Option<Instant>,
     spectator_list: Vec<String>,
 }
 
 impl SpectatorPanel {
-    fn new(x: f32, y: f32) -> Self {
+    pub fn new(x: f32, y: f32) -> Self {
         let rect = Rect::new(x, y, SPECTATOR_PANEL_WIDTH, SPECTATOR_PANEL_HEIGHT);
         let chat_rect = Rect::new(x, y + SPECTATOR_PANEL_HEIGHT - CHAT_HEIGHT, 
                                   SPECTATOR_PANEL_WIDTH, CHAT_HEIGHT);
@@ -138,7 +260,6 @@ impl SpectatorPanel {
             25.0,
             "Send"
         );
-        
         Self {
             rect,
             chat_rect,
@@ -146,12 +267,13 @@ impl SpectatorPanel {
             send_button,
             chat_messages: Vec::new(),
             chat_input: String::new(),
+            last_chat_sent: None,
             spectator_list: Vec::new(),
         }
     }
     
-    fn add_chat_message(&mut self, sender: String, message: String, is_spectator: bool) {
-        self.chat_messages.push((sender, message, is_spectator));
+    fn add_chat_message(&mut self, sender: String, message: String, is_spectator: bool, player_color_tag: Option<PlayerColorTag>) {
+        self.chat_messages.push((sender, message, is_spectator, player_color_tag));
         
         // Limit the number of messages
         if self.chat_messages.len() > MAX_CHAT_MESSAGES {
@@ -243,12 +365,8 @@ impl SpectatorPanel {
         );
         
         // Draw chat messages
-        for (i, (sender, message, is_spectator)) in self.chat_messages.iter().enumerate() {
-            let sender_color = if *is_spectator {
-                GgezColor::new(0.7, 0.7, 1.0, 1.0) // Blue for spectators
-            } else {
-                GgezColor::new(1.0, 0.7, 0.7, 1.0) // Red for players
-            };
+        for (i, (sender, message, is_spectator, player_color_tag)) in self.chat_messages.iter().enumerate() {
+            let sender_color = chat_sender_color(*is_spectator, *player_color_tag);
             
             // Draw sender name
             let sender_text = Text::new(format!("{}: ", sender));
@@ -309,6 +427,19 @@ impl SpectatorPanel {
                 .color(GgezColor::WHITE)
         );
         
+        // Characters left before the cap, right-aligned inside the input
+        let remaining_text = Text::new(format!("{}", self.remaining_chars()));
+        canvas.draw(
+            &remaining_text,
+            DrawParam::default()
+                .dest(Point2 {
+                    x: self.chat_input_rect.x + self.chat_input_rect.w - 5.0,
+                    y: self.chat_input_rect.y + self.chat_input_rect.h / 2.0,
+                })
+                .offset(Point2 { x: 1.0, y: 0.5 })
+                .color(GgezColor::new(0.7, 0.7, 0.7, 1.0))
+        );
+        
         // Draw send button
         self.send_button.draw(ctx, canvas)?;
         
@@ -323,37 +454,209 @@ impl SpectatorPanel {
         self.chat_input_rect.contains(point)
     }
     
-    fn handle_key_input(&mut self, key: char) {
+    pub fn handle_key_input(&mut self, key: char) {
         if key == '\u{08}' { // Backspace
             self.chat_input.pop();
-        } else if !key.is_control() {
+        } else if !key.is_control() && self.remaining_chars() > 0 {
             self.chat_input.push(key);
         }
     }
     
-    fn clear_input(&mut self) {
-        self.chat_input.clear();
+    pub fn chat_input(&self) -> &str {
+        &self.chat_input
     }
     
-    fn get_input(&self) -> &str {
-        &self.chat_input
+    pub fn remaining_chars(&self) -> usize {
+        MAX_CHAT_INPUT_LENGTH.saturating_sub(self.chat_input.chars().count())
+    }
+    
+    // The message to send, unless the input is empty or the last one went out too recently.
+    // A message held back stays in the input so it can be sent again
+    pub fn take_message(&mut self) -> Option<String> {
+        if self.chat_input.is_empty() {
+            return None;
+        }
+        if self.last_chat_sent.is_some_and(|sent| sent.elapsed() < CHAT_SEND_INTERVAL) {
+            return None;
+        }
+        
+        self.last_chat_sent = Some(Instant::now());
+        Some(std::mem::take(&mut self.chat_input))
+    }
+}
+
+// Boards a spectator has seen, so they can step back through the game while it carries on
+struct SpectatorReview {
+    positions: Vec<Board>,
+    review_index: usize, // Index into positions of the board on screen
+    following_live: bool, // Show each new position as it arrives
+}
+
+impl SpectatorReview {
+    fn new() -> Self {
+        Self {
+            positions: Vec::new(),
+            review_index: 0,
+            following_live: true,
+        }
+    }
+    
+    fn record(&mut self, board: Board) {
+        // The same snapshot can arrive twice, e.g. a resync after a missed move
+        if self.positions.last() == Some(&board) {
+            return;
+        }
+        
+        self.positions.push(board);
+        if self.following_live {
+            self.review_index = self.latest_index();
+        }
+    }
+    
+    fn latest_index(&self) -> usize {
+        self.positions.len().saturating_sub(1)
+    }
+    
+    fn is_behind_live(&self) -> bool {
+        self.review_index < self.latest_index()
     }
+    
+    fn step_back(&mut self) {
+        if self.review_index > 0 {
+            self.review_index -= 1;
+            self.following_live = false;
+        }
+    }
+    
+    fn step_forward(&mut self) {
+        if self.is_behind_live() {
+            self.review_index += 1;
+            self.following_live = !self.is_behind_live();
+        }
+    }
+    
+    fn jump_to_live(&mut self) {
+        self.review_index = self.latest_index();
+        self.following_live = true;
+    }
+    
+    // The board to show instead of the live one, if any
+    fn reviewed_board(&self) -> Option<&Board> {
+        if self.following_live {
+            None
+        } else {
+            self.positions.get(self.review_index)
+        }
+    }
+}
+
+// The moves played so far as SAN for the history panel, two to a row with move numbers
+struct MoveHistory {
+    recorded: Vec<RecordedMove>, // The log the SAN was worked out from
+    sans: Vec<String>,
+    captured: Vec<Piece>, // GameState::captured of the same game
+    first_move_number: u32,
+    black_first: bool, // A game set up with Black to move leaves White's half of the first row empty
+    scroll: usize, // Rows scrolled back from the newest, 0 keeps the latest move in view
+}
+
+impl MoveHistory {
+    fn new() -> Self {
+        Self {
+            recorded: Vec::new(),
+            sans: Vec::new(),
+            captured: Vec::new(),
+            first_move_number: 1,
+            black_first: false,
+            scroll: 0,
+        }
+    }
+    
+    // Work the SAN out again when the game's log no longer matches, returning whether it changed.
+    // A new move scrolls back down to it
+    fn sync(&mut self, game: &GameState) -> bool {
+        if game.move_log == self.recorded {
+            return false;
+        }
+        
+        let start = game.logged_start_position();
+        self.recorded = game.move_log.clone();
+        self.sans = game.move_log_san();
+        self.captured = game.captured.clone();
+        self.first_move_number = start.fullmove_number;
+        self.black_first = start.current_turn == Color::Black;
+        self.scroll = 0;
+        true
+    }
+    
+    // (move number, White's move, Black's move) for each row
+    fn rows(&self) -> Vec<(u32, Option<&str>, Option<&str>)> {
+        let mut sans = self.sans.iter().map(String::as_str);
+        let mut rows = Vec::new();
+        let mut number = self.first_move_number;
+        
+        if self.black_first {
+            if let Some(black) = sans.next() {
+                rows.push((number, None, Some(black)));
+                number += 1;
+            }
+        }
+        while let Some(white) = sans.next() {
+            rows.push((number, Some(white), sans.next()));
+            number += 1;
+        }
+        
+        rows
+    }
+    
+    // Positive steps scroll back towards the first move, stopping once it's at the top
+    fn scroll_by(&mut self, steps: isize, visible_rows: usize) {
+        let row_count = (self.sans.len() + self.black_first as usize).div_ceil(2);
+        let max_scroll = row_count.saturating_sub(visible_rows);
+        self.scroll = self.scroll.saturating_add_signed(steps).min(max_scroll);
+    }
+}
+
+// The last square whose legal destinations were worked out, reused while the position hasn't changed.
+// A move changes the position hash, so the entry goes stale on its own
+struct SelectionCache {
+    square: (usize, usize),
+    destinations: u64, // Bitboard, like ChessGui::possible_moves
+    position_hash: u64,
 }
 
 pub struct ChessGui {
     game_state: GameState,
     selected_square: Option<(usize, usize)>,
-    possible_moves: Vec<(usize, usize)>,
+    possible_moves: u64, // Bitboard of the selected piece's legal destinations
+    selection_cache: Option<SelectionCache>,
+    destination_lookups: usize, // Times cached_destinations had to work the destinations out
+    last_move: Option<Move>,
+    forced_move: Option<Move>, // Local games only, see refresh in update
+    move_animation: Option<MoveAnimation>,
     assets: EmbeddedAssets,
     show_square_coordinates: bool,
+    coords_button: Button,
+    show_eval_bar: bool,
+    eval_cache: ai::EvalCache,
+    board_flipped: bool,
+    ui_scale: f32,
     game_over: bool,
+    termination: Option<TerminationReason>,
     needs_redraw: bool,
+    paused_rendering: bool, // Minimized or in the background: keep the game in sync but don't draw
     is_network_game: bool,
     player_color: Option<Color>,
+    computer_depth: Option<u32>, // Local games against the AI, which plays whichever side player_color isn't
+    computer_search: Option<ai::BackgroundSearch>, // The computer's move while it's still thinking
     network_client: Option<ChessClient>,
     game_id: Option<String>,
     player_name: String,
     opponent_name: String,
+    time_control: Option<TimeControl>,
+    clock_ms: Option<(u64, u64)>, // Remaining (white, black) as last reported by the server
+    network_log: VecDeque<String>,
+    show_network_log: bool,
     available_games: Vec<GameInfo>,
     // Network buttons
     connect_button: Button,
@@ -368,24 +671,64 @@ pub struct ChessGui {
     // Dialog state
     draw_offered: bool,
     rematch_offered: bool,
+    rematch_deadline: Option<Instant>, // When the server stops waiting for a rematch of the last game
+    opponent_rejoin_deadline: Option<Instant>, // While the opponent is disconnected, when they forfeit
     // Button state
     server_address: String,
     show_game_list: bool,
-    hovered_button: Option<usize>, // Index of button being hovered (0=connect, 1=create, 2=refresh, 3+=join game buttons)
     // Spectator mode
     is_spectator: bool,
     spectator_panel: SpectatorPanel,
     show_spectator_panel: bool,
+    spectator_review: SpectatorReview,
+    live_button: Button,
+    move_history: MoveHistory,
+    flip_board_button: Button, // In the history panel's header, see move_history_rect
     input_active: bool,
     last_heartbeat: Instant,
+    heartbeat_interval: Duration,
+    takeover_token: Option<String>,
+    time_mode: TimeMode, // For games this client creates
+    requested_time_control: Option<TimeControl>, // Likewise, None for an untimed game
+    player_color_tag: Option<PlayerColorTag>, // Sent when creating, joining or spectating a game
+    opponent_color_tag: Option<PlayerColorTag>,
+    protocol_recorder: Option<ProtocolRecorder>,
+    config: ClientConfig,
+    config_path: Option<PathBuf>, // Where the config is saved, None to keep it in memory only
+    show_tutorial: bool,
+    tutorial_button: Button,
     // Promotion tracking
     pending_promotion_move: Option<MoveInfo>,
+    auto_queen: bool,
+    force_promotion_dialog: bool, // Modifier held to underpromote while auto-queen is on
+    input_mode: InputMode,
+    drag: Option<DragState>,
+}
+
+// Network failures inside the GUI's event handlers surface as ggez errors
+impl From<ChessNetError> for ggez::GameError {
+    fn from(e: ChessNetError) -> Self {
+        ggez::GameError::CustomError(format!("Network error: {}", e))
+    }
 }
 
 impl ChessGui {
     pub fn new(ctx: &mut Context) -> GameResult<Self> {
-        let game_state = GameState::new();
         let assets = EmbeddedAssets::new(ctx)?;
+        Self::with_assets(assets)
+    }
+    
+    // Build the GUI without loading piece images, drawing pieces as text glyphs instead
+    pub fn with_text_pieces() -> GameResult<Self> {
+        Self::with_assets(EmbeddedAssets::text_pieces())
+    }
+    
+    pub fn uses_text_pieces(&self) -> bool {
+        self.assets.uses_text_pieces()
+    }
+    
+    fn with_assets(assets: EmbeddedAssets) -> GameResult<Self> {
+        let game_state = GameState::new();
         
         let connect_button = Button::new(
             BOARD_OFFSET_X + (BOARD_SIZE as f32) * SQUARE_SIZE + BUTTON_MARGIN,
@@ -419,6 +762,41 @@ impl ChessGui {
             "Spectate Game"
         );
         
+        // Above the board's top-left corner, so it's on screen in every window size and mode
+        let coords_button = Button::new(
+            BOARD_OFFSET_X,
+            BOARD_OFFSET_Y - 40.0,
+            70.0,
+            25.0,
+            "Coords"
+        );
+        
+        // Beside the coordinates toggle, only shown while a spectator is looking at an earlier position
+        let live_button = Button::new(
+            BOARD_OFFSET_X + 80.0,
+            BOARD_OFFSET_Y - 40.0,
+            110.0,
+            25.0,
+            "Jump to Live"
+        );
+        
+        // Placed for a panel with nothing above it; update follows the panel when it moves
+        let flip_board_button = Button::new(
+            BOARD_OFFSET_X + (BOARD_SIZE as f32) * SQUARE_SIZE + BUTTON_MARGIN + HISTORY_PANEL_WIDTH - 105.0,
+            BOARD_OFFSET_Y + 5.0 * (BUTTON_HEIGHT + BUTTON_MARGIN) + 3.0,
+            100.0,
+            22.0,
+            "Flip Board"
+        );
+        
+        let tutorial_button = Button::new(
+            TUTORIAL_X + (TUTORIAL_WIDTH - DIALOG_BUTTON_WIDTH) / 2.0,
+            TUTORIAL_Y + TUTORIAL_HEIGHT - DIALOG_BUTTON_HEIGHT - 15.0,
+            DIALOG_BUTTON_WIDTH,
+            DIALOG_BUTTON_HEIGHT,
+            "Got it"
+        );
+        
         // Create game action buttons
         // Game action buttons - positioned below the board
         let offer_draw_button = Button::new(
@@ -454,17 +832,35 @@ impl ChessGui {
         Ok(Self {
             game_state,
             selected_square: None,
-            possible_moves: Vec::new(),
+            possible_moves: 0,
+            selection_cache: None,
+            destination_lookups: 0,
+            last_move: None,
+            forced_move: None,
+            move_animation: None,
             assets,
             show_square_coordinates: true,
+            coords_button,
+            show_eval_bar: false,
+            eval_cache: ai::EvalCache::default(),
+            board_flipped: false,
+            ui_scale: 1.0,
             game_over: false,
+            termination: None,
             needs_redraw: true,
+            paused_rendering: false,
             is_network_game: false,
             player_color: None,
+            computer_depth: None,
+            computer_search: None,
             network_client: None,
             game_id: None,
             player_name: String::new(),
             opponent_name: String::new(),
+            time_control: None,
+            clock_ms: None,
+            network_log: VecDeque::new(),
+            show_network_log: false,
             available_games: Vec::new(),
             connect_button,
             create_game_button,
@@ -476,37 +872,132 @@ impl ChessGui {
             rematch_button,
             draw_offered: false,
             rematch_offered: false,
+            rematch_deadline: None,
+            opponent_rejoin_deadline: None,
             server_address: "localhost:8080".to_string(),
             show_game_list: false,
-            hovered_button: None,
             is_spectator: false,
             spectator_panel,
             show_spectator_panel: false,
+            spectator_review: SpectatorReview::new(),
+            live_button,
+            move_history: MoveHistory::new(),
+            flip_board_button,
             input_active: false,
             last_heartbeat: Instant::now(),
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            takeover_token: None,
+            time_mode: TimeMode::Live,
+            requested_time_control: None,
+            player_color_tag: None,
+            opponent_color_tag: None,
+            protocol_recorder: None,
+            config: ClientConfig::default(),
+            config_path: None,
+            show_tutorial: false,
+            tutorial_button,
             pending_promotion_move: None,
+            auto_queen: false,
+            force_promotion_dialog: false,
+            input_mode: InputMode::Both,
+            drag: None,
         })
     }
     
     pub fn set_player_color(&mut self, is_white: bool) {
-        self.player_color = Some(if is_white { Color::White } else { Color::Black });
+        self.player_color = Some(Color::from_bool(is_white));
         self.is_network_game = true;
         self.needs_redraw = true;
     }
     
+    // A local game against the computer, which takes Black and searches this many plies
+    pub fn set_computer_opponent(&mut self, depth: u32) {
+        self.player_color = Some(Color::White);
+        self.opponent_name = "Computer".to_string();
+        self.computer_depth = Some(depth.clamp(1, ai::MAX_PLAYING_DEPTH));
+        self.needs_redraw = true;
+    }
+    
+    // Catch up on whatever changed while paused as soon as the window comes back
+    pub fn set_paused_rendering(&mut self, paused: bool) {
+        if self.paused_rendering && !paused {
+            self.needs_redraw = true;
+        }
+        self.paused_rendering = paused;
+    }
+    
+    pub fn is_rendering_paused(&self) -> bool {
+        self.paused_rendering
+    }
+    
     pub fn set_spectator_mode(&mut self, game_id: String) {
         self.is_spectator = true;
         self.is_network_game = true;
         self.game_id = Some(game_id);
         self.show_spectator_panel = true;
+        self.spectator_review = SpectatorReview::new();
+        self.needs_redraw = true;
+    }
+    
+    // Spectators can step through the positions they've seen, see SpectatorReview
+    pub fn review_step_back(&mut self) {
+        if self.is_spectator {
+            self.spectator_review.step_back();
+            self.needs_redraw = true;
+        }
+    }
+    
+    pub fn review_step_forward(&mut self) {
+        if self.is_spectator {
+            self.spectator_review.step_forward();
+            self.needs_redraw = true;
+        }
+    }
+    
+    pub fn jump_to_live(&mut self) {
+        self.spectator_review.jump_to_live();
+        self.needs_redraw = true;
+    }
+    
+    // The reviewed ply and the number of positions seen so far
+    pub fn review_position(&self) -> (usize, usize) {
+        (self.spectator_review.review_index, self.spectator_review.positions.len())
+    }
+    
+    pub fn is_following_live(&self) -> bool {
+        self.spectator_review.following_live
+    }
+    
+    fn is_reviewing(&self) -> bool {
+        self.is_spectator && self.spectator_review.reviewed_board().is_some()
+    }
+    
+    fn displayed_board(&self) -> &Board {
+        match self.spectator_review.reviewed_board() {
+            Some(board) if self.is_spectator => board,
+            _ => &self.game_state.board,
+        }
+    }
+    
+    // Undo an optimistic join or spectate and show the game list again
+    fn return_to_lobby(&mut self) -> GameResult<()> {
+        self.is_spectator = false;
+        self.is_network_game = false;
+        self.player_color = None;
+        self.game_id = None;
+        self.show_spectator_panel = false;
+        self.show_game_list = true;
         self.needs_redraw = true;
+        
+        self.request_game_list()
     }
     
     pub fn handle_spectator_joined(&mut self, name: String) {
         self.spectator_panel.add_spectator(name.clone());
         self.spectator_panel.add_chat_message("System".to_string(), 
                                              format!("{} joined as spectator", name), 
-                                             true);
+                                             true,
+                                             None);
         self.needs_redraw = true;
     }
     
@@ -514,48 +1005,52 @@ impl ChessGui {
         self.spectator_panel.remove_spectator(&name);
         self.spectator_panel.add_chat_message("System".to_string(), 
                                              format!("{} left", name), 
-                                             true);
+                                             true,
+                                             None);
         self.needs_redraw = true;
     }
     
-    pub fn handle_chat_message(&mut self, sender: String, message: String, is_spectator: bool) {
-        self.spectator_panel.add_chat_message(sender, message, is_spectator);
+    pub fn handle_chat_message(&mut self, sender: String, message: String, is_spectator: bool, player_color_tag: Option<PlayerColorTag>) {
+        self.spectator_panel.add_chat_message(sender, message, is_spectator, player_color_tag);
         self.needs_redraw = true;
     }
     
-    pub fn handle_network_move(&mut self, from: (u8, u8), to: (u8, u8), promotion: Option<char>) -> GameResult<()> {
+    pub fn handle_network_move(&mut self, from: (u8, u8), to: (u8, u8), _promotion: Option<char>) -> GameResult<()> {
         let from = (from.0 as usize, from.1 as usize);
         let to = (to.0 as usize, to.1 as usize);
         
-        if let Some(promotion) = promotion {
-            let piece_type = match promotion {
-                'Q' => PieceType::Queen,
-                'R' => PieceType::Rook,
-                'B' => PieceType::Bishop,
-                'N' => PieceType::Knight,
-                _ => return Ok(()),
-            };
-            if !self.game_state.promote_pawn(piece_type) {
-                return Ok(());
-            }
+        // Our own move echoed back by the server has already been applied and shown
+        if self.last_move == Some((from, to)) {
+            return Ok(());
         }
         
-        if !self.game_state.make_move(from, to) {
-            return Ok(());
+        // The board itself (including any promoted piece) arrives with the GameState that follows,
+        // so only animate the piece here
+        let piece = self.game_state.board[from.0][from.1].or(self.game_state.board[to.0][to.1]);
+        if let Some(piece) = piece {
+            self.move_animation = Some(MoveAnimation {
+                piece,
+                from,
+                to,
+                started: Instant::now(),
+            });
         }
         
+        self.last_move = Some((from, to));
         self.selected_square = None;
-        self.possible_moves.clear();
+        self.possible_moves = 0;
         self.needs_redraw = true;
         
         Ok(())
     }
     
-    pub fn update_game_state(&mut self, board: [[Option<(PieceType, Color)>; 8]; 8], current_turn: Color, promotion_pending: Option<(usize, usize, Color)>, game_over: bool) -> GameResult<()> {
+    pub fn update_game_state(&mut self, board: [[Option<(PieceType, Color)>; WIRE_BOARD_SIZE]; WIRE_BOARD_SIZE], current_turn: Color, promotion_pending: Option<(usize, usize, Color)>, game_over: bool) -> GameResult<()> {
+        let previous_board = self.game_state.board;
+        
         // Update the board
-        for rank in 0..8 {
-            for file in 0..8 {
-                self.game_state.board[rank][file] = board[rank][file].map(|(piece_type, color)| {
+        for (rank, row) in board.iter().enumerate().take(BOARD_SIZE) {
+            for (file, square) in row.iter().enumerate().take(BOARD_SIZE) {
+                self.game_state.board[rank][file] = square.map(|(piece_type, color)| {
                     Piece::new(piece_type, color)
                 });
             }
@@ -570,28 +1065,55 @@ impl ChessGui {
             }
         );
         self.game_over = game_over;
+        
+        // The snapshot carries only pieces, so the hash and rights are rebuilt from them
+        self.game_state.validate_and_repair();
+        
+        // Spectators may only get snapshots, so the last move is read off the change
+        if self.is_spectator {
+            if let Some((from, to, _)) = GameState::diff(&previous_board, &self.game_state.board) {
+                self.last_move = Some((from, to));
+            }
+            self.spectator_review.record(self.game_state.board);
+        }
 
         // Clear selection and possible moves
         self.selected_square = None;
-        self.possible_moves.clear();
+        self.possible_moves = 0;
         self.needs_redraw = true;
 
         Ok(())
     }
     
     pub fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        if !self.needs_redraw {
+        if self.paused_rendering || !self.needs_redraw {
             return Ok(());
         }
         
         let mut canvas = Canvas::from_frame(ctx, GgezColor::new(0.2, 0.2, 0.2, 1.0));
         
+        // Lay everything out in unscaled units and let the canvas stretch it to the window
+        let (width, height) = ctx.gfx.drawable_size();
+        canvas.set_screen_coordinates(Rect::new(0.0, 0.0, width / self.ui_scale, height / self.ui_scale));
+        
         self.draw_board(ctx, &mut canvas)?;
         
         self.draw_pieces(&mut canvas);
         
         self.draw_status(&mut canvas)?;
         
+        self.draw_captured_pieces(&mut canvas);
+        
+        self.coords_button.draw(ctx, &mut canvas)?;
+        
+        if self.is_spectator && self.spectator_review.is_behind_live() {
+            self.live_button.draw(ctx, &mut canvas)?;
+        }
+        
+        if self.show_eval_bar && self.analysis_allowed() {
+            self.draw_eval_bar(ctx, &mut canvas)?;
+        }
+        
         // Draw network buttons in the right sidebar
         self.connect_button.draw(ctx, &mut canvas)?;
         
@@ -601,13 +1123,13 @@ impl ChessGui {
             self.spectate_button.draw(ctx, &mut canvas)?;
             
             // Draw connection status
-            let connection_status = if self.network_client.as_ref().map_or(false, |c| c.is_connected()) {
-                "Connected"
-            } else {
-                "Disconnected"
+            let connection_status = match &self.network_client {
+                Some(client) if client.is_connected() => "Connected".to_string(),
+                Some(client) => client.reconnect_state().to_string(),
+                None => "Disconnected".to_string(),
             };
             
-            let status_text = Text::new(connection_status);
+            let status_text = Text::new(connection_status.as_str());
             canvas.draw(
                 &status_text,
                 DrawParam::default()
@@ -647,7 +1169,7 @@ impl ChessGui {
                     canvas.draw(&list_bg, DrawParam::default());
                     
                     // Draw game list items
-                    for (_i, button) in self.join_game_buttons.iter().enumerate() {
+                    for button in self.join_game_buttons.iter() {
                         button.draw(ctx, &mut canvas)?;
                     }
                 } else {
@@ -665,8 +1187,6 @@ impl ChessGui {
                 }
             }
             
-            // Draw game action buttons when appropriate
-            if self.is_network_game && !self.is_spectator {
             // Draw join game buttons if showing game list
             if self.show_game_list {
                 for button in &self.join_game_buttons {
@@ -685,6 +1205,10 @@ impl ChessGui {
             }
         }
         
+        if self.is_move_history_visible() {
+            self.draw_move_history(ctx, &mut canvas)?;
+        }
+        
         // Draw promotion dialog if needed
         if self.game_state.promotion_pending.is_some() {
             self.draw_promotion_dialog(ctx, &mut canvas)?;
@@ -696,15 +1220,26 @@ impl ChessGui {
         }
         
         // Draw rematch offer dialog if needed
-        if self.rematch_offered && self.game_over {
+        if self.rematch_offered {
             self.draw_rematch_offer_dialog(ctx, &mut canvas)?;
         }
         
+        if self.show_network_log {
+            self.draw_network_log(ctx, &mut canvas)?;
+        }
+        
         // Draw player names in network games
         if self.is_network_game {
             self.draw_player_names(&mut canvas)?;
         }
         
+        self.draw_dragged_piece(&mut canvas);
+        
+        // Drawn last so it sits on top of everything else. No drag can start while it's up
+        if self.show_tutorial {
+            self.draw_tutorial(ctx, &mut canvas)?;
+        }
+        
         canvas.finish(ctx)?;
         
         self.needs_redraw = false;
@@ -723,10 +1258,16 @@ impl ChessGui {
                 let is_light = (rank + file) % 2 == 0;
                 let color = if is_light { LIGHT_SQUARE } else { DARK_SQUARE };
                 
+                // Captures get a ring around the target instead of a filled square
+                let is_capture_target = self.is_possible_move((rank, file)) &&
+                    self.selected_square.is_some_and(|from| self.game_state.is_capture(from, (rank, file)));
+                
                 let color = if Some((rank, file)) == self.selected_square {
                     SELECTED_SQUARE
-                } else if self.possible_moves.contains(&(rank, file)) {
+                } else if self.is_possible_move((rank, file)) && !is_capture_target {
                     POSSIBLE_MOVE
+                } else if self.is_last_move_square((rank, file)) {
+                    tint(color, LAST_MOVE_SQUARE)
                 } else {
                     color
                 };
@@ -748,10 +1289,22 @@ impl ChessGui {
                 )?;
                 canvas.draw(&mesh, DrawParam::default());
                 
+                if is_capture_target {
+                    let ring = graphics::Mesh::new_circle(
+                        ctx,
+                        graphics::DrawMode::stroke(4.0),
+                        Point2 { x: x + SQUARE_SIZE / 2.0, y: y + SQUARE_SIZE / 2.0 },
+                        SQUARE_SIZE / 2.0 - 3.0,
+                        0.5,
+                        POSSIBLE_MOVE,
+                    )?;
+                    canvas.draw(&ring, DrawParam::default());
+                }
+                
                 // Draw square coordinates when enabled
                 if self.show_square_coordinates {
                     let file_char = (b'a' + file as u8) as char;
-                    let rank_num = 8 - rank;
+                    let rank_num = BOARD_SIZE - rank;
                     let coord_text = Text::new(format!("{}{}", file_char, rank_num));
                     
                     // Position in the top-left corner of each square
@@ -779,10 +1332,39 @@ impl ChessGui {
         Ok(())
     }
     
+    // Helper to format remaining clock time as m:ss
+    fn format_clock(ms: u64) -> String {
+        let seconds = ms / 1000;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+    
+    // Helper to size piece images to the board squares
+    fn piece_scale(&self) -> Vector2<f32> {
+        let scale = self.assets.piece_scale(SQUARE_SIZE);
+        Vector2 { x: scale, y: scale }
+    }
+    
     fn draw_pieces(&self, canvas: &mut Canvas) {
+        // A spectator reviewing an earlier position sees that board, without the live move's animation
+        let animation = if self.is_reviewing() { None } else { self.move_animation.as_ref() };
+        
         for rank in 0..BOARD_SIZE {
             for file in 0..BOARD_SIZE {
-                if let Some(piece) = self.game_state.board[rank][file] {
+                // The animated piece is drawn separately while it slides between squares
+                if let Some(animation) = animation {
+                    if (rank, file) == animation.from || (rank, file) == animation.to {
+                        continue;
+                    }
+                }
+                
+                // So is a piece being dragged
+                if let Some(ref drag) = self.drag {
+                    if (rank, file) == drag.from && drag.has_moved() {
+                        continue;
+                    }
+                }
+                
+                if let Some(piece) = self.displayed_board()[rank][file] {
                     // Invert coordinates if playing as black
                     let (display_rank, display_file) = self.get_display_coordinates(rank, file);
                     
@@ -801,7 +1383,51 @@ impl ChessGui {
                         DrawParam::default()
                             .dest(dest)
                             .offset(Point2 { x: 0.5, y: 0.5 }) // Center the image
-                            .scale(Vector2 { x: 0.45, y: 0.45 }) // Scale to fit the square
+                            .scale(self.piece_scale()) // Scale to fit the square
+                    );
+                }
+            }
+        }
+        
+        if let Some(animation) = animation {
+            let progress = (animation.started.elapsed().as_secs_f32() / MOVE_ANIMATION_DURATION.as_secs_f32()).min(1.0);
+            
+            let (from_rank, from_file) = self.get_display_coordinates(animation.from.0, animation.from.1);
+            let (to_rank, to_file) = self.get_display_coordinates(animation.to.0, animation.to.1);
+            
+            let display_file = from_file as f32 + (to_file as f32 - from_file as f32) * progress;
+            let display_rank = from_rank as f32 + (to_rank as f32 - from_rank as f32) * progress;
+            
+            let dest = Point2 {
+                x: BOARD_OFFSET_X + display_file * SQUARE_SIZE + SQUARE_SIZE / 2.0,
+                y: BOARD_OFFSET_Y + display_rank * SQUARE_SIZE + SQUARE_SIZE / 2.0,
+            };
+            
+            self.assets.draw_piece(
+                canvas,
+                animation.piece.piece_type,
+                animation.piece.color,
+                DrawParam::default()
+                    .dest(dest)
+                    .offset(Point2 { x: 0.5, y: 0.5 })
+                    .scale(self.piece_scale())
+            );
+        }
+    }
+    
+    // The piece being dragged follows the pointer, over the sidebar and dialogs as well as the board
+    fn draw_dragged_piece(&self, canvas: &mut Canvas) {
+        if let Some(ref drag) = self.drag {
+            if let Some(piece) = self.game_state.board[drag.from.0][drag.from.1] {
+                if drag.has_moved() {
+                    self.assets.draw_piece(
+                        canvas,
+                        piece.piece_type,
+                        piece.color,
+                        DrawParam::default()
+                            .dest(drag.position)
+                            .offset(Point2 { x: 0.5, y: 0.5 })
+                            .scale(self.piece_scale())
                     );
                 }
             }
@@ -811,28 +1437,88 @@ impl ChessGui {
     fn draw_status(&self, canvas: &mut Canvas) -> GameResult<()> {
         let mut status_text = format!("Current turn: {:?}", self.game_state.current_turn);
         
-        if self.is_spectator {
+        if self.is_reviewing() {
+            status_text = format!("Spectating - reviewing position {} of {}",
+                                  self.spectator_review.review_index + 1, self.spectator_review.positions.len());
+        } else if self.is_spectator {
             status_text = format!("Spectating - Current turn: {:?}", self.game_state.current_turn);
+        } else if self.is_opponents_turn() {
+            status_text = format!("{}'s turn ({:?} to move)", self.opponent_label(), self.game_state.current_turn);
+        }
+        
+        if let Some(deadline) = self.opponent_rejoin_deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            status_text = format!("{} disconnected - waiting {}s", self.opponent_label(), remaining.as_secs());
         }
         
-        if self.game_state.is_in_check(self.game_state.current_turn) {
-            if self.game_state.is_checkmate() {
+        // One rules pass per redraw; checking each ending separately generated the moves twice
+        let phase = self.game_state.status();
+        match phase.terminal {
+            Some(TerminationReason::Checkmate) => {
                 status_text = format!("{:?} is in CHECKMATE!", self.game_state.current_turn);
-            } else {
+            }
+            Some(TerminationReason::Stalemate) => status_text = "STALEMATE!".to_string(),
+            Some(TerminationReason::ThreefoldRepetition) => status_text = "DRAW by threefold repetition!".to_string(),
+            Some(TerminationReason::FiftyMoveRule) => status_text = "DRAW by fifty-move rule!".to_string(),
+            Some(TerminationReason::InsufficientMaterial) => status_text = "DRAW by insufficient material!".to_string(),
+            _ if phase.in_check => {
                 status_text = format!("{:?} is in CHECK!", self.game_state.current_turn);
             }
-        } else if self.game_state.is_stalemate() {
-            status_text = "STALEMATE!".to_string();
-        } else if self.game_state.is_threefold_repetition() {
-            status_text = "DRAW by threefold repetition!".to_string();
-        } else if self.game_state.is_fifty_move_rule() {
-            status_text = "DRAW by fifty-move rule!".to_string();
-        } else if self.game_state.is_insufficient_material() {
-            status_text = "DRAW by insufficient material!".to_string();
+            _ => {}
+        }
+        
+        if phase.terminal.is_none() && self.forced_move.is_some() {
+            status_text = format!("{} - only one legal move", status_text);
+        }
+        
+        // The server's reported cause wins over what the local board can infer (e.g. a draw by agreement)
+        if self.game_over {
+            if let Some(termination) = self.termination {
+                status_text = if termination.is_draw() {
+                    format!("DRAW: {}", termination)
+                } else {
+                    format!("GAME OVER: {}", termination)
+                };
+            }
+        }
+        
+        // Name the mate when it's a textbook one, as a hint for learners
+        if phase.terminal == Some(TerminationReason::Checkmate) {
+            if let Some(pattern) = self.game_state.mate_pattern() {
+                status_text = format!("{} {}!", status_text, pattern);
+            }
+        }
+        
+        // Warn when the selected piece can't leave the line to its king
+        if let Some(selected) = self.selected_square {
+            if let Some(piece) = self.game_state.board[selected.0][selected.1] {
+                if self.game_state.pinned_pieces(piece.color).iter().any(|(pinned, _)| *pinned == selected) {
+                    status_text = format!("{} - that piece is pinned", status_text);
+                }
+                if self.is_opponents_turn() {
+                    status_text = format!("{} - previewing moves", status_text);
+                }
+            }
         }
         
         let status_display = Text::new(status_text);
         
+        // In a network game the line takes the color tag of whoever is to move
+        let mover_tag = if !self.is_network_game || self.is_spectator {
+            None
+        } else if self.is_opponents_turn() {
+            self.opponent_color_tag
+        } else {
+            self.player_color_tag
+        };
+        let status_color = match mover_tag {
+            Some(tag) => {
+                let (r, g, b) = tag.to_rgb();
+                GgezColor::new(r, g, b, 1.0)
+            }
+            None => GgezColor::WHITE,
+        };
+        
         // Position status text at the left side below the board
         canvas.draw(
             &status_display,
@@ -841,7 +1527,7 @@ impl ChessGui {
                     x: BOARD_OFFSET_X,
                     y: BOARD_OFFSET_Y + (BOARD_SIZE as f32) * SQUARE_SIZE + 40.0,
                 })
-                .color(GgezColor::WHITE)
+                .color(status_color)
         );
         
         let halfmove_text = Text::new(format!("Halfmove clock: {}", self.game_state.halfmove_clock));
@@ -857,23 +1543,123 @@ impl ChessGui {
                 .color(GgezColor::WHITE)
         );
         
-        // If in spectator mode, draw spectator indicator
-        if self.is_spectator {
-            let spectator_text = Text::new("SPECTATOR MODE");
-            canvas.draw(
-                &spectator_text,
-                DrawParam::default()
-                    .dest(Point2 {
-                        x: BOARD_OFFSET_X,
-                        y: BOARD_OFFSET_Y + (BOARD_SIZE as f32) * SQUARE_SIZE + 20.0,
-                    })
-                    .color(GgezColor::new(1.0, 0.7, 0.3, 1.0)) // Orange
-            );
+        let info_text = Text::new(format!(
+            "Move {}  Since capture {}  Material {}-{}",
+            self.game_state.total_moves(),
+            self.game_state.moves_since_capture(),
+            self.game_state.material_count(Color::White),
+            self.game_state.material_count(Color::Black)
+        ));
+        
+        // Position move number and material beside the halfmove clock
+        canvas.draw(
+            &info_text,
+            DrawParam::default()
+                .dest(Point2 {
+                    x: BOARD_OFFSET_X + 200.0,
+                    y: BOARD_OFFSET_Y + (BOARD_SIZE as f32) * SQUARE_SIZE + 60.0,
+                })
+                .color(GgezColor::WHITE)
+        );
+        
+        let repetition_text = Text::new(format!("Repetition: {}/3", self.game_state.repetition_count()));
+        
+        // Position repetition count under halfmove clock
+        canvas.draw(
+            &repetition_text,
+            DrawParam::default()
+                .dest(Point2 {
+                    x: BOARD_OFFSET_X,
+                    y: BOARD_OFFSET_Y + (BOARD_SIZE as f32) * SQUARE_SIZE + 80.0,
+                })
+                .color(GgezColor::WHITE)
+        );
+        
+        if let Some((white_ms, black_ms)) = self.clock_ms {
+            let clock_text = Text::new(format!(
+                "White {}  Black {}",
+                Self::format_clock(white_ms),
+                Self::format_clock(black_ms)
+            ));
+            
+            // Position clocks under repetition count
+            canvas.draw(
+                &clock_text,
+                DrawParam::default()
+                    .dest(Point2 {
+                        x: BOARD_OFFSET_X,
+                        y: BOARD_OFFSET_Y + (BOARD_SIZE as f32) * SQUARE_SIZE + 100.0,
+                    })
+                    .color(GgezColor::WHITE)
+            );
+        }
+        
+        // If in spectator mode, draw spectator indicator
+        if self.is_spectator {
+            let spectator_text = Text::new("SPECTATOR MODE");
+            canvas.draw(
+                &spectator_text,
+                DrawParam::default()
+                    .dest(Point2 {
+                        x: BOARD_OFFSET_X,
+                        y: BOARD_OFFSET_Y + (BOARD_SIZE as f32) * SQUARE_SIZE + 20.0,
+                    })
+                    .color(GgezColor::new(1.0, 0.7, 0.3, 1.0)) // Orange
+            );
         }
         
         Ok(())
     }
     
+    // One row per side under the clocks with the pieces it has taken, cheapest first, and the
+    // material lead of whichever side is ahead
+    fn draw_captured_pieces(&self, canvas: &mut Canvas) {
+        let scale = self.assets.piece_scale(CAPTURED_PIECE_SIZE);
+        let lead = self.game_state.material_count(Color::White) as i32 - self.game_state.material_count(Color::Black) as i32;
+        
+        for (row, color) in [Color::White, Color::Black].into_iter().enumerate() {
+            let y = BOARD_OFFSET_Y + (BOARD_SIZE as f32) * SQUARE_SIZE + 120.0 + row as f32 * (CAPTURED_PIECE_SIZE + 4.0);
+            canvas.draw(
+                &Text::new(format!("{:?}", color)),
+                DrawParam::default()
+                    .dest(Point2 { x: BOARD_OFFSET_X, y: y + CAPTURED_PIECE_SIZE / 2.0 })
+                    .offset(Point2 { x: 0.0, y: 0.5 })
+                    .color(GgezColor::WHITE)
+            );
+            
+            let mut taken: Vec<PieceType> = self.move_history.captured.iter()
+                .filter(|piece| piece.color != color)
+                .map(|piece| piece.piece_type)
+                .collect();
+            taken.sort_by_key(|piece_type| *piece_type as u8);
+            
+            let mut x = BOARD_OFFSET_X + 55.0;
+            for piece_type in taken {
+                self.assets.draw_piece(
+                    canvas,
+                    piece_type,
+                    color.opposite(),
+                    DrawParam::default()
+                        .dest(Point2 { x: x + CAPTURED_PIECE_SIZE / 2.0, y: y + CAPTURED_PIECE_SIZE / 2.0 })
+                        .offset(Point2 { x: 0.5, y: 0.5 })
+                        .scale(Vector2 { x: scale, y: scale })
+                );
+                x += CAPTURED_PIECE_SIZE * 0.8; // Overlap a little, a long row still fits beside the board
+            }
+            
+            let side_lead = if color == Color::White { lead } else { -lead };
+            if side_lead > 0 {
+                canvas.draw(
+                    &Text::new(format!("+{}", side_lead)),
+                    DrawParam::default()
+                        .dest(Point2 { x: x + 8.0, y: y + CAPTURED_PIECE_SIZE / 2.0 })
+                        .offset(Point2 { x: 0.0, y: 0.5 })
+                        .color(GgezColor::WHITE)
+                );
+            }
+        }
+    }
+    
     fn draw_promotion_dialog(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
         if let Some(ref promotion) = self.game_state.promotion_pending {
             let (rank, file) = promotion.position;
@@ -886,7 +1672,7 @@ impl ChessGui {
             let square_y = BOARD_OFFSET_Y + (display_rank as f32) * SQUARE_SIZE;
             
             let dialog_width = SQUARE_SIZE;
-            let dialog_height = SQUARE_SIZE * 4.0; // Space for 4 pieces
+            let dialog_height = SQUARE_SIZE * self.game_state.promotion_options.len() as f32; // One square per choice
             
             // Adjust dialog position based on perspective
             let dialog_y = if self.is_inverted_board() {
@@ -912,9 +1698,7 @@ impl ChessGui {
             )?;
             canvas.draw(&dialog_mesh, DrawParam::default());
             
-            let promotion_pieces = [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight];
-            
-            for (i, &piece_type) in promotion_pieces.iter().enumerate() {
+            for (i, &piece_type) in self.game_state.promotion_options.iter().enumerate() {
                 let piece_y = dialog_y + (i as f32) * SQUARE_SIZE;
                 let dest = Point2 { 
                     x: square_x + SQUARE_SIZE / 2.0,
@@ -928,7 +1712,7 @@ impl ChessGui {
                     DrawParam::default()
                         .dest(dest)
                         .offset(Point2 { x: 0.5, y: 0.5 })
-                        .scale(Vector2 { x: 0.45, y: 0.45 })
+                        .scale(self.piece_scale())
                 );
             }
         }
@@ -983,7 +1767,7 @@ impl ChessGui {
             DIALOG_BUTTON_HEIGHT
         );
         
-        let accept_color = ACCEPT_BUTTON_BG; // Could add hover effect here
+        let accept_color = ACCEPT_BUTTON_BG;
         
         let accept_mesh = graphics::Mesh::new_rectangle(
             ctx,
@@ -1021,7 +1805,7 @@ impl ChessGui {
             DIALOG_BUTTON_HEIGHT
         );
         
-        let decline_color = DECLINE_BUTTON_BG; // Could add hover effect here
+        let decline_color = DECLINE_BUTTON_BG;
         
         let decline_mesh = graphics::Mesh::new_rectangle(
             ctx,
@@ -1054,6 +1838,39 @@ impl ChessGui {
         Ok(())
     }
 
+    fn draw_tutorial(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        let tutorial_rect = Rect::new(TUTORIAL_X, TUTORIAL_Y, TUTORIAL_WIDTH, TUTORIAL_HEIGHT);
+        let background = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            tutorial_rect,
+            DIALOG_BG,
+        )?;
+        canvas.draw(&background, DrawParam::default());
+        
+        let border = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::stroke(2.0),
+            tutorial_rect,
+            GgezColor::WHITE,
+        )?;
+        canvas.draw(&border, DrawParam::default());
+        
+        for (i, line) in TUTORIAL_LINES.iter().enumerate() {
+            canvas.draw(
+                &Text::new(*line),
+                DrawParam::default()
+                    .dest(Point2 {
+                        x: TUTORIAL_X + 20.0,
+                        y: TUTORIAL_Y + 20.0 + i as f32 * 30.0,
+                    })
+                    .color(GgezColor::WHITE)
+            );
+        }
+        
+        self.tutorial_button.draw(ctx, canvas)
+    }
+    
     fn draw_rematch_offer_dialog(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
         // Create a semi-transparent background for the dialog
         let window_width = ctx.gfx.size().0;
@@ -1177,31 +1994,50 @@ impl ChessGui {
             return Ok(None);
         }
         
+        // Mouse events arrive in window pixels, layout is in unscaled units
+        let (x, y) = (x / self.ui_scale, y / self.ui_scale);
         let point = Point2 { x, y };
         
-        // Check for dialog button clicks first
-        if self.draw_offered && !self.game_over {
-            if self.handle_dialog_click(x, y, true)? {
-                return Ok(None);
+        // The tutorial takes every click until it's dismissed
+        if self.show_tutorial {
+            if self.tutorial_button.contains(point) {
+                self.dismiss_tutorial();
             }
+            return Ok(None);
         }
         
-        if self.rematch_offered && self.game_over {
-            if self.handle_dialog_click(x, y, false)? {
-                return Ok(None);
-            }
+        // Check for dialog button clicks first
+        if self.draw_offered && !self.game_over && self.handle_dialog_click(x, y, true)? {
+            return Ok(None);
+        }
+        
+        if self.rematch_offered && self.handle_dialog_click(x, y, false)? {
+            return Ok(None);
+        }
+        
+        if self.coords_button.contains(point) {
+            self.toggle_square_coordinates();
+            return Ok(None);
+        }
+        
+        if self.is_spectator && self.spectator_review.is_behind_live() && self.live_button.contains(point) {
+            self.jump_to_live();
+            return Ok(None);
+        }
+        
+        if self.is_move_history_visible() && self.flip_board_button.contains(point) {
+            self.toggle_board_flip();
+            return Ok(None);
         }
         
         // Check if spectator panel is clicked
         if self.show_spectator_panel {
             if self.spectator_panel.contains_send_button(point) {
                 // Send chat message
-                if !self.spectator_panel.get_input().is_empty() {
+                if let Some(message) = self.spectator_panel.take_message() {
                     if let Some(client) = &mut self.network_client {
-                        let message = self.spectator_panel.get_input().to_string();
                         client.send_chat_message(message, self.player_name.clone())?;
                     }
-                    self.spectator_panel.clear_input();
                     self.needs_redraw = true;
                 }
                 return Ok(None);
@@ -1214,6 +2050,11 @@ impl ChessGui {
             }
         }
         
+        // Moves in the history can't be picked yet, but a click there shouldn't reach anything underneath
+        if self.is_move_history_visible() && self.move_history_rect().contains(point) {
+            return Ok(None);
+        }
+        
         // Deactivate input field if clicking outside
         if self.input_active {
             self.input_active = false;
@@ -1238,6 +2079,13 @@ impl ChessGui {
                 }
                 self.needs_redraw = true;
                 return Ok(None);
+            } else if let Some(client) = &mut self.network_client {
+                // Clicking connect after the client gave up starts a fresh round of attempts
+                if client.reconnect_state() == ReconnectState::Exhausted {
+                    client.reset_reconnect();
+                    self.needs_redraw = true;
+                    return Ok(None);
+                }
             }
         }
         
@@ -1276,7 +2124,7 @@ impl ChessGui {
                     return Ok(None);
                 }
             } else {
-                if self.rematch_button.contains(point) {
+                if self.rematch_button.contains(point) && self.rematch_window_open() {
                     if let Err(e) = self.request_rematch() {
                         println!("Error requesting rematch: {}", e);
                     }
@@ -1314,7 +2162,6 @@ impl ChessGui {
             for (i, button) in self.join_game_buttons.iter().enumerate() {
                 if button.contains(point) && i < self.available_games.len() {
                     let game_id = self.available_games[i].game_id.clone();
-                    let game_status = self.available_games[i].status.clone();
                     
                     if self.is_spectator || button.text.starts_with("Spectate") {
                         // Spectate the game
@@ -1344,42 +2191,37 @@ impl ChessGui {
             return Ok(None);
         }
 
-        if self.is_network_game {
-            if let Some(player_color) = self.player_color {
-                if player_color != self.game_state.current_turn {
-                    return Ok(None);
+        // While the opponent thinks, pieces can still be picked up to look at their moves
+        let previewing = self.is_opponents_turn();
+
+        // Clicking off the board puts the selected piece down without touching anything else
+        let (rank, file) = match self.get_square_from_coords(x, y) {
+            Some(square) => square,
+            None => {
+                if self.selected_square.take().is_some() {
+                    self.possible_moves = 0;
+                    self.needs_redraw = true;
                 }
+                return Ok(None);
             }
-        }
-
-        let (rank, file) = self.get_square_from_coords(x, y);
+        };
         
+        let mut reselected = false;
         if let Some(selected) = self.selected_square {
-            if self.possible_moves.contains(&(rank, file)) {
-                let from = (selected.0 as u8, selected.1 as u8);
-                let to = (rank as u8, file as u8);
-                
-                if self.game_state.make_move(selected, (rank, file)) {
-                    self.selected_square = None;
-                    self.possible_moves.clear();
-                    self.needs_redraw = true;
-                    
-                    if self.is_network_game {
-                        if self.game_state.promotion_pending.is_some() {
-                            // Store the move information for when promotion is selected
-                            self.pending_promotion_move = Some(MoveInfo { from, to, promotion: None });
-                            return Ok(None);
-                        }
-                        
-                        // This is a network game, send the move
-                        self.send_move(from, to, None)?;
-                    }
-                    
-                    return Ok(Some(MoveInfo { from, to, promotion: None }));
-                }
+            // Pure drag mode only moves on release
+            if self.input_mode.moves_on_click() && !previewing && self.is_possible_move((rank, file)) {
+                return self.complete_move(selected, (rank, file));
             }
             self.selected_square = None;
-            self.possible_moves.clear();
+            self.possible_moves = 0;
+            
+            // Clicking the selected piece again puts it down. Where it can be dragged, that waits
+            // for the release, since the press may be the start of a drag
+            reselected = selected == (rank, file);
+            if reselected && !self.input_mode.tracks_drags() {
+                self.needs_redraw = true;
+                return Ok(None);
+            }
         }
 
         if let Some(piece) = self.game_state.board[rank][file] {
@@ -1391,13 +2233,17 @@ impl ChessGui {
                 }
             }
             
-            if piece.color == self.game_state.current_turn {
+            if previewing {
+                self.selected_square = Some((rank, file));
+                self.possible_moves = self.cached_destinations((rank, file));
+            } else if piece.color == self.game_state.current_turn {
                 self.selected_square = Some((rank, file));
-                self.possible_moves = self.game_state.get_all_legal_moves()
-                    .iter()
-                    .filter(|&&(from, _)| from == (rank, file))
-                    .map(|&(_, to)| to)
-                    .collect();
+                self.possible_moves = self.cached_destinations((rank, file));
+                
+                // In click-click mode a drag is just a click-select, so no drag is tracked
+                if self.input_mode.tracks_drags() {
+                    self.drag = Some(DragState { from: (rank, file), origin: point, position: point, reselected });
+                }
             }
         }
 
@@ -1405,15 +2251,168 @@ impl ChessGui {
         Ok(None)
     }
     
+    pub fn handle_mouse_up(&mut self, button: MouseButton, x: f32, y: f32) -> GameResult<Option<MoveInfo>> {
+        if button != MouseButton::Left {
+            return Ok(None);
+        }
+        
+        let mut drag = match self.drag.take() {
+            Some(drag) => drag,
+            None => return Ok(None),
+        };
+        
+        let (x, y) = (x / self.ui_scale, y / self.ui_scale);
+        drag.position = Point2 { x, y };
+        self.needs_redraw = true;
+        
+        // A press that barely moved, or was let go without leaving its square, was a click. The selection
+        // stays for a click-click move unless the click was on the piece that was already selected
+        let target = self.get_square_from_coords(x, y);
+        if self.input_mode.release_is_click(drag.has_moved(), target == Some(drag.from)) {
+            if drag.reselected {
+                self.selected_square = None;
+                self.possible_moves = 0;
+            }
+            return Ok(None);
+        }
+        
+        if let Some(target) = target {
+            if self.selected_square == Some(drag.from) && self.is_possible_move(target) {
+                return self.complete_move(drag.from, target);
+            }
+        }
+        
+        // Dropped somewhere illegal, the piece goes back
+        self.selected_square = None;
+        self.possible_moves = 0;
+        Ok(None)
+    }
+    
+    // Play the selected piece's move, shared by click and drag input
+    fn complete_move(&mut self, from_square: (usize, usize), to_square: (usize, usize)) -> GameResult<Option<MoveInfo>> {
+        let from = (from_square.0 as u8, from_square.1 as u8);
+        let to = (to_square.0 as u8, to_square.1 as u8);
+        
+        if self.game_state.make_move(from_square, to_square).is_some() {
+            self.last_move = Some((from_square, to_square));
+            self.selected_square = None;
+            self.possible_moves = 0;
+            self.needs_redraw = true;
+            
+            // Skip the dialog and promote straight to a queen
+            if auto_promote(&mut self.game_state, self.auto_queen, self.force_promotion_dialog) {
+                if self.is_network_game {
+                    self.send_move(from, to, Some('Q'))?;
+                }
+                
+                self.check_game_end();
+                
+                return Ok(Some(MoveInfo { from, to, promotion: Some('Q') }));
+            }
+            
+            if self.is_network_game {
+                if self.game_state.promotion_pending.is_some() {
+                    // Store the move information for when promotion is selected
+                    self.pending_promotion_move = Some(MoveInfo { from, to, promotion: None });
+                    return Ok(None);
+                }
+                
+                // This is a network game, send the move
+                self.send_move(from, to, None)?;
+            }
+            
+            return Ok(Some(MoveInfo { from, to, promotion: None }));
+        }
+        
+        self.selected_square = None;
+        self.possible_moves = 0;
+        self.needs_redraw = true;
+        Ok(None)
+    }
+    
     pub fn handle_key_press(&mut self, key: char) -> GameResult<()> {
         if self.input_active {
             self.spectator_panel.handle_key_input(key);
             self.needs_redraw = true;
+        } else if key == 'c' || key == 'C' {
+            // Hotkeys only apply while the chat box isn't taking the input
+            self.toggle_square_coordinates();
+        } else if key == 'e' || key == 'E' {
+            self.toggle_eval_bar();
+        } else if key == 'u' || key == 'U' {
+            self.request_takeback()?;
+        } else if key == '\u{1b}' {
+            self.handle_escape();
         }
         
         Ok(())
     }
     
+    pub fn toggle_square_coordinates(&mut self) {
+        self.show_square_coordinates = !self.show_square_coordinates;
+        self.needs_redraw = true;
+    }
+    
+    pub fn shows_square_coordinates(&self) -> bool {
+        self.show_square_coordinates
+    }
+    
+    // Escape leaves the chat box or drops the selected piece, and never touches an open dialog.
+    // Returns whether it should quit, which is only when none of that applied
+    pub fn handle_escape(&mut self) -> bool {
+        let dialog_open = self.game_state.promotion_pending.is_some() || self.draw_offered || self.rematch_offered;
+        match EscapeAction::decide(self.input_active, dialog_open, self.selected_square.is_some()) {
+            EscapeAction::LeaveChatInput => {
+                self.input_active = false;
+                self.needs_redraw = true;
+            }
+            EscapeAction::KeepDialog => {}
+            EscapeAction::DropSelection => {
+                self.selected_square = None;
+                self.possible_moves = 0;
+                self.drag = None;
+                self.needs_redraw = true;
+            }
+            EscapeAction::Quit => return true,
+        }
+        false
+    }
+    
+    // A local view change only, nothing is sent to the server. It works on top of the automatic flip
+    // for Black, so a Black player can still look from White's side
+    pub fn toggle_board_flip(&mut self) {
+        self.board_flipped = !self.board_flipped;
+        self.needs_redraw = true;
+    }
+    
+    pub fn toggle_eval_bar(&mut self) {
+        self.show_eval_bar = !self.show_eval_bar;
+        self.needs_redraw = true;
+    }
+    
+    // Helper every analysis overlay checks, so nothing assists a player in a live game.
+    // Spectators and local games get them all
+    pub fn analysis_allowed(&self) -> bool {
+        !self.is_network_game || self.is_spectator
+    }
+    
+    // Helper for the last-move highlight. It only shows what both sides already saw happen, so unlike
+    // the analysis overlays it's on for players in a network game too
+    fn is_last_move_square(&self, square: (usize, usize)) -> bool {
+        !self.is_reviewing() && self.last_move.is_some_and(|(from, to)| from == square || to == square)
+    }
+    
+    // Helper to re-run the evaluation once the position has changed
+    fn refresh_eval(&mut self) {
+        if self.game_state.promotion_pending.is_some() {
+            return;
+        }
+        
+        if self.eval_cache.refresh(&self.game_state, EVAL_BAR_DEPTH) {
+            self.needs_redraw = true;
+        }
+    }
+    
     fn handle_dialog_click(&mut self, x: f32, y: f32, is_draw_dialog: bool) -> GameResult<bool> {
         // Get window dimensions from context size
         let window_width = 780.0; // Default window width from main.rs
@@ -1490,10 +2489,19 @@ impl ChessGui {
     }
     
     pub fn handle_mouse_move(&mut self, x: f32, y: f32) -> GameResult<()> {
+        let (x, y) = (x / self.ui_scale, y / self.ui_scale);
         let point = Point2 { x, y };
         let mut needs_redraw = false;
         
+        if let Some(drag) = &mut self.drag {
+            drag.position = point;
+            needs_redraw = true;
+        }
+        
         // Reset all button hover states
+        self.coords_button.set_hover(false);
+        self.live_button.set_hover(false);
+        self.tutorial_button.set_hover(false);
         self.connect_button.set_hover(false);
         self.create_game_button.set_hover(false);
         self.refresh_games_button.set_hover(false);
@@ -1502,6 +2510,8 @@ impl ChessGui {
         self.resign_button.set_hover(false);
         self.rematch_button.set_hover(false);
         
+        self.flip_board_button.set_hover(false);
+        
         if self.show_spectator_panel {
             self.spectator_panel.send_button.set_hover(false);
         }
@@ -1511,7 +2521,21 @@ impl ChessGui {
         }
         
         // Set hover state for the button under the mouse
-        if self.connect_button.contains(point) {
+        if self.show_tutorial {
+            if self.tutorial_button.contains(point) {
+                self.tutorial_button.set_hover(true);
+            }
+            needs_redraw = true;
+        } else if self.coords_button.contains(point) {
+            self.coords_button.set_hover(true);
+            needs_redraw = true;
+        } else if self.is_spectator && self.live_button.contains(point) {
+            self.live_button.set_hover(true);
+            needs_redraw = true;
+        } else if self.is_move_history_visible() && self.flip_board_button.contains(point) {
+            self.flip_board_button.set_hover(true);
+            needs_redraw = true;
+        } else if self.connect_button.contains(point) {
             self.connect_button.set_hover(true);
             needs_redraw = true;
         } else if self.network_client.is_some() {
@@ -1562,9 +2586,9 @@ impl ChessGui {
     }
     
     pub fn update(&mut self) -> GameResult<()> {
-        // Send heartbeat if needed (every 30 seconds)
+        // Send heartbeat if needed
         if let Some(client) = &mut self.network_client {
-            if client.is_connected() && self.last_heartbeat.elapsed() > Duration::from_secs(30) {
+            if client.is_connected() && self.last_heartbeat.elapsed() > self.heartbeat_interval {
                 if let Err(e) = client.send_message(NetworkMessage::Heartbeat) {
                     println!("Error sending heartbeat: {}", e);
                 } else {
@@ -1577,9 +2601,109 @@ impl ChessGui {
             self.handle_network_messages()?;
         }
         
+        // The computer thinks on a worker thread and its move is picked up here once it's ready
+        if let Some(depth) = self.computer_depth {
+            self.poll_computer_move();
+            if self.computer_search.is_none() && self.is_opponents_turn() && !self.game_state.is_game_over() &&
+               self.game_state.promotion_pending.is_none() {
+                self.computer_search = Some(ai::BackgroundSearch::start(&self.game_state, depth));
+            }
+        }
+        
+        // The history panel drops below the spectator panel while that's open, taking the button along
+        let history_top = self.move_history_rect().y;
+        self.flip_board_button.rect.y = history_top + 3.0;
+        
+        // Network games take their history from the server instead, see update_move_history
+        if !self.is_network_game && self.move_history.sync(&self.game_state) {
+            self.needs_redraw = true;
+        }
+        
+        // The evaluation is only for display, so there's no point searching while nobody can see it
+        if self.show_eval_bar && self.analysis_allowed() && !self.paused_rendering {
+            self.refresh_eval();
+        }
+        
+        // Network boards arrive without their hash, so the move cache can't be trusted there
+        self.forced_move = if !self.is_network_game && !self.game_over {
+            self.game_state.forced_move()
+        } else {
+            None
+        };
+        
+        // The server gives up on a rematch after REMATCH_WINDOW, so the offer and the button go with it
+        if let Some(deadline) = self.rematch_deadline {
+            if Instant::now() >= deadline && self.rematch_button.enabled {
+                if self.rematch_offered {
+                    self.log_network("The rematch offer has expired".to_string());
+                }
+                self.rematch_offered = false;
+                self.rematch_button.set_enabled(false);
+                self.needs_redraw = true;
+            }
+        }
+        
+        // The status line counts down while a disconnected opponent may still come back
+        if self.opponent_rejoin_deadline.is_some() {
+            self.needs_redraw = true;
+        }
+        
+        // Keep redrawing while a move animation is in progress
+        if let Some(ref animation) = self.move_animation {
+            if animation.started.elapsed() >= MOVE_ANIMATION_DURATION {
+                self.move_animation = None;
+            }
+            self.needs_redraw = true;
+        }
+        
         Ok(())
     }
     
+    fn poll_computer_move(&mut self) {
+        let best = match self.computer_search.as_ref().and_then(|search| search.try_result()) {
+            Some(best) => best,
+            None => return,
+        };
+        
+        // The answer is dropped if the position changed while the computer was thinking
+        let search = self.computer_search.take();
+        let (from, to) = match best {
+            Some(best) if search.is_some_and(|search| search.is_for(&self.game_state)) => best,
+            _ => return,
+        };
+        
+        let piece = self.game_state.board[from.0][from.1];
+        if self.game_state.make_move(from, to).is_some() {
+            if self.game_state.promotion_pending.is_some() {
+                if let Some(piece_type) = ai::promotion_piece(&self.game_state) {
+                    self.game_state.promote_pawn(piece_type);
+                }
+            }
+            if let Some(piece) = piece {
+                self.move_animation = Some(MoveAnimation { piece, from, to, started: Instant::now() });
+            }
+            
+            // A piece picked up to preview its moves goes back, its destinations are stale now
+            self.last_move = Some((from, to));
+            self.selected_square = None;
+            self.possible_moves = 0;
+            self.needs_redraw = true;
+            self.check_game_end();
+        }
+    }
+    
+    // Counts from the first sign the game is over; later GameEnd or offers don't extend it
+    fn start_rematch_window(&mut self) {
+        if self.rematch_deadline.is_none() {
+            self.rematch_deadline = Some(Instant::now() + REMATCH_WINDOW);
+            self.rematch_button.set_enabled(true);
+        }
+    }
+    
+    fn rematch_window_open(&self) -> bool {
+        self.rematch_deadline.is_some_and(|deadline| Instant::now() < deadline)
+    }
+    
     fn check_game_end(&mut self) {
         if self.game_state.is_checkmate() || 
            self.game_state.is_stalemate() || 
@@ -1600,7 +2724,7 @@ impl ChessGui {
             let square_y = BOARD_OFFSET_Y + (display_rank as f32) * SQUARE_SIZE;
             
             let dialog_width = SQUARE_SIZE;
-            let dialog_height = SQUARE_SIZE * 4.0;
+            let dialog_height = SQUARE_SIZE * self.game_state.promotion_options.len() as f32;
             
             // Adjust dialog position based on perspective
             let dialog_y = if self.is_inverted_board() {
@@ -1623,30 +2747,14 @@ impl ChessGui {
                 let relative_y = y - dialog_y;
                 let piece_index = (relative_y / SQUARE_SIZE) as usize;
                 
-                if piece_index < 4 {
-                    let promotion_pieces = [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight];
-                    let selected_piece = promotion_pieces[piece_index];
-                    
+                if let Some(&selected_piece) = self.game_state.promotion_options.get(piece_index) {
                     // Apply the promotion locally
                     self.game_state.promote_pawn(selected_piece);
 
                     // If in a network game, send the promotion choice to the server
                     if self.is_network_game {
                         // Convert selection to the character representation for the network message
-                        let promotion_char = match selected_piece {
-                            PieceType::Queen => 'Q',
-                            PieceType::Rook => 'R',
-                            PieceType::Bishop => 'B',
-                            PieceType::Knight => 'N',
-                            _ => panic!("Invalid promotion piece"),
-                        };
-                        
-                        if let Some(client) = &mut self.network_client {
-                            // The from/to positions were already sent, just need to send the promotion choice
-                            if let Err(e) = client.send_move((0, 0), (0, 0), Some(promotion_char)) {
-                                println!("Error sending promotion choice: {}", e);
-                            }
-                        }
+                        let promotion_char = Piece::new(selected_piece, Color::White).to_fen_char();
                         
                         // Use the stored move information from when the promotion was triggered
                         if let Some(mut move_info) = self.pending_promotion_move.take() {
@@ -1667,60 +2775,101 @@ impl ChessGui {
         Ok(())
     }
 
-    fn get_square_from_coords(&self, x: f32, y: f32) -> (usize, usize) {
-        // Calculate the display coordinates from the screen position
-        let display_file = ((x - BOARD_OFFSET_X) / SQUARE_SIZE) as isize;
-        let display_rank = ((y - BOARD_OFFSET_Y) / SQUARE_SIZE) as isize;
-        
-        // Check if the coordinates are within the board bounds
-        if display_file < 0 || display_file >= BOARD_SIZE as isize || 
-           display_rank < 0 || display_rank >= BOARD_SIZE as isize {
-            // If clicked outside the board, return a safe default position
-            return (0, 0);
+    // Helper method to get a square's legal destinations, reusing the last result while the position is
+    // unchanged. A piece of the side not to move is previewed as if it were its turn
+    fn cached_destinations(&mut self, square: (usize, usize)) -> u64 {
+        let position_hash = self.game_state.zobrist_hash();
+        if let Some(cache) = &self.selection_cache {
+            if cache.square == square && cache.position_hash == position_hash {
+                return cache.destinations;
+            }
         }
         
-        // Convert display coordinates to internal coordinates
-        self.get_internal_coordinates(display_rank as usize, display_file as usize)
+        let to_move = matches!(self.game_state.board[square.0][square.1],
+                               Some(piece) if piece.color == self.game_state.current_turn);
+        let destinations = if to_move {
+            self.game_state.legal_destinations_bitboard(square)
+        } else {
+            self.game_state.get_legal_moves_from(square)
+                .into_iter()
+                .fold(0, |bits, to| bits | square_bit(to))
+        };
+        
+        self.selection_cache = Some(SelectionCache { square, destinations, position_hash });
+        self.destination_lookups += 1;
+        destinations
+    }
+    
+    // How often a selection needed its destinations worked out rather than taken from the cache
+    pub fn destination_lookups(&self) -> usize {
+        self.destination_lookups
+    }
+    
+    // The picked-up piece and its highlighted destinations as a bitboard, see square_bit
+    pub fn selection(&self) -> Option<((usize, usize), u64)> {
+        self.selected_square.map(|square| (square, self.possible_moves))
+    }
+    
+    // The position as this client last saw it, moves played locally included
+    pub fn game_state(&self) -> &GameState {
+        &self.game_state
+    }
+    
+    // Whether this client thinks the game is over, and why, as the status line shows it
+    pub fn ending(&self) -> (bool, Option<TerminationReason>) {
+        (self.game_over, self.termination)
+    }
+    
+    fn is_possible_move(&self, square: (usize, usize)) -> bool {
+        self.possible_moves & square_bit(square) != 0
+    }
+
+    // None for points off the board, so a click just past an edge never lands on a corner square
+    fn get_square_from_coords(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        board_square_at(x, y, self.view_color())
+    }
+    
+    // Helper to tell whether this client is a player waiting on the other side's move
+    fn is_opponents_turn(&self) -> bool {
+        ((self.is_network_game && !self.is_spectator) || self.computer_depth.is_some()) &&
+            matches!(self.player_color, Some(color) if color != self.game_state.current_turn)
+    }
+    
+    // The opponent's name from GameStart, for the status line
+    fn opponent_label(&self) -> String {
+        if self.opponent_name.is_empty() {
+            "Opponent".to_string()
+        } else {
+            truncate_text(&self.opponent_name, MAX_TEXT_LENGTH)
+        }
     }
     
     // Helper method to check if board should be inverted
+    // The manual flip toggles whatever orientation the player color implies
     fn is_inverted_board(&self) -> bool {
-        matches!(self.player_color, Some(Color::Black))
+        matches!(self.player_color, Some(Color::Black)) != self.board_flipped
+    }
+    
+    // The color whose side of the board is drawn at the bottom
+    fn view_color(&self) -> Color {
+        if self.is_inverted_board() { Color::Black } else { Color::White }
     }
     
     // Convert internal coordinates to display coordinates based on perspective
     fn get_display_coordinates(&self, rank: usize, file: usize) -> (usize, usize) {
-        if self.is_inverted_board() {
-            // For black perspective: flip both rank and file
-            (7 - rank, 7 - file) 
-        } else {
-            // For white perspective: use coordinates as-is
-            (rank, file)
-        }
+        perspective_square((rank, file), self.view_color())
     }
     
-    // Convert display coordinates to internal coordinates based on perspective
-    fn get_internal_coordinates(&self, display_rank: usize, display_file: usize) -> (usize, usize) {
-        if self.is_inverted_board() {
-            // For black perspective: flip both rank and file
-            // Ensure the result stays in bounds (0-7)
-            let rank = if display_rank < BOARD_SIZE { 7 - display_rank } else { 0 };
-            let file = if display_file < BOARD_SIZE { 7 - display_file } else { 0 };
-            (rank, file)
-        } else {
-            // For white perspective: use coordinates as-is
-            // Ensure the result stays in bounds (0-7)
-            let rank = if display_rank < BOARD_SIZE { display_rank } else { 0 };
-            let file = if display_file < BOARD_SIZE { display_file } else { 0 };
-            (rank, file)
-        }
-    }
 
     // New method to initialize networking
     pub fn init_network(&mut self, server_address: &str, player_name: String) -> GameResult<()> {
         self.player_name = player_name;
         self.network_client = match ChessClient::new(server_address) {
-            Ok(client) => Some(client),
+            Ok(mut client) => {
+                client.set_heartbeat_interval(self.heartbeat_interval);
+                client.set_recorder(self.protocol_recorder.clone());
+                Some(client)
+            }
             Err(e) => {
                 println!("Failed to connect to server: {}", e);
                 return Err(ggez::GameError::CustomError(format!("Network error: {}", e)));
@@ -1735,7 +2884,11 @@ impl ChessGui {
         if let Some(client) = &mut self.network_client {
             // Create a new game
             let create_game = NetworkMessage::CreateGame { 
-                player_name: self.player_name.clone() 
+                player_name: self.player_name.clone(),
+                takeover_token: self.takeover_token.clone(),
+                time_mode: self.time_mode,
+                player_color_tag: self.player_color_tag,
+                time_control: self.requested_time_control,
             };
             client.send_message(create_game)?;
             println!("Waiting for another player to join...");
@@ -1748,7 +2901,8 @@ impl ChessGui {
             // Join existing game
             let join_game = NetworkMessage::JoinGame { 
                 game_id: game_id.clone(),
-                player_name: self.player_name.clone() 
+                player_name: self.player_name.clone(),
+                player_color_tag: self.player_color_tag,
             };
             client.send_message(join_game)?;
             println!("Joining game {}...", game_id);
@@ -1756,12 +2910,74 @@ impl ChessGui {
         Ok(())
     }
     
+    // Sit in for a player who dropped out of a game created with a take-over token
+    pub fn take_over_slot(&mut self, game_id: String, color: Color, token: String) -> GameResult<()> {
+        if let Some(client) = &mut self.network_client {
+            client.take_over_slot(game_id.clone(), color, token, self.player_name.clone())?;
+            println!("Taking over {:?} in game {}...", color, game_id);
+        }
+        Ok(())
+    }
+    
+    // Record the connection's messages, set before init_network
+    pub fn set_protocol_recorder(&mut self, recorder: Option<ProtocolRecorder>) {
+        self.protocol_recorder = recorder;
+    }
+    
+    // Settings from earlier launches. The tutorial is shown until it has been dismissed once
+    pub fn load_config(&mut self, path: PathBuf) {
+        self.config = ClientConfig::load(&path);
+        self.show_tutorial = self.config.should_show_tutorial();
+        self.set_input_mode(self.config.input_mode);
+        self.config_path = Some(path);
+        self.needs_redraw = true;
+    }
+    
+    // Whether the first-launch hints are covering the window
+    pub fn shows_tutorial(&self) -> bool {
+        self.show_tutorial
+    }
+    
+    fn dismiss_tutorial(&mut self) {
+        self.show_tutorial = false;
+        self.config.tutorial_seen = true;
+        self.save_config();
+        self.needs_redraw = true;
+    }
+    
+    fn save_config(&self) {
+        if let Some(path) = &self.config_path {
+            if let Err(e) = self.config.save(path) {
+                println!("Error saving config to {}: {}", path.display(), e);
+            }
+        }
+    }
+    
+    // Games this client creates can be taken over by anyone given the token
+    pub fn set_takeover_token(&mut self, token: Option<String>) {
+        self.takeover_token = token;
+    }
+    
+    pub fn set_time_mode(&mut self, time_mode: TimeMode) {
+        self.time_mode = time_mode;
+    }
+    
+    pub fn set_time_control(&mut self, time_control: Option<TimeControl>) {
+        self.requested_time_control = time_control;
+    }
+    
+    // Shown on this player's chat messages and on their opponent's status line
+    pub fn set_player_color_tag(&mut self, player_color_tag: Option<PlayerColorTag>) {
+        self.player_color_tag = player_color_tag;
+    }
+    
     pub fn spectate_game(&mut self, game_id: String) -> GameResult<()> {
         if let Some(client) = &mut self.network_client {
             // Spectate existing game
             let spectate_game = NetworkMessage::SpectateGame { 
                 game_id: game_id.clone(),
-                spectator_name: self.player_name.clone() 
+                spectator_name: self.player_name.clone(),
+                player_color_tag: self.player_color_tag,
             };
             client.send_message(spectate_game)?;
             println!("Spectating game {}...", game_id);
@@ -1785,35 +3001,68 @@ impl ChessGui {
     }
 
     pub fn handle_network_messages(&mut self) -> GameResult<()> {
-        if let Some(client) = &mut self.network_client {
-            if !client.is_connected() {
-                println!("Attempting to reconnect...");
-                if let Err(e) = client.reconnect() {
-                    println!("Failed to reconnect: {}", e);
+        // Once the client has given up, stay disconnected instead of retrying every frame
+        let needs_reconnect = self.network_client.as_ref().is_some_and(|client| {
+            !client.is_connected() && client.reconnect_state() != ReconnectState::Exhausted
+        });
+        if needs_reconnect {
+            let reconnected = self.network_client.as_mut().map(|client| client.reconnect());
+            match reconnected {
+                Some(Ok(true)) => self.log_network("Reconnected to server".to_string()),
+                Some(Ok(false)) => {
+                    self.needs_redraw = true;
+                    return Ok(());
+                }
+                Some(Err(e)) => {
+                    self.log_network(format!("Failed to reconnect: {}", e));
+                    self.needs_redraw = true;
                     return Ok(());
                 }
+                None => {}
             }
-    
+        }
+        
+        if let Some(client) = &mut self.network_client {
             match client.receive_message() {
-                Ok(Some(NetworkMessage::Move { from, to, promotion })) => {
+                Ok(Some(NetworkMessage::Move { from, to, promotion, .. })) => {
                     self.handle_network_move(from, to, promotion)?;
                 }
-                Ok(Some(NetworkMessage::GameStart { is_white, game_id, opponent_name })) => {
+                Ok(Some(NetworkMessage::GameStart { is_white, game_id, opponent_name, white_name, black_name, time_control, opponent_color_tag, session_token })) => {
+                    // Lets the client take this seat back by itself if the connection drops
+                    if let Some(session_token) = session_token {
+                        client.set_session(game_id.clone(), session_token);
+                    }
                     self.set_player_color(is_white);
                     self.game_id = Some(game_id.clone());
                     self.is_spectator = false;
-                    println!("Game started! You are playing as {}", if is_white { "white" } else { "black" });
                     self.opponent_name = opponent_name;
-                    println!("Game started! You are playing as {} against {}", 
-                        if is_white { "white" } else { "black" },
-                        if !self.opponent_name.is_empty() { &self.opponent_name } else { "an opponent" });
+                    self.opponent_color_tag = opponent_color_tag;
+                    self.time_control = time_control;
+                    self.log_network(format!("Game started! {} (White) vs {} (Black), you are playing as {}", 
+                        white_name, black_name,
+                        if is_white { "white" } else { "black" }));
+                    
+                    // The server doesn't start the game until both players have confirmed
+                    if let Some(client) = &mut self.network_client {
+                        if let Err(e) = client.send_ready() {
+                            println!("Failed to confirm game start: {}", e);
+                        }
+                    }
                 }
-                Ok(Some(NetworkMessage::GameState { board, current_turn, promotion_pending, game_over })) => {
+                Ok(Some(NetworkMessage::GameState { board, current_turn, promotion_pending, game_over, moves })) => {
                     self.update_game_state(board, current_turn, promotion_pending, game_over)?;
+                    self.update_move_history(&moves);
                 }
-                Ok(Some(NetworkMessage::GameEnd { reason })) => {
-                    println!("Game ended: {}", reason);
+                Ok(Some(NetworkMessage::GameEnd { reason, result, termination })) => {
+                    self.termination = termination;
+                    self.opponent_rejoin_deadline = None;
+                    let reason = match result {
+                        Some(result) => format!("{} ({})", reason, result),
+                        None => reason,
+                    };
+                    self.log_network(format!("Game ended: {}", reason));
                     self.game_over = true;
+                    self.start_rematch_window();
                     self.needs_redraw = true;
                     
                     // Add system message to chat if spectator panel is active
@@ -1821,32 +3070,32 @@ impl ChessGui {
                         self.spectator_panel.add_chat_message(
                             "System".to_string(),
                             format!("Game ended: {}", reason),
-                            true
+                            true,
+                            None
                         );
                     }
                 }
                 Ok(Some(NetworkMessage::GameCreated { game_id })) => {
                     self.game_id = Some(game_id.clone());
-                    println!("Game created with ID: {}", game_id);
-                    println!("Waiting for an opponent to join...");
+                    self.log_network(format!("Game created with ID: {}", game_id));
+                    self.log_network("Waiting for an opponent to join...".to_string());
                 }
                 Ok(Some(NetworkMessage::GameList { available_games })) => {
-                    self.available_games = available_games;
-                    println!("Available games:");
-                    for (i, game) in self.available_games.iter().enumerate() {
-                        println!("{}. {} (hosted by {}) - Status: {:?}, Players: {:?}, Spectators: {:?}", 
+                    self.log_network("Available games:".to_string());
+                    for (i, game) in available_games.iter().enumerate() {
+                        self.log_network(format!("{}. {} (hosted by {}) - Status: {:?}, Players: {:?}, Spectators: {:?}, {:?}", 
                                  i + 1, game.game_id, game.host_name, game.status,
-                                 game.player_count, game.spectator_count);
+                                 game.player_count, game.spectator_count, game.time_mode));
                     }
+                    self.available_games = available_games;
                     // Update join game buttons after receiving new game list
                     self.update_join_game_buttons(false);
-                    println!("Received game list with {} games", self.available_games.len());
+                    self.log_network(format!("Received game list with {} games", self.available_games.len()));
                     
-                    self.update_join_game_buttons();
                     self.needs_redraw = true;
                 }
                 Ok(Some(NetworkMessage::DrawOffered)) => {
-                    println!("Your opponent has offered a draw");
+                    self.log_network("Your opponent has offered a draw".to_string());
                     if !self.is_spectator {
                         self.draw_offered = true;
                     }
@@ -1856,14 +3105,15 @@ impl ChessGui {
                         self.spectator_panel.add_chat_message(
                             "System".to_string(),
                             "Draw has been offered".to_string(),
-                            true
+                            true,
+                            None
                         );
                     }
                     
                     self.needs_redraw = true;
                 }
                 Ok(Some(NetworkMessage::AcceptDraw)) => {
-                    println!("Draw accepted - game over");
+                    self.log_network("Draw accepted - game over".to_string());
                     self.game_over = true;
                     
                     // Add to chat if spectator panel is active
@@ -1871,28 +3121,30 @@ impl ChessGui {
                         self.spectator_panel.add_chat_message(
                             "System".to_string(),
                             "Draw offer accepted".to_string(),
-                            true
+                            true,
+                            None
                         );
                     }
                     
                     self.needs_redraw = true;
                 }
                 Ok(Some(NetworkMessage::DeclineDraw)) => {
-                    println!("Your opponent has declined your draw offer");
+                    self.log_network("Your opponent has declined your draw offer".to_string());
                     
                     // Add to chat if spectator panel is active
                     if self.show_spectator_panel {
                         self.spectator_panel.add_chat_message(
                             "System".to_string(),
                             "Draw offer declined".to_string(),
-                            true
+                            true,
+                            None
                         );
                     }
                     
                     self.needs_redraw = true;
                 }
                 Ok(Some(NetworkMessage::Resign)) => {
-                    println!("Your opponent has resigned");
+                    self.log_network("Your opponent has resigned".to_string());
                     self.game_over = true;
                     
                     // Add to chat if spectator panel is active
@@ -1900,49 +3152,71 @@ impl ChessGui {
                         self.spectator_panel.add_chat_message(
                             "System".to_string(),
                             "A player has resigned".to_string(),
-                            true
+                            true,
+                            None
                         );
                     }
                     
                     self.needs_redraw = true;
                 }
                 Ok(Some(NetworkMessage::RequestRematch)) => {
-                    println!("Your opponent wants to play again");
+                    self.log_network("Your opponent wants to play again".to_string());
                     if !self.is_spectator {
-                        self.rematch_offered = true;
+                        // The offer can outrun our GameEnd, and a later GameState may have cleared game_over
+                        self.start_rematch_window();
+                        if self.rematch_window_open() {
+                            self.rematch_offered = true;
+                            self.game_over = true;
+                        }
+                        self.needs_redraw = true;
                     }
                 }
                 Ok(Some(NetworkMessage::RematchAccepted { is_white })) => {
-                    println!("Rematch accepted! You are playing as {}", if is_white { "white" } else { "black" });
+                    self.log_network(format!("Rematch accepted! You are playing as {}", if is_white { "white" } else { "black" }));
                     self.set_player_color(is_white);
                     self.game_over = false;
+                    self.termination = None;
                     self.rematch_offered = false;
+                    self.rematch_deadline = None;
+                    self.rematch_button.set_enabled(true);
                     self.game_state = GameState::new();
                     self.needs_redraw = true;
                 }
-                Ok(Some(NetworkMessage::RequestRematch)) => {
-                    println!("Your opponent has requested a rematch");
-                    self.rematch_offered = true;
+                Ok(Some(NetworkMessage::MoveTakenBack { color })) => {
+                    // The restored board follows in a GameState message
+                    self.log_network(format!("{:?} took back their last move", color));
+                    self.last_move = None;
+                    self.move_animation = None;
+                    self.selected_square = None;
+                    self.possible_moves = 0;
                     self.needs_redraw = true;
                 }
-                Ok(Some(NetworkMessage::Resign)) => {
-                    println!("Your opponent has resigned");
-                    self.game_over = true;
+                Ok(Some(NetworkMessage::TakebackRefused { reason })) => {
+                    self.log_network(format!("Takeback refused: {}", reason));
+                }
+                Ok(Some(NetworkMessage::OpponentDisconnected { grace_secs })) => {
+                    self.log_network(format!("Opponent disconnected, they forfeit unless they're back within {}s", grace_secs));
+                    self.opponent_rejoin_deadline = Some(Instant::now() + Duration::from_secs(grace_secs));
+                    self.needs_redraw = true;
+                }
+                Ok(Some(NetworkMessage::OpponentReconnected)) => {
+                    self.log_network("Opponent reconnected".to_string());
+                    self.opponent_rejoin_deadline = None;
                     self.needs_redraw = true;
                 }
                 Ok(Some(NetworkMessage::SpectatorJoined { name })) => {
-                    println!("Spectator joined: {}", name);
+                    self.log_network(format!("Spectator joined: {}", name));
                     self.handle_spectator_joined(name);
                 }
                 Ok(Some(NetworkMessage::SpectatorLeft { name })) => {
-                    println!("Spectator left: {}", name);
+                    self.log_network(format!("Spectator left: {}", name));
                     self.handle_spectator_left(name);
                 }
-                Ok(Some(NetworkMessage::ChatMessage { sender, message, is_spectator })) => {
-                    println!("Chat: {}{}: {}", 
+                Ok(Some(NetworkMessage::ChatMessage { sender, message, is_spectator, player_color_tag })) => {
+                    self.log_network(format!("Chat: {}{}: {}", 
                              if is_spectator { "[Spectator] " } else { "" }, 
-                             sender, message);
-                    self.handle_chat_message(sender, message, is_spectator);
+                             sender, message));
+                    self.handle_chat_message(sender, message, is_spectator, player_color_tag);
                 }
                 Ok(Some(NetworkMessage::Heartbeat)) => {
                     // Heartbeat received, update last heartbeat time
@@ -1960,23 +3234,47 @@ impl ChessGui {
                 Ok(Some(NetworkMessage::OfferDraw)) => {
                     // Ignore unexpected OfferDraw messages from client
                 }
+                Ok(Some(NetworkMessage::JoinRejected { game_id, reason })) => {
+                    self.log_network(format!("Could not enter game {}: {}", game_id, reason));
+                    self.return_to_lobby()?;
+                }
                 Ok(Some(NetworkMessage::SpectateGame { .. })) => {
                     // Ignore unexpected SpectateGame messages from server
-                    println!("Received unexpected SpectateGame message");
+                    self.log_network("Received unexpected SpectateGame message".to_string());
+                }
+                Ok(Some(NetworkMessage::ClockUpdate { white_ms, black_ms })) => {
+                    // Display only, the server decides when a flag falls
+                    self.clock_ms = Some((white_ms, black_ms));
+                    self.needs_redraw = true;
                 }
-                Ok(Some(NetworkMessage::ConnectionStatus { .. })) => {
-                    // Handle connection status updates if needed
-                    println!("Received connection status update");
+                Ok(Some(NetworkMessage::MoveRejected { reason, .. })) => {
+                    // The GameState right behind it puts the board back
+                    self.log_network(format!("Move rejected by the server: {}", reason));
+                    self.last_move = None;
+                    self.pending_promotion_move = None;
+                    self.needs_redraw = true;
+                }
+                Ok(Some(NetworkMessage::ConnectionStatus { connected, message })) => {
+                    // The server also uses this to report rejected messages
+                    self.log_network(format!("Connection status (connected: {}): {}", connected, message));
                 }
                 Ok(None) => {
                     // No message available, continue
                 }
+                // An unreadable message is skipped, the connection itself is still fine
+                Err(ChessNetError::Parse(e)) => {
+                    self.log_network(format!("Ignored a message from the server that couldn't be read: {}", e));
+                }
+                Err(e) if e.is_disconnect() => {
+                    self.log_network(format!("Lost the connection to the server: {}", e));
+                    self.needs_redraw = true;
+                }
                 Err(e) => {
-                    println!("Network error: {}", e);
+                    self.log_network(format!("Network error: {}", e));
                 }
                 _ => {
                     // Catch-all for any other message variants that might be added in the future
-                    println!("Received unexpected network message");
+                    self.log_network("Received unexpected network message".to_string());
                 }
             }
         }
@@ -1984,6 +3282,196 @@ impl ChessGui {
     }
 
 
+    // Keep network events on screen as well as on stdout, so users can report what happened
+    fn log_network(&mut self, line: String) {
+        println!("{}", line);
+        
+        self.network_log.push_back(line);
+        while self.network_log.len() > MAX_NETWORK_LOG_LINES {
+            self.network_log.pop_front();
+        }
+        
+        if self.show_network_log {
+            self.needs_redraw = true;
+        }
+    }
+    
+    pub fn network_log(&self) -> &VecDeque<String> {
+        &self.network_log
+    }
+    
+    pub fn toggle_network_log(&mut self) {
+        self.show_network_log = !self.show_network_log;
+        self.needs_redraw = true;
+    }
+    
+    // Helper to draw White's share of the evaluation as a vertical bar beside the board,
+    // with White's part on White's side of the board
+    fn draw_eval_bar(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        let score = match self.eval_cache.score() {
+            Some(score) => score,
+            None => return Ok(()),
+        };
+        
+        let height = BOARD_SIZE as f32 * SQUARE_SIZE;
+        let x = BOARD_OFFSET_X - EVAL_BAR_GAP - EVAL_BAR_WIDTH;
+        let white_height = height * ai::eval_bar_fraction(score);
+        let white_y = if self.is_inverted_board() {
+            BOARD_OFFSET_Y
+        } else {
+            BOARD_OFFSET_Y + height - white_height
+        };
+        
+        let background = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            Rect::new(x, BOARD_OFFSET_Y, EVAL_BAR_WIDTH, height),
+            GgezColor::new(0.1, 0.1, 0.1, 1.0),
+        )?;
+        canvas.draw(&background, DrawParam::default());
+        
+        let white_part = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            Rect::new(x, white_y, EVAL_BAR_WIDTH, white_height),
+            GgezColor::new(0.95, 0.95, 0.95, 1.0),
+        )?;
+        canvas.draw(&white_part, DrawParam::default());
+        
+        Ok(())
+    }
+    
+    fn draw_network_log(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        let (width, height) = ctx.gfx.drawable_size();
+        let width = width / self.ui_scale;
+        let height = height / self.ui_scale;
+        let pane = Rect::new(0.0, height - NETWORK_LOG_HEIGHT, width, NETWORK_LOG_HEIGHT);
+        
+        let background = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            pane,
+            CHAT_BG,
+        )?;
+        canvas.draw(&background, DrawParam::default());
+        
+        // Newest lines at the bottom, as many as fit in the pane
+        let visible_lines = (NETWORK_LOG_HEIGHT / NETWORK_LOG_LINE_HEIGHT) as usize;
+        let skip = self.network_log.len().saturating_sub(visible_lines);
+        for (i, line) in self.network_log.iter().skip(skip).enumerate() {
+            canvas.draw(
+                &Text::new(line.as_str()),
+                DrawParam::default()
+                    .dest(Point2 {
+                        x: pane.x + 5.0,
+                        y: pane.y + 2.0 + i as f32 * NETWORK_LOG_LINE_HEIGHT,
+                    })
+                    .color(GgezColor::new(0.8, 0.9, 0.8, 1.0))
+                    .scale(Vector2 { x: 0.8, y: 0.8 })
+            );
+        }
+        
+        Ok(())
+    }
+    
+    // Network boards arrive as bare snapshots, so the history is replayed from the server's move list
+    fn update_move_history(&mut self, moves: &[String]) {
+        let mut replay = GameState::new();
+        for uci in moves {
+            if !replay.apply_uci_move(uci) {
+                break;
+            }
+        }
+        
+        if self.move_history.sync(&replay) {
+            self.needs_redraw = true;
+        }
+    }
+    
+    // The game list uses the same part of the sidebar, so the history makes way for it in the lobby
+    fn is_move_history_visible(&self) -> bool {
+        !self.show_game_list
+    }
+    
+    fn move_history_rect(&self) -> Rect {
+        let x = BOARD_OFFSET_X + (BOARD_SIZE as f32) * SQUARE_SIZE + BUTTON_MARGIN;
+        if self.show_spectator_panel {
+            let top = BOARD_OFFSET_Y + 5.0 * (BUTTON_HEIGHT + BUTTON_MARGIN) + SPECTATOR_PANEL_HEIGHT + 10.0;
+            Rect::new(x, top, HISTORY_PANEL_WIDTH, HISTORY_PANEL_BOTTOM - top)
+        } else {
+            let top = BOARD_OFFSET_Y + 5.0 * (BUTTON_HEIGHT + BUTTON_MARGIN);
+            Rect::new(x, top, HISTORY_PANEL_WIDTH, BOARD_OFFSET_Y + (BOARD_SIZE as f32) * SQUARE_SIZE - top)
+        }
+    }
+    
+    fn visible_history_rows(&self) -> usize {
+        ((self.move_history_rect().h - HISTORY_HEADER_HEIGHT) / HISTORY_LINE_HEIGHT) as usize
+    }
+    
+    // Mouse wheel: up goes back towards the first move
+    pub fn scroll_move_history(&mut self, wheel_y: f32) {
+        if wheel_y == 0.0 {
+            return;
+        }
+        
+        let visible_rows = self.visible_history_rows();
+        self.move_history.scroll_by(if wheel_y > 0.0 { 1 } else { -1 }, visible_rows);
+        self.needs_redraw = true;
+    }
+    
+    fn draw_move_history(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult<()> {
+        let panel = self.move_history_rect();
+        
+        let background = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            panel,
+            SPECTATOR_PANEL_BG,
+        )?;
+        canvas.draw(&background, DrawParam::default());
+        
+        let border = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::stroke(1.0),
+            panel,
+            GgezColor::WHITE,
+        )?;
+        canvas.draw(&border, DrawParam::default());
+        
+        canvas.draw(
+            &Text::new("Moves"),
+            DrawParam::default()
+                .dest(Point2 { x: panel.x + 10.0, y: panel.y + 5.0 })
+                .color(GgezColor::WHITE)
+        );
+        self.flip_board_button.draw(ctx, canvas)?;
+        
+        // The newest rows at the bottom, less however far the list has been scrolled back
+        let rows = self.move_history.rows();
+        let visible_rows = self.visible_history_rows();
+        let end = rows.len() - self.move_history.scroll.min(rows.len());
+        let start = end.saturating_sub(visible_rows);
+        for (i, (number, white, black)) in rows[start..end].iter().enumerate() {
+            let y = panel.y + HISTORY_HEADER_HEIGHT + i as f32 * HISTORY_LINE_HEIGHT;
+            let columns = [
+                (10.0, format!("{}.", number)),
+                (50.0, white.unwrap_or("...").to_string()),
+                (125.0, black.unwrap_or("").to_string()),
+            ];
+            for (offset, text) in columns {
+                canvas.draw(
+                    &Text::new(text),
+                    DrawParam::default()
+                        .dest(Point2 { x: panel.x + offset, y })
+                        .color(GgezColor::WHITE)
+                        .scale(Vector2 { x: 0.9, y: 0.9 })
+                );
+            }
+        }
+        
+        Ok(())
+    }
+    
     pub fn send_move(&mut self, from: (u8, u8), to: (u8, u8), promotion: Option<char>) -> GameResult<()> {
         if let Some(client) = &mut self.network_client {
             if !client.is_connected() {
@@ -2006,15 +3494,9 @@ impl ChessGui {
         for (i, game) in self.available_games.iter().enumerate() {
             let y = base_y + i as f32 * (BUTTON_HEIGHT + 5.0);
             
-            let button_text = if for_spectating {
-                format!("Spectate: {}", game.host_name)
-            } else if game.status == GameStatus::Waiting {
-                format!("Join: {}", game.host_name)
-            } else {
-                format!("Spectate: {}", game.host_name)
-            };
-            // Truncate the host name if needed
-            let button_text = format!("Join: {}", truncate_text(&game.host_name, MAX_TEXT_LENGTH - 6));
+            // The click handler spectates from buttons labelled "Spectate". Truncate the host name if needed
+            let label = if for_spectating { "Spectate" } else { "Join" };
+            let button_text = format!("{}: {}", label, truncate_text(&game.host_name, MAX_TEXT_LENGTH - 6));
             
             let button = Button::new(
                 BOARD_OFFSET_X + (BOARD_SIZE as f32) * SQUARE_SIZE + BUTTON_MARGIN,
@@ -2028,6 +3510,64 @@ impl ChessGui {
         }
     }
 
+    // Must match the server's interval, set before connecting
+    pub fn set_heartbeat_interval(&mut self, interval: Duration) {
+        self.heartbeat_interval = interval;
+    }
+    
+    pub fn set_auto_queen(&mut self, auto_queen: bool) {
+        self.auto_queen = auto_queen;
+    }
+    
+    pub fn set_input_mode(&mut self, input_mode: InputMode) {
+        self.input_mode = input_mode;
+        self.drag = None;
+    }
+    
+    pub fn input_mode(&self) -> InputMode {
+        self.input_mode
+    }
+    
+    pub fn cycle_input_mode(&mut self) {
+        self.set_input_mode(self.input_mode.next());
+        self.config.input_mode = self.input_mode;
+        self.save_config();
+        self.needs_redraw = true;
+    }
+    
+    // Called before each click so holding the modifier can still bring up the promotion dialog
+    pub fn set_force_promotion_dialog(&mut self, force: bool) {
+        self.force_promotion_dialog = force;
+    }
+    
+    // Scale factor applied to the whole layout, e.g. 2.0 on a high-DPI display
+    pub fn set_ui_scale(&mut self, scale: f32) {
+        if scale > 0.0 {
+            self.ui_scale = scale;
+            self.needs_redraw = true;
+        }
+    }
+    
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale
+    }
+    
+    // Pick up the display's DPI factor (or a manual override) and grow the window to match
+    pub fn init_display_scale(&mut self, ctx: &mut Context, scale_override: Option<f32>) -> GameResult<()> {
+        let scale = scale_override.unwrap_or_else(|| ctx.gfx.window().scale_factor() as f32);
+        if scale <= 0.0 {
+            return Ok(());
+        }
+        
+        if (scale - self.ui_scale).abs() > f32::EPSILON {
+            let (width, height) = ctx.gfx.drawable_size();
+            ctx.gfx.set_drawable_size(width * scale / self.ui_scale, height * scale / self.ui_scale)?;
+        }
+        
+        self.set_ui_scale(scale);
+        Ok(())
+    }
+    
     pub fn set_server_address(&mut self, address: String) {
         self.server_address = address;
     }
@@ -2049,6 +3589,23 @@ impl ChessGui {
         Ok(())
     }
     
+    // Only granted by servers running --instant-takeback, and only before the opponent replies
+    pub fn request_takeback(&mut self) -> GameResult<()> {
+        if self.is_spectator || self.game_over {
+            return Ok(());
+        }
+        if let Some(client) = &mut self.network_client {
+            if !client.is_connected() {
+                println!("Cannot request takeback - not connected to server");
+                return Ok(());
+            }
+            if let Err(e) = client.request_takeback() {
+                println!("Error requesting takeback: {}", e);
+            }
+        }
+        Ok(())
+    }
+    
     pub fn resign(&mut self) -> GameResult<()> {
         if let Some(client) = &mut self.network_client {
             if !client.is_connected() {
@@ -2058,8 +3615,10 @@ impl ChessGui {
             if let Err(e) = client.resign() {
                 println!("Error resigning: {}", e);
             } else {
-                // Set game as over immediately - don't wait for server confirmation
+                // Over for this side straight away. The server's GameEnd follows with the result, and
+                // its termination replaces this one
                 self.game_over = true;
+                self.termination = Some(TerminationReason::Resignation);
                 self.needs_redraw = true;
             }
         }
@@ -2078,9 +3637,36 @@ impl ChessGui {
         }
         Ok(())
     }
+    
+    // Whether the opponent's rematch offer is waiting for an answer
+    pub fn has_rematch_offer(&self) -> bool {
+        self.rematch_offered
+    }
 
     fn draw_player_names(&self, canvas: &mut Canvas) -> GameResult<()> {
-        let board_width = SQUARE_SIZE * 8.0;
+        let board_width = SQUARE_SIZE * BOARD_SIZE as f32;
+        
+        // Draw the match header, e.g. "You (White) vs Bob (Black) - 5+3"
+        if let Some(player_color) = self.player_color {
+            let opponent = if !self.opponent_name.is_empty() { &self.opponent_name } else { "Unknown" };
+            let mut header = format!("You ({:?}) vs {} ({:?})", 
+                player_color, truncate_text(opponent, MAX_TEXT_LENGTH), player_color.opposite());
+            if let Some(time_control) = self.time_control {
+                header = format!("{} - {}", header, time_control);
+            }
+            
+            let header_text = Text::new(header);
+            canvas.draw(
+                &header_text,
+                DrawParam::default()
+                    .dest(Point2 {
+                        x: BOARD_OFFSET_X + board_width / 2.0,
+                        y: BOARD_OFFSET_Y - 40.0,
+                    })
+                    .offset(Point2 { x: 0.5, y: 0.5 })
+                    .color(GgezColor::WHITE)
+            );
+        }
         
         // Draw player names based on board orientation
         let (top_name, bottom_name) = if self.is_inverted_board() {
@@ -2127,6 +3713,25 @@ impl ChessGui {
     }
 }
 
+// Helper function to pick a chat sender's name color: their own tag if they chose one, otherwise
+// blue for spectators and red for players
+pub fn chat_sender_color(is_spectator: bool, player_color_tag: Option<PlayerColorTag>) -> GgezColor {
+    match player_color_tag {
+        Some(tag) => {
+            let (r, g, b) = tag.to_rgb();
+            GgezColor::new(r, g, b, 1.0)
+        }
+        None if is_spectator => GgezColor::new(0.7, 0.7, 1.0, 1.0),
+        None => GgezColor::new(1.0, 0.7, 0.7, 1.0),
+    }
+}
+
+// Helper function to lay a translucent color over an opaque one, keeping light and dark squares apart
+fn tint(base: GgezColor, overlay: GgezColor) -> GgezColor {
+    let mix = |below: f32, above: f32| below + (above - below) * overlay.a;
+    GgezColor::new(mix(base.r, overlay.r), mix(base.g, overlay.g), mix(base.b, overlay.b), base.a)
+}
+
 // Helper function to truncate text to specified length
 fn truncate_text(text: &str, max_length: usize) -> String {
     if text.len() <= max_length {
@@ -2135,3 +3740,44 @@ fn truncate_text(text: &str, max_length: usize) -> String {
         format!("{}...", &text[0..max_length-3])
     }
 } 
+
+// The board square under a window position, with the board drawn from view's side. None off the board,
+// so a click beside it can't pick up a corner piece
+pub fn board_square_at(x: f32, y: f32, view: Color) -> Option<(usize, usize)> {
+    let display_file = board_axis_index(x - BOARD_OFFSET_X)?;
+    let display_rank = board_axis_index(y - BOARD_OFFSET_Y)?;
+    
+    // Convert display coordinates to internal coordinates
+    Some(perspective_square((display_rank, display_file), view))
+}
+
+// Helper function to find the square along one axis for a position measured from the board's top-left
+// corner. A position within float noise of a square boundary snaps onto it, and the outer edge gets a
+// little slack, so scaling can't push a click onto the neighbouring square or off the board
+fn board_axis_index(offset: f32) -> Option<usize> {
+    let board_extent = BOARD_SIZE as f32 * SQUARE_SIZE;
+    if offset < -BOARD_EDGE_TOLERANCE || offset >= board_extent + BOARD_EDGE_TOLERANCE {
+        return None;
+    }
+    
+    let squares = offset / SQUARE_SIZE;
+    let snapped = if (squares - squares.round()).abs() < SQUARE_SNAP_EPSILON { squares.round() } else { squares };
+    Some((snapped.floor().max(0.0) as usize).min(BOARD_SIZE - 1))
+}
+
+// Helper function to tell whether the window is minimized. Where the platform can't say, a window
+// resized to nothing is taken as minimized, since that's what minimizing does on most of them
+pub fn window_minimized(reported: Option<bool>, width: f32, height: f32) -> bool {
+    reported.unwrap_or(width <= 0.0 || height <= 0.0)
+}
+
+// Helper function to tell whether a window can be opened at all. On Linux and the BSDs winit panics
+// instead of returning an error when there's neither an X11 nor a Wayland display, so callers check
+// this before building a ggez context
+pub fn display_available() -> bool {
+    if cfg!(all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))) {
+        std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+    } else {
+        true
+    }
+}
@@ -4,4 +4,12 @@ pub mod gui;
 pub mod embedded_assets;
 pub mod zobrist;
 pub mod network;
-pub mod server; 
+pub mod server;
+pub mod thumbnail;
+pub mod ai;
+pub mod bot;
+pub mod protocol_log;
+pub mod config;
+pub mod analysis;
+#[cfg(feature = "websocket")]
+pub mod websocket;
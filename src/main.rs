@@ -1,35 +1,71 @@
 use ggez::{Context, ContextBuilder, GameResult};
 use ggez::event::{self, EventHandler};
 use ggez::input::mouse::MouseButton;
+use ggez::input::keyboard::{KeyCode, KeyInput, KeyMods};
 use ggez::conf::{WindowSetup, WindowMode};
 use std::env;
 use std::thread;
 use std::io::{self, Write};
-mod embedded_assets;
-mod piece;
-use chess::gui::ChessGui;
+use std::time::Duration;
+use chess::board::GameState;
+use chess::piece::Color;
+use chess::gui::{window_minimized, ChessGui, InputMode, UpdateThrottle};
+use chess::protocol_log::{self, ProtocolRecorder};
+use chess::config::DEFAULT_CONFIG_FILE;
+use chess::network::{PlayerColorTag, TimeControl, TimeMode};
+use std::path::{Path, PathBuf};
 
 enum GameMode {
     Local,
+    VsComputer { depth: u32 },
     NetworkHost,
     NetworkJoin(String),
-    Observer(String),
 }
 
+// Shown when the game window can't be opened, e.g. over SSH or on CI
+const HEADLESS_HINT: &str = "The modes that don't need a display are --server, --find-mate, --replay-protocol and --analyze";
+
+// How often a paused window still polls the network, instead of every frame
+const PAUSED_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
 struct ChessGame {
     gui: ChessGui,
+    focused: bool,
+    minimized: bool,
+    throttle: UpdateThrottle,
 }
 
 impl ChessGame {
-    fn new(ctx: &mut Context) -> GameResult<Self> {
-        let gui = ChessGui::new(ctx)?;
-        Ok(Self { gui })
+    fn new(ctx: &mut Context, text_pieces: bool) -> GameResult<Self> {
+        let gui = if text_pieces {
+            ChessGui::with_text_pieces()?
+        } else {
+            ChessGui::new(ctx)?
+        };
+        Ok(Self { gui, focused: true, minimized: false, throttle: UpdateThrottle::default() })
+    }
+    
+    fn update_paused_rendering(&mut self) {
+        self.gui.set_paused_rendering(!self.focused || self.minimized);
     }
 }
 
 impl EventHandler for ChessGame {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult<()> {
-        self.gui.update()
+    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        // Not every platform sends a resize when the window is minimized
+        if let Some(minimized) = ctx.gfx.window().is_minimized() {
+            if minimized != self.minimized {
+                self.minimized = minimized;
+                self.update_paused_rendering();
+            }
+        }
+        
+        // Moves and clocks still come in while paused, just less eagerly
+        let now = ctx.time.time_since_start();
+        if self.throttle.should_update(self.gui.is_rendering_paused(), now, PAUSED_UPDATE_INTERVAL) {
+            self.gui.update()?;
+        }
+        Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
@@ -38,15 +74,32 @@ impl EventHandler for ChessGame {
 
     fn mouse_button_down_event(
         &mut self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         button: MouseButton,
         x: f32,
         y: f32,
     ) -> GameResult<()> {
+        // Shift still opens the promotion dialog when auto-queen is on
+        self.gui.set_force_promotion_dialog(ctx.keyboard.is_mod_active(KeyMods::SHIFT));
         self.gui.handle_mouse_down(button, x, y)?;
         Ok(())
     }
     
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult<()> {
+        self.gui.handle_mouse_up(button, x, y)?;
+        Ok(())
+    }
+    
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> GameResult<()> {
+        self.gui.handle_key_press(character)
+    }
+    
     fn mouse_motion_event(
         &mut self,
         _ctx: &mut Context,
@@ -57,6 +110,123 @@ impl EventHandler for ChessGame {
     ) -> GameResult<()> {
         self.gui.handle_mouse_move(x, y)
     }
+
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) -> GameResult<()> {
+        self.gui.scroll_move_history(y);
+        Ok(())
+    }
+
+    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) -> GameResult<()> {
+        self.focused = gained;
+        self.update_paused_rendering();
+        Ok(())
+    }
+    
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) -> GameResult<()> {
+        self.minimized = window_minimized(ctx.gfx.window().is_minimized(), width, height);
+        self.update_paused_rendering();
+        Ok(())
+    }
+    
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, _repeated: bool) -> GameResult<()> {
+        match input.keycode {
+            Some(KeyCode::F12) => self.gui.toggle_network_log(),
+            Some(KeyCode::F2) => self.gui.cycle_input_mode(),
+            // Spectators step through the positions they've seen
+            Some(KeyCode::Left) => self.gui.review_step_back(),
+            Some(KeyCode::Right) => self.gui.review_step_forward(),
+            // Escape quits like ggez's default, once there's no chat box, dialog or selection to deal with
+            Some(KeyCode::Escape) if self.gui.handle_escape() => ctx.request_quit(),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+// Browsers can't open raw TCP sockets, so they go through a WebSocket bridge into the same server
+#[cfg(feature = "websocket")]
+fn start_websocket_bridge(ws_port: u16) {
+    println!("Starting WebSocket bridge...");
+    thread::spawn(move || {
+        if let Err(e) = chess::websocket::run_websocket_bridge(ws_port, "localhost:8080".to_string()) {
+            println!("WebSocket bridge error: {}", e);
+        }
+    });
+}
+
+#[cfg(not(feature = "websocket"))]
+fn start_websocket_bridge(_ws_port: u16) {
+    println!("--ws-port needs a build with the websocket feature (cargo run --features websocket)");
+}
+
+// Headless check for puzzle authors: is there a forced mate from this position, and how does it go
+fn run_mate_finder(fen: Option<&str>, max_moves: u32) {
+    let fen = match fen {
+        Some(fen) => fen,
+        None => {
+            println!("--find-mate needs a position, pass it with --fen \"<fen>\"");
+            return;
+        }
+    };
+    
+    let state = match GameState::from_fen(fen) {
+        Ok(state) => state,
+        Err(e) => {
+            println!("Invalid FEN: {}", e);
+            return;
+        }
+    };
+    
+    match chess::ai::find_mate(&state, max_moves) {
+        Some(line) => {
+            println!("Mate in {}: {}", line.len().div_ceil(2), state.line_to_san(&line));
+        }
+        None => println!("No forced mate in {} moves or fewer", max_moves),
+    }
+}
+
+// Headless replay of a recorded protocol log through the rules engine, for reproducing desyncs
+fn run_protocol_replay(path: &str) {
+    match protocol_log::replay(Path::new(path)) {
+        Ok(summary) => {
+            println!("Replayed {} moves ({} rejected): {}", summary.moves.len(), summary.rejected, summary.moves.join(" "));
+            let status = summary.game_state.status();
+            match status.terminal {
+                Some(reason) => println!("Final position: {}", reason),
+                None => println!("Final position: {:?} to move{}", summary.game_state.current_turn,
+                                 if status.in_check { ", in check" } else { "" }),
+            }
+        }
+        Err(e) => println!("Could not replay {}: {}", path, e),
+    }
+}
+
+// Headless game review: the PGN again with an evaluation after every move and blunders marked
+fn run_pgn_analysis(path: &str) {
+    let pgn = match std::fs::read_to_string(path) {
+        Ok(pgn) => pgn,
+        Err(e) => {
+            println!("Could not read {}: {}", path, e);
+            return;
+        }
+    };
+    
+    match chess::analysis::analyze_pgn(&pgn) {
+        Ok(annotated) => print!("{}", annotated),
+        Err(e) => println!("Could not analyze {}: {}", path, e),
+    }
+}
+
+// Open the --record-protocol file, carrying on without a log if it can't be opened
+fn open_protocol_recorder(path: Option<&str>) -> Option<ProtocolRecorder> {
+    let path = path?;
+    match ProtocolRecorder::open(Path::new(path)) {
+        Ok(recorder) => Some(recorder),
+        Err(e) => {
+            println!("Could not open protocol log {}: {}", path, e);
+            None
+        }
+    }
 }
 
 fn main() -> GameResult {
@@ -65,6 +235,10 @@ fn main() -> GameResult {
     let args: Vec<String> = env::args().collect();
     let is_server = args.iter().any(|arg| arg == "--server");
     let is_network = args.iter().any(|arg| arg == "--network");
+    let with_bot = args.iter().any(|arg| arg == "--bot");
+    let text_pieces = args.iter().any(|arg| arg == "--text-pieces");
+    let auto_queen = args.iter().any(|arg| arg == "--auto-queen");
+    let instant_takeback = args.iter().any(|arg| arg == "--instant-takeback");
     let server_address = args.iter().position(|arg| arg == "--address")
         .and_then(|pos| args.get(pos + 1))
         .map(|s| s.as_str())
@@ -72,13 +246,117 @@ fn main() -> GameResult {
     let join_game = args.iter().position(|arg| arg == "--join")
         .and_then(|pos| args.get(pos + 1))
         .map(|s| s.to_string());
+    let ui_scale = args.iter().position(|arg| arg == "--scale")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|s| s.parse::<f32>().ok());
+    let input_mode = args.iter().position(|arg| arg == "--input")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|s| InputMode::from_name(s));
+    let ws_port = args.iter().position(|arg| arg == "--ws-port")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|s| s.parse::<u16>().ok());
+    let heartbeat_interval = args.iter().position(|arg| arg == "--heartbeat")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|&secs| secs > 0)
+        .map(std::time::Duration::from_secs);
+    let find_mate = args.iter().position(|arg| arg == "--find-mate")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|s| s.parse::<u32>().ok())
+        .filter(|&moves| moves > 0);
+    let takeover_token = args.iter().position(|arg| arg == "--takeover-token")
+        .and_then(|pos| args.get(pos + 1))
+        .map(|s| s.to_string());
+    let color_tag = args.iter().position(|arg| arg == "--color-tag")
+        .and_then(|pos| args.get(pos + 1));
+    let time_control = args.iter().position(|arg| arg == "--time")
+        .and_then(|pos| args.get(pos + 1));
+    let correspondence = args.iter().any(|arg| arg == "--correspondence");
+    let correspondence_hours = args.iter().position(|arg| arg == "--correspondence-hours")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|&hours| hours > 0);
+    let reconnect_grace = args.iter().position(|arg| arg == "--reconnect-grace")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(std::time::Duration::from_secs);
+    let take_over = args.iter().position(|arg| arg == "--take-over")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|s| match s.to_lowercase().as_str() {
+            "white" => Some(Color::White),
+            "black" => Some(Color::Black),
+            _ => None,
+        });
+    let fen = args.iter().position(|arg| arg == "--fen")
+        .and_then(|pos| args.get(pos + 1))
+        .map(|s| s.as_str());
+    let record_protocol = args.iter().position(|arg| arg == "--record-protocol")
+        .and_then(|pos| args.get(pos + 1))
+        .map(|s| s.as_str());
+    let replay_protocol = args.iter().position(|arg| arg == "--replay-protocol")
+        .and_then(|pos| args.get(pos + 1))
+        .map(|s| s.as_str());
+    let analyze = args.iter().position(|arg| arg == "--analyze")
+        .and_then(|pos| args.get(pos + 1))
+        .map(|s| s.as_str());
+    let ai_depth = args.iter().position(|arg| arg == "--ai")
+        .and_then(|pos| args.get(pos + 1))
+        .and_then(|s| s.parse::<u32>().ok())
+        .filter(|depth| (1..=chess::ai::MAX_PLAYING_DEPTH).contains(depth));
+    
+    if let Some(max_moves) = find_mate {
+        run_mate_finder(fen, max_moves);
+        return Ok(());
+    }
+    
+    if let Some(path) = replay_protocol {
+        run_protocol_replay(path);
+        return Ok(());
+    }
+    
+    if let Some(path) = analyze {
+        run_pgn_analysis(path);
+        return Ok(());
+    }
     
     if is_server {
         println!("Starting server mode...");
         let mut server = chess::server::ChessServer::new(8080)?;
+        if let Some(interval) = heartbeat_interval {
+            server.set_heartbeat_interval(interval);
+        }
+        server.set_instant_takeback(instant_takeback);
+        if let Some(grace) = reconnect_grace {
+            server.set_reconnect_grace(grace);
+        }
+        if let Some(hours) = correspondence_hours {
+            server.set_correspondence_hours(hours);
+        }
+        if let Some(recorder) = open_protocol_recorder(record_protocol) {
+            server.set_protocol_recorder(recorder);
+        }
+        
+        // The house bot is just another client of this server
+        if with_bot {
+            println!("Starting house bot...");
+            let bot_address = format!("localhost:{}", server.local_port()?);
+            let bot_heartbeat = heartbeat_interval.unwrap_or(chess::network::DEFAULT_HEARTBEAT_INTERVAL);
+            thread::spawn(move || chess::bot::run_house_bot(&bot_address, bot_heartbeat));
+        }
+        
+        if let Some(ws_port) = ws_port {
+            start_websocket_bridge(ws_port);
+        }
+        
         server.run()?;
         Ok(())
     } else {
+        // Checked before any prompt, so nobody types a name only to have the window fail
+        if !chess::gui::display_available() {
+            println!("No display found, so the game window can't be opened. {}", HEADLESS_HINT);
+            std::process::exit(1);
+        }
+        
         let player_name = args.iter().position(|arg| arg == "--name")
             .and_then(|pos| args.get(pos + 1))
             .map(|s| s.to_string())
@@ -109,6 +387,8 @@ fn main() -> GameResult {
                     }
                 }
             }
+        } else if let Some(depth) = ai_depth {
+            GameMode::VsComputer { depth }
         } else {
             GameMode::Local
         };
@@ -119,9 +399,44 @@ fn main() -> GameResult {
             .window_setup(WindowSetup::default().title("Rust Chess"))
             .window_mode(WindowMode::default().dimensions(780.0, 750.0))
             .add_resource_path(resource_dir)
-            .build()?;
+            .build()
+            .map_err(|e| {
+                println!("Could not open the game window: {}. {}", e, HEADLESS_HINT);
+                e
+            })?;
 
-        let mut game = ChessGame::new(&mut ctx)?;
+        let mut game = ChessGame::new(&mut ctx, text_pieces)?;
+        game.gui.load_config(PathBuf::from(DEFAULT_CONFIG_FILE));
+        game.gui.set_auto_queen(auto_queen);
+        if let Some(input_mode) = input_mode {
+            game.gui.set_input_mode(input_mode);
+        }
+        if let Some(interval) = heartbeat_interval {
+            game.gui.set_heartbeat_interval(interval);
+        }
+        game.gui.set_takeover_token(takeover_token.clone());
+        if correspondence {
+            game.gui.set_time_mode(TimeMode::Correspondence);
+        }
+        if let Some(text) = time_control {
+            match TimeControl::from_notation(text) {
+                Some(time_control) => game.gui.set_time_control(Some(time_control)),
+                None => println!("Ignoring --time {}, expected minutes+increment like 5+3", text),
+            }
+        }
+        if let Some(text) = color_tag {
+            match PlayerColorTag::from_hex(text) {
+                Some(tag) => game.gui.set_player_color_tag(Some(tag)),
+                None => println!("Ignoring --color-tag {}, expected a color like #ff8800", text),
+            }
+        }
+        game.gui.set_protocol_recorder(open_protocol_recorder(record_protocol));
+        game.gui.init_display_scale(&mut ctx, ui_scale)?;
+        
+        if let GameMode::VsComputer { depth } = game_mode {
+            println!("Playing White against the computer (depth {})", depth);
+            game.gui.set_computer_opponent(depth);
+        }
         
         // Set up network connection if needed
         if is_network {
@@ -137,8 +452,11 @@ fn main() -> GameResult {
                     game.gui.create_game()?;
                 },
                 GameMode::NetworkJoin(game_id) if !game_id.is_empty() => {
-                    // Join a specific game
-                    game.gui.join_game(game_id)?;
+                    // Sit in for a dropped player, or join a specific game
+                    match (take_over, takeover_token) {
+                        (Some(color), Some(token)) => game.gui.take_over_slot(game_id, color, token)?,
+                        _ => game.gui.join_game(game_id)?,
+                    }
                 },
                 GameMode::NetworkJoin(_) => {
                     // Get list of available games and let user choose
@@ -178,10 +496,6 @@ fn main() -> GameResult {
                         }
                     }
                 },
-                GameMode::Observer(_) => {
-                    // Not implemented yet
-                    println!("Observer mode not implemented yet");
-                },
                 _ => {}
             }
         }
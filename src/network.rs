@@ -1,13 +1,82 @@
 use std::net::{TcpStream, TcpListener};
 use std::io::{Read, Write, ErrorKind};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
 use std::time::{Duration, Instant};
 use serde::{Serialize, Deserialize};
 use crate::piece::{PieceType, Color};
+// Game endings live with the rules, but are part of the protocol too
+pub use crate::board::{GameResult, TerminationReason};
+use crate::protocol_log::{Direction, ProtocolRecorder};
 
 // Timeout values
 const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
-const RECONNECT_ATTEMPTS: u32 = 3;
+const DEFAULT_PORT: u16 = 8080; // Used when a server address is just a host
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const DEAD_CONNECTION_FACTOR: f32 = 2.5; // Heartbeat intervals of silence before a peer counts as gone
+const DEFAULT_RECONNECT_ATTEMPTS: u32 = 3;
+const DEFAULT_RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+const DEFAULT_RECONNECT_WINDOW: Duration = Duration::from_secs(60);
+// Snapshots on the wire stay 8x8 even if board::BOARD_SIZE changes, so older clients keep working
+pub const WIRE_BOARD_SIZE: usize = 8;
+const MOVE_ACK_TIMEOUT: Duration = Duration::from_secs(3); // Resync with the server if a move isn't acknowledged by then
+pub const REMATCH_WINDOW: Duration = Duration::from_secs(60); // How long after a game the server waits for a rematch
+
+// What the client and server report instead of a bare io::Error, so callers can tell a dropped
+// connection from a peer that sent something unreadable
+#[derive(Debug)]
+pub enum ChessNetError {
+    Io(std::io::Error),
+    Parse(serde_json::Error), // A line that isn't a NetworkMessage
+    Protocol(String),         // A well-formed message that isn't allowed at this point
+    NotConnected,
+    Rejected(String),         // The server refused us and hung up, with its reason
+}
+
+impl ChessNetError {
+    // The peer went away, as opposed to sending something wrong
+    pub fn is_disconnect(&self) -> bool {
+        match self {
+            ChessNetError::Io(e) => matches!(e.kind(), ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset),
+            ChessNetError::NotConnected | ChessNetError::Rejected(_) => true,
+            ChessNetError::Parse(_) | ChessNetError::Protocol(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ChessNetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ChessNetError::Io(e) => write!(f, "{}", e),
+            ChessNetError::Parse(e) => write!(f, "Failed to parse message: {}", e),
+            ChessNetError::Protocol(message) => write!(f, "Protocol error: {}", message),
+            ChessNetError::NotConnected => write!(f, "Not connected to server"),
+            ChessNetError::Rejected(reason) => write!(f, "Rejected by server: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ChessNetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChessNetError::Io(e) => Some(e),
+            ChessNetError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ChessNetError {
+    fn from(e: std::io::Error) -> Self {
+        ChessNetError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ChessNetError {
+    fn from(e: serde_json::Error) -> Self {
+        ChessNetError::Parse(e)
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum NetworkMessage {
@@ -15,39 +84,129 @@ pub enum NetworkMessage {
         from: (u8, u8),
         to: (u8, u8),
         promotion: Option<char>,
+        // Set by the mover's client and echoed in a MoveAck once the server accepts the move
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        move_seq: Option<u64>,
+    },
+    MoveAck {
+        move_seq: u64,
+    },
+    // The server refused a move and didn't play it; the GameState it sends next is the real board
+    MoveRejected {
+        from: (u8, u8),
+        to: (u8, u8),
+        reason: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        move_seq: Option<u64>,
+    },
+    // Ask for the current GameState again, e.g. when a move was never acknowledged
+    RequestGameState,
+    // Ask for your legal moves in the current position, so a bot can check its own move generation.
+    // Answered with an empty list when it isn't your turn
+    RequestLegalMoves,
+    LegalMoves {
+        moves: Vec<String>, // UCI, e.g. "e2e4" or "e7e8q"
     },
+    // A player's reply to GameStart once it has set up the game
+    Ready,
     GameStart {
         is_white: bool,
         game_id: String,
         opponent_name: String,
+        white_name: String,
+        black_name: String,
+        time_control: Option<TimeControl>,
+        #[serde(default)]
+        opponent_color_tag: Option<PlayerColorTag>,
+        // This player's own secret for Rejoin after a dropped connection
+        #[serde(default)]
+        session_token: Option<String>,
     },
     GameEnd {
         reason: String,
+        result: Option<GameResult>,
+        termination: Option<TerminationReason>,
     },
     GameState {
-        board: [[Option<(PieceType, Color)>; 8]; 8],
+        board: [[Option<(PieceType, Color)>; WIRE_BOARD_SIZE]; WIRE_BOARD_SIZE],
         current_turn: Color,
         promotion_pending: Option<(usize, usize, Color)>,
         game_over: bool,
+        // Every move so far as UCI, so a client that joins mid-game can show the whole history
+        #[serde(default)]
+        moves: Vec<String>,
     },
     CreateGame {
         player_name: String,
+        // Lets a substitute take over a disconnected player's seat with TakeOverSlot. Without a
+        // token the seats can't change hands
+        #[serde(default)]
+        takeover_token: Option<String>,
+        #[serde(default)]
+        time_mode: TimeMode,
+        #[serde(default)]
+        player_color_tag: Option<PlayerColorTag>,
+        // Clocks for a live game; correspondence games go by their move time limit instead
+        #[serde(default)]
+        time_control: Option<TimeControl>,
     },
     JoinGame {
         game_id: String,
         player_name: String,
+        #[serde(default)]
+        player_color_tag: Option<PlayerColorTag>,
     },
     SpectateGame {
         game_id: String,
         spectator_name: String,
+        #[serde(default)]
+        player_color_tag: Option<PlayerColorTag>,
+    },
+    // Sit down in a disconnected player's seat, answered with GameStart and the current GameState
+    TakeOverSlot {
+        game_id: String,
+        color: Color,
+        token: String,
+        player_name: String,
     },
+    // Take your own seat back after a dropped connection, answered like TakeOverSlot
+    Rejoin {
+        game_id: String,
+        session_token: String,
+    },
+    // The opponent's connection dropped; they forfeit unless they rejoin within grace_secs
+    OpponentDisconnected {
+        grace_secs: u64,
+    },
+    OpponentReconnected,
     GameCreated {
         game_id: String,
     },
     GameList {
         available_games: Vec<GameInfo>,
     },
+    // Reply to JoinGame or SpectateGame when the game can't be entered
+    JoinRejected {
+        game_id: String,
+        reason: String,
+    },
     RequestGameList,
+    // A small PNG of a game's current position, for the game list
+    RequestThumbnail {
+        game_id: String,
+    },
+    // None when there's no game with that ID
+    Thumbnail {
+        game_id: String,
+        png: Option<Vec<u8>>,
+    },
+    // Completed games a player took part in, for a profile page
+    PlayerHistory {
+        name: String,
+    },
+    HistoryResult {
+        games: Vec<GameSummary>,
+    },
     OfferDraw,
     AcceptDraw,
     DeclineDraw,
@@ -56,6 +215,14 @@ pub enum NetworkMessage {
     RematchAccepted {
         is_white: bool,
     },
+    // Take back your own last move before the opponent replies (servers started with --instant-takeback)
+    RequestTakeback,
+    TakebackRefused {
+        reason: String,
+    },
+    MoveTakenBack {
+        color: Color,
+    },
     DrawOffered,
     // Heartbeat to keep connection alive
     Heartbeat,
@@ -64,6 +231,9 @@ pub enum NetworkMessage {
         sender: String,
         message: String,
         is_spectator: bool,
+        // The sender's tag from when they joined, filled in by the server whatever the client sent
+        #[serde(default)]
+        player_color_tag: Option<PlayerColorTag>,
     },
     // Spectator notifications
     SpectatorJoined {
@@ -72,6 +242,11 @@ pub enum NetworkMessage {
     SpectatorLeft {
         name: String,
     },
+    // Remaining time per side, as measured by the server
+    ClockUpdate {
+        white_ms: u64,
+        black_ms: u64,
+    },
     // Connection status update
     ConnectionStatus {
         connected: bool,
@@ -87,6 +262,80 @@ pub struct GameInfo {
     pub player_count: Option<u8>, // Make it optional
     pub spectator_count: u8,
     pub created_at: u64, // timestamp
+    #[serde(default)]
+    pub time_mode: TimeMode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameSummary {
+    pub game_id: String,
+    pub white_name: String,
+    pub black_name: String,
+    pub result: GameResult,
+    pub termination: Option<TerminationReason>,
+    pub finished_at: u64, // timestamp
+    pub pgn_path: Option<String>,
+}
+
+// How long a player may take over a move. Correspondence games allow hours or days, survive both
+// players leaving, and are picked up again after a server restart
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum TimeMode {
+    #[default]
+    Live,
+    Correspondence,
+}
+
+// The color a player picked to show next to their name in chat and on the status line
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerColorTag {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl PlayerColorTag {
+    // From "#rrggbb", with or without the '#'
+    pub fn from_hex(text: &str) -> Option<Self> {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Self { r: channel(0)?, g: channel(2)?, b: channel(4)? })
+    }
+    
+    // Channels from 0.0 to 1.0, as the renderer takes them
+    pub fn to_rgb(self) -> (f32, f32, f32) {
+        (self.r as f32 / 255.0, self.g as f32 / 255.0, self.b as f32 / 255.0)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TimeControl {
+    pub initial: Duration,
+    pub increment: Duration,
+}
+
+impl TimeControl {
+    // The same "minutes+increment" notation Display writes, e.g. "5+3"
+    pub fn from_notation(text: &str) -> Option<Self> {
+        let (minutes, increment) = text.split_once('+')?;
+        let minutes = minutes.trim().parse::<u64>().ok().filter(|&minutes| minutes > 0)?;
+        let increment = increment.trim().parse::<u64>().ok()?;
+        Some(Self {
+            initial: Duration::from_secs(minutes * 60),
+            increment: Duration::from_secs(increment),
+        })
+    }
+}
+
+impl std::fmt::Display for TimeControl {
+    // Standard "minutes+increment" notation, e.g. "5+3"
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}+{}", self.initial.as_secs() / 60, self.increment.as_secs())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -102,18 +351,75 @@ pub enum ClientRole {
     Spectator,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    pub max_attempts: u32,
+    pub base_backoff: Duration, // Doubles after every failed attempt
+    pub max_window: Duration,   // Give up once this long has passed since the first attempt
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_RECONNECT_ATTEMPTS,
+            base_backoff: DEFAULT_RECONNECT_BACKOFF,
+            max_window: DEFAULT_RECONNECT_WINDOW,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    // Delay to wait after the given failed attempt (1-based)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_backoff.saturating_mul(factor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReconnectState {
+    Idle,
+    Reconnecting { attempt: u32, max_attempts: u32 },
+    Exhausted, // No more attempts until reset_reconnect is called
+}
+
+impl std::fmt::Display for ReconnectState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReconnectState::Idle => write!(f, "Disconnected"),
+            ReconnectState::Reconnecting { attempt, max_attempts } => {
+                write!(f, "Reconnecting {}/{}...", attempt, max_attempts)
+            }
+            ReconnectState::Exhausted => write!(f, "Disconnected (gave up reconnecting)"),
+        }
+    }
+}
+
 pub struct ChessClient {
     pub stream: Option<TcpStream>,
     pub role: ClientRole,
     buffer: Vec<u8>,
     server_address: String,
     last_heartbeat: Instant,
+    heartbeat_interval: Duration,
+    last_received: Instant,
     connection_id: String,
     pub player_name: String,
+    pub player_color_tag: Option<PlayerColorTag>,
+    reconnect_policy: ReconnectPolicy,
+    reconnect_state: ReconnectState,
+    reconnect_started: Option<Instant>,
+    next_reconnect_at: Option<Instant>,
+    pending_connect: Option<Receiver<Result<TcpStream, std::io::Error>>>, // The attempt reconnect started, still connecting
+    read_timeout: Option<Duration>, // None means non-blocking reads
+    next_move_seq: u64,
+    unacked_move: Option<(u64, Instant)>, // Sequence number of the last move sent and when
+    recorder: Option<ProtocolRecorder>,
+    session: Option<(String, String)>, // Game ID and session token from GameStart, for rejoining after a reconnect
 }
 
 impl ChessClient {
-    pub fn new(server_address: &str) -> Result<Self, std::io::Error> {
+    pub fn new(server_address: &str) -> Result<Self, ChessNetError> {
         let stream = Self::connect_with_timeout(server_address, CONNECTION_TIMEOUT)?;
         stream.set_nonblocking(true)?;
         
@@ -125,27 +431,66 @@ impl ChessClient {
             buffer: Vec::new(),
             server_address: server_address.to_string(),
             last_heartbeat: Instant::now(),
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            last_received: Instant::now(),
             connection_id,
             player_name: String::new(),
+            player_color_tag: None,
+            reconnect_policy: ReconnectPolicy::default(),
+            reconnect_state: ReconnectState::Idle,
+            reconnect_started: None,
+            next_reconnect_at: None,
+            pending_connect: None,
+            read_timeout: None,
+            next_move_seq: 1,
+            unacked_move: None,
+            recorder: None,
+            session: None,
         })
     }
 
+    // Tries every address the name resolves to, IPv4 and IPv6 alike. A lookup failure comes back as
+    // NotFound; otherwise the error keeps the last attempt's kind and lists what each address said
     fn connect_with_timeout(addr: &str, timeout: Duration) -> Result<TcpStream, std::io::Error> {
         use std::net::ToSocketAddrs;
         
-        let addrs = addr.to_socket_addrs()?;
-        let mut last_err = None;
+        let addr = Self::with_default_port(addr);
+        let addrs: Vec<_> = match addr.to_socket_addrs() {
+            Ok(addrs) => addrs.collect(),
+            Err(e) => {
+                return Err(std::io::Error::new(ErrorKind::NotFound, format!("Could not resolve {}: {}", addr, e)));
+            }
+        };
+        if addrs.is_empty() {
+            return Err(std::io::Error::new(ErrorKind::NotFound, format!("{} did not resolve to any address", addr)));
+        }
         
-        for addr in addrs {
-            match TcpStream::connect_timeout(&addr, timeout) {
+        let mut attempts = Vec::new();
+        let mut last_kind = ErrorKind::Other;
+        for socket_addr in addrs {
+            match TcpStream::connect_timeout(&socket_addr, timeout) {
                 Ok(stream) => return Ok(stream),
-                Err(e) => last_err = Some(e),
+                Err(e) => {
+                    last_kind = e.kind();
+                    attempts.push(format!("{}: {}", socket_addr, e));
+                }
             }
         }
         
-        Err(last_err.unwrap_or_else(|| {
-            std::io::Error::new(ErrorKind::AddrNotAvailable, "Could not resolve address")
-        }))
+        Err(std::io::Error::new(last_kind, format!("Could not connect to {} (tried {})", addr, attempts.join("; "))))
+    }
+    
+    // "host" and bare IP addresses get the default port; "host:port" and "[v6]:port" are kept
+    fn with_default_port(addr: &str) -> String {
+        let unbracketed = addr.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = unbracketed.parse::<std::net::IpAddr>() {
+            return std::net::SocketAddr::new(ip, DEFAULT_PORT).to_string();
+        }
+        
+        match addr.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => addr.to_string(),
+            _ => format!("{}:{}", addr, DEFAULT_PORT),
+        }
     }
 
     pub fn with_role(stream: TcpStream, role: ClientRole, server_address: &str) -> Self {
@@ -157,62 +502,227 @@ impl ChessClient {
             buffer: Vec::new(),
             server_address: server_address.to_string(),
             last_heartbeat: Instant::now(),
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            last_received: Instant::now(),
             connection_id,
             player_name: String::new(),
+            player_color_tag: None,
+            reconnect_policy: ReconnectPolicy::default(),
+            reconnect_state: ReconnectState::Idle,
+            reconnect_started: None,
+            next_reconnect_at: None,
+            pending_connect: None,
+            read_timeout: None,
+            next_move_seq: 1,
+            unacked_move: None,
+            recorder: None,
+            session: None,
         }
     }
 
-    pub fn reconnect(&mut self) -> Result<(), std::io::Error> {
-        println!("Attempting to reconnect to server...");
+    // With a timeout, receive_message sleeps until data arrives or the timeout passes instead of
+    // returning straight away, so a loop around it doesn't need its own sleep. None goes back to
+    // non-blocking reads, which is what a frame-driven caller like the GUI wants
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<(), ChessNetError> {
+        self.read_timeout = timeout;
+        if let Some(stream) = &self.stream {
+            Self::apply_read_timeout(stream, timeout)?;
+        }
+        Ok(())
+    }
+    
+    fn apply_read_timeout(stream: &TcpStream, timeout: Option<Duration>) -> Result<(), std::io::Error> {
+        stream.set_nonblocking(timeout.is_none())?;
+        stream.set_read_timeout(timeout)
+    }
+    
+    // True when a complete message is already buffered, so receive_message won't touch the socket
+    pub fn has_pending_message(&self) -> bool {
+        self.buffer.contains(&b'\n')
+    }
+    
+    // Both ends should agree on this: we send a heartbeat after this long without sending anything,
+    // and expect the peer to do the same
+    pub fn set_heartbeat_interval(&mut self, interval: Duration) {
+        self.heartbeat_interval = interval;
+    }
+    
+    pub fn heartbeat_interval(&self) -> Duration {
+        self.heartbeat_interval
+    }
+    
+    // A peer that vanished without closing the connection never produces a read error,
+    // so it's recognised by having sent nothing, not even a heartbeat, for too long
+    pub fn is_unresponsive(&self) -> bool {
+        self.last_received.elapsed() > self.heartbeat_interval.mul_f32(DEAD_CONNECTION_FACTOR)
+    }
+    
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.reconnect_policy = policy;
+    }
+    
+    // Remember the seat from GameStart, so reconnect() rejoins the game instead of landing in the lobby
+    pub fn set_session(&mut self, game_id: String, session_token: String) {
+        self.session = Some((game_id, session_token));
+    }
+    
+    pub fn reconnect_state(&self) -> ReconnectState {
+        self.reconnect_state
+    }
+    
+    // Allow reconnecting again after the attempts were exhausted, e.g. when the user asks for it
+    pub fn reset_reconnect(&mut self) {
+        self.reconnect_state = ReconnectState::Idle;
+        self.reconnect_started = None;
+        self.next_reconnect_at = None;
+        self.pending_connect = None;
+    }
+    
+    // Never blocks, so it can be called every frame: each attempt connects on a worker thread, and a
+    // later call picks up how it went. Returns Ok(true) once reconnected, Ok(false) while an attempt is
+    // under way or the next one is waiting out its backoff
+    pub fn reconnect(&mut self) -> Result<bool, ChessNetError> {
+        if self.reconnect_state == ReconnectState::Exhausted {
+            return Err(std::io::Error::new(
+                ErrorKind::ConnectionRefused,
+                "Gave up reconnecting to the server"
+            ).into());
+        }
         
-        for attempt in 1..=RECONNECT_ATTEMPTS {
-            match Self::connect_with_timeout(&self.server_address, CONNECTION_TIMEOUT) {
-                Ok(stream) => {
-                    stream.set_nonblocking(true)?;
-                    self.stream = Some(stream);
-                    self.last_heartbeat = Instant::now();
-                    
-                    println!("Successfully reconnected to server (attempt {}/{})", 
-                             attempt, RECONNECT_ATTEMPTS);
-                    
-                    // Send reconnection message with connection ID
-                    let reconnect_msg = NetworkMessage::ConnectionStatus {
+        if let Some(pending) = &self.pending_connect {
+            let outcome = match pending.try_recv() {
+                Ok(outcome) => outcome,
+                Err(TryRecvError::Empty) => return Ok(false),
+                Err(TryRecvError::Disconnected) => Err(std::io::Error::other("The connection attempt was abandoned")),
+            };
+            self.pending_connect = None;
+            return self.finish_reconnect(outcome);
+        }
+        
+        let now = Instant::now();
+        if self.next_reconnect_at.is_some_and(|at| now < at) {
+            return Ok(false);
+        }
+        
+        let started = *self.reconnect_started.get_or_insert(now);
+        let attempt = match self.reconnect_state {
+            ReconnectState::Reconnecting { attempt, .. } => attempt + 1,
+            _ => 1,
+        };
+        
+        if attempt > self.reconnect_policy.max_attempts || now.duration_since(started) > self.reconnect_policy.max_window {
+            self.reconnect_state = ReconnectState::Exhausted;
+            return Err(std::io::Error::new(
+                ErrorKind::ConnectionRefused,
+                format!("Failed to reconnect after {} attempts", attempt - 1)
+            ).into());
+        }
+        
+        self.reconnect_state = ReconnectState::Reconnecting {
+            attempt,
+            max_attempts: self.reconnect_policy.max_attempts,
+        };
+        
+        // connect_with_timeout can take CONNECTION_TIMEOUT per address, so it runs off the caller's thread
+        let (sender, receiver) = mpsc::channel();
+        let address = self.server_address.clone();
+        thread::spawn(move || {
+            // Nobody is listening any more if the attempts were reset meanwhile
+            let _ = sender.send(Self::connect_with_timeout(&address, CONNECTION_TIMEOUT));
+        });
+        self.pending_connect = Some(receiver);
+        Ok(false)
+    }
+    
+    // Take the connection from a finished attempt, or schedule the next attempt after the backoff
+    fn finish_reconnect(&mut self, outcome: Result<TcpStream, std::io::Error>) -> Result<bool, ChessNetError> {
+        let attempt = match self.reconnect_state {
+            ReconnectState::Reconnecting { attempt, .. } => attempt,
+            _ => 1,
+        };
+        
+        match outcome {
+            Ok(stream) => {
+                Self::apply_read_timeout(&stream, self.read_timeout)?;
+                self.stream = Some(stream);
+                self.last_heartbeat = Instant::now();
+                self.last_received = Instant::now();
+                self.reset_reconnect();
+                
+                println!("Successfully reconnected to server (attempt {}/{})", 
+                         attempt, self.reconnect_policy.max_attempts);
+                
+                // A player in a game asks for its seat back, anyone else just reports the connection ID
+                let reconnect_msg = match &self.session {
+                    Some((game_id, session_token)) => NetworkMessage::Rejoin {
+                        game_id: game_id.clone(),
+                        session_token: session_token.clone(),
+                    },
+                    None => NetworkMessage::ConnectionStatus {
                         connected: true,
                         message: format!("Reconnected client {}", self.connection_id),
-                    };
-                    
-                    let serialized = serde_json::to_string(&reconnect_msg)?;
-                    if let Some(stream) = &mut self.stream {
-                        stream.write_all(format!("{}\n", serialized).as_bytes())?;
-                    }
-                    
-                    return Ok(());
-                }
-                Err(e) => {
-                    println!("Reconnection attempt {}/{} failed: {}", 
-                             attempt, RECONNECT_ATTEMPTS, e);
-                    
-                    if attempt < RECONNECT_ATTEMPTS {
-                        // Exponential backoff
-                        let backoff = Duration::from_millis(500 * 2u64.pow(attempt - 1));
-                        std::thread::sleep(backoff);
-                    }
+                    },
+                };
+                
+                let serialized = serde_json::to_string(&reconnect_msg)?;
+                if let Some(stream) = &mut self.stream {
+                    stream.write_all(format!("{}\n", serialized).as_bytes())?;
                 }
+                
+                Ok(true)
+            }
+            Err(e) => {
+                println!("Reconnection attempt {}/{} failed: {}", 
+                         attempt, self.reconnect_policy.max_attempts, e);
+                
+                // Exponential backoff, without blocking the caller
+                self.next_reconnect_at = Some(Instant::now() + self.reconnect_policy.backoff(attempt));
+                Ok(false)
             }
         }
-        
-        Err(std::io::Error::new(
-            ErrorKind::ConnectionRefused,
-            format!("Failed to reconnect after {} attempts", RECONNECT_ATTEMPTS)
-        ))
     }
 
-    pub fn send_move(&mut self, from: (u8, u8), to: (u8, u8), promotion: Option<char>) -> Result<(), std::io::Error> {
-        let message = NetworkMessage::Move { from, to, promotion };
-        self.send_message(message)
+    pub fn send_move(&mut self, from: (u8, u8), to: (u8, u8), promotion: Option<char>) -> Result<(), ChessNetError> {
+        let move_seq = self.next_move_seq;
+        self.next_move_seq += 1;
+        
+        let message = NetworkMessage::Move { from, to, promotion, move_seq: Some(move_seq) };
+        self.send_message(message)?;
+        self.unacked_move = Some((move_seq, Instant::now()));
+        Ok(())
+    }
+    
+    // Log this connection's traffic to a protocol file, see protocol_log. Heartbeats are left out
+    pub fn set_recorder(&mut self, recorder: Option<ProtocolRecorder>) {
+        self.recorder = recorder;
+    }
+    
+    fn record(&self, direction: Direction, message: &NetworkMessage) {
+        if let Some(recorder) = &self.recorder {
+            if !matches!(message, NetworkMessage::Heartbeat) {
+                recorder.record(direction, message);
+            }
+        }
+    }
+    
+    pub fn send_ready(&mut self) -> Result<(), ChessNetError> {
+        self.send_message(NetworkMessage::Ready)
+    }
+    
+    pub fn request_game_state(&mut self) -> Result<(), ChessNetError> {
+        self.send_message(NetworkMessage::RequestGameState)
+    }
+    
+    pub fn request_legal_moves(&mut self) -> Result<(), ChessNetError> {
+        self.send_message(NetworkMessage::RequestLegalMoves)
+    }
+    
+    // Only the latest move is tracked: the server handles a player's moves in order, so its ack covers the rest
+    pub fn has_unacked_move(&self) -> bool {
+        self.unacked_move.is_some()
     }
     
-    pub fn send_message(&mut self, message: NetworkMessage) -> Result<(), std::io::Error> {
+    pub fn send_message(&mut self, message: NetworkMessage) -> Result<(), ChessNetError> {
         let serialized = serde_json::to_string(&message)?;
         
         if let Some(stream) = &mut self.stream {
@@ -220,48 +730,42 @@ impl ChessClient {
                 Ok(_) => {
                     // Update heartbeat timestamp on successful send
                     self.last_heartbeat = Instant::now();
+                    self.record(Direction::Sent, &message);
                     Ok(())
                 }
                 Err(e) => {
                     println!("Error sending message: {}", e);
                     self.stream = None;
-                    Err(e)
+                    Err(e.into())
                 }
             }
         } else {
-            Err(std::io::Error::new(ErrorKind::NotConnected, "Not connected to server"))
+            Err(ChessNetError::NotConnected)
         }
     }
 
-    pub fn receive_message(&mut self) -> Result<Option<NetworkMessage>, std::io::Error> {
+    pub fn receive_message(&mut self) -> Result<Option<NetworkMessage>, ChessNetError> {
         // First, check if we need to send a heartbeat
-        if self.is_connected() && self.last_heartbeat.elapsed() > HEARTBEAT_INTERVAL {
+        if self.is_connected() && self.last_heartbeat.elapsed() > self.heartbeat_interval {
             self.send_heartbeat()?;
         }
         
+        // A move that was lost or refused never gets its ack, so fetch the board the server really has
+        if let Some((move_seq, sent)) = self.unacked_move {
+            if self.is_connected() && sent.elapsed() > MOVE_ACK_TIMEOUT {
+                println!("Move {} was not acknowledged, requesting the game state", move_seq);
+                self.unacked_move = None;
+                self.request_game_state()?;
+            }
+        }
+        
         if self.stream.is_none() {
-            return Err(std::io::Error::new(ErrorKind::NotConnected, "Not connected to server"));
+            return Err(ChessNetError::NotConnected);
         }
 
-        let mut temp_buffer = [0; 1024];
-        match self.stream.as_mut().unwrap().read(&mut temp_buffer) {
-            Ok(0) => {
-                // Connection closed
-                println!("Connection closed by server");
-                self.stream = None;
-                return Err(std::io::Error::new(ErrorKind::ConnectionAborted, "Connection closed"));
-            }
-            Ok(n) => {
-                self.buffer.extend_from_slice(&temp_buffer[..n]);
-            }
-            Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                // No data available, continue
-            }
-            Err(e) => {
-                println!("Error reading from server: {}", e);
-                self.stream = None;
-                return Err(e);
-            }
+        // Don't wait on the socket when the next message is already here
+        if !self.has_pending_message() {
+            self.fill_buffer()?;
         }
 
         // Try to find a complete message (ending with newline)
@@ -274,19 +778,28 @@ impl ChessClient {
             
             match message {
                 Ok(msg) => {
-                    // Update heartbeat timestamp on successful receive
+                    // The peer's heartbeat only shows it's alive, which fill_buffer already noted. It doesn't
+                    // reset our own send timer, or two ends on the same interval could leave one side silent
                     if let NetworkMessage::Heartbeat = msg {
-                        self.last_heartbeat = Instant::now();
                         return self.receive_message(); // Skip heartbeat messages, try to get real message
                     }
+                    if let NetworkMessage::MoveAck { move_seq } | NetworkMessage::MoveRejected { move_seq: Some(move_seq), .. } = msg {
+                        if matches!(self.unacked_move, Some((pending, _)) if pending <= move_seq) {
+                            self.unacked_move = None;
+                        }
+                    }
+                    self.record(Direction::Received, &msg);
+                    
+                    // The server's last word before it hangs up on a client that broke the protocol
+                    if let NetworkMessage::ConnectionStatus { connected: false, message } = msg {
+                        self.stream = None;
+                        return Err(ChessNetError::Rejected(message));
+                    }
                     Ok(Some(msg))
                 }
                 Err(e) => {
                     println!("Failed to parse message: {}", e);
-                    Err(std::io::Error::new(
-                        ErrorKind::InvalidData,
-                        format!("Failed to parse message: {}", e)
-                    ))
+                    Err(ChessNetError::Parse(e))
                 }
             }
         } else {
@@ -295,7 +808,66 @@ impl ChessClient {
         }
     }
     
-    fn send_heartbeat(&mut self) -> Result<(), std::io::Error> {
+    // Hand over the next buffered message only if it's one the caller is looking for, leaving
+    // anything else queued for receive_message. Never reads from the socket itself.
+    // Heartbeats and Readys nobody asked for are dropped so they can't hold up what's behind them
+    pub fn receive_message_if<F>(&mut self, wanted: F) -> Option<NetworkMessage>
+    where
+        F: Fn(&NetworkMessage) -> bool,
+    {
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            match serde_json::from_slice::<NetworkMessage>(&self.buffer[..pos]) {
+                Ok(NetworkMessage::Heartbeat) => {
+                    self.buffer.drain(..=pos);
+                }
+                Ok(msg) if wanted(&msg) => {
+                    self.buffer.drain(..=pos);
+                    self.record(Direction::Received, &msg);
+                    return Some(msg);
+                }
+                Ok(NetworkMessage::Ready) => {
+                    self.buffer.drain(..=pos);
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+    
+    // Move whatever has arrived on the socket into the buffer without handling any of it.
+    // Keeps track of when the peer was last heard from even while its messages wait their turn
+    pub fn fill_buffer(&mut self) -> Result<(), ChessNetError> {
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => return Err(ChessNetError::NotConnected),
+        };
+        
+        let mut temp_buffer = [0; 1024];
+        match stream.read(&mut temp_buffer) {
+            Ok(0) => {
+                // Connection closed
+                println!("Connection closed by server");
+                self.stream = None;
+                Err(std::io::Error::new(ErrorKind::ConnectionAborted, "Connection closed").into())
+            }
+            Ok(n) => {
+                self.buffer.extend_from_slice(&temp_buffer[..n]);
+                self.last_received = Instant::now();
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                // No data available (a read timeout shows up as either kind depending on the platform)
+                Ok(())
+            }
+            Err(e) => {
+                println!("Error reading from server: {}", e);
+                self.stream = None;
+                Err(e.into())
+            }
+        }
+    }
+    
+    fn send_heartbeat(&mut self) -> Result<(), ChessNetError> {
         let heartbeat = NetworkMessage::Heartbeat;
         self.send_message(heartbeat)
     }
@@ -317,45 +889,62 @@ impl ChessClient {
     }
     
     // Draw, resignation, and rematch functionality
-    pub fn offer_draw(&mut self) -> Result<(), std::io::Error> {
+    pub fn offer_draw(&mut self) -> Result<(), ChessNetError> {
         let message = NetworkMessage::OfferDraw;
         self.send_message(message)
     }
     
-    pub fn accept_draw(&mut self) -> Result<(), std::io::Error> {
+    pub fn accept_draw(&mut self) -> Result<(), ChessNetError> {
         let message = NetworkMessage::AcceptDraw;
         self.send_message(message)
     }
     
-    pub fn decline_draw(&mut self) -> Result<(), std::io::Error> {
+    pub fn decline_draw(&mut self) -> Result<(), ChessNetError> {
         let message = NetworkMessage::DeclineDraw;
         self.send_message(message)
     }
     
-    pub fn resign(&mut self) -> Result<(), std::io::Error> {
+    pub fn request_thumbnail(&mut self, game_id: &str) -> Result<(), ChessNetError> {
+        let message = NetworkMessage::RequestThumbnail { game_id: game_id.to_string() };
+        self.send_message(message)
+    }
+    
+    pub fn resign(&mut self) -> Result<(), ChessNetError> {
         let message = NetworkMessage::Resign;
         self.send_message(message)
     }
     
-    pub fn request_rematch(&mut self) -> Result<(), std::io::Error> {
+    pub fn request_rematch(&mut self) -> Result<(), ChessNetError> {
         let message = NetworkMessage::RequestRematch;
         self.send_message(message)
     }
     
+    pub fn take_over_slot(&mut self, game_id: String, color: Color, token: String, player_name: String) -> Result<(), ChessNetError> {
+        let message = NetworkMessage::TakeOverSlot { game_id, color, token, player_name };
+        self.send_message(message)
+    }
+    
+    pub fn request_takeback(&mut self) -> Result<(), ChessNetError> {
+        let message = NetworkMessage::RequestTakeback;
+        self.send_message(message)
+    }
+    
     // New spectator functionality
-    pub fn spectate_game(&mut self, game_id: String, spectator_name: String) -> Result<(), std::io::Error> {
+    pub fn spectate_game(&mut self, game_id: String, spectator_name: String) -> Result<(), ChessNetError> {
         let message = NetworkMessage::SpectateGame { 
             game_id, 
-            spectator_name 
+            spectator_name,
+            player_color_tag: self.player_color_tag,
         };
         self.send_message(message)
     }
     
-    pub fn send_chat_message(&mut self, message: String, name: String) -> Result<(), std::io::Error> {
+    pub fn send_chat_message(&mut self, message: String, name: String) -> Result<(), ChessNetError> {
         let chat_message = NetworkMessage::ChatMessage {
             sender: name,
             message,
             is_spectator: self.is_spectator(),
+            player_color_tag: self.player_color_tag,
         };
         self.send_message(chat_message)
     }
@@ -366,13 +955,13 @@ pub struct ChessServer {
 }
 
 impl ChessServer {
-    pub fn new(port: u16) -> Result<Self, std::io::Error> {
+    pub fn new(port: u16) -> Result<Self, ChessNetError> {
         let listener = TcpListener::bind(format!("0.0.0.0:{}", port))?;
         listener.set_nonblocking(true)?;
         Ok(Self { listener })
     }
 
-    pub fn accept_connections(&self) -> Result<(ChessClient, ChessClient), std::io::Error> {
+    pub fn accept_connections(&self) -> Result<(ChessClient, ChessClient), ChessNetError> {
         println!("Waiting for players to connect...");
         
         // Accept first player
@@ -384,27 +973,22 @@ impl ChessServer {
         println!("Second player connected");
 
         // Create clients and assign colors
-        let mut client1 = ChessClient {
-            stream: Some(stream1),
-            is_white: true,
-            buffer: Vec::new(),
-            server_address: "".to_string(),
-            player_name: String::new(),
-        };
-        let mut client2 = ChessClient {
-            stream: Some(stream2),
-            is_white: false,
-            buffer: Vec::new(),
-            server_address: "".to_string(),
-            player_name: String::new(),
-        };
+        let mut client1 = ChessClient::with_role(stream1, ClientRole::Player { is_white: true }, "");
+        let mut client2 = ChessClient::with_role(stream2, ClientRole::Player { is_white: false }, "");
 
         // Send color assignments
-        let message1 = NetworkMessage::GameStart { is_white: true, game_id: "".to_string(), opponent_name: "".to_string() };
-        let message2 = NetworkMessage::GameStart { is_white: false, game_id: "".to_string(), opponent_name: "".to_string() };
-        
-        client1.stream.as_mut().unwrap().write_all(serde_json::to_string(&message1)?.as_bytes())?;
-        client2.stream.as_mut().unwrap().write_all(serde_json::to_string(&message2)?.as_bytes())?;
+        for (client, is_white) in [(&mut client1, true), (&mut client2, false)] {
+            client.send_message(NetworkMessage::GameStart {
+                is_white,
+                game_id: "".to_string(),
+                opponent_name: "".to_string(),
+                white_name: "".to_string(),
+                black_name: "".to_string(),
+                time_control: None,
+                opponent_color_tag: None,
+                session_token: None,
+            })?;
+        }
 
         Ok((client1, client2))
     }
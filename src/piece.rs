@@ -1,3 +1,4 @@
+use crate::board::{Board, BOARD_SIZE};
 use serde::{Serialize, Deserialize};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, Hash)]
@@ -10,6 +11,26 @@ pub enum PieceType {
     King,
 }
 
+const DIAGONALS: [(isize, isize); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+const STRAIGHTS: [(isize, isize); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+const ALL_DIRECTIONS: [(isize, isize); 8] = [(-1, -1), (-1, 1), (1, -1), (1, 1), (-1, 0), (0, 1), (1, 0), (0, -1)];
+
+impl PieceType {
+    // Rays a sliding piece moves along, empty for pieces that step or jump
+    pub fn slide_directions(&self) -> &'static [(isize, isize)] {
+        match self {
+            PieceType::Bishop => &DIAGONALS,
+            PieceType::Rook => &STRAIGHTS,
+            PieceType::Queen => &ALL_DIRECTIONS,
+            _ => &[],
+        }
+    }
+    
+    pub fn is_slider(&self) -> bool {
+        !self.slide_directions().is_empty()
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, Hash)]
 pub enum Color {
     White,
@@ -23,9 +44,18 @@ impl Color {
             Color::Black => Color::White,
         }
     }
+    
+    // The network protocol says which side a player has with an is_white flag
+    pub fn from_bool(is_white: bool) -> Self {
+        if is_white { Color::White } else { Color::Black }
+    }
+    
+    pub fn is_white(&self) -> bool {
+        *self == Color::White
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Piece {
     pub piece_type: PieceType,
     pub color: Color,
@@ -41,7 +71,9 @@ impl Piece {
         }
     }
     
-    pub fn get_possible_moves(&self, position: (usize, usize), board: &[[Option<Piece>; 8]; 8]) -> Vec<(usize, usize)> {
+    // `en_passant_target` is the square behind a pawn that just moved two, the only empty square a pawn
+    // may capture onto. Callers only looking for attacks on a king can pass None
+    pub fn get_possible_moves(&self, position: (usize, usize), board: &Board, en_passant_target: Option<(usize, usize)>) -> Vec<(usize, usize)> {
         let (rank, file) = position;
         let mut moves = Vec::new();
         
@@ -50,7 +82,7 @@ impl Piece {
                 let direction = if self.color == Color::White { -1isize } else { 1isize };
                 
                 let new_rank = (rank as isize + direction) as usize;
-                if new_rank < 8 && board[new_rank][file].is_none() {
+                if new_rank < BOARD_SIZE && board[new_rank][file].is_none() {
                     moves.push((new_rank, file));
                     
                     // Check if pawn is on its starting rank
@@ -61,7 +93,7 @@ impl Piece {
                     
                     if is_on_starting_rank {
                         let double_rank = (rank as isize + 2 * direction) as usize;
-                        if double_rank < 8 && board[double_rank][file].is_none() {
+                        if double_rank < BOARD_SIZE && board[double_rank][file].is_none() {
                             moves.push((double_rank, file));
                         }
                     }
@@ -69,29 +101,18 @@ impl Piece {
                 
                 for file_offset in [-1, 1] {
                     let new_file = file as isize + file_offset;
-                    if new_file >= 0 && new_file < 8 {
+                    if new_file >= 0 && new_file < BOARD_SIZE as isize {
                         let new_rank = (rank as isize + direction) as usize;
-                        if new_rank < 8 {
+                        if new_rank < BOARD_SIZE {
                             // Normal diagonal capture - ONLY if there's an opponent's piece
                             if let Some(piece) = board[new_rank][new_file as usize] {
                                 if piece.color != self.color {
                                     moves.push((new_rank, new_file as usize));
                                 }
                             }
-                            // En passant capture - ONLY on the correct rank and with en_passant_target
-                            else {
-                                let en_passant_rank = match self.color {
-                                    Color::White => 3, // 5th rank for white pawns (index 3)
-                                    Color::Black => 4, // 4th rank for black pawns (index 4)
-                                };
-                                
-                                if rank == en_passant_rank && // Pawn is on the correct rank for en passant
-                                   board[rank][new_file as usize].is_some() && // Adjacent square has a piece
-                                   board[rank][new_file as usize].unwrap().piece_type == PieceType::Pawn && // It's a pawn
-                                   board[rank][new_file as usize].unwrap().color != self.color { // It's an opponent's pawn
-                                    // The actual en passant validation will be done in make_move
-                                    moves.push((new_rank, new_file as usize));
-                                }
+                            // En passant capture - ONLY onto the target the last double push left
+                            else if en_passant_target == Some((new_rank, new_file as usize)) {
+                                moves.push((new_rank, new_file as usize));
                             }
                         }
                     }
@@ -107,7 +128,7 @@ impl Piece {
                     let new_rank = rank as isize + rank_offset;
                     let new_file = file as isize + file_offset;
                     
-                    if new_rank >= 0 && new_rank < 8 && new_file >= 0 && new_file < 8 {
+                    if new_rank >= 0 && new_rank < BOARD_SIZE as isize && new_file >= 0 && new_file < BOARD_SIZE as isize {
                         let new_rank = new_rank as usize;
                         let new_file = new_file as usize;
                         
@@ -121,15 +142,8 @@ impl Piece {
                     }
                 }
             },
-            PieceType::Bishop => {
-                self.add_diagonal_moves(rank, file, board, &mut moves);
-            },
-            PieceType::Rook => {
-                self.add_straight_moves(rank, file, board, &mut moves);
-            },
-            PieceType::Queen => {
-                self.add_diagonal_moves(rank, file, board, &mut moves);
-                self.add_straight_moves(rank, file, board, &mut moves);
+            PieceType::Bishop | PieceType::Rook | PieceType::Queen => {
+                self.add_sliding_moves(rank, file, self.piece_type.slide_directions(), board, &mut moves);
             },
             PieceType::King => {
                 for rank_offset in -1..=1 {
@@ -141,7 +155,7 @@ impl Piece {
                         let new_rank = rank as isize + rank_offset;
                         let new_file = file as isize + file_offset;
                         
-                        if new_rank >= 0 && new_rank < 8 && new_file >= 0 && new_file < 8 {
+                        if new_rank >= 0 && new_rank < BOARD_SIZE as isize && new_file >= 0 && new_file < BOARD_SIZE as isize {
                             let new_rank = new_rank as usize;
                             let new_file = new_file as usize;
                             
@@ -156,46 +170,16 @@ impl Piece {
                     }
                 }
                 
-                if !self.has_moved {
-                    let king_rank = match self.color {
-                        Color::White => 7,
-                        Color::Black => 0,
-                    };
-                    
-                    if rank == king_rank && file == 4 {
-                        if board[king_rank][5].is_none() && board[king_rank][6].is_none() {
-                            if let Some(rook) = board[king_rank][7] {
-                                if rook.piece_type == PieceType::Rook && 
-                                   rook.color == self.color && 
-                                   !rook.has_moved {
-                                    moves.push((king_rank, 6));
-                                }
-                            }
-                        }
-                        
-                        if board[king_rank][1].is_none() && 
-                           board[king_rank][2].is_none() && 
-                           board[king_rank][3].is_none() {
-                            if let Some(rook) = board[king_rank][0] {
-                                if rook.piece_type == PieceType::Rook && 
-                                   rook.color == self.color && 
-                                   !rook.has_moved {
-                                    moves.push((king_rank, 2));
-                                }
-                            }
-                        }
-                    }
-                }
+                // Castling is added by GameState, which knows the castling rights and the attacked squares
             },
         }
         
         moves
     }
     
-    fn add_diagonal_moves(&self, rank: usize, file: usize, board: &[[Option<Piece>; 8]; 8], moves: &mut Vec<(usize, usize)>) {
-        let directions = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
-        
-        for (rank_dir, file_dir) in directions {
+    // Bishops, rooks and queens run along each of their directions until something is in the way
+    fn add_sliding_moves(&self, rank: usize, file: usize, directions: &[(isize, isize)], board: &Board, moves: &mut Vec<(usize, usize)>) {
+        for &(rank_dir, file_dir) in directions {
             let mut new_rank = rank as isize;
             let mut new_file = file as isize;
             
@@ -203,37 +187,7 @@ impl Piece {
                 new_rank += rank_dir;
                 new_file += file_dir;
                 
-                if new_rank < 0 || new_rank >= 8 || new_file < 0 || new_file >= 8 {
-                    break;
-                }
-                
-                let new_rank = new_rank as usize;
-                let new_file = new_file as usize;
-                
-                if let Some(piece) = board[new_rank][new_file] {
-                    if piece.color != self.color {
-                        moves.push((new_rank, new_file));
-                    }
-                    break;
-                } else {
-                    moves.push((new_rank, new_file));
-                }
-            }
-        }
-    }
-    
-    fn add_straight_moves(&self, rank: usize, file: usize, board: &[[Option<Piece>; 8]; 8], moves: &mut Vec<(usize, usize)>) {
-        let directions = [(-1, 0), (0, 1), (1, 0), (0, -1)];
-        
-        for (rank_dir, file_dir) in directions {
-            let mut new_rank = rank as isize;
-            let mut new_file = file as isize;
-            
-            loop {
-                new_rank += rank_dir;
-                new_file += file_dir;
-                
-                if new_rank < 0 || new_rank >= 8 || new_file < 0 || new_file >= 8 {
+                if new_rank < 0 || new_rank >= BOARD_SIZE as isize || new_file < 0 || new_file >= BOARD_SIZE as isize {
                     break;
                 }
                 
@@ -268,4 +222,36 @@ impl Piece {
             (PieceType::King, Color::Black) => '♚',
         }
     }
+    
+    // Letter used in FEN: uppercase for white, lowercase for black
+    pub fn to_fen_char(&self) -> char {
+        let letter = match self.piece_type {
+            PieceType::Pawn => 'p',
+            PieceType::Knight => 'n',
+            PieceType::Bishop => 'b',
+            PieceType::Rook => 'r',
+            PieceType::Queen => 'q',
+            PieceType::King => 'k',
+        };
+        
+        match self.color {
+            Color::White => letter.to_ascii_uppercase(),
+            Color::Black => letter,
+        }
+    }
+    
+    pub fn from_fen_char(c: char) -> Option<Self> {
+        let piece_type = match c.to_ascii_lowercase() {
+            'p' => PieceType::Pawn,
+            'n' => PieceType::Knight,
+            'b' => PieceType::Bishop,
+            'r' => PieceType::Rook,
+            'q' => PieceType::Queen,
+            'k' => PieceType::King,
+            _ => return None,
+        };
+        let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+        
+        Some(Self::new(piece_type, color))
+    }
 } 
@@ -0,0 +1,173 @@
+use crate::board::{GameState, MoveOutcome};
+use crate::network::NetworkMessage;
+use crate::piece::{Color, Piece};
+use serde::{Serialize, Deserialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+// One line of a protocol log
+#[derive(Debug, Deserialize)]
+pub struct ProtocolEntry {
+    pub timestamp_ms: u64, // Since the Unix epoch
+    pub direction: Direction,
+    pub message: NetworkMessage,
+}
+
+// Same layout as ProtocolEntry, borrowing the message so it doesn't have to be cloned to be written
+#[derive(Serialize)]
+struct ProtocolEntryRef<'a> {
+    timestamp_ms: u64,
+    direction: Direction,
+    message: &'a NetworkMessage,
+}
+
+// Appends the messages a connection sends and receives to a JSON-lines file, for reproducing
+// reported bugs. Clones share the file, so the server hands one to every connection
+#[derive(Clone)]
+pub struct ProtocolRecorder {
+    file: Arc<Mutex<File>>,
+    record_sent: bool,
+}
+
+impl ProtocolRecorder {
+    pub fn open(path: &Path) -> Result<Self, std::io::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            record_sent: true,
+        })
+    }
+
+    // The server writes most of its replies straight to the sockets, so it only logs what clients send it
+    pub fn incoming_only(mut self) -> Self {
+        self.record_sent = false;
+        self
+    }
+
+    pub fn record(&self, direction: Direction, message: &NetworkMessage) {
+        if direction == Direction::Sent && !self.record_sent {
+            return;
+        }
+
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let entry = ProtocolEntryRef { timestamp_ms, direction, message };
+
+        // A broken log shouldn't take the connection down with it
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                println!("Error recording message: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            println!("Error writing protocol log: {}", e);
+        }
+    }
+}
+
+pub struct ReplaySummary {
+    pub game_state: GameState,
+    pub moves: Vec<String>, // Numbered SAN of the moves that were played, e.g. "1. e4" or "e5"
+    pub rejected: usize, // Moves the rules engine refused, like the server would have
+}
+
+// Play the moves a log received through the rules engine, without any network.
+// A client's log receives the server's broadcast of every applied move; a server's log receives the
+// moves players sent it, which interleave when several games ran at once. A log can span rematches,
+// and the replay ends with the last game in it
+pub fn replay(path: &Path) -> Result<ReplaySummary, std::io::Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut summary = ReplaySummary {
+        game_state: GameState::new(),
+        moves: Vec::new(),
+        rejected: 0,
+    };
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: ProtocolEntry = serde_json::from_str(&line).map_err(|e| {
+            std::io::Error::new(ErrorKind::InvalidData, format!("Line {}: {}", index + 1, e))
+        })?;
+        if entry.direction != Direction::Received {
+            continue;
+        }
+
+        match entry.message {
+            NetworkMessage::GameStart { .. } | NetworkMessage::RematchAccepted { .. } => {
+                summary.game_state = GameState::new();
+                summary.moves.clear();
+            }
+            NetworkMessage::Move { from, to, promotion, .. } => {
+                let from = (from.0 as usize, from.1 as usize);
+                let to = (to.0 as usize, to.1 as usize);
+                if !replay_move(&mut summary, from, to, promotion) {
+                    summary.rejected += 1;
+                }
+            }
+            NetworkMessage::MoveTakenBack { .. } if summary.game_state.take_back() => {
+                summary.moves.pop();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(summary)
+}
+
+// Applies a move the way the server's game loop does, including a Move that only supplies the piece
+// for a promotion that is already pending
+fn replay_move(summary: &mut ReplaySummary, from: (usize, usize), to: (usize, usize), promotion: Option<char>) -> bool {
+    let state = &mut summary.game_state;
+    let piece_type = match promotion {
+        Some(c) => match Piece::from_fen_char(c) {
+            Some(piece) => Some(piece.piece_type),
+            None => return false,
+        },
+        None => None,
+    };
+
+    if state.promotion_pending.is_some() {
+        return match piece_type {
+            Some(piece_type) => state.promote_pawn(piece_type),
+            None => false,
+        };
+    }
+
+    let san = state.move_to_san(from, to, piece_type);
+    let numbered = match state.current_turn {
+        Color::White => format!("{}. {}", state.fullmove_number, san),
+        Color::Black => san,
+    };
+
+    match state.apply_move_checked(from, to) {
+        Ok(MoveOutcome::Moved) => {}
+        Ok(MoveOutcome::PromotionRequired) => {
+            if let Some(piece_type) = piece_type {
+                if !state.promote_pawn(piece_type) {
+                    return false;
+                }
+            }
+        }
+        Err(_) => return false,
+    }
+
+    summary.moves.push(numbered);
+    true
+}
@@ -1,18 +1,226 @@
-use std::net::TcpListener;
-use std::io::Write;
-use std::collections::{HashMap, HashSet};
+use std::net::{TcpListener, TcpStream};
+use std::io::{BufRead, BufReader, Write};
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
+use serde::{Serialize, Deserialize};
 use serde_json;
-use crate::network::{ChessClient, ClientRole, NetworkMessage, GameInfo, GameStatus};
+use crate::network::{ChessClient, ChessNetError, ClientRole, NetworkMessage, GameInfo, GameStatus, GameResult, GameSummary, PlayerColorTag, TerminationReason, TimeControl, TimeMode, DEFAULT_HEARTBEAT_INTERVAL, REMATCH_WINDOW};
 use crate::board::GameState;
-use crate::piece::{PieceType, Color};
+use crate::piece::{Piece, PieceType, Color};
+use crate::protocol_log::ProtocolRecorder;
+use crate::thumbnail::{render_thumbnail_png, DEFAULT_THUMBNAIL_SQUARE_SIZE};
 
 const SERVER_VERSION: &str = "1.0.0";
 const MAX_INACTIVE_TIME: Duration = Duration::from_secs(300); // 5 minutes
 const GAME_CLEANUP_INTERVAL: Duration = Duration::from_secs(3600); // 1 hour
+const DEFAULT_DRAW_OFFER_COOLDOWN: u32 = 3; // Moves before a declined player may offer a draw again
+const MAX_UNEXPECTED_MESSAGES: u32 = 3; // Before a client has created or joined a game
+const GAME_POLL_INTERVAL: Duration = Duration::from_millis(10); // Pause between game loop passes, with the game unlocked
+const GAME_READ_TIMEOUT: Duration = Duration::from_millis(50); // Longest wait for the player to move before the next pass
+const EMPTY_SEATS_INTERVAL: Duration = Duration::from_millis(100); // Game loop pause while neither player is connected
+const LOBBY_READ_TIMEOUT: Duration = Duration::from_millis(100); // Longest wait for a lobby client before checking on it
+const READY_TIMEOUT: Duration = Duration::from_secs(5); // Longest wait for both players' Ready before the game starts anyway
+const DEFAULT_HISTORY_FILE: &str = "games/history.jsonl"; // One GameRecord per line, next to the saved PGN files
+const DEFAULT_CORRESPONDENCE_DIR: &str = "games/correspondence"; // One CorrespondenceSnapshot per running game
+const DEFAULT_CORRESPONDENCE_HOURS: u64 = 72; // How long a correspondence player may be away on their move
+const CORRESPONDENCE_IDLE_INTERVAL: Duration = Duration::from_secs(1); // Game loop pause while a correspondence player is away
+const DEFAULT_RECONNECT_GRACE: Duration = Duration::from_secs(30); // How long a live player may be disconnected before forfeiting
+
+// How a game ended when it wasn't decided on the board; the color is the losing side
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GameEnding {
+    Resignation(Color),
+    Forfeit(Color),
+    Timeout(Color),
+    DrawAgreed,
+}
+
+// A finished game as kept in the server's history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GameRecord {
+    game_id: String,
+    white_name: String,
+    black_name: String,
+    result: GameResult,
+    termination: Option<TerminationReason>,
+    finished_at: u64, // timestamp
+    pgn_path: Option<String>,
+}
+
+impl GameRecord {
+    fn involves(&self, name: &str) -> bool {
+        self.white_name == name || self.black_name == name
+    }
+    
+    fn summary(&self) -> GameSummary {
+        GameSummary {
+            game_id: self.game_id.clone(),
+            white_name: self.white_name.clone(),
+            black_name: self.black_name.clone(),
+            result: self.result,
+            termination: self.termination,
+            finished_at: self.finished_at,
+            pgn_path: self.pgn_path.clone(),
+        }
+    }
+}
+
+// A running correspondence game as saved after every move, enough to pick it up after a restart
+#[derive(Debug, Serialize, Deserialize)]
+struct CorrespondenceSnapshot {
+    game_id: String,
+    host_name: String,
+    takeover_token: Option<String>,
+    created_at: u64, // timestamp
+    last_activity_at: u64, // timestamp, so a restart doesn't give the player to move extra time
+    moves: Vec<String>, // UCI, from the initial position
+    #[serde(default)]
+    white_name: String,
+    #[serde(default)]
+    black_name: String,
+    #[serde(default)]
+    white_session: Option<String>, // Rejoin tokens, so the players can get back in after a restart
+    #[serde(default)]
+    black_session: Option<String>,
+}
+
+// Completed games, kept in memory and appended to a file so they survive a restart
+#[derive(Clone)]
+struct GameHistory {
+    records: Arc<Mutex<Vec<GameRecord>>>,
+    path: PathBuf,
+}
+
+impl GameHistory {
+    fn load(path: PathBuf) -> Self {
+        let mut records = Vec::new();
+        
+        // No file yet just means no games have finished
+        if let Ok(file) = File::open(&path) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                match serde_json::from_str::<GameRecord>(&line) {
+                    Ok(record) => records.push(record),
+                    Err(e) => println!("Skipping unreadable game history entry: {}", e),
+                }
+            }
+        }
+        
+        Self {
+            records: Arc::new(Mutex::new(records)),
+            path,
+        }
+    }
+    
+    // The PGN is saved first, next to the history file, so the record can point at it
+    fn record(&self, mut record: GameRecord, pgn: &str) {
+        match self.save_pgn(&record, pgn) {
+            Ok(path) => record.pgn_path = Some(path.to_string_lossy().into_owned()),
+            Err(e) => println!("Error saving PGN of game {}: {}", record.game_id, e),
+        }
+        
+        if let Err(e) = self.append_to_file(&record) {
+            println!("Error saving game {} to history: {}", record.game_id, e);
+        }
+        self.records.lock().unwrap().push(record);
+    }
+    
+    fn append_to_file(&self, record: &GameRecord) -> Result<(), std::io::Error> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)
+    }
+    
+    // "history.jsonl" keeps its PGN files in "history-pgn/"
+    fn pgn_dir(&self) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        self.path.with_file_name(format!("{}-pgn", stem))
+    }
+    
+    fn save_pgn(&self, record: &GameRecord, pgn: &str) -> Result<PathBuf, std::io::Error> {
+        let dir = self.pgn_dir();
+        fs::create_dir_all(&dir)?;
+        
+        // Rematches keep their game ID, so number the games played under it
+        let earlier_games = self.records.lock().unwrap()
+            .iter()
+            .filter(|earlier| earlier.game_id == record.game_id)
+            .count();
+        let path = dir.join(format!("{}-{}.pgn", record.game_id, earlier_games + 1));
+        fs::write(&path, pgn)?;
+        Ok(path)
+    }
+    
+    fn for_player(&self, name: &str) -> Vec<GameSummary> {
+        self.records.lock().unwrap()
+            .iter()
+            .filter(|record| record.involves(name))
+            .map(GameRecord::summary)
+            .collect()
+    }
+}
+
+// The authoritative clocks; time is measured here and clients only display ClockUpdate
+struct GameClock {
+    white_remaining: Duration,
+    black_remaining: Duration,
+    increment: Duration,
+    turn_started: Instant,
+}
+
+impl GameClock {
+    fn new(time_control: TimeControl) -> Self {
+        Self {
+            white_remaining: time_control.initial,
+            black_remaining: time_control.initial,
+            increment: time_control.increment,
+            turn_started: Instant::now(),
+        }
+    }
+    
+    fn remaining_mut(&mut self, color: Color) -> &mut Duration {
+        match color {
+            Color::White => &mut self.white_remaining,
+            Color::Black => &mut self.black_remaining,
+        }
+    }
+    
+    fn is_flagged(&self, side_to_move: Color) -> bool {
+        let remaining = match side_to_move {
+            Color::White => self.white_remaining,
+            Color::Black => self.black_remaining,
+        };
+        self.turn_started.elapsed() >= remaining
+    }
+    
+    // Charge the mover for the time since their turn began, then start the opponent's turn
+    fn complete_move(&mut self, mover: Color) {
+        let elapsed = self.turn_started.elapsed();
+        let increment = self.increment;
+        let remaining = self.remaining_mut(mover);
+        *remaining = remaining.saturating_sub(elapsed) + increment;
+        self.turn_started = Instant::now();
+    }
+    
+    // The side to move changed without a move being completed, e.g. after a takeback
+    fn restart_turn(&mut self) {
+        self.turn_started = Instant::now();
+    }
+    
+    fn update_message(&self) -> NetworkMessage {
+        NetworkMessage::ClockUpdate {
+            white_ms: self.white_remaining.as_millis() as u64,
+            black_ms: self.black_remaining.as_millis() as u64,
+        }
+    }
+}
 
 struct Game {
     id: String,
@@ -24,7 +232,27 @@ struct Game {
     status: GameStatus,
     created_at: u64,
     last_activity: SystemTime,
-    chat_history: Vec<(String, String, bool)>, // (sender, message, is_spectator)
+    chat_history: Vec<(String, String, bool, Option<PlayerColorTag>)>, // (sender, message, is_spectator, color tag)
+    time_control: Option<TimeControl>,
+    clock: Option<GameClock>,
+    ending: Option<GameEnding>,
+    draw_offer_cooldown: u32,
+    declined_draw_offers: HashMap<Color, u32>, // Fullmove number at which each side's offer was declined
+    draw_offer: Option<Color>, // Who has a draw offer waiting for an answer
+    pending_promotion_move: Option<((u8, u8), (u8, u8))>, // Move that is waiting for its promotion piece
+    white_to_move: bool, // Whose messages the game loop is reading
+    instant_takeback: bool, // Undo a player's last move on request until the opponent replies
+    takeover_token: Option<String>, // Shared secret for substitutes; None locks the seats
+    white_ready: bool, // Each player has answered the last GameStart with Ready
+    black_ready: bool,
+    time_mode: TimeMode,
+    max_inactive_time: Duration, // How long the player to move may be away before forfeiting
+    snapshot_path: Option<PathBuf>, // Correspondence games only
+    session_tokens: HashMap<Color, String>, // Each seat's secret for Rejoin, sent only to its player
+    reconnect_grace: Duration, // How long a live player may be disconnected before forfeiting
+    disconnected_at: HashMap<Color, Instant>, // When each currently disconnected player dropped
+    saved_names: HashMap<Color, String>, // Names from a resumed snapshot, for seats nobody has rejoined yet
+    announced_absence: bool, // The wait for the missing player to move has been logged
 }
 
 impl Game {
@@ -45,23 +273,317 @@ impl Game {
             created_at: timestamp,
             last_activity: SystemTime::now(),
             chat_history: Vec::new(),
+            time_control: None,
+            clock: None,
+            ending: None,
+            draw_offer_cooldown: DEFAULT_DRAW_OFFER_COOLDOWN,
+            declined_draw_offers: HashMap::new(),
+            draw_offer: None,
+            pending_promotion_move: None,
+            white_to_move: true,
+            instant_takeback: false,
+            takeover_token: None,
+            white_ready: false,
+            black_ready: false,
+            time_mode: TimeMode::Live,
+            max_inactive_time: MAX_INACTIVE_TIME,
+            snapshot_path: None,
+            session_tokens: [Color::White, Color::Black].into_iter()
+                .map(|color| (color, Uuid::new_v4().to_string()))
+                .collect(),
+            reconnect_grace: DEFAULT_RECONNECT_GRACE,
+            disconnected_at: HashMap::new(),
+            saved_names: HashMap::new(),
+            announced_absence: false,
         }
     }
-
-    fn broadcast_game_state(&mut self) -> Result<(), std::io::Error> {
-        // Update last activity timestamp
-        self.last_activity = SystemTime::now();
+    
+    // Correspondence games wait far longer for a move and are saved to snapshot_dir as they go
+    fn set_correspondence(&mut self, max_inactive_time: Duration, snapshot_dir: &Path) {
+        self.time_mode = TimeMode::Correspondence;
+        self.max_inactive_time = max_inactive_time;
+        self.snapshot_path = Some(snapshot_dir.join(format!("{}.json", self.id)));
+    }
+    
+    // Rebuild a saved correspondence game with empty seats, which players take back with Rejoin
+    // or TakeOverSlot
+    fn from_snapshot(snapshot: CorrespondenceSnapshot, max_inactive_time: Duration, snapshot_dir: &Path) -> Option<Self> {
+        let mut game = Game::new(snapshot.game_id, snapshot.host_name);
+        for uci in &snapshot.moves {
+            if !game.game_state.apply_uci_move(uci) {
+                println!("Saved game {} has an illegal move {}, not resuming it", game.id, uci);
+                return None;
+            }
+        }
+        
+        game.set_correspondence(max_inactive_time, snapshot_dir);
+        game.takeover_token = snapshot.takeover_token;
+        for (color, name, session) in [
+            (Color::White, snapshot.white_name, snapshot.white_session),
+            (Color::Black, snapshot.black_name, snapshot.black_session),
+        ] {
+            game.saved_names.insert(color, name);
+            if let Some(session) = session {
+                game.session_tokens.insert(color, session);
+            }
+        }
+        game.created_at = snapshot.created_at;
+        game.last_activity = UNIX_EPOCH + Duration::from_secs(snapshot.last_activity_at);
+        game.white_to_move = game.game_state.current_turn.is_white();
+        game.status = GameStatus::InProgress;
+        Some(game)
+    }
+    
+    fn save_snapshot(&self) {
+        let path = match &self.snapshot_path {
+            Some(path) => path,
+            None => return,
+        };
+        
+        let last_activity_at = self.last_activity
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let snapshot = CorrespondenceSnapshot {
+            game_id: self.id.clone(),
+            host_name: self.host_name.clone(),
+            takeover_token: self.takeover_token.clone(),
+            created_at: self.created_at,
+            last_activity_at,
+            moves: self.game_state.move_log_uci(),
+            white_name: self.player_name(Color::White),
+            black_name: self.player_name(Color::Black),
+            white_session: self.session_tokens.get(&Color::White).cloned(),
+            black_session: self.session_tokens.get(&Color::Black).cloned(),
+        };
+        
+        let result = path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, serde_json::to_string(&snapshot)?));
+        if let Err(e) = result {
+            println!("Error saving correspondence game {}: {}", self.id, e);
+        }
+    }
+    
+    fn remove_snapshot(&self) {
+        if let Some(path) = &self.snapshot_path {
+            let _ = fs::remove_file(path);
+        }
+    }
+    
+    // How long the game loop sleeps when neither player is connected to wait on
+    fn idle_interval(&self) -> Duration {
+        match self.time_mode {
+            TimeMode::Live => EMPTY_SEATS_INTERVAL,
+            TimeMode::Correspondence => CORRESPONDENCE_IDLE_INTERVAL,
+        }
+    }
+    
+    fn can_offer_draw(&self, color: Color) -> bool {
+        match self.declined_draw_offers.get(&color) {
+            Some(&declined_at) => self.game_state.fullmove_number >= declined_at + self.draw_offer_cooldown,
+            None => true,
+        }
+    }
+    
+    // Pass a draw offer on to the opponent, unless the offerer is still in their cooldown
+    fn offer_draw(&mut self, offerer: Color) -> Result<(), ChessNetError> {
+        if !self.can_offer_draw(offerer) {
+            // Drop the offer, only the offerer is told why
+            let notice = NetworkMessage::ChatMessage {
+                sender: "System".to_string(),
+                message: format!("You can offer another draw after {} moves", self.draw_offer_cooldown),
+                is_spectator: false,
+                player_color_tag: None,
+            };
+            return self.send_to_player(offerer, &notice);
+        }
+        
+        self.draw_offer = Some(offerer);
+        self.send_to_player(offerer.opposite(), &NetworkMessage::DrawOffered)
+    }
+    
+    // End the game as a draw if the opponent has an offer waiting, returning whether it ended.
+    // An AcceptDraw with nothing to accept is refused and the game goes on
+    fn accept_draw(&mut self, accepter: Color) -> Result<bool, ChessNetError> {
+        if self.draw_offer != Some(accepter.opposite()) {
+            println!("{:?} accepted a draw nobody offered in game {}", accepter, self.id);
+            let notice = NetworkMessage::ChatMessage {
+                sender: "System".to_string(),
+                message: "There is no draw offer to accept".to_string(),
+                is_spectator: false,
+                player_color_tag: None,
+            };
+            self.send_to_player(accepter, &notice)?;
+            return Ok(false);
+        }
+        self.draw_offer = None;
+        
+        // Both players hear the acceptance, then the result
+        for color in [Color::White, Color::Black] {
+            self.send_to_player(color, &NetworkMessage::AcceptDraw)?;
+        }
+        
+        self.ending = Some(GameEnding::DrawAgreed);
+        let end_message = self.end_message("Draw agreed".to_string());
+        self.broadcast_message(&end_message)?;
+        
+        self.status = GameStatus::Completed;
+        self.game_state.game_over = true;
+        Ok(true)
+    }
+    
+    // Tell the offerer their offer was turned down and start their cooldown
+    fn decline_draw(&mut self, decliner: Color) -> Result<(), ChessNetError> {
+        let offerer = decliner.opposite();
+        if self.draw_offer != Some(offerer) {
+            println!("{:?} declined a draw nobody offered in game {}", decliner, self.id);
+            return Ok(());
+        }
+        self.draw_offer = None;
+        self.declined_draw_offers.insert(offerer, self.game_state.fullmove_number);
+        self.send_to_player(offerer, &NetworkMessage::DeclineDraw)
+    }
+    
+    // The name a seat was taken with, remembered from the snapshot while its player is yet to rejoin
+    fn player_name(&self, color: Color) -> String {
+        let client = match color {
+            Color::White => &self.white_client,
+            Color::Black => &self.black_client,
+        };
+        match client {
+            Some(client) => client.player_name.clone(),
+            None => self.saved_names.get(&color).cloned().unwrap_or_default(),
+        }
+    }
+    
+    // The color tag a player joined with, shown to their opponent and on their chat
+    fn player_color_tag(&self, color: Color) -> Option<PlayerColorTag> {
+        let client = match color {
+            Color::White => &self.white_client,
+            Color::Black => &self.black_client,
+        };
+        client.as_ref().and_then(|client| client.player_color_tag)
+    }
+    
+    // Send a message to one player only
+    fn send_to_player(&mut self, color: Color, message: &NetworkMessage) -> Result<(), ChessNetError> {
+        let serialized = format!("{}\n", serde_json::to_string(message)?);
+        
+        let client = match color {
+            Color::White => &mut self.white_client,
+            Color::Black => &mut self.black_client,
+        };
+        
+        if let Some(client) = client {
+            if let Some(stream) = &mut client.stream {
+                if let Err(e) = stream.write_all(serialized.as_bytes()) {
+                    println!("Error sending to {:?} client: {}", color, e);
+                    client.stream = None;
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    // Every end path records how the game ended, so the GameEnd banner and any PGN export agree
+    fn termination_reason(&self) -> Option<TerminationReason> {
+        match self.ending {
+            Some(GameEnding::Resignation(_)) => Some(TerminationReason::Resignation),
+            Some(GameEnding::Forfeit(_)) => Some(TerminationReason::Forfeit),
+            Some(GameEnding::Timeout(_)) => Some(TerminationReason::Timeout),
+            Some(GameEnding::DrawAgreed) => Some(TerminationReason::DrawAgreed),
+            None => self.game_state.status().terminal,
+        }
+    }
+    
+    // Single source of truth for who won, used for GameEnd and any scoring
+    fn final_result(&self) -> GameResult {
+        match self.ending {
+            Some(GameEnding::Resignation(loser)) |
+            Some(GameEnding::Forfeit(loser)) |
+            Some(GameEnding::Timeout(loser)) => GameResult::win_for(loser.opposite()),
+            Some(GameEnding::DrawAgreed) => GameResult::Draw,
+            None => match self.game_state.winner() {
+                Some(color) => GameResult::win_for(color),
+                None => GameResult::Draw,
+            },
+        }
+    }
+    
+    // The GameEnd everyone gets. An ending the board didn't decide is written into the board too,
+    // so the game's PGN carries the same result and Termination tag
+    fn end_message(&mut self, reason: String) -> NetworkMessage {
+        let result = self.final_result();
+        let termination = self.termination_reason();
+        if let Some(termination) = termination.filter(|_| self.ending.is_some()) {
+            self.game_state.set_ending(termination, result);
+        }
+        NetworkMessage::GameEnd { reason, result: Some(result), termination }
+    }
+    
+    fn send_game_start(&mut self) -> Result<(), ChessNetError> {
+        self.white_ready = false;
+        self.black_ready = false;
+        
+        for color in [Color::White, Color::Black] {
+            let message = self.game_start_message(color);
+            self.send_to_player(color, &message)?;
+        }
         
+        Ok(())
+    }
+    
+    // Each player learns both names, the clock settings and their own session token in a single message
+    fn game_start_message(&self, color: Color) -> NetworkMessage {
+        let white_name = self.player_name(Color::White);
+        let black_name = self.player_name(Color::Black);
+        let opponent_name = match color {
+            Color::White => black_name.clone(),
+            Color::Black => white_name.clone(),
+        };
+        
+        NetworkMessage::GameStart {
+            is_white: color.is_white(),
+            game_id: self.id.clone(),
+            opponent_name,
+            white_name,
+            black_name,
+            time_control: self.time_control,
+            opponent_color_tag: self.player_color_tag(color.opposite()),
+            session_token: self.session_tokens.get(&color).cloned(),
+        }
+    }
+
+    fn game_state_message(&self) -> NetworkMessage {
         let board_state = self.game_state.board.map(|row| {
             row.map(|cell| cell.map(|piece| (piece.piece_type, piece.color)))
         });
 
-        let message = NetworkMessage::GameState {
+        NetworkMessage::GameState {
             board: board_state,
             current_turn: self.game_state.current_turn,
             promotion_pending: self.game_state.promotion_pending.as_ref().map(|p| (p.position.0, p.position.1, p.color)),
             game_over: self.game_state.is_game_over(),
+            moves: self.game_state.move_log_uci(),
+        }
+    }
+
+    // The asker's own moves, which means an empty list unless it's their turn
+    fn legal_moves_message(&mut self, requester: Color) -> NetworkMessage {
+        let moves = if requester == self.game_state.current_turn {
+            self.game_state.legal_moves_uci()
+        } else {
+            Vec::new()
         };
+        NetworkMessage::LegalMoves { moves }
+    }
+
+    fn broadcast_game_state(&mut self) -> Result<(), ChessNetError> {
+        // Update last activity timestamp
+        self.last_activity = SystemTime::now();
+        
+        let message = self.game_state_message();
 
         let serialized = format!("{}\n", serde_json::to_string(&message)?);
         
@@ -107,7 +629,7 @@ impl Game {
         Ok(())
     }
     
-    fn broadcast_message(&mut self, message: &NetworkMessage) -> Result<(), std::io::Error> {
+    fn broadcast_message(&mut self, message: &NetworkMessage) -> Result<(), ChessNetError> {
         // Update last activity timestamp
         self.last_activity = SystemTime::now();
         
@@ -155,7 +677,7 @@ impl Game {
         Ok(())
     }
     
-    fn add_spectator(&mut self, mut spectator: ChessClient, name: String) -> Result<(), std::io::Error> {
+    fn add_spectator(&mut self, mut spectator: ChessClient, name: String) -> Result<(), ChessNetError> {
         // Generate a unique spectator ID
         let spectator_id = Uuid::new_v4().to_string();
         
@@ -168,39 +690,30 @@ impl Game {
         
         // Add to chat history
         let system_message = format!("{} joined as spectator", name);
-        self.chat_history.push(("System".to_string(), system_message.clone(), true));
+        self.chat_history.push(("System".to_string(), system_message.clone(), true, None));
         
         // Send current chat history to the new spectator
         if let Some(stream) = &mut spectator.stream {
             // First send the game state
-            let board_state = self.game_state.board.map(|row| {
-                row.map(|cell| cell.map(|piece| (piece.piece_type, piece.color)))
-            });
-
-            let state_message = NetworkMessage::GameState {
-                board: board_state,
-                current_turn: self.game_state.current_turn,
-                promotion_pending: self.game_state.promotion_pending.as_ref()
-                    .map(|p| (p.position.0, p.position.1, p.color)),
-                game_over: self.game_state.is_game_over(),
-            };
+            let state_message = self.game_state_message();
             
             let serialized = format!("{}\n", serde_json::to_string(&state_message)?);
             if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                return Err(e);
+                return Err(e.into());
             }
             
             // Then send chat history
-            for (sender, message, is_spectator) in &self.chat_history {
+            for (sender, message, is_spectator, player_color_tag) in &self.chat_history {
                 let chat_message = NetworkMessage::ChatMessage {
                     sender: sender.clone(),
                     message: message.clone(),
                     is_spectator: *is_spectator,
+                    player_color_tag: *player_color_tag,
                 };
                 
                 let serialized = format!("{}\n", serde_json::to_string(&chat_message)?);
                 if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                    return Err(e);
+                    return Err(e.into());
                 }
             }
             
@@ -209,11 +722,12 @@ impl Game {
                 sender: "System".to_string(),
                 message: system_message,
                 is_spectator: true,
+                player_color_tag: None,
             };
             
             let serialized = format!("{}\n", serde_json::to_string(&system_chat)?);
             if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                return Err(e);
+                return Err(e.into());
             }
         }
         
@@ -223,22 +737,9 @@ impl Game {
         Ok(())
     }
     
-    fn remove_spectator(&mut self, spectator_id: &str) -> Result<(), std::io::Error> {
-        if let Some(spectator) = self.spectators.remove(spectator_id) {
-            // Notify others that a spectator has left
-            // We'd need to store spectator names to make this work properly
-            let left_message = NetworkMessage::SpectatorLeft { 
-                name: "Spectator".to_string() 
-            };
-            self.broadcast_message(&left_message)?;
-        }
-        
-        Ok(())
-    }
-    
-    fn handle_chat_message(&mut self, sender: String, message: String, is_spectator: bool) -> Result<(), std::io::Error> {
+    fn handle_chat_message(&mut self, sender: String, message: String, is_spectator: bool, player_color_tag: Option<PlayerColorTag>) -> Result<(), ChessNetError> {
         // Add to chat history
-        self.chat_history.push((sender.clone(), message.clone(), is_spectator));
+        self.chat_history.push((sender.clone(), message.clone(), is_spectator, player_color_tag));
         
         // Limit chat history size
         if self.chat_history.len() > 100 {
@@ -250,19 +751,23 @@ impl Game {
             sender,
             message,
             is_spectator,
+            player_color_tag,
         };
         
         self.broadcast_message(&chat_message)
     }
     
-    fn handle_forfeit(&mut self, white_forfeits: bool) -> Result<(), std::io::Error> {
+    fn handle_forfeit(&mut self, white_forfeits: bool) -> Result<(), ChessNetError> {
         let reason = if white_forfeits {
             "White player forfeited the game"
         } else {
             "Black player forfeited the game"
         };
         
-        let end_message = NetworkMessage::GameEnd { reason: reason.to_string() };
+        let loser = Color::from_bool(white_forfeits);
+        self.ending = Some(GameEnding::Forfeit(loser));
+        
+        let end_message = self.end_message(reason.to_string());
         self.broadcast_message(&end_message)?;
         
         self.status = GameStatus::Completed;
@@ -271,676 +776,1647 @@ impl Game {
         Ok(())
     }
 
-    fn run(&mut self) -> Result<(), std::io::Error> {
-        println!("Starting game: {}", self.id);
+    // One GameEnd for everyone, spectators included, so nobody has to piece the result together
+    fn handle_resignation(&mut self, resigner: Color) -> Result<(), ChessNetError> {
+        self.ending = Some(GameEnding::Resignation(resigner));
         
-        // Send initial game state
-        self.broadcast_game_state()?;
+        let end_message = self.end_message(format!("{:?} resigned", resigner));
+        self.broadcast_message(&end_message)?;
+        
+        self.status = GameStatus::Completed;
+        self.game_state.game_over = true;
+        
+        Ok(())
+    }
 
-        // Start game loop
-        let mut current_turn = true; // true for white, false for black
+    fn handle_timeout(&mut self, flagged: Color) -> Result<(), ChessNetError> {
+        self.ending = Some(GameEnding::Timeout(flagged));
         
-        self.status = GameStatus::InProgress;
+        let end_message = self.end_message(format!("{:?} loses on time", flagged));
+        self.broadcast_message(&end_message)?;
         
-        loop {
-            // Process spectator messages first
-            let mut disconnected_spectators = Vec::new();
-            
-            let mut chat_messages = Vec::new();
-            for (id, spectator) in &mut self.spectators {
-                match spectator.receive_message() {
-                    Ok(Some(NetworkMessage::ChatMessage { sender, message, is_spectator })) => {
-                        // Collect chat messages to handle later
-                        chat_messages.push((sender, message, is_spectator));
-                    },
-                    Ok(Some(_)) => {
-                        // Ignore other messages from spectators
-                    },
-                    Ok(None) => {
-                        // No message, continue
-                    },
-                    Err(_) => {
-                        // Connection error, mark for removal
-                        disconnected_spectators.push(id.clone());
-                    }
-                }
-            }
-            
-            // Remove disconnected spectators
-            // Remove disconnected spectators
-            for id in &disconnected_spectators {
-                self.spectators.remove(id);
+        self.status = GameStatus::Completed;
+        self.game_state.game_over = true;
+        
+        Ok(())
+    }
+
+    // A substitute may only sit down in a running game that allows it, in a seat that's been vacated
+    fn check_takeover(&self, color: Color, token: &str) -> Result<(), &'static str> {
+        if self.takeover_token.as_deref() != Some(token) {
+            return Err(if self.takeover_token.is_none() {
+                "This game doesn't allow take-overs"
+            } else {
+                "Wrong take-over token"
+            });
+        }
+        if self.status != GameStatus::InProgress {
+            return Err("The game is not in progress");
+        }
+        
+        let seat = match color {
+            Color::White => &self.white_client,
+            Color::Black => &self.black_client,
+        };
+        if seat.as_ref().is_some_and(|client| client.stream.is_some()) {
+            return Err("That player is still connected");
+        }
+        
+        Ok(())
+    }
+    
+    // Seat the substitute and bring everyone up to date, the opponent included since the name changed
+    fn seat_substitute(&mut self, mut client: ChessClient, color: Color, player_name: String) -> Result<(), ChessNetError> {
+        let is_white = color.is_white();
+        client.set_role(ClientRole::Player { is_white });
+        client.player_name = player_name.clone();
+        match color {
+            Color::White => self.white_client = Some(client),
+            Color::Black => self.black_client = Some(client),
+        }
+        
+        self.send_game_start()?;
+        self.broadcast_game_state()?;
+        if let Some(clock) = &self.clock {
+            let clock_message = clock.update_message();
+            self.broadcast_message(&clock_message)?;
+        }
+        
+        self.handle_chat_message("System".to_string(), format!("{} took over as {:?}", player_name, color), true, None)
+    }
+    
+    // The seat a session token belongs to. The token proves who it is, so a seat whose old connection
+    // hasn't been noticed as dead yet can be taken back too
+    fn check_rejoin(&self, session_token: &str) -> Result<Color, &'static str> {
+        let color = self.session_tokens.iter()
+            .find(|(_, token)| token.as_str() == session_token)
+            .map(|(color, _)| *color)
+            .ok_or("Unknown session token")?;
+        if self.status != GameStatus::InProgress {
+            return Err("The game is not in progress");
+        }
+        
+        Ok(color)
+    }
+    
+    // Put a returning player back in their seat, keeping the name and color tag they joined with.
+    // The next pass of the game loop tells the opponent they're back
+    fn rejoin(&mut self, mut client: ChessClient, color: Color) -> Result<(), ChessNetError> {
+        let seat = match color {
+            Color::White => &mut self.white_client,
+            Color::Black => &mut self.black_client,
+        };
+        match seat.take() {
+            Some(previous) => {
+                client.player_name = previous.player_name;
+                client.player_color_tag = previous.player_color_tag;
             }
+            None => client.player_name = self.saved_names.get(&color).cloned().unwrap_or_default(),
+        }
+        client.set_role(ClientRole::Player { is_white: color.is_white() });
+        let player_name = client.player_name.clone();
+        *seat = Some(client);
+        
+        let start = self.game_start_message(color);
+        self.send_to_player(color, &start)?;
+        let state = self.game_state_message();
+        self.send_to_player(color, &state)?;
+        if let Some(clock) = &self.clock {
+            let clock_message = clock.update_message();
+            self.send_to_player(color, &clock_message)?;
+        }
+        
+        self.handle_chat_message("System".to_string(), format!("{} reconnected", player_name), true, None)
+    }
+    
+    // Notice players dropping and coming back, and tell their opponent either way. Only live games
+    // count down, a correspondence player has max_inactive_time on their move instead
+    fn track_disconnects(&mut self) -> Result<(), ChessNetError> {
+        for color in [Color::White, Color::Black] {
+            let seat = match color {
+                Color::White => &self.white_client,
+                Color::Black => &self.black_client,
+            };
+            let connected = matches!(seat, Some(client) if client.stream.is_some());
             
-            // Handle collected chat messages
-            for (sender, message, is_spectator) in chat_messages {
-                if let Err(e) = self.handle_chat_message(sender, message, is_spectator) {
-                    println!("Error handling chat message: {}", e);
+            if !connected && !self.disconnected_at.contains_key(&color) {
+                self.disconnected_at.insert(color, Instant::now());
+                if self.time_mode == TimeMode::Live {
+                    println!("{:?} disconnected, waiting {}s for them to rejoin", color, self.reconnect_grace.as_secs());
+                    let notice = NetworkMessage::OpponentDisconnected { grace_secs: self.reconnect_grace.as_secs() };
+                    self.send_to_player(color.opposite(), &notice)?;
                 }
+            } else if connected && self.disconnected_at.remove(&color).is_some() {
+                println!("{:?} rejoined", color);
+                self.send_to_player(color.opposite(), &NetworkMessage::OpponentReconnected)?;
             }
-            // Check if both players are still connected
-            let white_connected = self.white_client.as_ref().map_or(false, |c| c.stream.is_some());
-            let black_connected = self.black_client.as_ref().map_or(false, |c| c.stream.is_some());
-            
-            if !white_connected && !black_connected && self.spectators.is_empty() {
-                println!("All clients disconnected, ending game");
-                self.status = GameStatus::Completed;
-                break;
-            }
-
-            let sender = if current_turn {
-                match self.white_client.as_mut() {
-                    Some(client) if client.stream.is_some() => client,
-                    _ => {
-                        // White player disconnected or not available, skip turn
-                        // If we've been waiting too long and have a black player, white forfeits
-                        if black_connected && 
-                           self.last_activity.elapsed().unwrap_or_default() > MAX_INACTIVE_TIME {
-                            println!("White player inactive too long, forfeiting");
-                            self.handle_forfeit(true)?; // true = white forfeits
-                            break;
-                        }
-                        
-                        // Just skip turn and keep waiting
-                        println!("White player not available, waiting...");
-                        thread::sleep(Duration::from_millis(100));
-                        continue;
-                    }
-                }
-            } else {
-                match self.black_client.as_mut() {
-                    Some(client) if client.stream.is_some() => client,
-                    _ => {
-                        // Black player disconnected or not available
-                        // If we've been waiting too long and have a white player, black forfeits
-                        if white_connected && 
-                           self.last_activity.elapsed().unwrap_or_default() > MAX_INACTIVE_TIME {
-                            println!("Black player inactive too long, forfeiting");
-                            self.handle_forfeit(false)?; // false = black forfeits
-                            break;
-                        }
-                        
-                        // Just skip turn and keep waiting
-                        println!("Black player not available, waiting...");
-                        thread::sleep(Duration::from_millis(100));
-                        continue;
-                    }
-                }
+        }
+        
+        Ok(())
+    }
+    
+    // A live player who stayed away past the grace period, while their opponent is still here
+    fn grace_expired(&self) -> Option<Color> {
+        if self.time_mode != TimeMode::Live {
+            return None;
+        }
+        
+        [Color::White, Color::Black].into_iter().find(|&color| {
+            let opponent = match color {
+                Color::White => &self.black_client,
+                Color::Black => &self.white_client,
             };
-
-            // Wait for move from current player
-            match sender.receive_message() {
-                Ok(Some(NetworkMessage::ConnectionStatus { .. })) => {
-                    println!("Unexpected ConnectionStatus message during game");
-                },
-                Ok(Some(NetworkMessage::SpectateGame { .. })) => {
-                    println!("Unexpected SpectateGame message during game");
-                },
-                Ok(Some(NetworkMessage::SpectatorJoined { .. })) => {
-                    println!("Unexpected SpectatorJoined message from client");
-                },
-                Ok(Some(NetworkMessage::SpectatorLeft { .. })) => {
-                    println!("Unexpected SpectatorLeft message from client");
-                },
-                Ok(Some(NetworkMessage::Heartbeat)) => {
-                    // Respond to heartbeat with a heartbeat
-                    let heartbeat = NetworkMessage::Heartbeat;
-                    if let Some(stream) = &mut sender.stream {
-                        let serialized = format!("{}\n", serde_json::to_string(&heartbeat)?);
-                        if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                            println!("Error sending heartbeat: {}", e);
+            let opponent_connected = matches!(opponent, Some(client) if client.stream.is_some());
+            let expired = matches!(self.disconnected_at.get(&color), Some(since) if since.elapsed() > self.reconnect_grace);
+            opponent_connected && expired
+        })
+    }
+    
+    // Only the requester's own last move can go, and only while the opponent hasn't answered it
+    fn handle_takeback_request(&mut self, requester: Color) -> Result<(), ChessNetError> {
+        let side_to_move = Color::from_bool(self.white_to_move);
+        let refusal = if !self.instant_takeback {
+            Some("Takebacks are not enabled on this server")
+        } else if requester == side_to_move || self.game_state.promotion_pending.is_some() {
+            Some("Your opponent has already replied")
+        } else if !self.game_state.take_back() {
+            Some("There is no move to take back")
+        } else {
+            None
+        };
+        
+        if let Some(reason) = refusal {
+            let message = NetworkMessage::TakebackRefused { reason: reason.to_string() };
+            return self.send_to_player(requester, &message);
+        }
+        
+        println!("{:?} took back their last move", requester);
+        self.white_to_move = !self.white_to_move;
+        self.last_activity = SystemTime::now();
+        if let Some(clock) = &mut self.clock {
+            clock.restart_turn();
+        }
+        
+        self.broadcast_message(&NetworkMessage::MoveTakenBack { color: requester })?;
+        if let Some(clock) = &self.clock {
+            let clock_message = clock.update_message();
+            self.broadcast_message(&clock_message)?;
+        }
+        self.broadcast_game_state()?;
+        self.save_snapshot();
+        Ok(())
+    }
+    
+    // Whether the player whose messages are being read may play this move now. While a promotion
+    // is pending the only acceptable Move is the one naming the piece. A promotion piece outside
+    // this game's options is refused here, before the pawn has moved
+    fn check_move(&mut self, mover: Color, from: (u8, u8), to: (u8, u8), piece_type: Option<PieceType>) -> Result<(), &'static str> {
+        if let Some(pending) = &self.game_state.promotion_pending {
+            return match piece_type {
+                _ if pending.color != mover => Err("It is not your turn"),
+                None => Err("Choose the piece to promote the pawn to first"),
+                Some(piece_type) if !self.game_state.promotion_options.contains(&piece_type) => Err("The pawn can't promote to that piece"),
+                Some(_) => Ok(()),
+            };
+        }
+        
+        if mover != self.game_state.current_turn {
+            return Err("It is not your turn");
+        }
+        
+        let from = (from.0 as usize, from.1 as usize);
+        let to = (to.0 as usize, to.1 as usize);
+        if !self.game_state.get_all_legal_moves().contains(&(from, to)) {
+            return Err("That move is not legal in this position");
+        }
+        
+        match piece_type {
+            Some(piece_type) if self.game_state.is_promotion_move(from, to) && !self.game_state.promotion_options.contains(&piece_type) => {
+                Err("The pawn can't promote to that piece")
+            }
+            _ => Ok(()),
+        }
+    }
+    
+    // Tell a player their move wasn't played, followed by the board the server really has so the
+    // client can't stay out of step with it. Nothing changes, including whose turn it is
+    fn reject_move(&mut self, mover: Color, from: (u8, u8), to: (u8, u8), move_seq: Option<u64>, reason: &str) -> Result<(), ChessNetError> {
+        println!("Rejected {:?} move {:?} -> {:?} in game {}: {}", mover, from, to, self.id, reason);
+        let rejection = NetworkMessage::MoveRejected { from, to, reason: reason.to_string(), move_seq };
+        self.send_to_player(mover, &rejection)?;
+        let state = self.game_state_message();
+        self.send_to_player(mover, &state)
+    }
+    
+    // Charge the clock and tell everyone about a move that has been fully applied
+    fn finish_move(&mut self, mover: Color, from: (u8, u8), to: (u8, u8), promotion: Option<char>) {
+        if let Some(clock) = &mut self.clock {
+            clock.complete_move(mover);
+        }
+        
+        // Moving instead of answering lets the opponent's draw offer lapse
+        if self.draw_offer == Some(mover.opposite()) {
+            self.draw_offer = None;
+        }
+        
+        // Mate and the draws the board decides end the game here; poll sends GameEnd once it sees game_over
+        if self.game_state.status().terminal.is_some() {
+            self.game_state.game_over = true;
+        }
+        
+        // Broadcast the move itself so clients can animate it
+        let move_message = NetworkMessage::Move { from, to, promotion, move_seq: None };
+        if let Err(e) = self.broadcast_message(&move_message) {
+            println!("Error broadcasting move: {}", e);
+        }
+        
+        if let Some(clock) = &self.clock {
+            let clock_message = clock.update_message();
+            if let Err(e) = self.broadcast_message(&clock_message) {
+                println!("Error broadcasting clocks: {}", e);
+            }
+        }
+        
+        // Broadcast updated game state to both clients
+        if let Err(e) = self.broadcast_game_state() {
+            println!("Error broadcasting game state: {}", e);
+        }
+        
+        self.save_snapshot();
+    }
+    
+    // A player who drops before choosing a promotion piece would freeze the game, so pick the first
+    // piece this game offers for them, a queen in standard chess
+    fn auto_queen_pending_promotion(&mut self, mover: Color) -> bool {
+        let owes_promotion = matches!(&self.game_state.promotion_pending, Some(pending) if pending.color == mover);
+        if !owes_promotion {
+            return false;
+        }
+        
+        let piece_type = match self.game_state.promotion_options.first() {
+            Some(&piece_type) => piece_type,
+            None => return false,
+        };
+        if let Some((from, to)) = self.pending_promotion_move.take() {
+            if self.game_state.promote_pawn(piece_type) {
+                let promotion = Piece::new(piece_type, Color::White).to_fen_char();
+                self.finish_move(mover, from, to, Some(promotion));
+                return true;
+            }
+        }
+        
+        false
+    }
+    
+    fn start(&mut self) -> Result<(), ChessNetError> {
+        println!("Starting game: {}", self.id);
+        
+        // Send initial game state
+        self.broadcast_game_state()?;
+        
+        // Start white's clock for timed games
+        self.clock = self.time_control.map(GameClock::new);
+        if let Some(clock) = &self.clock {
+            let clock_message = clock.update_message();
+            self.broadcast_message(&clock_message)?;
+        }
+
+        self.white_to_move = true;
+        self.status = GameStatus::InProgress;
+        self.save_snapshot();
+        
+        Ok(())
+    }
+    
+    // Pick up the players' replies to GameStart, true once both have arrived.
+    // Only a Ready at the front of a queue is taken, so nothing sent after it is skipped
+    fn collect_ready(&mut self) -> bool {
+        for (client, ready) in [(&mut self.white_client, &mut self.white_ready), (&mut self.black_client, &mut self.black_ready)] {
+            if let Some(client) = client {
+                if !*ready && client.stream.is_some() {
+                    let _ = client.fill_buffer();
+                    *ready = client.receive_message_if(|msg| matches!(msg, NetworkMessage::Ready)).is_some();
+                }
+            }
+        }
+        
+        self.white_ready && self.black_ready
+    }
+    
+    // One pass of the game loop. The caller holds this game's lock only for the pass, so the
+    // server can list, join and spectate games in between. Returns true once the game is over.
+    fn poll(&mut self) -> Result<bool, ChessNetError> {
+        // Process spectator messages first
+        let mut disconnected_spectators = Vec::new();
+        
+        let mut chat_messages = Vec::new();
+        for (id, spectator) in &mut self.spectators {
+            if spectator.is_unresponsive() {
+                println!("Spectator {} stopped responding", spectator.player_name);
+                disconnected_spectators.push(id.clone());
+                continue;
+            }
+            
+            match spectator.receive_message() {
+                Ok(Some(NetworkMessage::ChatMessage { sender, message, is_spectator, .. })) => {
+                    // Collect chat messages to handle later, with the tag this spectator joined with
+                    chat_messages.push((sender, message, is_spectator, spectator.player_color_tag));
+                },
+                Ok(Some(_)) => {
+                    // Ignore other messages from spectators
+                },
+                Ok(None) => {
+                    // No message, continue
+                },
+                Err(_) => {
+                    // Connection error, mark for removal
+                    disconnected_spectators.push(id.clone());
+                }
+            }
+        }
+        
+        // Remove disconnected spectators
+        // Remove disconnected spectators
+        for id in &disconnected_spectators {
+            self.spectators.remove(id);
+        }
+        
+        // Handle collected chat messages
+        for (sender, message, is_spectator, player_color_tag) in chat_messages {
+            if let Err(e) = self.handle_chat_message(sender, message, is_spectator, player_color_tag) {
+                println!("Error handling chat message: {}", e);
+            }
+        }
+        // Flag fall is decided by the server's own timing, whatever the client claims
+        let side_to_move = Color::from_bool(self.white_to_move);
+        if self.clock.as_ref().is_some_and(|clock| clock.is_flagged(side_to_move)) {
+            println!("{:?} ran out of time", side_to_move);
+            self.handle_timeout(side_to_move)?;
+            return Ok(true);
+        }
+        
+        // A player whose connection died silently is only noticed by the missing heartbeats.
+        // The side not to move isn't read otherwise, so pull in what it sent to keep its timer fresh.
+        // Nothing that side sends has to wait for its turn, so its whole queue is handled here and one
+        // message can never hold up the ones behind it
+        let white_to_move = self.white_to_move;
+        let mut waiting_messages = Vec::new();
+        for (client, is_white) in [(&mut self.white_client, true), (&mut self.black_client, false)] {
+            if let Some(client) = client {
+                if client.stream.is_none() {
+                    continue;
+                }
+                if is_white != white_to_move {
+                    let _ = client.fill_buffer();
+                    while let Some(message) = client.receive_message_if(|_| true) {
+                        // A takeback hands this side the move, so whatever follows it is read as the mover's
+                        let takeback = matches!(message, NetworkMessage::RequestTakeback);
+                        waiting_messages.push(message);
+                        if takeback {
                             break;
                         }
                     }
-                },
+                }
+                if client.stream.is_some() && client.is_unresponsive() {
+                    println!("{} player stopped responding, treating as disconnected",
+                             if is_white { "White" } else { "Black" });
+                    client.stream = None;
+                }
+            }
+        }
+        
+        let waiting = Color::from_bool(white_to_move).opposite();
+        for message in waiting_messages {
+            match message {
+                // Resigning doesn't wait for the turn, and the win goes to the side to move
+                NetworkMessage::Resign => {
+                    self.handle_resignation(waiting)?;
+                    return Ok(true);
+                }
+                NetworkMessage::ChatMessage { sender, message, is_spectator, .. } => {
+                    let player_color_tag = self.player_color_tag(waiting);
+                    if let Err(e) = self.handle_chat_message(sender, message, is_spectator, player_color_tag) {
+                        println!("Error handling chat message: {}", e);
+                    }
+                }
+                NetworkMessage::RequestGameState => {
+                    let state = self.game_state_message();
+                    self.send_to_player(waiting, &state)?;
+                }
+                NetworkMessage::RequestLegalMoves => {
+                    let moves = self.legal_moves_message(waiting);
+                    self.send_to_player(waiting, &moves)?;
+                }
+                // Refused now rather than played once the turn comes round
+                NetworkMessage::Move { from, to, move_seq, .. } => {
+                    self.reject_move(waiting, from, to, move_seq, "It is not your turn")?;
+                }
+                NetworkMessage::OfferDraw => self.offer_draw(waiting)?,
+                NetworkMessage::AcceptDraw => {
+                    if self.accept_draw(waiting)? {
+                        return Ok(true);
+                    }
+                }
+                NetworkMessage::DeclineDraw => self.decline_draw(waiting)?,
+                NetworkMessage::RequestRematch => {
+                    self.send_to_player(waiting.opposite(), &NetworkMessage::RequestRematch)?;
+                }
+                NetworkMessage::RequestTakeback => {
+                    self.handle_takeback_request(waiting)?;
+                    return Ok(false);
+                }
+                NetworkMessage::Ready => {}
+                other => println!("Ignoring {:?} from the player not on move", other),
+            }
+        }
+        
+        self.track_disconnects()?;
+        if let Some(color) = self.grace_expired() {
+            println!("{:?} did not rejoin within {}s, forfeiting", color, self.reconnect_grace.as_secs());
+            self.handle_forfeit(color.is_white())?;
+            return Ok(true);
+        }
+        
+        // Check if both players are still connected
+        let white_connected = self.white_client.as_ref().is_some_and(|c| c.stream.is_some());
+        let black_connected = self.black_client.as_ref().is_some_and(|c| c.stream.is_some());
+        
+        // A correspondence game waits for its players to come back, up to the forfeit below
+        if !white_connected && !black_connected && self.spectators.is_empty() && self.time_mode == TimeMode::Live {
+            println!("All clients disconnected, ending game");
+            self.status = GameStatus::Completed;
+            return Ok(true);
+        }
+
+        if (self.white_to_move && white_connected) || (!self.white_to_move && black_connected) {
+            self.announced_absence = false;
+        }
+        
+        let sender = if self.white_to_move {
+            match self.white_client.as_mut() {
+                Some(client) if client.stream.is_some() => client,
+                _ => {
+                    // Not ordinary inactivity: the opponent can't move until the promotion is finished
+                    if self.auto_queen_pending_promotion(Color::White) {
+                        println!("White disconnected during promotion, promoted to a queen");
+                        self.white_to_move = !self.white_to_move;
+                        return Ok(false);
+                    }
+                    
+                    // White player disconnected or not available, skip turn
+                    // If we've been waiting too long and have a black player, white forfeits.
+                    // A correspondence opponent doesn't need to be online to win this way
+                    if (black_connected || self.time_mode == TimeMode::Correspondence) && 
+                       self.last_activity.elapsed().unwrap_or_default() > self.max_inactive_time {
+                        println!("White player inactive too long, forfeiting");
+                        self.handle_forfeit(true)?; // true = white forfeits
+                        return Ok(true);
+                    }
+                    
+                    // Just skip turn and keep waiting, quietly for a correspondence game that may wait for days
+                    if self.time_mode == TimeMode::Live && !self.announced_absence {
+                        println!("White player not available, waiting...");
+                        self.announced_absence = true;
+                    }
+                    return Ok(false);
+                }
+            }
+        } else {
+            match self.black_client.as_mut() {
+                Some(client) if client.stream.is_some() => client,
+                _ => {
+                    if self.auto_queen_pending_promotion(Color::Black) {
+                        println!("Black disconnected during promotion, promoted to a queen");
+                        self.white_to_move = !self.white_to_move;
+                        return Ok(false);
+                    }
+                    
+                    // Black player disconnected or not available
+                    // If we've been waiting too long and have a white player, black forfeits
+                    if (white_connected || self.time_mode == TimeMode::Correspondence) && 
+                       self.last_activity.elapsed().unwrap_or_default() > self.max_inactive_time {
+                        println!("Black player inactive too long, forfeiting");
+                        self.handle_forfeit(false)?; // false = black forfeits
+                        return Ok(true);
+                    }
+                    
+                    // Just skip turn and keep waiting, quietly for a correspondence game that may wait for days
+                    if self.time_mode == TimeMode::Live && !self.announced_absence {
+                        println!("Black player not available, waiting...");
+                        self.announced_absence = true;
+                    }
+                    return Ok(false);
+                }
+            }
+        };
+
+        // Wait for move from current player
+        match sender.receive_message() {
+            Ok(Some(NetworkMessage::ConnectionStatus { .. })) => {
+                println!("Unexpected ConnectionStatus message during game");
+            },
+            Ok(Some(NetworkMessage::ClockUpdate { .. })) => {
+                // Clients never report time, the server keeps the clocks
+                println!("Ignoring ClockUpdate sent by a client");
+            },
+            Ok(Some(NetworkMessage::SpectateGame { .. })) => {
+                println!("Unexpected SpectateGame message during game");
+            },
+            Ok(Some(NetworkMessage::TakeOverSlot { .. })) => {
+                println!("Unexpected TakeOverSlot message during game");
+            },
+            Ok(Some(NetworkMessage::Rejoin { .. })) => {
+                println!("Unexpected Rejoin message during game");
+            },
+            Ok(Some(NetworkMessage::OpponentDisconnected { .. })) | Ok(Some(NetworkMessage::OpponentReconnected)) => {
+                println!("Unexpected opponent connection notice from client");
+            },
+            Ok(Some(NetworkMessage::SpectatorJoined { .. })) => {
+                println!("Unexpected SpectatorJoined message from client");
+            },
+            Ok(Some(NetworkMessage::SpectatorLeft { .. })) => {
+                println!("Unexpected SpectatorLeft message from client");
+            },
+            Ok(Some(NetworkMessage::Heartbeat)) => {
+                // Respond to heartbeat with a heartbeat
+                let heartbeat = NetworkMessage::Heartbeat;
+                if let Some(stream) = &mut sender.stream {
+                    let serialized = format!("{}\n", serde_json::to_string(&heartbeat)?);
+                    if let Err(e) = stream.write_all(serialized.as_bytes()) {
+                        println!("Error sending heartbeat: {}", e);
+                        return Ok(true);
+                    }
+                }
+            },
+
+            Ok(Some(NetworkMessage::ChatMessage { sender, message, is_spectator, .. })) => {
+                // Handle chat message from player
+                let player_color_tag = self.player_color_tag(Color::from_bool(self.white_to_move));
+                if let Err(e) = self.handle_chat_message(sender, message, is_spectator, player_color_tag) {
+                    println!("Error handling chat message: {}", e);
+                }
+            }
+
+            
+
+            Ok(Some(NetworkMessage::Move { from: wire_from, to: wire_to, promotion, move_seq })) => {
+                let mover = Color::from_bool(self.white_to_move);
+                let piece_type = match promotion {
+                    None => None,
+                    Some('Q') => Some(PieceType::Queen),
+                    Some('R') => Some(PieceType::Rook),
+                    Some('B') => Some(PieceType::Bishop),
+                    Some('N') => Some(PieceType::Knight),
+                    Some(_) => {
+                        self.reject_move(mover, wire_from, wire_to, move_seq, "Invalid promotion piece")?;
+                        return Ok(false);
+                    }
+                };
+                
+                // Checked before anything changes, so a refused move leaves the board and the turn alone
+                if let Err(reason) = self.check_move(mover, wire_from, wire_to, piece_type) {
+                    self.reject_move(mover, wire_from, wire_to, move_seq, reason)?;
+                    return Ok(false);
+                }
+                
+                // A Move while a promotion is pending only supplies the piece for the earlier move
+                if self.game_state.promotion_pending.is_none() {
+                    let from = (wire_from.0 as usize, wire_from.1 as usize);
+                    let to = (wire_to.0 as usize, wire_to.1 as usize);
+                    self.game_state.make_move(from, to);
+                }
+                
+                // A piece named for a move that doesn't promote is ignored
+                let mut promoted = None;
+                if let (Some(piece_type), true) = (piece_type, self.game_state.promotion_pending.is_some()) {
+                    // check_move already refused pieces outside the promotion options
+                    if self.game_state.promote_pawn(piece_type) {
+                        promoted = promotion;
+                    }
+                }
+                
+                if let Some(move_seq) = move_seq {
+                    self.send_to_player(mover, &NetworkMessage::MoveAck { move_seq })?;
+                }
+                
+                // The turn stays with the mover until they pick a piece
+                if self.game_state.promotion_pending.is_some() {
+                    self.pending_promotion_move = Some((wire_from, wire_to));
+                    if let Err(e) = self.broadcast_game_state() {
+                        println!("Error broadcasting game state: {}", e);
+                    }
+                    return Ok(false);
+                }
+                
+                let (from, to) = self.pending_promotion_move.take().unwrap_or((wire_from, wire_to));
+                self.finish_move(mover, from, to, promoted);
+                
+                // Switch turns
+                self.white_to_move = !self.white_to_move;
+            }
+            Ok(Some(NetworkMessage::Ready)) => {
+                // A late reply, or one to the GameStart a substitute's arrival sends mid-game
+                if self.white_to_move {
+                    self.white_ready = true;
+                } else {
+                    self.black_ready = true;
+                }
+            }
+            Ok(Some(NetworkMessage::RequestGameState)) => {
+                let mover = Color::from_bool(self.white_to_move);
+                let state = self.game_state_message();
+                self.send_to_player(mover, &state)?;
+            }
+            Ok(Some(NetworkMessage::RequestLegalMoves)) => {
+                let mover = Color::from_bool(self.white_to_move);
+                let moves = self.legal_moves_message(mover);
+                self.send_to_player(mover, &moves)?;
+            }
+            Ok(Some(NetworkMessage::LegalMoves { .. })) => {
+                // Move lists only go from the server to clients
+                println!("Received unexpected LegalMoves message");
+            }
+            Ok(Some(NetworkMessage::OfferDraw)) => {
+                let offerer = Color::from_bool(self.white_to_move);
+                self.offer_draw(offerer)?;
+            }
+            Ok(Some(NetworkMessage::AcceptDraw)) => {
+                let accepter = Color::from_bool(self.white_to_move);
+                if self.accept_draw(accepter)? {
+                    return Ok(true);
+                }
+            }
+            Ok(Some(NetworkMessage::DeclineDraw)) => {
+                let decliner = Color::from_bool(self.white_to_move);
+                self.decline_draw(decliner)?;
+            }
+            Ok(Some(NetworkMessage::Resign)) => {
+                // Only the side to move is read here, the other side's resignation is picked up above
+                let resigner = Color::from_bool(self.white_to_move);
+                self.handle_resignation(resigner)?;
+                return Ok(true);
+            }
+            Ok(Some(NetworkMessage::RequestRematch)) => {
+                // Forward rematch request to the other player
+                let rematch_request = NetworkMessage::RequestRematch;
+                let serialized = format!("{}\n", serde_json::to_string(&rematch_request)?);
+                
+                if self.white_to_move {
+                    // White is requesting a rematch, send to black
+                    if let Some(black_client) = &mut self.black_client {
+                        if let Some(stream) = &mut black_client.stream {
+                            if let Err(e) = stream.write_all(serialized.as_bytes()) {
+                                println!("Error sending rematch request to black client: {}", e);
+                                black_client.stream = None;
+                            }
+                        }
+                    }
+                } else {
+                    // Black is requesting a rematch, send to white
+                    if let Some(white_client) = &mut self.white_client {
+                        if let Some(stream) = &mut white_client.stream {
+                            if let Err(e) = stream.write_all(serialized.as_bytes()) {
+                                println!("Error sending rematch request to white client: {}", e);
+                                white_client.stream = None;
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(Some(NetworkMessage::RequestTakeback)) => {
+                let requester = Color::from_bool(self.white_to_move);
+                self.handle_takeback_request(requester)?;
+            }
+            Ok(Some(NetworkMessage::TakebackRefused { .. })) | Ok(Some(NetworkMessage::MoveTakenBack { .. })) => {
+                println!("Received unexpected takeback message from client, ignoring");
+            }
+            Ok(Some(NetworkMessage::RematchAccepted { .. })) => {
+                // This message should come from a client accepting a rematch
+                println!("Received RematchAccepted message from client, ignoring");
+                // The actual rematch handling happens in the main game loop after the game ends
+            }
+            Ok(Some(NetworkMessage::DrawOffered)) => {
+                // This message should come from the server to clients, not from clients
+                println!("Received unexpected DrawOffered message from client, ignoring");
+            }
+            Ok(Some(NetworkMessage::GameStart { .. })) => {
+                // Ignore GameStart messages after initial setup
+                println!("Received unexpected GameStart message");
+            }
+            Ok(Some(NetworkMessage::GameState { .. })) => {
+                // Ignore GameState messages from clients
+                println!("Received unexpected GameState message");
+            }
+            Ok(Some(NetworkMessage::MoveAck { .. })) | Ok(Some(NetworkMessage::MoveRejected { .. })) => {
+                // Acks and rejections only go from the server to clients
+                println!("Received unexpected move acknowledgement from client");
+            }
+            Ok(Some(NetworkMessage::GameEnd { .. })) => {
+                // Only the server decides when a game is over
+                println!("Received unexpected GameEnd message from client, ignoring");
+            }
+            Ok(Some(NetworkMessage::CreateGame { .. })) => {
+                // Ignore CreateGame messages during game
+                println!("Received unexpected CreateGame message");
+            }
+            Ok(Some(NetworkMessage::JoinGame { .. })) => {
+                // Ignore JoinGame messages during game
+                println!("Received unexpected JoinGame message");
+            }
+            Ok(Some(NetworkMessage::GameCreated { .. })) => {
+                // Ignore GameCreated messages during game
+                println!("Received unexpected GameCreated message");
+            }
+            Ok(Some(NetworkMessage::GameList { .. })) => {
+                // Ignore GameList messages during game
+                println!("Received unexpected GameList message");
+            }
+            Ok(Some(NetworkMessage::JoinRejected { .. })) => {
+                println!("Received unexpected JoinRejected message");
+            }
+            Ok(Some(NetworkMessage::RequestGameList)) => {
+                // Ignore RequestGameList messages during game
+                println!("Received unexpected RequestGameList message");
+            }
+            Ok(Some(NetworkMessage::PlayerHistory { .. })) | Ok(Some(NetworkMessage::HistoryResult { .. })) => {
+                println!("Ignoring history message during game");
+            }
+            Ok(Some(NetworkMessage::RequestThumbnail { .. })) | Ok(Some(NetworkMessage::Thumbnail { .. })) => {
+                println!("Ignoring thumbnail message during game");
+            }
+            Ok(None) => {
+                // No message received, continue
+            }
+            Err(e) => {
+                println!("Error receiving message: {}", e);
+                if e.is_disconnect() {
+                    println!("Client disconnected");
+                    if self.white_to_move {
+                        // White disconnected
+                        if let Some(white_client) = &mut self.white_client {
+                            white_client.stream = None;
+                        }
+                    } else {
+                        // Black disconnected
+                        if let Some(black_client) = &mut self.black_client {
+                            black_client.stream = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Check if game is over
+        if self.game_state.is_game_over() {
+            let reason = self.termination_reason()
+                .map(|termination| termination.to_string())
+                .unwrap_or_else(|| "Unknown".to_string());
+            
+            // Spectators hear how it ended too
+            let end_message = self.end_message(reason);
+            self.broadcast_message(&end_message)?;
+            self.status = GameStatus::Completed;
+            return Ok(true);
+        }
+        
+        Ok(false)
+    }
+    
+    // poll stops once the game is over, so until a rematch both players are read here instead.
+    // Rematch requests are passed on, and a late or duplicate move is refused with the final board
+    // so it can never touch it. Returns whether a rematch was accepted
+    fn poll_finished(&mut self) -> Result<bool, ChessNetError> {
+        let mut rematch_accepted = false;
+        for color in [Color::White, Color::Black] {
+            let seat = match color {
+                Color::White => &mut self.white_client,
+                Color::Black => &mut self.black_client,
+            };
+            let message = match seat {
+                Some(client) if client.stream.is_some() => client.receive_message(),
+                _ => continue,
+            };
+            
+            match message {
+                Ok(Some(NetworkMessage::Move { from, to, move_seq, .. })) => {
+                    self.reject_move(color, from, to, move_seq, "The game is already over")?;
+                }
+                Ok(Some(NetworkMessage::RequestGameState)) => {
+                    let state = self.game_state_message();
+                    self.send_to_player(color, &state)?;
+                }
+                Ok(Some(NetworkMessage::RequestRematch)) => {
+                    self.send_to_player(color.opposite(), &NetworkMessage::RequestRematch)?;
+                }
+                // Using AcceptDraw as a proxy for accepting rematch
+                Ok(Some(NetworkMessage::AcceptDraw)) => rematch_accepted = true,
+                Ok(_) => {}
+                Err(e) => println!("Error reading from {:?} after the game: {}", color, e),
+            }
+        }
+        
+        Ok(rematch_accepted)
+    }
+    
+    fn reset_game(&mut self, swap_colors: bool) -> Result<(), ChessNetError> {
+        // Reset the game state
+        self.game_state = GameState::new();
+        self.status = GameStatus::InProgress;
+        self.ending = None;
+        self.clock = None;
+        self.declined_draw_offers.clear();
+        self.draw_offer = None;
+        self.pending_promotion_move = None;
+        
+        // Clear chat history except for a system message about the new game
+        self.chat_history.clear();
+        self.chat_history.push((
+            "System".to_string(), 
+            "A new game has started".to_string(), 
+            true,
+            None
+        ));
+        
+        // Optionally swap player colors, each player keeping their session token
+        if swap_colors {
+            std::mem::swap(&mut self.white_client, &mut self.black_client);
+            let white_token = self.session_tokens.remove(&Color::White);
+            let black_token = self.session_tokens.remove(&Color::Black);
+            if let (Some(white_token), Some(black_token)) = (white_token, black_token) {
+                self.session_tokens.insert(Color::White, black_token);
+                self.session_tokens.insert(Color::Black, white_token);
+            }
+        }
+        self.disconnected_at.clear();
+        
+        // Notify clients about the new game and their colors
+        if let Some(white_client) = &mut self.white_client {
+            let message = NetworkMessage::RematchAccepted { is_white: true };
+            if let Some(stream) = &mut white_client.stream {
+                if let Err(e) = stream.write_all(format!("{}\n", serde_json::to_string(&message)?).as_bytes()) {
+                    println!("Error sending rematch accepted to white client: {}", e);
+                    white_client.stream = None;
+                }
+            }
+            white_client.set_role(ClientRole::Player { is_white: true });
+        }
+        
+        if let Some(black_client) = &mut self.black_client {
+            let message = NetworkMessage::RematchAccepted { is_white: false };
+            if let Some(stream) = &mut black_client.stream {
+                if let Err(e) = stream.write_all(format!("{}\n", serde_json::to_string(&message)?).as_bytes()) {
+                    println!("Error sending rematch accepted to black client: {}", e);
+                    black_client.stream = None;
+                }
+            }
+            black_client.set_role(ClientRole::Player { is_white: false });
+        }
+        
+        // Send system message about new game to all spectators
+        let new_game_message = NetworkMessage::ChatMessage {
+            sender: "System".to_string(),
+            message: "A new game has started".to_string(),
+            is_spectator: true,
+            player_color_tag: None,
+        };
+        
+        for spectator in self.spectators.values_mut() {
+            if let Some(stream) = &mut spectator.stream {
+                if let Err(e) = stream.write_all(format!("{}\n", serde_json::to_string(&new_game_message)?).as_bytes()) {
+                    println!("Error sending new game message to spectator: {}", e);
+                    spectator.stream = None;
+                }
+            }
+        }
+        
+        // Send initial game state
+        self.broadcast_game_state()?;
+        
+        Ok(())
+    }
+    
+    fn spectator_count(&self) -> u8 {
+        self.spectators.len() as u8
+    }
+    
+    fn player_count(&self) -> u8 {
+        let mut count = 0;
+        if self.white_client.is_some() {
+            println!("White client is connected.");
+            count += 1;
+        } else {
+            println!("White client is not connected.");
+        }
+        if self.black_client.is_some() {
+            println!("Black client is connected.");
+            count += 1;
+        } else {
+            println!("Black client is not connected.");
+        }
+        println!("Total player count: {}", count);
+        count
+    }
+    
+    // A handle on a socket for the game loop to wait on between passes: the player to move's, or while
+    // they're away their opponent's, so an empty seat doesn't spin the loop. None when neither is connected
+    fn wake_stream(&self) -> Option<TcpStream> {
+        let (mover, other) = if self.white_to_move {
+            (&self.white_client, &self.black_client)
+        } else {
+            (&self.black_client, &self.white_client)
+        };
+        let mover_stream = mover.as_ref().and_then(|client| client.stream.as_ref());
+        let other_stream = other.as_ref().and_then(|client| client.stream.as_ref());
+        mover_stream.or(other_stream).and_then(|stream| stream.try_clone().ok())
+    }
+    
+    // The player to move's next message is already buffered, so the next pass shouldn't wait for one
+    fn mover_has_pending_message(&self) -> bool {
+        let mover = if self.white_to_move { &self.white_client } else { &self.black_client };
+        mover.as_ref().is_some_and(|client| client.stream.is_some() && client.has_pending_message())
+    }
+    
+    // Only games that were actually decided go into the history, not ones everybody walked away from.
+    // Comes with the game's PGN, for the history to save alongside
+    fn record(&self) -> Option<(GameRecord, String)> {
+        let termination = self.termination_reason();
+        if self.status != GameStatus::Completed || termination.is_none() {
+            return None;
+        }
+        
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        
+        Some((GameRecord {
+            game_id: self.id.clone(),
+            white_name: self.player_name(Color::White),
+            black_name: self.player_name(Color::Black),
+            result: self.final_result(),
+            termination,
+            finished_at,
+            pgn_path: None, // Filled in once the history has saved the PGN
+        }, self.game_state.to_pgn()))
+    }
+    
+    fn info(&self) -> GameInfo {
+        GameInfo {
+            game_id: self.id.clone(),
+            host_name: self.host_name.clone(),
+            status: self.status.clone(),
+            player_count: Some(self.player_count()),
+            spectator_count: self.spectator_count(),
+            created_at: self.created_at,
+            time_mode: self.time_mode,
+        }
+    }
+    
+    fn is_inactive(&self) -> bool {
+        // Calculate how long since last activity
+        let elapsed = self.last_activity.elapsed().unwrap_or_default();
+        
+        // Remove games that have been inactive for too long, or are completed and have no spectators.
+        // A finished game is kept through the rematch window, counted from the GameEnd broadcast
+        (self.status == GameStatus::Completed && self.spectators.is_empty() && elapsed > REMATCH_WINDOW) ||
+        (elapsed > self.max_inactive_time && self.spectators.is_empty())
+    }
+}
 
-                Ok(Some(NetworkMessage::ChatMessage { sender, message, is_spectator })) => {
-                    // Handle chat message from player
-                    if let Err(e) = self.handle_chat_message(sender, message, is_spectator) {
-                        println!("Error handling chat message: {}", e);
-                    }
-                }
+// Play a game to the end, locking it only for one pass of the loop at a time
+fn run_game(game: &Arc<Mutex<Game>>) -> Result<(), ChessNetError> {
+    wait_for_ready(game);
+    game.lock().unwrap().start()?;
+    play_until_over(game)
+}
 
-                
+// The game loop of a game that is already under way, like one restored from a snapshot
+fn play_until_over(game: &Arc<Mutex<Game>>) -> Result<(), ChessNetError> {
+    loop {
+        let (pending, wake_stream, idle_interval) = {
+            let mut game = game.lock().unwrap();
+            if game.poll()? {
+                game.remove_snapshot();
+                return Ok(());
+            }
+            (game.mover_has_pending_message(), game.wake_stream(), game.idle_interval())
+        };
+        if pending {
+            continue;
+        }
+        
+        // Sleep until a player sends something, with the game unlocked
+        match wake_stream {
+            Some(stream) => wait_for_data(&stream, GAME_READ_TIMEOUT),
+            None => thread::sleep(idle_interval),
+        }
+    }
+}
 
-                Ok(Some(NetworkMessage::Move { from, to, promotion })) => {
-                    let from = (from.0 as usize, from.1 as usize);
-                    let to = (to.0 as usize, to.1 as usize);
+// Hold the game back until both players have confirmed GameStart, so white's first move can't
+// reach the server before black has set up. Clients that never answer are given up on after READY_TIMEOUT.
+// A rematch keeps the flags from the first game and starts straight away
+fn wait_for_ready(game: &Arc<Mutex<Game>>) {
+    let deadline = Instant::now() + READY_TIMEOUT;
+    loop {
+        {
+            let mut game = game.lock().unwrap();
+            if game.collect_ready() {
+                return;
+            }
+            if Instant::now() >= deadline {
+                println!("Players of game {} did not all confirm the start, starting anyway", game.id);
+                return;
+            }
+        }
+        
+        thread::sleep(GAME_POLL_INTERVAL);
+    }
+}
 
-                    // Apply the move to the server's game state
-                    if self.game_state.make_move(from, to) {
-                        if let Some(promotion) = promotion {
-                            let piece_type = match promotion {
-                                'Q' => PieceType::Queen,
-                                'R' => PieceType::Rook,
-                                'B' => PieceType::Bishop,
-                                'N' => PieceType::Knight,
-                                _ => {
-                                    println!("Invalid promotion piece: {}", promotion);
-                                    continue;
-                                },
-                            };
-                            if !self.game_state.promote_pawn(piece_type) {
-                                println!("Failed to promote pawn");
-                                continue;
-                            }
-                        }
+// Block on the socket until data arrives or the timeout passes, without consuming anything.
+// The socket is shared with the game's client, so it's put back in non-blocking mode afterwards
+fn wait_for_data(stream: &TcpStream, timeout: Duration) {
+    let mut byte = [0u8; 1];
+    if stream.set_nonblocking(false).is_ok() && stream.set_read_timeout(Some(timeout)).is_ok() {
+        let _ = stream.peek(&mut byte);
+    }
+    let _ = stream.set_read_timeout(None);
+    let _ = stream.set_nonblocking(true);
+}
 
-                        // Switch turns
-                        current_turn = !current_turn;
+// All games by id. The map lock is only held long enough to look up, add or remove an entry,
+// so work on one game never blocks the lobby or any other game
+#[derive(Clone)]
+struct GameRegistry {
+    games: Arc<Mutex<HashMap<String, Arc<Mutex<Game>>>>>,
+}
 
-                        // Broadcast updated game state to both clients
-                        if let Err(e) = self.broadcast_game_state() {
-                            println!("Error broadcasting game state: {}", e);
-                        }
-                    }
-                }
-                Ok(Some(NetworkMessage::OfferDraw)) => {
-                    // Forward draw offer to the other player
-                    let draw_offer = NetworkMessage::DrawOffered;
-                    let serialized = format!("{}\n", serde_json::to_string(&draw_offer)?);
-                    
-                    // Send to the non-current player
-                    if current_turn {
-                        // White is offering a draw, send to black
-                        if let Some(black_client) = &mut self.black_client {
-                            if let Some(stream) = &mut black_client.stream {
-                                if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                    println!("Error sending draw offer to black client: {}", e);
-                                    black_client.stream = None;
-                                }
-                            }
-                        }
+impl GameRegistry {
+    fn new() -> Self {
+        Self {
+            games: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+    
+    fn insert(&self, game: Game) {
+        let game_id = game.id.clone();
+        self.games.lock().unwrap().insert(game_id, Arc::new(Mutex::new(game)));
+    }
+    
+    fn get(&self, game_id: &str) -> Option<Arc<Mutex<Game>>> {
+        self.games.lock().unwrap().get(game_id).cloned()
+    }
+    
+    fn len(&self) -> usize {
+        self.games.lock().unwrap().len()
+    }
+    
+    // Copy out the handles so the map is unlocked before any game is
+    fn snapshot(&self) -> Vec<Arc<Mutex<Game>>> {
+        self.games.lock().unwrap().values().cloned().collect()
+    }
+    
+    fn game_infos(&self) -> Vec<GameInfo> {
+        self.snapshot()
+            .iter()
+            .map(|game| game.lock().unwrap().info())
+            .collect()
+    }
+    
+    // Remove inactive games and return their ids, deciding and removing under one lock so a game
+    // can't come back to life in between. A game locked by its thread is in play, so it is skipped
+    // rather than waited on
+    fn remove_inactive(&self) -> Vec<String> {
+        let mut removed = Vec::new();
+        self.games.lock().unwrap().retain(|game_id, game| {
+            let inactive = game.try_lock().is_ok_and(|game| game.is_inactive());
+            if inactive {
+                removed.push(game_id.clone());
+            }
+            !inactive
+        });
+        removed
+    }
+}
+
+// What a connection needs before it's in a game: the registry to create or join games in, and the
+// settings each new game starts with
+#[derive(Clone)]
+struct Lobby {
+    games: GameRegistry,
+    history: GameHistory,
+    instant_takeback: bool,
+    correspondence_inactive_time: Duration,
+    correspondence_dir: PathBuf,
+    reconnect_grace: Duration,
+    max_inactive_time: Duration,
+}
+
+impl Lobby {
+    // Answer one connection until it creates, joins, spectates or takes a seat in a game, which then
+    // owns it, or until it goes away
+    fn serve(&self, mut client: ChessClient) {
+        // Wait for initial message from client
+        let mut unexpected_messages = 0;
+        loop {
+            match client.receive_message() {
+                Ok(Some(NetworkMessage::CreateGame { player_name, takeover_token, time_mode, player_color_tag, time_control })) => {
+                    let game_id = Uuid::new_v4().to_string();
+                    let player_name_clone = player_name.clone();
+                    let mut game = Game::new(game_id.clone(), player_name);
+                    game.instant_takeback = self.instant_takeback;
+                    game.takeover_token = takeover_token;
+                    game.reconnect_grace = self.reconnect_grace;
+                    game.max_inactive_time = self.max_inactive_time;
+                    if time_mode == TimeMode::Correspondence {
+                        game.set_correspondence(self.correspondence_inactive_time, &self.correspondence_dir);
                     } else {
-                        // Black is offering a draw, send to white
-                        if let Some(white_client) = &mut self.white_client {
-                            if let Some(stream) = &mut white_client.stream {
-                                if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                    println!("Error sending draw offer to white client: {}", e);
-                                    white_client.stream = None;
-                                }
-                            }
-                        }
-                    }
-                }
-                Ok(Some(NetworkMessage::AcceptDraw)) => {
-                    // Forward draw acceptance to both players
-                    let accept_draw = NetworkMessage::AcceptDraw;
-                    let serialized = format!("{}\n", serde_json::to_string(&accept_draw)?);
-                    
-                    // Send to both players
-                    if let Some(white_client) = &mut self.white_client {
-                        if let Some(stream) = &mut white_client.stream {
-                            if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                println!("Error sending draw acceptance to white client: {}", e);
-                                white_client.stream = None;
-                            }
-                        }
-                    }
-                    
-                    if let Some(black_client) = &mut self.black_client {
-                        if let Some(stream) = &mut black_client.stream {
-                            if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                println!("Error sending draw acceptance to black client: {}", e);
-                                black_client.stream = None;
-                            }
-                        }
+                        game.time_control = time_control;
                     }
                     
-                    // End the game
-                    let end_message = NetworkMessage::GameEnd { reason: "Draw agreed".to_string() };
-                    let serialized = format!("{}\n", serde_json::to_string(&end_message)?);
-                    
-                    if let Some(white_client) = &mut self.white_client {
-                        if let Some(stream) = &mut white_client.stream {
-                            if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                println!("Error sending game end to white client: {}", e);
-                                white_client.stream = None;
-                            }
-                        }
-                    }
+                    // First player is white
+                    client.set_role(ClientRole::Player { is_white: true });
+                    client.player_name = player_name_clone.clone();
+                    client.player_color_tag = player_color_tag;
+                    game.white_client = Some(client);
                     
-                    if let Some(black_client) = &mut self.black_client {
-                        if let Some(stream) = &mut black_client.stream {
-                            if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                println!("Error sending game end to black client: {}", e);
-                                black_client.stream = None;
-                            }
-                        }
+                    // Send game created confirmation
+                    let message = NetworkMessage::GameCreated { game_id: game_id.clone() };
+                    if let Err(e) = game.white_client.as_mut().unwrap().send_message(message) {
+                        println!("Error sending game created confirmation: {}", e);
+                        break;
                     }
                     
-                    self.status = GameStatus::Completed;
-                    self.game_state.game_over = true;
-                    break;
-                }
-                Ok(Some(NetworkMessage::DeclineDraw)) => {
-                    // Forward draw decline to the other player
-                    let decline_draw = NetworkMessage::DeclineDraw;
-                    let serialized = format!("{}\n", serde_json::to_string(&decline_draw)?);
+                    // Add game to list
+                    self.games.insert(game);
                     
-                    // Send to the non-current player (the one who offered the draw)
-                    if !current_turn {
-                        // White offered a draw, send decline to white
-                        if let Some(white_client) = &mut self.white_client {
-                            if let Some(stream) = &mut white_client.stream {
-                                if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                    println!("Error sending draw decline to white client: {}", e);
-                                    white_client.stream = None;
+                    // Start game thread
+                    let games_for_thread = self.games.clone();
+                    let history_for_thread = self.history.clone();
+                    thread::spawn(move || {
+                        let game_id_clone = game_id.clone();
+                        
+                        // Wait until both players join
+                        loop {
+                            let run_game = {
+                                if let Some(game) = games_for_thread.get(&game_id_clone) {
+                                    let game = game.lock().unwrap();
+                                    game.white_client.is_some() && game.black_client.is_some()
+                                } else {
+                                    // Game was removed
+                                    false
                                 }
+                            };
+                            
+                            if run_game {
+                                break;
                             }
+                            
+                            // Sleep to avoid busy waiting
+                            std::thread::sleep(std::time::Duration::from_millis(100));
                         }
-                    } else {
-                        // Black offered a draw, send decline to black
-                        if let Some(black_client) = &mut self.black_client {
-                            if let Some(stream) = &mut black_client.stream {
-                                if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                    println!("Error sending draw decline to black client: {}", e);
-                                    black_client.stream = None;
+                        
+                        // Send game start messages
+                        {
+                            let entry = games_for_thread.get(&game_id_clone);
+                            if let Some(game) = entry {
+                                if let Err(e) = game.lock().unwrap().send_game_start() {
+                                    println!("Error sending game start for {}: {}", game_id_clone, e);
                                 }
                             }
                         }
-                    }
-                }
-                Ok(Some(NetworkMessage::Resign)) => {
-                    // Handle resignation
-                    let resigner_color = if current_turn { "White" } else { "Black" };
-                    let reason = format!("{} resigned", resigner_color);
-                    
-                    // Forward resignation to both players
-                    let resign_message = NetworkMessage::Resign;
-                    let serialized = format!("{}\n", serde_json::to_string(&resign_message)?);
-                    
-                    // For non-resigning player
-                    if current_turn {
-                        // White resigned, send to black
-                        if let Some(black_client) = &mut self.black_client {
-                            if let Some(stream) = &mut black_client.stream {
-                                if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                    println!("Error sending resignation to black client: {}", e);
-                                    black_client.stream = None;
+                        
+                        // Loop for multiple games (to handle rematches)
+                        loop {
+                            // Run the game
+                            {
+                                // Only the game itself is locked while it runs, the registry stays free
+                                let entry = games_for_thread.get(&game_id_clone);
+                                if let Some(game) = entry {
+                                    let outcome = run_game(&game);
+                                    
+                                    // Each finished game is recorded, rematches included
+                                    let record = game.lock().unwrap().record();
+                                    if let Some((record, pgn)) = record {
+                                        history_for_thread.record(record, &pgn);
+                                    }
+                                    
+                                    if let Err(e) = outcome {
+                                        println!("Error running game {}: {}", game_id_clone, e);
+                                        break;
+                                    }
+                                } else {
+                                    break;
                                 }
                             }
-                        }
-                    } else {
-                        // Black resigned, send to white
-                        if let Some(white_client) = &mut self.white_client {
-                            if let Some(stream) = &mut white_client.stream {
-                                if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                    println!("Error sending resignation to white client: {}", e);
-                                    white_client.stream = None;
+                            
+                            // Game is over, wait for rematch requests
+                            let mut rematch_accepted = false;
+                            
+                            // Wait out the rematch window, which clients count down too
+                            let rematch_deadline = Instant::now() + REMATCH_WINDOW;
+                            while Instant::now() < rematch_deadline {
+                                {
+                                    let entry = games_for_thread.get(&game_id_clone);
+                                    if let Some(game) = entry {
+                                        let mut game = game.lock().unwrap();
+                                        match game.poll_finished() {
+                                            Ok(accepted) => rematch_accepted = accepted,
+                                            Err(e) => println!("Error after game {}: {}", game_id_clone, e),
+                                        }
+                                        
+                                        // If rematch accepted, reset the game with swapped colors
+                                        if rematch_accepted {
+                                            println!("Rematch accepted for game {}", game_id_clone);
+                                            if let Err(e) = game.reset_game(true) { // Swap colors for fairness
+                                                println!("Error resetting game {}: {}", game_id_clone, e);
+                                            }
+                                            break;
+                                        }
+                                    } else {
+                                        // Game was removed
+                                        break;
+                                    }
                                 }
+                                
+                                // Sleep to avoid busy waiting
+                                std::thread::sleep(std::time::Duration::from_millis(100));
+                            }
+                            
+                            // If no rematch was accepted, break the loop
+                            if !rematch_accepted {
+                                break;
                             }
                         }
-                    }
+                    });
                     
-                    // Send game end message to both
-                    let end_message = NetworkMessage::GameEnd { reason };
-                    let serialized = format!("{}\n", serde_json::to_string(&end_message)?);
+                    break;
+                },
+                Ok(Some(NetworkMessage::JoinGame { game_id, player_name, player_color_tag })) => {
+                    let entry = self.games.get(&game_id);
                     
-                    if let Some(white_client) = &mut self.white_client {
-                        if let Some(stream) = &mut white_client.stream {
-                            if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                println!("Error sending game end to white client: {}", e);
-                                white_client.stream = None;
-                            }
+                    if let Some(game) = entry {
+                        let mut guard = game.lock().unwrap();
+                        let game = &mut *guard;
+                        if game.status == GameStatus::Waiting && game.black_client.is_none() {
+                            println!("{} joined game {}", player_name, game_id);
+                            
+                            // Second player is black
+                            client.set_role(ClientRole::Player { is_white: false });
+                            client.player_name = player_name.clone();
+                            client.player_color_tag = player_color_tag;
+                            game.black_client = Some(client);
+                            
+                            // Add a system message to chat history
+                            game.chat_history.push((
+                                "System".to_string(),
+                                format!("{} joined as black", player_name),
+                                true,
+                                None
+                            ));
+                            
+                            // The game thread sends GameStart to both players once it sees them seated
+                            break;
+                        } else {
+                            println!("Game {} is not available for joining", game_id);
+                            Self::reject_join(&mut client, game_id, "The game is not open for joining");
                         }
+                    } else {
+                        println!("Game {} not found", game_id);
+                        Self::reject_join(&mut client, game_id, "No game with that ID");
                     }
+                },
+                Ok(Some(NetworkMessage::SpectateGame { game_id, spectator_name, player_color_tag })) => {
+                    let entry = self.games.get(&game_id);
                     
-                    if let Some(black_client) = &mut self.black_client {
-                        if let Some(stream) = &mut black_client.stream {
-                            if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                println!("Error sending game end to black client: {}", e);
-                                black_client.stream = None;
-                            }
+                    // A running game is only locked for one pass at a time, so this waits at most briefly
+                    if let Some(game) = entry {
+                        let mut game = game.lock().unwrap();
+                        println!("{} spectating game {}", spectator_name, game_id);
+                        
+                        // Set role to spectator
+                        client.set_role(ClientRole::Spectator);
+                        client.player_color_tag = player_color_tag;
+                        
+                        // Add the spectator to the game
+                        if let Err(e) = game.add_spectator(client, spectator_name.clone()) {
+                            println!("Error adding spectator to game {}: {}", game_id, e);
                         }
+                        
+                        break;
+                    } else {
+                        println!("Game {} not found for spectating", game_id);
+                        Self::reject_join(&mut client, game_id, "No game with that ID");
                     }
+                },
+                Ok(Some(NetworkMessage::TakeOverSlot { game_id, color, token, player_name })) => {
+                    let entry = self.games.get(&game_id);
                     
-                    self.status = GameStatus::Completed;
-                    self.game_state.game_over = true;
-                    break;
-                }
-                Ok(Some(NetworkMessage::RequestRematch)) => {
-                    // Forward rematch request to the other player
-                    let rematch_request = NetworkMessage::RequestRematch;
-                    let serialized = format!("{}\n", serde_json::to_string(&rematch_request)?);
-                    
-                    if current_turn {
-                        // White is requesting a rematch, send to black
-                        if let Some(black_client) = &mut self.black_client {
-                            if let Some(stream) = &mut black_client.stream {
-                                if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                    println!("Error sending rematch request to black client: {}", e);
-                                    black_client.stream = None;
+                    if let Some(game) = entry {
+                        let mut game = game.lock().unwrap();
+                        match game.check_takeover(color, &token) {
+                            Ok(()) => {
+                                println!("{} taking over {:?} in game {}", player_name, color, game_id);
+                                if let Err(e) = game.seat_substitute(client, color, player_name) {
+                                    println!("Error seating substitute in game {}: {}", game_id, e);
                                 }
+                                break;
                             }
-                        }
-                    } else {
-                        // Black is requesting a rematch, send to white
-                        if let Some(white_client) = &mut self.white_client {
-                            if let Some(stream) = &mut white_client.stream {
-                                if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                    println!("Error sending rematch request to white client: {}", e);
-                                    white_client.stream = None;
-                                }
+                            Err(reason) => {
+                                println!("Refused take-over of {:?} in game {}: {}", color, game_id, reason);
+                                Self::reject_join(&mut client, game_id, reason);
                             }
                         }
+                    } else {
+                        println!("Game {} not found for take-over", game_id);
+                        Self::reject_join(&mut client, game_id, "No game with that ID");
                     }
-                }
-                Ok(Some(NetworkMessage::RematchAccepted { .. })) => {
-                    // This message should come from a client accepting a rematch
-                    println!("Received RematchAccepted message from client, ignoring");
-                    // The actual rematch handling happens in the main game loop after the game ends
-                }
-                Ok(Some(NetworkMessage::DrawOffered)) => {
-                    // This message should come from the server to clients, not from clients
-                    println!("Received unexpected DrawOffered message from client, ignoring");
-                }
-                Ok(Some(NetworkMessage::GameStart { .. })) => {
-                    // Ignore GameStart messages after initial setup
-                    println!("Received unexpected GameStart message");
-                }
-                Ok(Some(NetworkMessage::GameState { .. })) => {
-                    // Ignore GameState messages from clients
-                    println!("Received unexpected GameState message");
-                }
-                Ok(Some(NetworkMessage::GameEnd { reason })) => {
-                    // Forward game end to both players
-                    let end_message = NetworkMessage::GameEnd { reason: reason.clone() };
-                    let serialized = format!("{}\n", serde_json::to_string(&end_message)?);
+                },
+                Ok(Some(NetworkMessage::Rejoin { game_id, session_token })) => {
+                    let entry = self.games.get(&game_id);
                     
-                    if let Some(white_client) = &mut self.white_client {
-                        if let Some(stream) = &mut white_client.stream {
-                            if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                println!("Error sending game end to white client: {}", e);
-                                white_client.stream = None;
+                    if let Some(game) = entry {
+                        let mut game = game.lock().unwrap();
+                        match game.check_rejoin(&session_token) {
+                            Ok(color) => {
+                                println!("{:?} rejoined game {}", color, game_id);
+                                if let Err(e) = game.rejoin(client, color) {
+                                    println!("Error rejoining game {}: {}", game_id, e);
+                                }
+                                break;
                             }
-                        }
-                    }
-                    
-                    if let Some(black_client) = &mut self.black_client {
-                        if let Some(stream) = &mut black_client.stream {
-                            if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                println!("Error sending game end to black client: {}", e);
-                                black_client.stream = None;
+                            Err(reason) => {
+                                println!("Refused rejoin of game {}: {}", game_id, reason);
+                                Self::reject_join(&mut client, game_id, reason);
                             }
                         }
+                    } else {
+                        println!("Game {} not found for rejoin", game_id);
+                        Self::reject_join(&mut client, game_id, "No game with that ID");
                     }
-                    self.status = GameStatus::Completed;
-                    break;
-                }
-                Ok(Some(NetworkMessage::CreateGame { .. })) => {
-                    // Ignore CreateGame messages during game
-                    println!("Received unexpected CreateGame message");
-                }
-                Ok(Some(NetworkMessage::JoinGame { .. })) => {
-                    // Ignore JoinGame messages during game
-                    println!("Received unexpected JoinGame message");
-                }
-                Ok(Some(NetworkMessage::GameCreated { .. })) => {
-                    // Ignore GameCreated messages during game
-                    println!("Received unexpected GameCreated message");
-                }
-                Ok(Some(NetworkMessage::GameList { .. })) => {
-                    // Ignore GameList messages during game
-                    println!("Received unexpected GameList message");
-                }
+                },
                 Ok(Some(NetworkMessage::RequestGameList)) => {
-                    // Ignore RequestGameList messages during game
-                    println!("Received unexpected RequestGameList message");
-                }
+                    if let Err(e) = self.send_game_list(&mut client) {
+                        println!("Error sending game list: {}", e);
+                        break;
+                    }
+                },
+                Ok(Some(NetworkMessage::RequestThumbnail { game_id })) => {
+                    let png = self.games.get(&game_id).and_then(|game| {
+                        let game = game.lock().unwrap();
+                        render_thumbnail_png(&game.game_state, DEFAULT_THUMBNAIL_SQUARE_SIZE).ok()
+                    });
+                    if let Err(e) = client.send_message(NetworkMessage::Thumbnail { game_id, png }) {
+                        println!("Error sending thumbnail: {}", e);
+                        break;
+                    }
+                },
+                Ok(Some(NetworkMessage::PlayerHistory { name })) => {
+                    let games = self.history.for_player(&name);
+                    if let Err(e) = client.send_message(NetworkMessage::HistoryResult { games }) {
+                        println!("Error sending history for {}: {}", name, e);
+                        break;
+                    }
+                },
+                Ok(Some(NetworkMessage::Heartbeat)) => {
+                    // Respond to heartbeat with a heartbeat
+                    if let Err(e) = client.send_message(NetworkMessage::Heartbeat) {
+                        println!("Error sending heartbeat: {}", e);
+                        break;
+                    }
+                },
                 Ok(None) => {
-                    // No message received, continue
-                }
-                Err(e) => {
-                    println!("Error receiving message: {}", e);
-                    if e.kind() == std::io::ErrorKind::ConnectionAborted || 
-                       e.kind() == std::io::ErrorKind::ConnectionReset {
-                        println!("Client disconnected");
-                        if current_turn {
-                            // White disconnected
-                            if let Some(white_client) = &mut self.white_client {
-                                white_client.stream = None;
-                            }
-                        } else {
-                            // Black disconnected
-                            if let Some(black_client) = &mut self.black_client {
-                                black_client.stream = None;
-                            }
-                        }
+                    // A connection that has gone quiet, not even sending heartbeats, is dropped
+                    if client.is_unresponsive() {
+                        println!("Lobby client went silent, disconnecting");
+                        break;
                     }
-                }
-            }
-
-            // Check if game is over
-            if self.game_state.is_game_over() {
-                let reason = if self.game_state.is_checkmate() {
-                    "Checkmate"
-                } else if self.game_state.is_stalemate() {
-                    "Stalemate"
-                } else if self.game_state.is_threefold_repetition() {
-                    "Threefold repetition"
-                } else if self.game_state.is_fifty_move_rule() {
-                    "Fifty-move rule"
-                } else if self.game_state.is_insufficient_material() {
-                    "Insufficient material"
-                } else {
-                    "Unknown"
-                };
-                
-                let end_message = NetworkMessage::GameEnd { reason: reason.to_string() };
-                let serialized = format!("{}\n", serde_json::to_string(&end_message)?);
-                
-                if let Some(white_client) = &mut self.white_client {
-                    if let Some(stream) = &mut white_client.stream {
-                        if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                            println!("Error sending game end to white client: {}", e);
-                            white_client.stream = None;
-                        }
+                    
+                    // Block until the client sends something rather than polling on a timer
+                    if let Some(stream) = &client.stream {
+                        wait_for_data(stream, LOBBY_READ_TIMEOUT);
+                    }
+                },
+                Err(e) => {
+                    println!("Error receiving message from new client: {}", e);
+                    break;
+                },
+                _ => {
+                    unexpected_messages += 1;
+                    println!("Unexpected message from client before joining a game ({}/{})",
+                             unexpected_messages, MAX_UNEXPECTED_MESSAGES);
+                    
+                    // Tell the client what went wrong, and drop it if it keeps going
+                    let give_up = unexpected_messages >= MAX_UNEXPECTED_MESSAGES;
+                    let reply = NetworkMessage::ConnectionStatus {
+                        connected: !give_up,
+                        message: if give_up {
+                            "Too many messages before creating or joining a game, disconnecting".to_string()
+                        } else {
+                            "Create or join a game first".to_string()
+                        },
+                    };
+                    
+                    if let Err(e) = client.send_message(reply) {
+                        println!("Error sending error reply to client: {}", e);
+                        break;
                     }
-                }
-                
-                if let Some(black_client) = &mut self.black_client {
-                    if let Some(stream) = &mut black_client.stream {
-                        if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                            println!("Error sending game end to black client: {}", e);
-                            black_client.stream = None;
-                        }
+                    
+                    if give_up {
+                        break;
                     }
                 }
-                self.status = GameStatus::Completed;
-                break;
             }
         }
-
-        Ok(())
     }
     
-    fn reset_game(&mut self, swap_colors: bool) -> Result<(), std::io::Error> {
-        // Reset the game state
-        self.game_state = GameState::new();
-        self.status = GameStatus::InProgress;
-        
-        // Clear chat history except for a system message about the new game
-        self.chat_history.clear();
-        self.chat_history.push((
-            "System".to_string(), 
-            "A new game has started".to_string(), 
-            true
-        ));
-        
-        // Optionally swap player colors
-        if swap_colors {
-            std::mem::swap(&mut self.white_client, &mut self.black_client);
-        }
-        
-        // Notify clients about the new game and their colors
-        if let Some(white_client) = &mut self.white_client {
-            let message = NetworkMessage::RematchAccepted { is_white: true };
-            if let Some(stream) = &mut white_client.stream {
-                if let Err(e) = stream.write_all(format!("{}\n", serde_json::to_string(&message)?).as_bytes()) {
-                    println!("Error sending rematch accepted to white client: {}", e);
-                    white_client.stream = None;
-                }
-            }
-            white_client.set_role(ClientRole::Player { is_white: true });
-        }
-        
-        if let Some(black_client) = &mut self.black_client {
-            let message = NetworkMessage::RematchAccepted { is_white: false };
-            if let Some(stream) = &mut black_client.stream {
-                if let Err(e) = stream.write_all(format!("{}\n", serde_json::to_string(&message)?).as_bytes()) {
-                    println!("Error sending rematch accepted to black client: {}", e);
-                    black_client.stream = None;
-                }
-            }
-            black_client.set_role(ClientRole::Player { is_white: false });
-        }
-        
-        // Send system message about new game to all spectators
-        let new_game_message = NetworkMessage::ChatMessage {
-            sender: "System".to_string(),
-            message: "A new game has started".to_string(),
-            is_spectator: true,
-        };
+    fn send_game_list(&self, client: &mut ChessClient) -> Result<(), ChessNetError> {
+        let game_infos = self.games.game_infos();
+
+        let message = NetworkMessage::GameList { available_games: game_infos };
+        let serialized = format!("{}\n", serde_json::to_string(&message)?);
         
-        for (_id, spectator) in &mut self.spectators {
-            if let Some(stream) = &mut spectator.stream {
-                if let Err(e) = stream.write_all(format!("{}\n", serde_json::to_string(&new_game_message)?).as_bytes()) {
-                    println!("Error sending new game message to spectator: {}", e);
-                    spectator.stream = None;
-                }
+        if let Some(stream) = &mut client.stream {
+            if let Err(e) = stream.write_all(serialized.as_bytes()) {
+                println!("Error sending game list: {}", e);
+                client.stream = None;
+                return Err(e.into());
             }
         }
         
-        // Send initial game state
-        self.broadcast_game_state()?;
-        
         Ok(())
     }
     
-    fn spectator_count(&self) -> u8 {
-        self.spectators.len() as u8
-    }
-    
-    fn player_count(&self) -> u8 {
-        let mut count = 0;
-        if self.white_client.is_some() {
-            println!("White client is connected.");
-            count += 1;
-        } else {
-            println!("White client is not connected.");
-        }
-        if self.black_client.is_some() {
-            println!("Black client is connected.");
-            count += 1;
-        } else {
-            println!("Black client is not connected.");
+    // The client stays in the lobby afterwards, so it can ask for the game list and try again
+    fn reject_join(client: &mut ChessClient, game_id: String, reason: &str) {
+        let rejection = NetworkMessage::JoinRejected {
+            game_id,
+            reason: reason.to_string(),
+        };
+        if let Err(e) = client.send_message(rejection) {
+            println!("Error sending join rejection: {}", e);
         }
-        println!("Total player count: {}", count);
-        count
-    }
-    
-    fn is_inactive(&self) -> bool {
-        // Calculate how long since last activity
-        let elapsed = self.last_activity.elapsed().unwrap_or_default();
-        
-        // Remove games that have been inactive for too long or are completed and have no spectators
-        (self.status == GameStatus::Completed && self.spectators.is_empty()) ||
-        (elapsed > MAX_INACTIVE_TIME && self.spectators.is_empty())
     }
 }
 
 pub struct ChessServer {
     listener: TcpListener,
-    games: Arc<Mutex<HashMap<String, Game>>>,
+    games: GameRegistry,
+    history: GameHistory,
+    heartbeat_interval: Duration,
+    instant_takeback: bool,
+    protocol_recorder: Option<ProtocolRecorder>,
+    correspondence_inactive_time: Duration,
+    correspondence_dir: PathBuf,
+    reconnect_grace: Duration,
+    max_inactive_time: Duration,
 }
 
 impl ChessServer {
-    pub fn new(port: u16) -> Result<Self, std::io::Error> {
+    pub fn new(port: u16) -> Result<Self, ChessNetError> {
         let listener = TcpListener::bind(format!("0.0.0.0:{}", port))?;
         listener.set_nonblocking(true)?;
         println!("Chess server v{} started on port {}", SERVER_VERSION, port);
         
         Ok(Self { 
             listener,
-            games: Arc::new(Mutex::new(HashMap::new())),
+            games: GameRegistry::new(),
+            history: GameHistory::load(PathBuf::from(DEFAULT_HISTORY_FILE)),
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            instant_takeback: false,
+            protocol_recorder: None,
+            correspondence_inactive_time: Duration::from_secs(DEFAULT_CORRESPONDENCE_HOURS * 3600),
+            correspondence_dir: PathBuf::from(DEFAULT_CORRESPONDENCE_DIR),
+            reconnect_grace: DEFAULT_RECONNECT_GRACE,
+            max_inactive_time: MAX_INACTIVE_TIME,
         })
     }
-
-    fn send_game_list(&self, client: &mut ChessClient) -> Result<(), std::io::Error> {
-        let games = self.games.lock().unwrap();
-        
-        let game_infos: Vec<GameInfo> = games.values()
-            .map(|game| GameInfo {
-                game_id: game.id.clone(),
-                host_name: game.host_name.clone(),
-                status: game.status.clone(),
-                player_count: Some(game.player_count()),
-                spectator_count: game.spectator_count(),
-                created_at: game.created_at,
-            })
-            .collect();
-
-        let message = NetworkMessage::GameList { available_games: game_infos };
-        let serialized = format!("{}\n", serde_json::to_string(&message)?);
+    
+    // The port the server listens on, which is how to find it after binding port 0
+    pub fn local_port(&self) -> Result<u16, ChessNetError> {
+        Ok(self.listener.local_addr()?.port())
+    }
+    
+    // Keep the history of completed games somewhere other than the default file
+    pub fn set_history_file(&mut self, path: PathBuf) {
+        self.history = GameHistory::load(path);
+    }
+    
+    // Clients should be started with the same interval, or they'll be dropped for missing heartbeats
+    pub fn set_heartbeat_interval(&mut self, interval: Duration) {
+        self.heartbeat_interval = interval;
+    }
+    
+    // Casual rule for training servers: a player may take back their move until the opponent replies
+    pub fn set_instant_takeback(&mut self, enabled: bool) {
+        self.instant_takeback = enabled;
+    }
+    
+    // Log what every connection sends the server, for replaying a reported game
+    pub fn set_protocol_recorder(&mut self, recorder: ProtocolRecorder) {
+        self.protocol_recorder = Some(recorder.incoming_only());
+    }
+    
+    // How long a correspondence player may be away on their move before forfeiting
+    pub fn set_correspondence_hours(&mut self, hours: u64) {
+        self.correspondence_inactive_time = Duration::from_secs(hours * 3600);
+    }
+    
+    // How long a live player may be disconnected before forfeiting, while their opponent waits
+    pub fn set_reconnect_grace(&mut self, grace: Duration) {
+        self.reconnect_grace = grace;
+    }
+    
+    // How long the player to move in a live game may be away before forfeiting, and how long an idle
+    // game may sit before cleanup removes it
+    pub fn set_max_inactive_time(&mut self, time: Duration) {
+        self.max_inactive_time = time;
+    }
+    
+    // Keep correspondence snapshots somewhere other than the default folder
+    pub fn set_correspondence_dir(&mut self, dir: PathBuf) {
+        self.correspondence_dir = dir;
+    }
+    
+    // Pick up the correspondence games that were running when the server last stopped. Each gets
+    // its own thread like any other game, but without a rematch, since both seats start empty
+    fn resume_correspondence_games(&self) {
+        let entries = match fs::read_dir(&self.correspondence_dir) {
+            Ok(entries) => entries,
+            Err(_) => return, // No folder yet just means none were saved
+        };
         
-        if let Some(stream) = &mut client.stream {
-            if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                println!("Error sending game list: {}", e);
-                client.stream = None;
-                return Err(e);
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            let snapshot = fs::read_to_string(&path).ok()
+                .and_then(|text| serde_json::from_str::<CorrespondenceSnapshot>(&text).ok());
+            let game = match snapshot {
+                Some(snapshot) => Game::from_snapshot(snapshot, self.correspondence_inactive_time, &self.correspondence_dir),
+                None => {
+                    println!("Skipping unreadable correspondence game {}", path.display());
+                    continue;
+                }
+            };
+            
+            if let Some(game) = game {
+                let game_id = game.id.clone();
+                println!("Resuming correspondence game {}", game_id);
+                self.games.insert(game);
+                
+                let games = self.games.clone();
+                let history = self.history.clone();
+                thread::spawn(move || {
+                    if let Some(game) = games.get(&game_id) {
+                        if let Err(e) = play_until_over(&game) {
+                            println!("Error running game {}: {}", game_id, e);
+                        }
+                        let record = game.lock().unwrap().record();
+                        if let Some((record, pgn)) = record {
+                            history.record(record, &pgn);
+                        }
+                    }
+                });
             }
         }
-        
-        Ok(())
     }
-    
+
     fn cleanup_inactive_games(&self) {
-        let mut games = self.games.lock().unwrap();
-        let mut games_to_remove = Vec::new();
-        
-        // Identify inactive games
-        for (game_id, game) in games.iter() {
-            if game.is_inactive() {
-                games_to_remove.push(game_id.clone());
-            }
+        for game_id in self.games.remove_inactive() {
+            println!("Removing inactive game: {}", game_id);
         }
-        
-        // Remove inactive games
-        for game_id in games_to_re          println!("Removing inactive game: {}", game_id);
-            games.remove(&game_id);
+    }
+
+    fn lobby(&self) -> Lobby {
+        Lobby {
+            games: self.games.clone(),
+            history: self.history.clone(),
+            instant_takeback: self.instant_takeback,
+            correspondence_inactive_time: self.correspondence_inactive_time,
+            correspondence_dir: self.correspondence_dir.clone(),
+            reconnect_grace: self.reconnect_grace,
+            max_inactive_time: self.max_inactive_time,
         }
     }
 
-    pub fn run(&mut self) -> Result<(), std::io::Error> {
+    pub fn run(&mut self) -> Result<(), ChessNetError> {
         println!("Chess server started, waiting for connections...");
+        self.resume_correspondence_games();
         
-        let games_clone = Arc::clone(&self.games);
+        let games_clone = self.games.clone();
         
         // Start a thread for periodic cleanup of inactive games
         thread::spawn(move || {
             loop {
                 thread::sleep(GAME_CLEANUP_INTERVAL);
                 
-                // Get a lock on the games map and clean up inactive games
-                let mut games = games_clone.lock().unwrap();
-                let mut games_to_remove = Vec::new();
-                
-                // Identify inactive games
-                for (game_id, game) in games.iter() {
-                    if game.is_inactive() {
-                        games_to_remove.push(game_id.clone());
-                    }
-                }
-                
-                // Remove inactive games
-                for game_id in &games_to_remove {
+                // Remove inactive games, skipping any that are mid-pass
+                let games_removed = games_clone.remove_inactive();
+                for game_id in &games_removed {
                     println!("Cleanup: Removing inactive game: {}", game_id);
-                    games.remove(game_id);
                 }
                 
                 println!("Cleanup: Removed {} inactive games. Active games: {}", 
-                         games_to_remove.len(), games.len());
+                         games_removed.len(), games_clone.len());
             }
         });
         
@@ -954,326 +2430,12 @@ impl ChessServer {
                     stream.set_nonblocking(true)?;
                     
                     let mut client = ChessClient::with_role(stream, ClientRole::Spectator, "");
+                    client.set_heartbeat_interval(self.heartbeat_interval);
+                    client.set_recorder(self.protocol_recorder.clone());
                     
-                    let games_clone = Arc::clone(&self.games);
-                    
-                    // Wait for initial message from client
-                    let connected = true;
-                    while connected {
-                        match client.receive_message() {
-                            Ok(Some(NetworkMessage::CreateGame { player_name })) => {
-                                let game_id = Uuid::new_v4().to_string();
-                                let player_name_clone = player_name.clone();
-                                let mut game = Game::new(game_id.clone(), player_name);
-                                
-                                // First player is white
-                                client.set_role(ClientRole::Player { is_white: true });
-                                client.is_white = true;
-                                client.player_name = player_name_clone.clone();
-                                game.white_client = Some(client);
-                                
-                                // Send game created confirmation
-                                let message = NetworkMessage::GameCreated { game_id: game_id.clone() };
-                                if let Some(ref mut stream) = game.white_client.as_mut().unwrap().stream {
-                                    if let Err(e) = stream.write_all(format!("{}\n", serde_json::to_string(&message)?).as_bytes()) {
-                                        println!("Error sending game created confirmation: {}", e);
-                                        break;
-                                    }
-                                }
-                                
-                                // Add game to list
-                                let mut games = games_clone.lock().unwrap();
-                                games.insert(game_id.clone(), game);
-                                
-                                // Start game thread
-                                let games_for_thread = Arc::clone(&games_clone);
-                                thread::spawn(move || {
-                                    let game_id_clone = game_id.clone();
-                                    
-                                    // Wait until both players join
-                                    loop {
-                                        let run_game = {
-                                            let games = games_for_thread.lock().unwrap();
-                                            if let Some(game) = games.get(&game_id_clone) {
-                                                game.white_client.is_some() && game.black_client.is_some()
-                                            } else {
-                                                // Game was removed
-                                                false
-                                            }
-                                        };
-                                        
-                                        if run_game {
-                                            break;
-                                        }
-                                        
-                                        // Sleep to avoid busy waiting
-                                        std::thread::sleep(std::time::Duration::from_millis(100));
-                                    }
-                                    
-                                    // Send game start messages
-                                    {
-                                        let mut games = games_for_thread.lock().unwrap();
-                                        if let Some(game) = games.get_mut(&game_id_clone) {
-                                            if let Some(white_client) = &mut game.white_client {
-                                                let message = NetworkMessage::GameStart { 
-                                                    is_white: true, 
-                                                    game_id: game_id_clone.clone() 
-                                            // Store the player names for messaging
-                                            let host_name = game.host_name.clone();
-                                            let joiner_name = if let Some(black_client) = &game.black_client {
-                                                black_client.player_name.clone()
-                                            } else {
-                                                "Opponent".to_string()
-                                            };
-                                            
-                                            // Send game start messages with correct opponent names
-                                            if let Some(white_client) = &mut game.white_client {
-                                                let message = NetworkMessage::GameStart { 
-                                                    is_white: true, 
-                                                    game_id: game_id_clone.clone(),
-                                                    opponent_name: joiner_name.clone(),
-                                                };
-                                                if let Some(stream) = &mut white_client.stream {
-                                                    if let Err(e) = stream.write_all(format!("{}\n", 
-                                                               serde_json::to_string(&message).unwrap()).as_bytes()) {
-                                                        println!("Error sending game start to white client: {}", e);
-                                                        white_client.stream = None;
-                                                    }
-                                                }
-                                            }
-                                            
-                                            if let Some(black_client) = &mut game.black_client {
-                                                let message = NetworkMessage::GameStart { 
-                                                    is_white: false, 
-                                                    game_id: game_id_clone.clone() 
-                                                    game_id: game_id_clone.clone(),
-                                                    opponent_name: host_name,
-                                                };
-                                                if let Some(stream) = &mut black_client.stream {
-                                                    if let Err(e) = stream.write_all(format!("{}\n", 
-                                                               serde_json::to_string(&message).unwrap()).as_bytes()) {
-                                                        println!("Error sending game start to black client: {}", e);
-                                                        black_client.stream = None;
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                    
-                                    // Loop for multiple games (to handle rematches)
-                                    loop {
-                                        // Run the game
-                                        {
-                                            let mut games = games_for_thread.lock().unwrap();
-                                            if let Some(game) = games.get_mut(&game_id_clone) {
-                                                if let Err(e) = game.run() {
-                                                    println!("Error running game {}: {}", game_id_clone, e);
-                                                    break;
-                                                }
-                                            } else {
-                                                break;
-                                            }
-                                        }
-                                        
-                                        // Game is over, wait for rematch requests
-                                        let mut rematch_requested = false;
-                                        let mut rematch_accepted = false;
-                                        
-                                        // Wait for up to 60 seconds for a rematch request
-                                        for _ in 0..600 { // 600 * 100ms = 60 seconds
-                                            {
-                                                let mut games = games_for_thread.lock().unwrap();
-                                                if let Some(game) = games.get_mut(&game_id_clone) {
-                                                    // Check if white requested rematch
-                                                    if let Some(white_client) = &mut game.white_client {
-                                                        if let Ok(Some(NetworkMessage::RequestRematch)) = white_client.receive_message() {
-                                                            rematch_requested = true;
-                                                            
-                                                            // Forward to black
-                                                            if let Some(black_client) = &mut game.black_client {
-                                                                let message = NetworkMessage::RequestRematch;
-                                                                if let Some(stream) = &mut black_client.stream {
-                                                                    if let Err(e) = stream.write_all(format!("{}\n", 
-                                                                          serde_json::to_string(&message).unwrap()).as_bytes()) {
-                                                                        println!("Error sending rematch request to black client: {}", e);
-                                                                        black_client.stream = None;
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                    
-                                                    // Check if black requested rematch
-                                                    if let Some(black_client) = &mut game.black_client {
-                                                        if let Ok(Some(NetworkMessage::RequestRematch)) = black_client.receive_message() {
-                                                            rematch_requested = true;
-                                                            
-                                                            // Forward to white
-                                                            if let Some(white_client) = &mut game.white_client {
-                                                                let message = NetworkMessage::RequestRematch;
-                                                                if let Some(stream) = &mut white_client.stream {
-                                                                    if let Err(e) = stream.write_all(format!("{}\n", 
-                                                                          serde_json::to_string(&message).unwrap()).as_bytes()) {
-                                                                        println!("Error sending rematch request to white client: {}", e);
-                                                                        white_client.stream = None;
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                    
-                                                    // Check if rematch was accepted
-                                                    if let Some(white_client) = &mut game.white_client {
-                                                        if let Ok(Some(NetworkMessage::AcceptDraw)) = white_client.receive_message() {
-                                                            // Using AcceptDraw as a proxy for accepting rematch
-                                                            rematch_accepted = true;
-                                                        }
-                                                    }
-                                                    
-                                                    if let Some(black_client) = &mut game.black_client {
-                                                        if let Ok(Some(NetworkMessage::AcceptDraw)) = black_client.receive_message() {
-                                                            // Using AcceptDraw as a proxy for accepting rematch
-                                                            rematch_accepted = true;
-                                                        }
-                                                    }
-                                                    
-                                                    // If rematch accepted, reset the game with swapped colors
-                                                    if rematch_accepted {
-                                                        println!("Rematch accepted for game {}", game_id_clone);
-                                                        if let Err(e) = game.reset_game(true) { // Swap colors for fairness
-                                                            println!("Error resetting game {}: {}", game_id_clone, e);
-                                                        }
-                                                        break;
-                                                    }
-                                                } else {
-                                                    // Game was removed
-                                                    break;
-                                                }
-                                            }
-                                            
-                                            // Sleep to avoid busy waiting
-                                            std::thread::sleep(std::time::Duration::from_millis(100));
-                                        }
-                                        
-                                        // If no rematch was accepted, break the loop
-                                        if !rematch_accepted {
-                                            break;
-                                        }
-                                    }
-                                });
-                                
-                                break;
-                            },
-                            Ok(Some(NetworkMessage::JoinGame { game_id, player_name })) => {
-                                let mut games = games_clone.lock().unwrap();
-                                
-                                if let Some(game) = games.get_mut(&game_id) {
-                                    if game.status == GameStatus::Waiting && game.black_client.is_none() {
-                                        println!("{} joined game {}", player_name, game_id);
-                                        
-                                        // Store the host name
-                                        let host_name = game.host_name.clone();
-                                        
-                                        // Second player is black
-                                        client.set_role(ClientRole::Player { is_white: false });
-                                        game.black_client = Some(client);
-                                        
-                                        // Add a system message to chat history
-                                        game.chat_history.push((
-                                            "System".to_string(),
-                                            format!("{} joined as black", player_name),
-                                            true
-                                        ));
-                                        client.is_white = false;
-                                        client.player_name = player_name.clone();
-                                        game.black_client = Some(client);
-                                        
-                                        // Send game start messages to both clients with correct opponent names
-                                        if let Some(white_client) = &mut game.white_client {
-                                            let message = NetworkMessage::GameStart {
-                                                is_white: true,
-                                                game_id: game_id.clone(),
-                                                opponent_name: player_name.clone(),
-                                            };
-                                            if let Some(stream) = &mut white_client.stream {
-                                                if let Err(e) = stream.write_all(format!("{}\n", serde_json::to_string(&message).unwrap()).as_bytes()) {
-                                                    println!("Error sending game start to white client: {}", e);
-                                                    white_client.stream = None;
-                                                }
-                                            }
-                                        }
-                                        
-                                        if let Some(black_client) = &mut game.black_client {
-                                            let message = NetworkMessage::GameStart {
-                                                is_white: false,
-                                                game_id: game_id.clone(),
-                                                opponent_name: host_name,
-                                            };
-                                            if let Some(stream) = &mut black_client.stream {
-                                                if let Err(e) = stream.write_all(format!("{}\n", serde_json::to_string(&message).unwrap()).as_bytes()) {
-                                                    println!("Error sending game start to black client: {}", e);
-                                                    black_client.stream = None;
-                                                }
-                                            }
-                                        }
-                                        
-                                        break;
-                                    } else {
-                                        println!("Game {} is not available for joining", game_id);
-                                    }
-                                } else {
-                                    println!("Game {} not found", game_id);
-                                }
-                            },
-                            Ok(Some(NetworkMessage::SpectateGame { game_id, spectator_name })) => {
-                                let mut games = games_clone.lock().unwrap();
-                                
-                                if let Some(game) = games.get_mut(&game_id) {
-                                    println!("{} spectating game {}", spectator_name, game_id);
-                                    
-                                    // Set role to spectator
-                                    client.set_role(ClientRole::Spectator);
-                                    
-                                    // Add the spectator to the game
-                                    if let Err(e) = game.add_spectator(client, spectator_name.clone()) {
-                                        println!("Error adding spectator to game {}: {}", game_id, e);
-                                    }
-                                    
-                                    break;
-                                } else {
-                                    println!("Game {} not found for spectating", game_id);
-                                }
-                            },
-                            Ok(Some(NetworkMessage::RequestGameList)) => {
-                                if let Err(e) = self.send_game_list(&mut client) {
-                                    println!("Error sending game list: {}", e);
-                                    break;
-                                }
-                            },
-                            Ok(Some(NetworkMessage::Heartbeat)) => {
-                                // Respond to heartbeat with a heartbeat
-                                let heartbeat = NetworkMessage::Heartbeat;
-                                if let Some(stream) = &mut client.stream {
-                                    let serialized = format!("{}\n", serde_json::to_string(&heartbeat)?);
-                                    if let Err(e) = stream.write_all(serialized.as_bytes()) {
-                                        println!("Error sending heartbeat: {}", e);
-                                        break;
-                                    }
-                                }
-                            },
-                            Ok(None) => {
-                                // No message received yet, wait
-                                std::thread::sleep(std::time::Duration::from_millis(100));
-                            },
-                            Err(e) => {
-                                println!("Error receiving message from new client: {}", e);
-                                break;
-                            },
-                            _ => {
-                                println!("Unexpected message from client");
-                            }
-                        }
-                    }
+                    // Each connection waits in the lobby on its own thread, so a slow one holds up nobody
+                    let lobby = self.lobby();
+                    thread::spawn(move || lobby.serve(client));
                 },
                 Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                     // No new connection, continue
@@ -1285,111 +2447,4 @@ impl ChessServer {
             }
         }
     }
-
-    fn handle_join_game(&mut self, client: ChessClient, game_id: String, player_name: String) -> Result<(), std::io::Error> {
-        let mut games = self.games.lock().unwrap();
-        
-        if let Some(game) = games.get_mut(&game_id) {
-            if game.white_client.is_some() && game.black_client.is_some() {
-                println!("Game {} is already full", game_id);
-                return Ok(());
-            }
-            
-            if game.white_client.is_none() {
-                // Assign the client as white
-                let mut white_client = client;
-                white_client.player_name = player_name.clone();
-                game.white_client = Some(white_client);
-                game.status = GameStatus::InProgress;
-                
-                // If black is already assigned, start the game
-                if let Some(black_client) = &mut game.black_client {
-                    // Send game start messages to both players
-                    let white_message = NetworkMessage::GameStart { 
-                        is_white: true, 
-                        game_id: game_id.clone(),
-                        opponent_name: black_client.player_name.clone(),
-                    };
-                    
-                    let black_message = NetworkMessage::GameStart { 
-                        is_white: false, 
-                        game_id: game_id.clone(),
-                        opponent_name: player_name.clone(),
-                    };
-                    
-                    if let Some(white_client) = &mut game.white_client {
-                        if let Some(stream) = &mut white_client.stream {
-                            stream.write_all(format!("{}\n", serde_json::to_string(&white_message)?).as_bytes())?;
-                        }
-                    }
-                    
-                    if let Some(stream) = &mut black_client.stream {
-                        stream.write_all(format!("{}\n", serde_json::to_string(&black_message)?).as_bytes())?;
-                    }
-                    
-                    // Start the game in a new thread
-                    let game_id_clone = game_id.clone();
-                    let games_clone = Arc::clone(&self.games);
-                    thread::spawn(move || {
-                        let mut games = games_clone.lock().unwrap();
-                        if let Some(game) = games.get_mut(&game_id_clone) {
-                            if let Err(e) = game.run() {
-                                println!("Error running game {}: {}", game_id_clone, e);
-                            }
-                        }
-                    });
-                }
-            } else if game.black_client.is_none() {
-                // Assign the client as black
-                let mut black_client = client;
-                black_client.player_name = player_name.clone();
-                game.black_client = Some(black_client);
-                game.status = GameStatus::InProgress;
-                
-                // If white is already assigned, start the game
-                if let Some(white_client) = &mut game.white_client {
-                    // Send game start messages to both players
-                    let white_message = NetworkMessage::GameStart { 
-                        is_white: true, 
-                        game_id: game_id.clone(),
-                        opponent_name: player_name.clone(),
-                    };
-                    
-                    let black_message = NetworkMessage::GameStart { 
-                        is_white: false, 
-                        game_id: game_id.clone(),
-                        opponent_name: white_client.player_name.clone(),
-                    };
-                    
-                    if let Some(stream) = &mut white_client.stream {
-                        stream.write_all(format!("{}\n", serde_json::to_string(&white_message)?).as_bytes())?;
-                    }
-                    
-                    if let Some(black_client) = &mut game.black_client {
-                        if let Some(stream) = &mut black_client.stream {
-                            stream.write_all(format!("{}\n", serde_json::to_string(&black_message)?).as_bytes())?;
-                        }
-                    }
-                    
-                    // Start the game in a new thread
-                    let game_id_clone = game_id.clone();
-                    let games_clone = Arc::clone(&self.games);
-                    thread::spawn(move || {
-                        let mut games = games_clone.lock().unwrap();
-                        if let Some(game) = games.get_mut(&game_id_clone) {
-                            if let Err(e) = game.run() {
-                                println!("Error running game {}: {}", game_id_clone, e);
-                            }
-                        }
-                    });
-                }
-            }
-            
-            println!("Player '{}' joined game {}", player_name, game_id);
-        } else {
-            println!("Game {} not found", game_id);
-        }
-        
-        Ok(())
-    }
 } 
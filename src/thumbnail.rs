@@ -0,0 +1,81 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageOutputFormat, ImageResult, Rgba, RgbaImage};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use crate::board::{GameState, BOARD_SIZE};
+use crate::piece::{PieceType, Color};
+
+// Same palette as the GUI board
+const LIGHT_SQUARE: Rgba<u8> = Rgba([230, 230, 204, 255]);
+const DARK_SQUARE: Rgba<u8> = Rgba([128, 128, 102, 255]);
+
+pub const DEFAULT_THUMBNAIL_SQUARE_SIZE: u32 = 16;
+
+lazy_static! {
+    // Piece images decoded once, at full resolution
+    static ref PIECE_IMAGES: HashMap<(PieceType, Color), RgbaImage> = {
+        let sources: [((PieceType, Color), &'static [u8]); 12] = [
+            ((PieceType::King, Color::White), include_bytes!("../embedded_assets/white_king.png")),
+            ((PieceType::Queen, Color::White), include_bytes!("../embedded_assets/white_queen.png")),
+            ((PieceType::Rook, Color::White), include_bytes!("../embedded_assets/white_rook.png")),
+            ((PieceType::Bishop, Color::White), include_bytes!("../embedded_assets/white_bishop.png")),
+            ((PieceType::Knight, Color::White), include_bytes!("../embedded_assets/white_knight.png")),
+            ((PieceType::Pawn, Color::White), include_bytes!("../embedded_assets/white_pawn.png")),
+            ((PieceType::King, Color::Black), include_bytes!("../embedded_assets/black_king.png")),
+            ((PieceType::Queen, Color::Black), include_bytes!("../embedded_assets/black_queen.png")),
+            ((PieceType::Rook, Color::Black), include_bytes!("../embedded_assets/black_rook.png")),
+            ((PieceType::Bishop, Color::Black), include_bytes!("../embedded_assets/black_bishop.png")),
+            ((PieceType::Knight, Color::Black), include_bytes!("../embedded_assets/black_knight.png")),
+            ((PieceType::Pawn, Color::Black), include_bytes!("../embedded_assets/black_pawn.png")),
+        ];
+
+        sources
+            .iter()
+            .map(|(key, bytes)| {
+                let image = image::load_from_memory(bytes).expect("Invalid embedded piece image");
+                (*key, image.to_rgba8())
+            })
+            .collect()
+    };
+}
+
+// Rasterize the board from white's side into an RGBA image, without needing a graphics context
+pub fn render_board(game_state: &GameState, square_size: u32) -> RgbaImage {
+    let board_size = square_size * BOARD_SIZE as u32;
+    let mut image = RgbaImage::from_pixel(board_size, board_size, LIGHT_SQUARE);
+
+    for rank in 0..BOARD_SIZE {
+        for file in 0..BOARD_SIZE {
+            let x = file as u32 * square_size;
+            let y = rank as u32 * square_size;
+
+            if (rank + file) % 2 == 1 {
+                for py in y..y + square_size {
+                    for px in x..x + square_size {
+                        image.put_pixel(px, py, DARK_SQUARE);
+                    }
+                }
+            }
+
+            if let Some(piece) = game_state.board[rank][file] {
+                let source = &PIECE_IMAGES[&(piece.piece_type, piece.color)];
+                let scaled = imageops::resize(source, square_size, square_size, FilterType::Triangle);
+                imageops::overlay(&mut image, &scaled, x as i64, y as i64);
+            }
+        }
+    }
+
+    image
+}
+
+// PNG thumbnail of the current position, e.g. for the game list
+pub fn render_thumbnail_png(game_state: &GameState, square_size: u32) -> ImageResult<Vec<u8>> {
+    let image = render_board(game_state, square_size);
+
+    let mut bytes = Vec::new();
+    DynamicImage::ImageRgba8(image).write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)?;
+
+    Ok(bytes)
+}
@@ -0,0 +1,279 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+// Fixed GUID from RFC 6455, appended to the client's key to build the accept key
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_HANDSHAKE_SIZE: usize = 8 * 1024;
+const MAX_FRAME_SIZE: u64 = 1024 * 1024; // NetworkMessages are small, anything bigger is a broken client
+
+pub const OPCODE_CONTINUATION: u8 = 0x0;
+pub const OPCODE_TEXT: u8 = 0x1;
+pub const OPCODE_BINARY: u8 = 0x2;
+pub const OPCODE_CLOSE: u8 = 0x8;
+pub const OPCODE_PING: u8 = 0x9;
+pub const OPCODE_PONG: u8 = 0xA;
+
+// Close code for a peer that broke the framing rules
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+const MAX_CONTROL_PAYLOAD: u64 = 125;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool, // Last frame of its message
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+// Puts fragmented messages back together. Control frames may come between the fragments of a message,
+// and are handed over as they arrive
+#[derive(Debug, Default)]
+pub struct MessageReader {
+    partial: Option<(u8, Vec<u8>)>, // Opcode and payload so far of a message still missing fragments
+}
+
+impl MessageReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    // The next whole message or control frame. Breaking the fragmentation rules is an InvalidData
+    // error, which the connection answers with CLOSE_PROTOCOL_ERROR
+    pub fn read<R: Read>(&mut self, reader: &mut R) -> Result<Frame, std::io::Error> {
+        loop {
+            let frame = read_frame(reader)?;
+            
+            if frame.opcode >= OPCODE_CLOSE {
+                if !frame.fin || frame.payload.len() as u64 > MAX_CONTROL_PAYLOAD {
+                    return Err(invalid_data("control frames can't be fragmented or longer than 125 bytes"));
+                }
+                return Ok(frame);
+            }
+            
+            let (opcode, mut payload) = match (frame.opcode, self.partial.take()) {
+                (OPCODE_CONTINUATION, Some(partial)) => partial,
+                (OPCODE_CONTINUATION, None) => return Err(invalid_data("continuation frame without a message to continue")),
+                (_, Some(_)) => return Err(invalid_data("new message before the last one was finished")),
+                (opcode, None) => (opcode, Vec::new()),
+            };
+            
+            payload.extend_from_slice(&frame.payload);
+            if payload.len() as u64 > MAX_FRAME_SIZE {
+                return Err(invalid_data("WebSocket message too large"));
+            }
+            
+            if frame.fin {
+                return Ok(Frame { fin: true, opcode, payload });
+            }
+            self.partial = Some((opcode, payload));
+        }
+    }
+}
+
+pub fn accept_key(client_key: &str) -> String {
+    let mut hasher = sha1_smol::Sha1::new();
+    hasher.update(client_key.trim().as_bytes());
+    hasher.update(WEBSOCKET_GUID.as_bytes());
+    BASE64.encode(hasher.digest().bytes())
+}
+
+// Read the HTTP upgrade request and answer it, leaving the stream ready for frames
+pub fn handshake<S: Read + Write>(stream: &mut S) -> Result<(), std::io::Error> {
+    let mut request = Vec::new();
+    let mut byte = [0u8; 1];
+    while !request.ends_with(b"\r\n\r\n") {
+        if request.len() >= MAX_HANDSHAKE_SIZE {
+            return Err(invalid_data("WebSocket handshake too large"));
+        }
+        stream.read_exact(&mut byte)?;
+        request.push(byte[0]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let client_key = request.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Sec-WebSocket-Key"))
+        .map(|(_, value)| value.trim().to_string());
+
+    match client_key {
+        Some(client_key) => {
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\n\
+                 Upgrade: websocket\r\n\
+                 Connection: Upgrade\r\n\
+                 Sec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(&client_key)
+            );
+            stream.write_all(response.as_bytes())?;
+            stream.flush()
+        }
+        None => {
+            stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n")?;
+            Err(invalid_data("missing Sec-WebSocket-Key header"))
+        }
+    }
+}
+
+// Server-to-client frames are never masked
+pub fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode); // FIN set, we never fragment
+
+    if payload.len() < 126 {
+        frame.push(payload.len() as u8);
+    } else if payload.len() <= u16::MAX as usize {
+        frame.push(126);
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    }
+
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// The close frame for a status code, e.g. CLOSE_PROTOCOL_ERROR, with a short reason
+pub fn encode_close(code: u16, reason: &str) -> Vec<u8> {
+    let mut payload = code.to_be_bytes().to_vec();
+    payload.extend_from_slice(reason.as_bytes());
+    payload.truncate(MAX_CONTROL_PAYLOAD as usize);
+    encode_frame(OPCODE_CLOSE, &payload)
+}
+
+// One frame from a browser. Clients must mask every frame, so an unmasked one is an InvalidData error
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Frame, std::io::Error> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    if !masked {
+        return Err(invalid_data("client frames must be masked"));
+    }
+
+    let length = match header[1] & 0x7F {
+        126 => {
+            let mut extended = [0u8; 2];
+            reader.read_exact(&mut extended)?;
+            u16::from_be_bytes(extended) as u64
+        }
+        127 => {
+            let mut extended = [0u8; 8];
+            reader.read_exact(&mut extended)?;
+            u64::from_be_bytes(extended)
+        }
+        length => length as u64,
+    };
+
+    if length > MAX_FRAME_SIZE {
+        return Err(invalid_data("WebSocket frame too large"));
+    }
+
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask)?;
+
+    let mut payload = vec![0u8; length as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+
+    Ok(Frame { fin, opcode, payload })
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Accept browser connections and relay each one to the regular TCP server as a normal client
+pub fn run_websocket_bridge(ws_port: u16, server_address: String) -> Result<(), std::io::Error> {
+    let listener = TcpListener::bind(format!("0.0.0.0:{}", ws_port))?;
+    println!("WebSocket bridge listening on port {}", ws_port);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let server_address = server_address.clone();
+                thread::spawn(move || {
+                    if let Err(e) = bridge_connection(stream, &server_address) {
+                        println!("WebSocket connection closed: {}", e);
+                    }
+                });
+            }
+            Err(e) => println!("Error accepting WebSocket connection: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+fn bridge_connection(mut browser: TcpStream, server_address: &str) -> Result<(), std::io::Error> {
+    handshake(&mut browser)?;
+
+    let server = TcpStream::connect(server_address)?;
+    let browser_writer = Arc::new(Mutex::new(browser.try_clone()?));
+
+    // Browser to server: each text message is one NetworkMessage
+    let mut server_writer = server.try_clone()?;
+    let pong_writer = Arc::clone(&browser_writer);
+    let mut browser_reader = browser;
+    let upstream = thread::spawn(move || -> Result<(), std::io::Error> {
+        let mut messages = MessageReader::new();
+        loop {
+            let frame = match messages.read(&mut browser_reader) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    // A browser breaking the framing rules is told why before the connection goes
+                    if e.kind() == std::io::ErrorKind::InvalidData {
+                        if let Ok(mut writer) = pong_writer.lock() {
+                            let _ = writer.write_all(&encode_close(CLOSE_PROTOCOL_ERROR, &e.to_string()));
+                        }
+                    }
+                    let _ = server_writer.shutdown(std::net::Shutdown::Both);
+                    return Err(e);
+                }
+            };
+            match frame.opcode {
+                OPCODE_TEXT => {
+                    server_writer.write_all(&frame.payload)?;
+                    server_writer.write_all(b"\n")?;
+                }
+                OPCODE_PING => {
+                    let pong = encode_frame(OPCODE_PONG, &frame.payload);
+                    if let Ok(mut writer) = pong_writer.lock() {
+                        writer.write_all(&pong)?;
+                    }
+                }
+                OPCODE_CLOSE => {
+                    server_writer.shutdown(std::net::Shutdown::Both)?;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    });
+
+    // Server to browser: each line becomes a text frame
+    let reader = BufReader::new(server);
+    for line in reader.lines() {
+        let frame = encode_frame(OPCODE_TEXT, line?.as_bytes());
+        if let Ok(mut writer) = browser_writer.lock() {
+            writer.write_all(&frame)?;
+        }
+    }
+
+    if let Ok(mut writer) = browser_writer.lock() {
+        let _ = writer.write_all(&encode_frame(OPCODE_CLOSE, &[]));
+        let _ = writer.shutdown(std::net::Shutdown::Both);
+    }
+
+    // The upstream thread ends with an error once the sockets are shut down, which is expected here
+    let _ = upstream.join();
+    Ok(())
+}
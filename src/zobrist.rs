@@ -1,3 +1,4 @@
+use crate::board::{BOARD_SIZE, SQUARE_COUNT};
 use crate::piece::{PieceType, Color};
 use rand::prelude::*;
 
@@ -11,33 +12,35 @@ pub const QUEEN: usize = 4;
 pub const KING: usize = 5;
 
 pub struct ZobristKeys {
-    pub piece_keys: [[[u64; 64]; 6]; 2],
+    pub piece_keys: [[[u64; SQUARE_COUNT]; 6]; 2],
     pub castling_keys: [u64; 4],
-    pub en_passant_keys: [u64; 8],
+    pub en_passant_keys: [u64; BOARD_SIZE],
     pub side_to_move_key: u64,
 }
 
+impl Default for ZobristKeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ZobristKeys {
     pub fn new() -> Self {
         let mut rng = rand::thread_rng();
         
-        let mut piece_keys = [[[0; 64]; 6]; 2];
-        for color in 0..2 {
-            for piece_type in 0..6 {
-                for square in 0..64 {
-                    piece_keys[color][piece_type][square] = rng.gen::<u64>();
-                }
-            }
+        let mut piece_keys = [[[0; SQUARE_COUNT]; 6]; 2];
+        for key in piece_keys.iter_mut().flatten().flatten() {
+            *key = rng.gen::<u64>();
         }
         
         let mut castling_keys = [0; 4];
-        for i in 0..4 {
-            castling_keys[i] = rng.gen::<u64>();
+        for key in castling_keys.iter_mut() {
+            *key = rng.gen::<u64>();
         }
         
-        let mut en_passant_keys = [0; 8];
-        for i in 0..8 {
-            en_passant_keys[i] = rng.gen::<u64>();
+        let mut en_passant_keys = [0; BOARD_SIZE];
+        for key in en_passant_keys.iter_mut() {
+            *key = rng.gen::<u64>();
         }
         
         let side_to_move_key = rng.gen::<u64>();
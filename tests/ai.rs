@@ -0,0 +1,135 @@
+// The computer opponent's search
+use chess::ai::{self, BackgroundSearch};
+use chess::board::{square_from_uci, GameState, Move};
+use chess::piece::PieceType;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Poll the way the window does each frame until the search answers
+fn wait_for(search: &BackgroundSearch) -> Option<Move> {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while Instant::now() < deadline {
+        if let Some(best) = search.try_result() {
+            return best;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    panic!("search did not finish");
+}
+
+#[test]
+fn background_search_finds_a_legal_move_without_blocking() {
+    let mut game = GameState::new();
+    let search = BackgroundSearch::start(&game, 2);
+    assert!(search.is_for(&game));
+    
+    let (from, to) = wait_for(&search).expect("the start position has moves");
+    assert!(game.get_all_legal_moves().contains(&(from, to)));
+}
+
+#[test]
+fn background_search_takes_a_hanging_queen() {
+    // Black's queen on d5 is en prise to the e4 pawn and nothing else matters
+    let game = GameState::from_fen("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1").unwrap();
+    let search = BackgroundSearch::start(&game, 2);
+    assert_eq!(wait_for(&search), Some((square_from_uci("e4").unwrap(), square_from_uci("d5").unwrap())));
+    assert_eq!(ai::best_move(&game, 2), wait_for(&BackgroundSearch::start(&game, 2)));
+}
+
+#[test]
+fn a_search_no_longer_applies_once_the_position_changes() {
+    let mut game = GameState::new();
+    let search = BackgroundSearch::start(&game, 1);
+    assert!(game.apply_uci_move("e2e4"));
+    assert!(!search.is_for(&game));
+    wait_for(&search);
+}
+
+#[test]
+fn finds_a_mate_in_two_with_the_longest_defence() {
+    let game = GameState::from_fen("r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 1 1").unwrap();
+    assert_eq!(ai::find_mate(&game, 1), None);
+    
+    let line = ai::find_mate(&game, 2).expect("there is a mate in two");
+    assert_eq!(game.line_to_san(&line), "1. Qd8+ Bxd8 2. Re8#");
+}
+
+#[test]
+fn finds_a_mate_that_needs_a_knight_promotion() {
+    // f8=Q doesn't even give check, only the knight mates the boxed-in king
+    let game = GameState::from_fen("6nb/5Ppk/6pp/8/8/8/8/K7 w - - 0 1").unwrap();
+    let line = ai::find_mate(&game, 1).expect("f8=N is mate");
+    
+    let f7 = square_from_uci("f7").unwrap();
+    let f8 = square_from_uci("f8").unwrap();
+    assert_eq!(line, vec![(f7, f8, Some(PieceType::Knight))]);
+    assert_eq!(game.line_to_san(&line), "1. f8=N#");
+}
+
+#[test]
+fn eval_cache_keeps_the_score_of_an_unchanged_position() {
+    let game = GameState::new();
+    let mut cache = ai::EvalCache::default();
+    assert_eq!(cache.score(), None);
+    
+    assert!(cache.refresh(&game, 1));
+    assert_eq!(cache.score(), Some(ai::white_score(&game, 1)));
+    assert!(!cache.refresh(&game.clone(), 1));
+}
+
+#[test]
+fn eval_cache_tells_apart_castling_rights_and_en_passant() {
+    let mut cache = ai::EvalCache::default();
+    
+    // The same pieces with the same side to move, differing only in what may still be played
+    let positions = [
+        "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w - - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3",
+    ];
+    for fen in positions {
+        let game = GameState::from_fen(fen).unwrap();
+        assert!(cache.refresh(&game, 1), "{} was served another position's score", fen);
+        assert_eq!(cache.score(), Some(ai::white_score(&game, 1)));
+    }
+}
+
+#[test]
+fn seeded_tie_break_is_repeatable_and_varies_between_seeds() {
+    // The knights' first moves mirror each other, so the search scores several of them the same
+    let game = GameState::new();
+    
+    for seed in 0..8 {
+        let first = ai::best_move_seeded(&game, 1, seed).expect("the start position has moves");
+        for _ in 0..3 {
+            assert_eq!(ai::best_move_seeded(&game, 1, seed), Some(first), "seed {} chose differently", seed);
+        }
+    }
+    
+    let choices: std::collections::HashSet<Move> = (0..32)
+        .filter_map(|seed| ai::best_move_seeded(&game, 1, seed))
+        .collect();
+    assert!(choices.len() > 1, "every seed chose {:?}", choices);
+    
+    // Only moves that tie for the best score are drawn from, so every choice is one of them
+    let mut best = None;
+    for choice in &choices {
+        let mut after = game.clone();
+        assert!(after.make_move(choice.0, choice.1).is_some());
+        let score = ai::white_score(&after, 0);
+        assert!(best.is_none_or(|best| best == score), "{:?} scored {} against {:?}", choice, score, best);
+        best = Some(score);
+    }
+}
+
+#[test]
+fn the_computer_promotes_to_the_strongest_piece_the_game_allows() {
+    let mut game = GameState::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(ai::promotion_piece(&game), Some(PieceType::Queen));
+    
+    // Without queens the pawn still promotes, and the search still sees the move
+    game.promotion_options = vec![PieceType::Knight, PieceType::Rook];
+    assert_eq!(ai::promotion_piece(&game), Some(PieceType::Rook));
+    assert_eq!(ai::best_move(&game, 1), Some((square_from_uci("a7").unwrap(), square_from_uci("a8").unwrap())));
+}
@@ -0,0 +1,38 @@
+// The --analyze review of a PGN: an eval after every move and the blunders marked. The search is
+// slow in a debug build, so the game is analysed once and everything is checked on that
+use chess::analysis::analyze_pgn;
+
+// Black's 3...Nf6 leaves f7 to the queen and bishop
+const BLUNDERED_GAME: &str = r#"[Event "Casual game"]
+[White "Alice"]
+[Black "Bob"]
+[Result "1-0"]
+
+1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0
+"#;
+
+#[test]
+fn every_move_gets_an_eval_and_the_blunder_is_flagged() {
+    let annotated = analyze_pgn(BLUNDERED_GAME).unwrap();
+    
+    assert!(annotated.starts_with("[Event \"Casual game\"]\n[White \"Alice\"]\n[Black \"Bob\"]\n[Result \"1-0\"]\n\n"));
+    assert_eq!(annotated.matches("[%eval ").count(), 7, "{}", annotated);
+    
+    // Movetext is wrapped, so a move and its comment may be split across lines
+    let flat = annotated.split_whitespace().collect::<Vec<_>>().join(" ");
+    for numbered in ["1. e4 {", "1... e5 {", "2. Qh5 {", "2... Nc6 {", "3. Bc4 {", "3... Nf6?? {", "4. Qxf7# {"] {
+        assert!(flat.contains(numbered), "{} is missing from {}", numbered, annotated);
+    }
+    assert!(annotated.trim_end().ends_with("1-0"));
+    
+    // Only Nf6 is a blunder: it allows mate in one, and the search would have played something else
+    assert_eq!(annotated.matches("??").count(), 1, "{}", annotated);
+    assert_eq!(flat.matches("Blunder, best was ").count(), 1, "{}", annotated);
+    assert!(flat.contains("Nf6?? {[%eval #1] Blunder, best was "), "{}", annotated);
+    assert!(!flat.contains("best was Nf6}"), "{}", annotated);
+}
+
+#[test]
+fn unreadable_pgn_is_an_error() {
+    assert!(analyze_pgn("1. e4 e5 2. Ke3 Ke6").is_err());
+}
@@ -0,0 +1,94 @@
+// The server fixture shared by the tests that play over TCP: a server on a free port, and clients
+// that wait for a message with a deadline instead of a fixed sleep
+#![allow(dead_code)]
+
+use chess::network::{ChessClient, NetworkMessage, TimeMode};
+use chess::server::ChessServer;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const MESSAGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Start a server on a free port and return its address
+pub fn start_server() -> String {
+    start_server_configured(|_| {})
+}
+
+// A server with its options set by the test, e.g. a short heartbeat interval. The history goes to a
+// file of its own unless the test picks one
+pub fn start_server_configured(configure: impl FnOnce(&mut ChessServer)) -> String {
+    let mut server = ChessServer::new(0).expect("server should bind a free port");
+    let port = server.local_port().expect("server should report its port");
+    server.set_history_file(std::env::temp_dir().join(format!("chess-test-history-{}.jsonl", port)));
+    configure(&mut server);
+    
+    thread::spawn(move || {
+        if let Err(e) = server.run() {
+            panic!("server stopped: {}", e);
+        }
+    });
+    
+    format!("127.0.0.1:{}", port)
+}
+
+// Read messages until one matches, skipping heartbeats, acknowledgements and anything else
+pub fn expect_message<F>(client: &mut ChessClient, what: &str, wanted: F) -> NetworkMessage
+where
+    F: FnMut(&NetworkMessage) -> bool,
+{
+    expect_message_within(client, what, MESSAGE_TIMEOUT, wanted)
+}
+
+pub fn expect_message_within<F>(client: &mut ChessClient, what: &str, timeout: Duration, mut wanted: F) -> NetworkMessage
+where
+    F: FnMut(&NetworkMessage) -> bool,
+{
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        match client.receive_message() {
+            Ok(Some(message)) if wanted(&message) => return message,
+            Ok(Some(_)) => {}
+            Ok(None) => thread::sleep(Duration::from_millis(2)),
+            Err(e) => panic!("connection failed while waiting for {}: {}", what, e),
+        }
+    }
+    panic!("no {} within {:?}", what, timeout);
+}
+
+// A live game with no clock, waiting for an opponent
+pub fn create_game(address: &str, host: &str) -> (ChessClient, String) {
+    let mut client = ChessClient::new(address).expect("host should connect");
+    client.send_message(NetworkMessage::CreateGame {
+        player_name: host.to_string(),
+        takeover_token: None,
+        time_mode: TimeMode::Live,
+        player_color_tag: None,
+        time_control: None,
+    }).unwrap();
+    let game_id = match expect_message(&mut client, "GameCreated", |message| matches!(message, NetworkMessage::GameCreated { .. })) {
+        NetworkMessage::GameCreated { game_id } => game_id,
+        _ => unreachable!(),
+    };
+    (client, game_id)
+}
+
+// Join a game and answer both GameStarts, returning once the opening board shows its thread is
+// running the game loop
+pub fn start_game(address: &str, host: &str) -> (ChessClient, ChessClient, String) {
+    let (mut white, game_id) = create_game(address, host);
+    let mut black = ChessClient::new(address).expect("guest should connect");
+    black.send_message(NetworkMessage::JoinGame {
+        game_id: game_id.clone(),
+        player_name: format!("{} guest", host),
+        player_color_tag: None,
+    }).unwrap();
+    
+    for client in [&mut white, &mut black] {
+        expect_message(client, "GameStart", |message| matches!(message, NetworkMessage::GameStart { .. }));
+        client.send_ready().unwrap();
+    }
+    for client in [&mut white, &mut black] {
+        expect_message(client, "the opening GameState", |message| matches!(message, NetworkMessage::GameState { .. }));
+    }
+    (white, black, game_id)
+}
@@ -0,0 +1,75 @@
+// Client settings survive a restart, and old or damaged config files fall back to the defaults
+use chess::config::{ClientConfig, InputMode};
+use chess::gui::ChessGui;
+use ggez::event::MouseButton;
+use std::fs;
+use std::path::PathBuf;
+
+fn config_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("chess-config-{}-{}", name, uuid::Uuid::new_v4())).join("chess_config.json")
+}
+
+#[test]
+fn input_mode_and_tutorial_are_saved() {
+    let path = config_path("saved");
+    let config = ClientConfig { tutorial_seen: true, input_mode: InputMode::Drag };
+    config.save(&path).expect("config should save into a new folder");
+    
+    let loaded = ClientConfig::load(&path);
+    assert!(loaded.tutorial_seen);
+    assert!(!loaded.should_show_tutorial());
+    assert_eq!(loaded.input_mode, InputMode::Drag);
+    
+    // Switching back to click-click is saved the same way
+    ClientConfig { input_mode: InputMode::ClickClick, ..loaded }.save(&path).unwrap();
+    assert_eq!(ClientConfig::load(&path).input_mode, InputMode::ClickClick);
+    
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn missing_and_damaged_configs_use_the_defaults() {
+    let path = config_path("missing");
+    let config = ClientConfig::load(&path);
+    assert!(config.should_show_tutorial());
+    assert_eq!(config.input_mode, InputMode::Both);
+    
+    // A file from before the input mode was saved keeps its tutorial flag
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, r#"{ "tutorial_seen": true }"#).unwrap();
+    let config = ClientConfig::load(&path);
+    assert!(config.tutorial_seen);
+    assert_eq!(config.input_mode, InputMode::Both);
+    
+    fs::write(&path, "not json").unwrap();
+    assert!(ClientConfig::load(&path).should_show_tutorial());
+    
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
+
+// The "Got it" button is centred along the bottom edge of the tutorial overlay
+const GOT_IT: (f32, f32) = (390.0, 460.0);
+
+#[test]
+fn dismissed_tutorial_stays_hidden_on_later_launches() {
+    let path = config_path("tutorial");
+    
+    let mut first_launch = ChessGui::with_text_pieces().unwrap();
+    first_launch.load_config(path.clone());
+    assert!(first_launch.shows_tutorial());
+    
+    // A click anywhere else is swallowed by the overlay
+    first_launch.handle_mouse_down(MouseButton::Left, 100.0, 100.0).unwrap();
+    assert!(first_launch.shows_tutorial());
+    assert!(!path.exists(), "nothing is saved until the tutorial is dismissed");
+    
+    first_launch.handle_mouse_down(MouseButton::Left, GOT_IT.0, GOT_IT.1).unwrap();
+    assert!(!first_launch.shows_tutorial());
+    assert!(ClientConfig::load(&path).tutorial_seen);
+    
+    let mut next_launch = ChessGui::with_text_pieces().unwrap();
+    next_launch.load_config(path.clone());
+    assert!(!next_launch.shows_tutorial());
+    
+    let _ = fs::remove_dir_all(path.parent().unwrap());
+}
@@ -0,0 +1,88 @@
+// Positions read from and written back to FEN, field by field
+use chess::board::{square_from_uci, FenError, GameState};
+use chess::piece::{Color, PieceType};
+
+const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[test]
+fn start_position_reads_and_writes_the_standard_fen() {
+    assert_eq!(GameState::new().to_fen(), START);
+    
+    let game = GameState::from_fen(START).unwrap();
+    assert_eq!(game.to_fen(), START);
+    assert_eq!(game.zobrist_hash(), GameState::new().zobrist_hash());
+}
+
+#[test]
+fn every_field_is_read() {
+    let game = GameState::from_fen("r3k2r/8/8/3pP3/8/8/8/R3K2R w Kq d6 7 42").unwrap();
+    
+    let piece_at = |name: &str| {
+        let (rank, file) = square_from_uci(name).unwrap();
+        game.board[rank][file].map(|piece| (piece.piece_type, piece.color))
+    };
+    assert_eq!(piece_at("a8"), Some((PieceType::Rook, Color::Black)));
+    assert_eq!(piece_at("e1"), Some((PieceType::King, Color::White)));
+    assert_eq!(piece_at("d5"), Some((PieceType::Pawn, Color::Black)));
+    assert_eq!(piece_at("e5"), Some((PieceType::Pawn, Color::White)));
+    assert_eq!(piece_at("e4"), None);
+    
+    assert_eq!(game.current_turn, Color::White);
+    assert!(game.white_can_castle_kingside);
+    assert!(!game.white_can_castle_queenside);
+    assert!(!game.black_can_castle_kingside);
+    assert!(game.black_can_castle_queenside);
+    assert_eq!(game.en_passant_target, square_from_uci("d6"));
+    assert_eq!(game.halfmove_clock, 7);
+    assert_eq!(game.fullmove_number, 42);
+}
+
+#[test]
+fn hash_and_history_are_seeded_from_the_position() {
+    let game = GameState::from_fen("4k3/8/8/8/8/8/8/R3K3 b Q - 3 20").unwrap();
+    assert_eq!(game.zobrist_hash(), game.calculate_zobrist_hash());
+    assert_eq!(game.repetition_count(), 1);
+}
+
+#[test]
+fn en_passant_square_is_written_in_algebraic_form() {
+    let mut game = GameState::new();
+    assert!(game.apply_uci_move("e2e4"));
+    assert_eq!(game.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+}
+
+#[test]
+fn positions_round_trip() {
+    let fens = [
+        "r3k2r/pppq1ppp/2np1n2/4p3/2B1P3/2NP1N2/PPPQ1PPP/R3K2R w KQkq - 5 8",
+        "8/5k2/8/3Pp3/8/8/8/4K3 w - e6 0 51",
+        "4k3/8/8/8/8/8/8/4K2R b K - 99 120",
+        "8/8/8/8/8/8/6k1/4K2R w K - 0 1",
+    ];
+    for fen in fens {
+        assert_eq!(GameState::from_fen(fen).unwrap().to_fen(), fen);
+    }
+}
+
+#[test]
+fn four_field_fen_starts_the_move_counters_afresh() {
+    let game = GameState::from_fen("4k3/8/8/8/8/8/8/4K3 b - -").unwrap();
+    assert_eq!(game.halfmove_clock, 0);
+    assert_eq!(game.fullmove_number, 1);
+    assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+}
+
+#[test]
+fn malformed_fields_are_reported() {
+    let cases = [
+        ("4k3/8/8/8/8/8/8/4K3 w -", FenError::WrongFieldCount(3)),
+        ("4k3/8/8/8/8/8/4K3 w - - 0 1", FenError::InvalidPlacement("4k3/8/8/8/8/8/4K3".to_string())),
+        ("4k3/8/8/8/8/8/8/4K3 x - - 0 1", FenError::InvalidTurn("x".to_string())),
+        ("4k3/8/8/8/8/8/8/4K3 w Z - 0 1", FenError::InvalidCastling("Z".to_string())),
+        ("4k3/8/8/8/8/8/8/4K3 w - e9 0 1", FenError::InvalidEnPassant("e9".to_string())),
+        ("4k3/8/8/8/8/8/8/4K3 w - - x 1", FenError::InvalidClock("x".to_string())),
+    ];
+    for (fen, expected) in cases {
+        assert_eq!(GameState::from_fen(fen).err(), Some(expected), "{}", fen);
+    }
+}
@@ -0,0 +1,211 @@
+// The server's game registry: the game list stays quick to answer while games are being played,
+// and cleanup removes idle games without getting in anyone's way. A lobby connection that never joins a
+// game is answered, then dropped
+mod common;
+
+use chess::network::{ChessClient, ChessNetError, GameInfo, GameStatus, NetworkMessage};
+use common::{create_game, expect_message, expect_message_within, start_game, start_server_configured, MESSAGE_TIMEOUT};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Far longer than a game list takes to build, short enough to catch a registry held for a whole game
+const RESPONSIVE_WITHIN: Duration = Duration::from_secs(1);
+
+// A server that cleans up games left idle for this long
+fn start_server(max_inactive_time: Duration) -> String {
+    start_server_configured(|server| server.set_max_inactive_time(max_inactive_time))
+}
+
+// A fresh connection asking for the game list, which must come back promptly
+fn game_list(address: &str) -> Vec<GameInfo> {
+    let mut client = ChessClient::new(address).expect("lobby client should connect");
+    ask_game_list(&mut client)
+}
+
+fn ask_game_list(client: &mut ChessClient) -> Vec<GameInfo> {
+    let asked = Instant::now();
+    client.send_message(NetworkMessage::RequestGameList).unwrap();
+    let list = expect_message_within(client, "GameList", RESPONSIVE_WITHIN, |message| matches!(message, NetworkMessage::GameList { .. }));
+    assert!(asked.elapsed() < RESPONSIVE_WITHIN, "game list took {:?}", asked.elapsed());
+    match list {
+        NetworkMessage::GameList { available_games } => available_games,
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn game_list_answers_while_a_game_is_running() {
+    let address = start_server(Duration::from_secs(300));
+    let (mut white, mut black, game_id) = start_game(&address, "Alice");
+    
+    // Ask over and over while the game thread is busy with moves and heartbeats
+    for (i, (from, to)) in [((6, 4), (4, 4)), ((1, 4), (3, 4)), ((7, 6), (5, 5)), ((0, 1), (2, 2))].into_iter().enumerate() {
+        let (moving, waiting) = if i % 2 == 0 { (&mut white, &mut black) } else { (&mut black, &mut white) };
+        moving.send_move(from, to, None).unwrap();
+        
+        let games = game_list(&address);
+        let game = games.iter().find(|game| game.game_id == game_id).expect("the running game should be listed");
+        assert_eq!(game.status, GameStatus::InProgress);
+        
+        expect_message(waiting, "the opponent's move", |message| matches!(message, NetworkMessage::Move { .. }));
+    }
+}
+
+#[test]
+fn cleanup_removes_idle_games_but_not_running_ones() {
+    let address = start_server(Duration::from_millis(300));
+    let (_idle_host, idle_game) = create_game(&address, "Idle");
+    let (mut white, mut black, running_game) = start_game(&address, "Busy");
+    
+    // Cleanup runs as connections come in. The running game keeps moving, so it stays
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    let mut moves = [((6, 4), (4, 4)), ((1, 4), (3, 4)), ((7, 6), (5, 5)), ((0, 1), (2, 2)), ((7, 5), (4, 2)), ((0, 6), (2, 5))].into_iter();
+    let mut white_to_move = true;
+    loop {
+        let games = game_list(&address);
+        if games.iter().all(|game| game.game_id != idle_game) {
+            assert!(games.iter().any(|game| game.game_id == running_game), "a game in play was removed");
+            break;
+        }
+        assert!(Instant::now() < deadline, "the idle game was never removed");
+        
+        let (from, to) = moves.next().expect("the idle game should go before the moves run out");
+        let (moving, waiting) = if white_to_move { (&mut white, &mut black) } else { (&mut black, &mut white) };
+        moving.send_move(from, to, None).unwrap();
+        expect_message(waiting, "the opponent's move", |message| matches!(message, NetworkMessage::Move { .. }));
+        white_to_move = !white_to_move;
+        thread::sleep(Duration::from_millis(150));
+    }
+}
+
+#[test]
+fn many_games_at_once_keep_the_registry_responsive() {
+    const GAMES: usize = 8;
+    let address = start_server(Duration::from_secs(5));
+    
+    let games: Vec<_> = (0..GAMES)
+        .map(|i| {
+            let address = address.clone();
+            thread::spawn(move || start_game(&address, &format!("Player {}", i)))
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|game| game.join().expect("every game should start alongside the others"))
+        .collect();
+    let game_ids: Vec<String> = games.iter().map(|(_, _, game_id)| game_id.clone()).collect();
+    
+    // Every game plays a short opening on its own thread while the lobby keeps asking for the list
+    let players: Vec<_> = games.into_iter()
+        .map(|(mut white, mut black, _)| {
+            thread::spawn(move || {
+                let opening = [((6, 4), (4, 4)), ((1, 4), (3, 4)), ((7, 6), (5, 5)), ((0, 1), (2, 2))];
+                for (i, (from, to)) in opening.into_iter().enumerate() {
+                    let (moving, waiting) = if i % 2 == 0 { (&mut white, &mut black) } else { (&mut black, &mut white) };
+                    moving.send_move(from, to, None).unwrap();
+                    expect_message(waiting, "the opponent's move", |message| matches!(message, NetworkMessage::Move { .. }));
+                    thread::sleep(Duration::from_millis(20));
+                }
+                (white, black)
+            })
+        })
+        .collect();
+    
+    let mut lobby = ChessClient::new(&address).expect("lobby client should connect");
+    for _ in 0..20 {
+        let listed = ask_game_list(&mut lobby);
+        for game_id in &game_ids {
+            assert!(listed.iter().any(|game| &game.game_id == game_id), "game {} went missing", game_id);
+        }
+    }
+    drop(lobby);
+    
+    let players: Vec<_> = players.into_iter()
+        .map(|player| player.join().expect("every game should be playable alongside the others"))
+        .collect();
+    
+    // Once everyone has left and the games have gone idle, cleanup removes all of them
+    drop(players);
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while !game_list(&address).is_empty() {
+        assert!(Instant::now() < deadline, "abandoned games were never removed");
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+#[test]
+fn moves_before_joining_a_game_are_answered_then_dropped() {
+    let address = start_server(Duration::from_secs(300));
+    let mut client = ChessClient::new(&address).expect("client should connect");
+    
+    // The first stray moves are told to create or join a game
+    for _ in 0..2 {
+        client.send_move((6, 4), (4, 4), None).unwrap();
+        let status = expect_message(&mut client, "ConnectionStatus", |message| matches!(message, NetworkMessage::ConnectionStatus { .. }));
+        assert!(matches!(status, NetworkMessage::ConnectionStatus { connected: true, .. }));
+    }
+    
+    // The last one is refused with the reason, and the server hangs up
+    client.send_move((6, 4), (4, 4), None).unwrap();
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    let error = loop {
+        match client.receive_message() {
+            Err(e) => break e,
+            Ok(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+            Ok(other) => panic!("connection still open after too many stray messages, last read {:?}", other),
+        }
+    };
+    assert!(matches!(error, ChessNetError::Rejected(_)), "expected a rejection, got {:?}", error);
+    
+    // Meanwhile the server is free to answer the next connection
+    assert!(game_list(&address).is_empty());
+}
+
+#[test]
+fn spectating_a_missing_game_is_rejected_and_leaves_the_client_in_the_lobby() {
+    let address = start_server(Duration::from_secs(300));
+    let mut client = ChessClient::new(&address).expect("client should connect");
+    
+    client.spectate_game("no-such-game".to_string(), "Carol".to_string()).unwrap();
+    match expect_message(&mut client, "JoinRejected", |message| matches!(message, NetworkMessage::JoinRejected { .. })) {
+        NetworkMessage::JoinRejected { game_id, .. } => assert_eq!(game_id, "no-such-game"),
+        _ => unreachable!(),
+    }
+    
+    // The same connection can go straight back to the game list
+    assert!(ask_game_list(&mut client).is_empty());
+}
+
+#[test]
+fn a_quiet_lobby_connection_holds_up_nobody() {
+    let address = start_server(Duration::from_secs(300));
+    
+    // Connected and saying nothing, as a player reading the game list would
+    let _browsing = ChessClient::new(&address).expect("lobby client should connect");
+    let mut refused = ChessClient::new(&address).expect("client should connect");
+    refused.spectate_game("no-such-game".to_string(), "Carol".to_string()).unwrap();
+    expect_message(&mut refused, "JoinRejected", |message| matches!(message, NetworkMessage::JoinRejected { .. }));
+    
+    // Both still connected, a new game is created and listed promptly
+    let (_host, game_id) = create_game(&address, "Alice");
+    assert!(game_list(&address).iter().any(|game| game.game_id == game_id));
+}
+
+#[test]
+fn a_lobby_connection_that_goes_silent_is_dropped() {
+    let interval = Duration::from_millis(100);
+    let address = start_server_configured(|server| server.set_heartbeat_interval(interval));
+    let mut silent = ChessClient::new(&address).expect("lobby client should connect");
+    
+    // Nothing sent, not even a heartbeat, for well past two and a half intervals
+    thread::sleep(interval * 6);
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    loop {
+        match silent.receive_message() {
+            Err(_) => break,
+            Ok(_) => {
+                assert!(Instant::now() < deadline, "the silent connection was never dropped");
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+    }
+}
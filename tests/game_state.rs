@@ -0,0 +1,574 @@
+// GameState helpers a frontend builds on, checked without a window
+use chess::board::{perspective_square, square_from_uci, GameState, GameStateBuilder, LineMove, MatePattern, SavedPosition};
+use chess::piece::{Color, PieceType};
+
+#[test]
+fn black_perspective_puts_black_at_the_bottom() {
+    let game = GameState::new();
+    let view = game.perspective_board(Color::Black);
+    
+    for row in &view[6..8] {
+        for square in row {
+            assert_eq!(square.map(|piece| piece.color), Some(Color::Black));
+        }
+    }
+    for row in &view[0..2] {
+        for square in row {
+            assert_eq!(square.map(|piece| piece.color), Some(Color::White));
+        }
+    }
+    
+    // Flipping both axes keeps the kings facing each other on the e-file, which is now the d column
+    assert_eq!(view[7][3].map(|piece| piece.piece_type), Some(PieceType::King));
+    assert_eq!(view[0][3].map(|piece| piece.piece_type), Some(PieceType::King));
+    assert_eq!(perspective_square((7, 3), Color::Black), (0, 4));
+}
+
+#[test]
+fn white_perspective_is_the_board_itself() {
+    let game = GameState::new();
+    assert_eq!(game.perspective_board(Color::White), game.board);
+}
+
+#[test]
+fn move_log_san_follows_the_game() {
+    let mut game = GameState::new();
+    for uci in ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "g8f6", "e1g1"] {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    }
+    
+    assert_eq!(game.move_log_san(), vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "Nf6", "O-O"]);
+    assert!(game.to_pgn().contains("1. e4 e5 2. Nf3 Nc6 3. Bb5 Nf6 4. O-O *"));
+}
+
+#[test]
+fn captures_are_tallied_and_given_back_on_unmake() {
+    let mut game = GameState::new();
+    // 1. e4 d5 2. exd5 Qxd5, a pawn lost by each side
+    for uci in ["e2e4", "d7d5", "e4d5", "d8d5"] {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    }
+    
+    let taken: Vec<(PieceType, Color)> = game.captured.iter().map(|piece| (piece.piece_type, piece.color)).collect();
+    assert_eq!(taken, vec![(PieceType::Pawn, Color::Black), (PieceType::Pawn, Color::White)]);
+    
+    assert!(game.take_back());
+    assert_eq!(game.captured.len(), 1);
+    assert!(game.take_back());
+    assert!(game.captured.is_empty());
+}
+
+#[test]
+fn en_passant_captures_the_passed_pawn() {
+    let mut game = GameState::new();
+    for uci in ["e2e4", "a7a6", "e4e5", "d7d5", "e5d6"] {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    }
+    
+    assert_eq!(game.captured.len(), 1);
+    assert_eq!((game.captured[0].piece_type, game.captured[0].color), (PieceType::Pawn, Color::Black));
+}
+
+#[test]
+fn null_move_passes_the_turn_and_comes_back() {
+    let mut game = GameState::new();
+    assert!(game.apply_uci_move("e2e4"));
+    let before = (game.to_fen(), game.zobrist_hash());
+    
+    // Passing also gives up the en passant capture the double push allowed
+    assert!(game.make_null_move());
+    assert_eq!(game.current_turn, Color::White);
+    assert_eq!(game.en_passant_target, None);
+    assert!(game.verify_hash());
+    
+    assert!(game.unmake_null_move());
+    assert_eq!((game.to_fen(), game.zobrist_hash()), before);
+    assert!(!game.unmake_null_move(), "there was only one null move to take back");
+}
+
+#[test]
+fn null_moves_and_moves_come_back_in_the_order_they_were_made() {
+    let mut game = GameState::new();
+    let start = game.to_fen();
+    assert!(game.apply_uci_move("g1f3"));
+    let after_move = game.to_fen();
+    
+    // A real move can't be taken back from under a null move, nor a null move that wasn't the last
+    assert!(game.make_null_move());
+    assert!(!game.take_back());
+    assert!(game.apply_uci_move("b1c3"));
+    assert!(!game.unmake_null_move());
+    
+    assert!(game.take_back());
+    assert!(game.unmake_null_move());
+    assert_eq!(game.to_fen(), after_move);
+    assert!(game.take_back());
+    assert_eq!(game.to_fen(), start);
+    assert!(game.verify_hash());
+}
+
+#[test]
+fn null_move_is_refused_in_check() {
+    let mut game = GameState::from_fen("4k3/8/8/8/8/8/8/4K2r w - - 0 1").unwrap();
+    assert!(!game.make_null_move());
+    assert!(!game.unmake_null_move());
+    assert_eq!(game.current_turn, Color::White);
+}
+
+fn save_file(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("chess-{}-{}.json", name, uuid::Uuid::new_v4()))
+}
+
+#[test]
+fn saved_positions_load_back() {
+    let mut game = GameState::new();
+    for uci in ["e2e4", "c7c5", "g1f3"] {
+        assert!(game.apply_uci_move(uci));
+    }
+    
+    let path = save_file("saved");
+    game.save(&path).unwrap();
+    let loaded = GameState::load(&path).unwrap();
+    assert_eq!(loaded.to_fen(), game.to_fen());
+    assert_eq!(loaded.zobrist_hash(), game.zobrist_hash());
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn a_stored_wrong_hash_is_corrected_on_load() {
+    let fen = "r3k2r/pppq1ppp/2n2n2/3pp3/3PP3/2N2N2/PPPQ1PPP/R3K2R b KQkq d3 0 7";
+    let saved = SavedPosition { fen: fen.to_string(), hash: 0xDEAD_BEEF };
+    let path = save_file("wrong-hash");
+    std::fs::write(&path, serde_json::to_string(&saved).unwrap()).unwrap();
+    
+    let loaded = GameState::load(&path).unwrap();
+    assert_ne!(loaded.zobrist_hash(), 0xDEAD_BEEF);
+    assert_eq!(loaded.zobrist_hash(), GameState::from_fen(fen).unwrap().zobrist_hash());
+    assert!(loaded.verify_hash());
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn unreadable_save_files_are_an_error() {
+    let path = save_file("damaged");
+    std::fs::write(&path, r#"{ "fen": "not a position", "hash": 1 }"#).unwrap();
+    assert_eq!(GameState::load(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    
+    std::fs::write(&path, "not json").unwrap();
+    assert!(GameState::load(&path).is_err());
+    let _ = std::fs::remove_file(path);
+}
+
+// The move diff reads back from the boards before and after a UCI move
+fn diff_of(fen: &str, uci: &str) -> Option<LineMove> {
+    let mut game = GameState::from_fen(fen).unwrap();
+    let before = game.board;
+    assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    GameState::diff(&before, &game.board)
+}
+
+fn square(uci: &str) -> (usize, usize) {
+    square_from_uci(uci).unwrap()
+}
+
+#[test]
+fn diff_finds_a_quiet_move() {
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(diff_of(start, "g1f3"), Some((square("g1"), square("f3"), None)));
+    assert_eq!(diff_of(start, "e2e4"), Some((square("e2"), square("e4"), None)));
+}
+
+#[test]
+fn diff_finds_a_capture() {
+    let fen = "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2";
+    assert_eq!(diff_of(fen, "e4d5"), Some((square("e4"), square("d5"), None)));
+    
+    // En passant empties the passed pawn's square too
+    let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+    assert_eq!(diff_of(fen, "e5f6"), Some((square("e5"), square("f6"), None)));
+}
+
+#[test]
+fn diff_finds_a_promotion() {
+    let fen = "3r3k/4P3/8/8/8/8/8/K7 w - - 0 1";
+    assert_eq!(diff_of(fen, "e7e8q"), Some((square("e7"), square("e8"), Some(PieceType::Queen))));
+    assert_eq!(diff_of(fen, "e7d8n"), Some((square("e7"), square("d8"), Some(PieceType::Knight))));
+}
+
+#[test]
+fn diff_reports_castling_as_the_kings_move() {
+    let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+    assert_eq!(diff_of(fen, "e1g1"), Some((square("e1"), square("g1"), None)));
+    assert_eq!(diff_of(fen, "e1c1"), Some((square("e1"), square("c1"), None)));
+    
+    let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+    assert_eq!(diff_of(fen, "e8g8"), Some((square("e8"), square("g8"), None)));
+}
+
+#[test]
+fn diff_of_more_than_one_move_is_none() {
+    let mut game = GameState::new();
+    let before = game.board;
+    assert!(game.apply_uci_move("e2e4"));
+    assert!(game.apply_uci_move("e7e5"));
+    assert_eq!(GameState::diff(&before, &game.board), None);
+    assert_eq!(GameState::diff(&before, &before), None);
+}
+
+#[test]
+fn smothered_mate_is_recognised() {
+    let game = GameState::from_fen("6rk/5Npp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+    assert_eq!(game.mate_pattern(), Some(MatePattern::Smothered));
+    assert_eq!(MatePattern::Smothered.to_string(), "Smothered mate");
+}
+
+#[test]
+fn back_rank_mate_is_recognised() {
+    let mut game = GameState::from_fen("6k1/5ppp/8/8/8/8/8/3R2K1 w - - 0 1").unwrap();
+    assert_eq!(game.mate_pattern(), None, "nothing is mated yet");
+    assert!(game.apply_uci_move("d1d8"));
+    assert_eq!(game.mate_pattern(), Some(MatePattern::BackRank));
+}
+
+#[test]
+fn ladder_mate_is_recognised() {
+    let game = GameState::from_fen("R6k/1R6/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+    assert_eq!(game.mate_pattern(), Some(MatePattern::Ladder));
+}
+
+#[test]
+fn other_mates_have_no_pattern() {
+    // Fool's mate: a queen on the diagonal fits none of them
+    let mut game = GameState::new();
+    for uci in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+        assert!(game.apply_uci_move(uci));
+    }
+    assert!(game.is_checkmate());
+    assert_eq!(game.mate_pattern(), None);
+}
+
+#[test]
+fn repeating_a_position_bumps_its_count() {
+    let mut game = GameState::new();
+    assert_eq!(game.repetition_count(), 1);
+    
+    // Knights out and back brings the start position round again
+    for expected in [2, 3] {
+        for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+        }
+        assert_eq!(game.repetition_count(), expected);
+    }
+    assert!(game.is_threefold_repetition());
+    
+    // A position seen only once is still counted once
+    assert!(game.apply_uci_move("e2e4"));
+    assert_eq!(game.repetition_count(), 1);
+}
+
+// The halfmove clock after each move of a line, from a position whose clock already stands at 5
+fn halfmove_clocks(line: &[&str]) -> Vec<u32> {
+    let mut game = GameState::from_fen("r3k2r/pppq1ppp/2np1n2/4p3/2B1P3/2NP1N2/PPPQ1PPP/R3K2R w KQkq - 5 8").unwrap();
+    line.iter().map(|uci| {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+        game.halfmove_clock
+    }).collect()
+}
+
+#[test]
+fn halfmove_clock_counts_quiet_moves_and_castling() {
+    // A knight move, then castling on each side
+    assert_eq!(halfmove_clocks(&["c3b5", "e8g8", "e1c1"]), vec![6, 7, 8]);
+}
+
+#[test]
+fn halfmove_clock_resets_on_pawn_moves_and_captures() {
+    // A pawn push, a quiet move, then a bishop capture
+    assert_eq!(halfmove_clocks(&["h2h3", "c6b4", "c4f7"]), vec![0, 1, 0]);
+}
+
+#[test]
+fn fullmove_number_advances_after_black_moves() {
+    let mut game = GameState::new();
+    assert_eq!(game.fullmove_number, 1);
+    assert!(game.apply_uci_move("e2e4"));
+    assert_eq!(game.fullmove_number, 1);
+    assert!(game.apply_uci_move("e7e5"));
+    assert_eq!(game.fullmove_number, 2);
+}
+
+#[test]
+fn a_bishop_pinning_a_knight_is_reported() {
+    // 1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. d4 Nf6 5. Nc3 d6: the c6 knight is pinned by the b5 bishop
+    let mut game = GameState::new();
+    for uci in ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "d2d4", "g8f6", "b1c3", "d7d6"] {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    }
+    
+    assert_eq!(game.pinned_pieces(Color::Black), vec![(square("c6"), square("b5"))]);
+    assert!(game.pinned_pieces(Color::White).is_empty());
+}
+
+#[test]
+fn a_piece_screened_by_another_is_not_pinned() {
+    // The rook on e4 would be pinned, but the e2 pawn also stands between it and the king
+    let game = GameState::from_fen("4r1k1/8/8/8/4R3/8/4P3/4K3 w - - 0 1").unwrap();
+    assert!(game.pinned_pieces(Color::White).is_empty());
+    
+    let game = GameState::from_fen("4r1k1/8/8/8/4R3/8/8/4K3 w - - 0 1").unwrap();
+    assert_eq!(game.pinned_pieces(Color::White), vec![(square("e4"), square("e8"))]);
+}
+
+fn after(line: &[&str]) -> GameState {
+    let mut game = GameState::new();
+    for uci in line {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    }
+    game
+}
+
+#[test]
+fn start_positions_are_equal_until_one_moves() {
+    let mut game = GameState::new();
+    assert_eq!(game, GameState::new());
+    
+    assert!(game.apply_uci_move("e2e4"));
+    assert_ne!(game, GameState::new());
+    
+    assert!(game.take_back());
+    assert_eq!(game, GameState::new());
+}
+
+#[test]
+fn transposed_move_orders_reach_the_same_position() {
+    assert_eq!(after(&["g1f3", "g8f6", "b1c3"]), after(&["b1c3", "g8f6", "g1f3"]));
+    
+    // The same pieces on the same squares, but with Black to move in only one of them
+    assert_ne!(after(&["g1f3", "g8f6", "f3g1"]), after(&["g1f3", "g8f6", "f3g1", "f6g8"]));
+}
+
+#[test]
+fn knights_that_went_out_and_back_repeat_the_start_position() {
+    let shuffled = after(&["g1f3", "g8f6", "f3g1", "f6g8"]);
+    assert_eq!(shuffled, GameState::new());
+    assert_eq!(shuffled.zobrist_hash(), GameState::new().zobrist_hash());
+    assert_eq!(shuffled.repetition_count(), 2);
+}
+
+#[test]
+fn kings_that_stepped_out_and_back_make_a_different_position() {
+    // The same pieces on the same squares, but neither side can castle any more
+    let shuffled = after(&["e2e4", "e7e5", "e1e2", "e8e7", "e2e1", "e7e8"]);
+    let direct = after(&["e2e4", "e7e5"]);
+    let placement = |game: &GameState| game.board.map(|row| row.map(|square| square.map(|piece| (piece.piece_type, piece.color))));
+    assert_eq!(placement(&shuffled), placement(&direct));
+    assert_ne!(shuffled, direct);
+}
+
+#[test]
+fn piece_placement_is_the_first_fen_field() {
+    let mut game = GameState::new();
+    assert_eq!(game.piece_placement_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR");
+    
+    assert!(game.apply_uci_move("e2e4"));
+    assert_eq!(game.piece_placement_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR");
+    assert_eq!(game.to_fen().split(' ').next(), Some(game.piece_placement_fen().as_str()));
+}
+
+#[test]
+fn a_pawn_move_starts_the_repetition_count_afresh() {
+    let mut game = after(&["g1f3", "g8f6", "f3g1", "f6g8"]);
+    assert_eq!(game.repetition_count(), 2);
+    
+    // Nothing before the pawn move can come back, so only the new position is counted
+    assert!(game.apply_uci_move("e2e4"));
+    assert_eq!(game.repetition_count(), 1);
+    
+    // Two more knight tours reach the position after 1. e4 only twice more, as the first time had the
+    // en passant square, so no threefold yet
+    for uci in ["g8f6", "g1f3", "f6g8", "f3g1", "g8f6", "g1f3", "f6g8", "f3g1"] {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    }
+    assert_eq!(game.repetition_count(), 2);
+    assert!(!game.is_threefold_repetition());
+    
+    // Taking the pawn move back brings the old counts back too
+    let mut game = after(&["g1f3", "g8f6", "f3g1", "f6g8", "e2e4"]);
+    assert!(game.take_back());
+    assert_eq!(game.repetition_count(), 2);
+}
+
+#[test]
+fn trainer_stats_follow_the_game() {
+    let game = GameState::new();
+    assert_eq!((game.moves_since_capture(), game.total_moves()), (0, 1));
+    assert_eq!(game.material_count(Color::White), 39);
+    assert_eq!(game.material_count(Color::Black), 39);
+    
+    // 1. e4 d5 2. exd5 Qxd5 3. Nc3 Qa5 4. Nf3
+    let game = after(&["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a5", "g1f3"]);
+    assert_eq!(game.moves_since_capture(), game.halfmove_clock);
+    assert_eq!(game.moves_since_capture(), 3);
+    assert_eq!(game.total_moves(), 4);
+    assert_eq!(game.material_count(Color::White), 38);
+    assert_eq!(game.material_count(Color::Black), 38);
+    
+    // A pawn move resets the halfmove clock but not the count since the capture
+    let game = after(&["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a5", "a2a3"]);
+    assert_eq!((game.moves_since_capture(), game.halfmove_clock), (3, 0));
+    
+    let mut game = GameState::from_fen("4k3/8/8/8/8/8/8/R2QK3 w - - 12 40").unwrap();
+    assert_eq!((game.moves_since_capture(), game.total_moves()), (12, 40));
+    assert_eq!(game.material_count(Color::White), 14);
+    assert_eq!(game.material_count(Color::Black), 0);
+    
+    // With no capture played, the count goes on from the clock the position came with
+    assert!(game.apply_uci_move("a1a7"));
+    assert!(game.apply_uci_move("e8f8"));
+    assert_eq!((game.moves_since_capture(), game.halfmove_clock), (14, 14));
+}
+
+#[test]
+fn builder_makes_a_playable_rook_ending() {
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("a1", PieceType::Rook, Color::White)
+        .place("e8", PieceType::King, Color::Black)
+        .turn(Color::Black)
+        .build();
+    
+    // The same position FEN would give, hash and history included, and nothing left to repair
+    let from_fen = GameState::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+    assert_eq!(game, from_fen);
+    assert_eq!(game.zobrist_hash(), from_fen.zobrist_hash());
+    assert!(game.verify_hash());
+    assert_eq!(game.repetition_count(), 1);
+    assert!(!game.validate_and_repair());
+    
+    assert!(!game.is_game_over());
+    assert!(!game.is_in_check(Color::Black));
+    assert!(!game.is_insufficient_material());
+    assert!(!game.get_all_legal_moves().is_empty());
+}
+
+#[test]
+fn builder_keeps_only_castling_rights_the_pieces_allow() {
+    // No rook on h1, so white's kingside right is dropped
+    let game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("a1", PieceType::Rook, Color::White)
+        .place("e8", PieceType::King, Color::Black)
+        .place("d5", PieceType::Pawn, Color::Black)
+        .place("e5", PieceType::Pawn, Color::White)
+        .castling("KQ")
+        .en_passant("d6")
+        .build();
+    
+    assert_eq!(game.to_fen(), "4k3/8/8/3pP3/8/8/8/R3K3 w Q d6 0 1");
+}
+
+#[test]
+fn single_escape_from_check_is_the_forced_move() {
+    // The rook checks along the first rank and the bishop covers g2, leaving only Kh2
+    let mut game = GameStateBuilder::new()
+        .place("h1", PieceType::King, Color::White)
+        .place("a8", PieceType::King, Color::Black)
+        .place("a1", PieceType::Rook, Color::Black)
+        .place("d5", PieceType::Bishop, Color::Black)
+        .build();
+    
+    assert_eq!(game.legal_move_count(), 1);
+    assert_eq!(game.forced_move(), Some((square("h1"), square("h2"))));
+}
+
+#[test]
+fn no_forced_move_with_a_choice_or_with_none() {
+    let mut start = GameState::new();
+    assert_eq!(start.forced_move(), None);
+    
+    // Fool's mate: white has no legal moves at all
+    let mut mated = after(&["f2f3", "e7e5", "g2g4", "d8h4"]);
+    assert_eq!(mated.legal_move_count(), 0);
+    assert_eq!(mated.forced_move(), None);
+}
+
+// Everything unmake_move has to put back, besides the board the FEN already covers
+fn snapshot(game: &GameState) -> (String, u64, u32, usize, usize) {
+    (game.to_fen(), game.zobrist_hash(), game.repetition_count(), game.captured.len(), game.move_log.len())
+}
+
+#[test]
+fn unmaking_every_kind_of_move_restores_each_earlier_position() {
+    // Castling both ways, a capture, en passant and a promotion with capture
+    let line = [
+        "e2e4", "d7d5", "e4e5", "f7f5", "e5f6", "g8h6", "g1f3", "c8e6", "f1c4", "b8c6",
+        "e1g1", "d8d7", "f6g7", "e8c8", "g7h8q",
+    ];
+    let mut game = GameState::new();
+    let mut before_each = Vec::new();
+    for uci in line {
+        before_each.push(snapshot(&game));
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    }
+    
+    for uci in line.iter().rev() {
+        assert!(game.take_back(), "{} should come back", uci);
+        assert_eq!(snapshot(&game), before_each.pop().unwrap(), "wrong position after taking back {}", uci);
+        assert!(game.verify_hash());
+    }
+    assert!(!game.take_back(), "there is nothing left to take back");
+    assert_eq!(game, GameState::new());
+}
+
+#[test]
+fn unmade_moves_can_be_played_again() {
+    let mut game = GameState::new();
+    for uci in ["e2e4", "e7e5", "g1f3"] {
+        assert!(game.apply_uci_move(uci));
+    }
+    let played = snapshot(&game);
+    
+    assert!(game.take_back());
+    assert!(game.take_back());
+    assert!(game.apply_uci_move("e7e5"));
+    assert!(game.apply_uci_move("g1f3"));
+    assert_eq!(snapshot(&game), played);
+}
+
+#[test]
+fn a_move_comes_back_from_the_undo_make_move_returned() {
+    let square = |name| square_from_uci(name).unwrap();
+    let mut game = GameState::from_fen("4k3/1P6/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+    let start = snapshot(&game);
+    
+    let castle = game.make_move(square("e1"), square("g1")).expect("White may castle");
+    let reply = game.make_move(square("e8"), square("d7")).expect("the king may step aside");
+    
+    // Only the last move made can come back
+    assert!(!game.unmake_move(castle));
+    assert!(game.unmake_move(reply));
+    assert!(game.unmake_move(castle));
+    assert_eq!(snapshot(&game), start);
+    assert!(game.verify_hash());
+    
+    // A promotion comes back from the same undo once its piece has been chosen
+    let promotion = game.make_move(square("b7"), square("b8")).expect("the pawn may promote");
+    assert!(game.promote_pawn(PieceType::Knight));
+    assert!(game.unmake_move(promotion));
+    assert_eq!(snapshot(&game), start);
+    assert_eq!(game, GameState::from_fen("4k3/1P6/8/8/8/8/8/4K2R w K - 0 1").unwrap());
+    assert!(!game.unmake_move(promotion), "a move can only be taken back once");
+}
+
+#[test]
+fn colors_round_trip_through_their_bool() {
+    assert_eq!(Color::from_bool(true), Color::White);
+    assert_eq!(Color::from_bool(false), Color::Black);
+    
+    for color in [Color::White, Color::Black] {
+        assert_eq!(Color::from_bool(color.is_white()), color);
+    }
+    for is_white in [true, false] {
+        assert_eq!(Color::from_bool(is_white).is_white(), is_white);
+    }
+}
@@ -0,0 +1,373 @@
+// Input decisions the window makes, checked without opening one
+use chess::board::{perspective_square, square_bit, square_from_uci, GameState, GameStateBuilder, BOARD_SIZE};
+use chess::gui::{auto_promote, board_square_at, chat_sender_color, ChessGui, MoveInfo, SpectatorPanel, window_minimized, EscapeAction, InputMode, UpdateThrottle, BOARD_OFFSET_X, BOARD_OFFSET_Y, SQUARE_SIZE};
+use chess::network::PlayerColorTag;
+use chess::piece::{Color, PieceType};
+use ggez::event::MouseButton;
+use std::time::Duration;
+
+const BOARD_EXTENT: f32 = BOARD_SIZE as f32 * SQUARE_SIZE;
+
+#[test]
+fn escape_quits_only_from_an_idle_board() {
+    assert_eq!(EscapeAction::decide(false, false, false), EscapeAction::Quit);
+    assert_eq!(EscapeAction::decide(false, false, true), EscapeAction::DropSelection);
+}
+
+#[test]
+fn escape_never_quits_while_a_dialog_is_open() {
+    // The promotion dialog keeps its selection underneath, and Escape must leave both alone
+    assert_eq!(EscapeAction::decide(false, true, false), EscapeAction::KeepDialog);
+    assert_eq!(EscapeAction::decide(false, true, true), EscapeAction::KeepDialog);
+}
+
+#[test]
+fn escape_leaves_the_chat_input_first() {
+    assert_eq!(EscapeAction::decide(true, false, false), EscapeAction::LeaveChatInput);
+    assert_eq!(EscapeAction::decide(true, true, true), EscapeAction::LeaveChatInput);
+}
+
+#[test]
+fn clicks_beside_the_board_hit_no_square() {
+    let middle_x = BOARD_OFFSET_X + BOARD_EXTENT / 2.0;
+    let middle_y = BOARD_OFFSET_Y + BOARD_EXTENT / 2.0;
+    let outside = [
+        (BOARD_OFFSET_X - 20.0, middle_y),
+        (BOARD_OFFSET_X + BOARD_EXTENT + 20.0, middle_y),
+        (middle_x, BOARD_OFFSET_Y - 20.0),
+        (middle_x, BOARD_OFFSET_Y + BOARD_EXTENT + 20.0),
+        // Diagonally past each corner, where the old clamping picked up the corner piece
+        (BOARD_OFFSET_X - 20.0, BOARD_OFFSET_Y - 20.0),
+        (BOARD_OFFSET_X + BOARD_EXTENT + 20.0, BOARD_OFFSET_Y + BOARD_EXTENT + 20.0),
+        (0.0, 0.0),
+        (-500.0, -500.0),
+    ];
+    
+    for view in [Color::White, Color::Black] {
+        for (x, y) in outside {
+            assert_eq!(board_square_at(x, y, view), None, "({}, {}) seen from {:?}", x, y, view);
+        }
+    }
+}
+
+#[test]
+fn corner_squares_follow_the_orientation() {
+    let top_left = (BOARD_OFFSET_X + SQUARE_SIZE / 2.0, BOARD_OFFSET_Y + SQUARE_SIZE / 2.0);
+    let bottom_right = (BOARD_OFFSET_X + BOARD_EXTENT - SQUARE_SIZE / 2.0, BOARD_OFFSET_Y + BOARD_EXTENT - SQUARE_SIZE / 2.0);
+    
+    assert_eq!(board_square_at(top_left.0, top_left.1, Color::White), square_from_uci("a8"));
+    assert_eq!(board_square_at(bottom_right.0, bottom_right.1, Color::White), square_from_uci("h1"));
+    assert_eq!(board_square_at(top_left.0, top_left.1, Color::Black), square_from_uci("h1"));
+    assert_eq!(board_square_at(bottom_right.0, bottom_right.1, Color::Black), square_from_uci("a8"));
+}
+
+#[test]
+fn off_board_clicks_pick_up_no_piece() {
+    // Every corner of the start position holds a rook, so any click mapped onto a corner would find one
+    let game = GameState::new();
+    let near_corners = [
+        (BOARD_OFFSET_X - 10.0, BOARD_OFFSET_Y - 10.0),
+        (BOARD_OFFSET_X + BOARD_EXTENT + 10.0, BOARD_OFFSET_Y - 10.0),
+        (BOARD_OFFSET_X - 10.0, BOARD_OFFSET_Y + BOARD_EXTENT + 10.0),
+        (BOARD_OFFSET_X + BOARD_EXTENT + 10.0, BOARD_OFFSET_Y + BOARD_EXTENT + 10.0),
+    ];
+    
+    for view in [Color::White, Color::Black] {
+        for (x, y) in near_corners {
+            let piece = board_square_at(x, y, view).and_then(|(rank, file)| game.board[rank][file]);
+            assert!(piece.is_none(), "({}, {}) seen from {:?} picked up {:?}", x, y, view, piece);
+        }
+    }
+}
+
+// The piece a click at (x, y) lands on, seen from White's side
+fn piece_under(game: &GameState, x: f32, y: f32) -> Option<PieceType> {
+    board_square_at(x, y, Color::White).and_then(|(rank, file)| game.board[rank][file]).map(|piece| piece.piece_type)
+}
+
+#[test]
+fn clicks_near_a_square_boundary_land_on_the_right_side() {
+    let game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("f1", PieceType::Bishop, Color::White)
+        .place("e8", PieceType::King, Color::Black)
+        .place("e7", PieceType::Pawn, Color::Black)
+        .build();
+    
+    // The e/f boundary on the first rank
+    let boundary = BOARD_OFFSET_X + 5.0 * SQUARE_SIZE;
+    let first_rank = BOARD_OFFSET_Y + 7.5 * SQUARE_SIZE;
+    assert_eq!(piece_under(&game, boundary - 0.5, first_rank), Some(PieceType::King));
+    assert_eq!(piece_under(&game, boundary + 0.5, first_rank), Some(PieceType::Bishop));
+    // Float noise just short of the line snaps onto it instead of staying on e1
+    assert_eq!(piece_under(&game, boundary - 0.01, first_rank), Some(PieceType::Bishop));
+    
+    // The rank 8/7 boundary on the e-file
+    let e_file = BOARD_OFFSET_X + 4.5 * SQUARE_SIZE;
+    let rank_boundary = BOARD_OFFSET_Y + SQUARE_SIZE;
+    assert_eq!(piece_under(&game, e_file, rank_boundary - 0.5), Some(PieceType::King));
+    assert_eq!(piece_under(&game, e_file, rank_boundary + 0.5), Some(PieceType::Pawn));
+}
+
+#[test]
+fn the_last_row_and_column_reach_the_outer_edge() {
+    let game = GameStateBuilder::new()
+        .place("h1", PieceType::Rook, Color::White)
+        .place("a1", PieceType::King, Color::White)
+        .place("h8", PieceType::King, Color::Black)
+        .build();
+    
+    let right = BOARD_OFFSET_X + BOARD_EXTENT;
+    let bottom = BOARD_OFFSET_Y + BOARD_EXTENT;
+    assert_eq!(piece_under(&game, right - 0.01, bottom - 0.01), Some(PieceType::Rook));
+    assert_eq!(board_square_at(right - 0.01, BOARD_OFFSET_Y + 0.01, Color::White), square_from_uci("h8"));
+    assert_eq!(board_square_at(BOARD_OFFSET_X + 0.01, bottom - 0.01, Color::White), square_from_uci("a1"));
+    
+    // A click a pixel past the outer edge still counts as the edge square, further out it doesn't
+    assert_eq!(piece_under(&game, right + 1.0, bottom + 1.0), Some(PieceType::Rook));
+    assert_eq!(board_square_at(BOARD_OFFSET_X - 1.0, bottom - 1.0, Color::White), square_from_uci("a1"));
+    assert_eq!(board_square_at(right + 5.0, bottom - 1.0, Color::White), None);
+    assert_eq!(board_square_at(BOARD_OFFSET_X - 5.0, bottom - 1.0, Color::White), None);
+}
+
+#[test]
+fn black_sees_the_edges_mirrored() {
+    let right = BOARD_OFFSET_X + BOARD_EXTENT;
+    let bottom = BOARD_OFFSET_Y + BOARD_EXTENT;
+    assert_eq!(board_square_at(right - 0.01, bottom - 0.01, Color::Black), square_from_uci("a8"));
+    assert_eq!(board_square_at(BOARD_OFFSET_X + 0.01, BOARD_OFFSET_Y + 0.01, Color::Black), square_from_uci("h1"));
+    assert_eq!(board_square_at(right + 1.0, BOARD_OFFSET_Y - 1.0, Color::Black), square_from_uci("a1"));
+}
+
+#[test]
+fn click_click_mode_treats_a_drag_as_a_click_select() {
+    // No drag is tracked, so letting go anywhere leaves the piece selected for a second click
+    assert!(!InputMode::ClickClick.tracks_drags());
+    assert!(InputMode::ClickClick.moves_on_click());
+}
+
+#[test]
+fn drag_mode_only_moves_on_release() {
+    assert!(InputMode::Drag.tracks_drags());
+    assert!(!InputMode::Drag.moves_on_click());
+    
+    // Even a release that barely moved is a drop
+    assert!(!InputMode::Drag.release_is_click(false, true));
+    assert!(InputMode::Both.release_is_click(false, false));
+    assert!(InputMode::Both.release_is_click(true, true));
+    assert!(!InputMode::Both.release_is_click(true, false));
+}
+
+#[test]
+fn input_modes_cycle_from_click_click_to_drag_and_back() {
+    assert_eq!(InputMode::ClickClick.next(), InputMode::Drag);
+    assert_eq!(InputMode::Drag.next(), InputMode::Both);
+    assert_eq!(InputMode::Both.next(), InputMode::ClickClick);
+    
+    assert_eq!(InputMode::from_name("click"), Some(InputMode::ClickClick));
+    assert_eq!(InputMode::from_name("drag"), Some(InputMode::Drag));
+    assert_eq!(InputMode::from_name("swipe"), None);
+}
+
+#[test]
+fn a_paused_window_updates_once_per_interval() {
+    let interval = Duration::from_millis(100);
+    let mut throttle = UpdateThrottle::default();
+    
+    // The first paused frame still updates, then frames inside the interval are skipped
+    assert!(throttle.should_update(true, Duration::from_millis(1000), interval));
+    assert!(!throttle.should_update(true, Duration::from_millis(1016), interval));
+    assert!(!throttle.should_update(true, Duration::from_millis(1099), interval));
+    assert!(throttle.should_update(true, Duration::from_millis(1100), interval));
+    
+    // A window in use updates every frame, and the interval starts over when it's paused again
+    assert!(throttle.should_update(false, Duration::from_millis(1116), interval));
+    assert!(throttle.should_update(false, Duration::from_millis(1132), interval));
+    assert!(!throttle.should_update(true, Duration::from_millis(1148), interval));
+    assert!(throttle.should_update(true, Duration::from_millis(1232), interval));
+}
+
+#[test]
+fn the_platform_decides_whether_the_window_is_minimized() {
+    // A window the platform reports as minimized is paused even if it kept its size
+    assert!(window_minimized(Some(true), 780.0, 750.0));
+    
+    // A restored window that happens to be tiny isn't
+    assert!(!window_minimized(Some(false), 0.0, 0.0));
+    
+    // Only where the platform can't tell does a 0x0 window count as minimized
+    assert!(window_minimized(None, 0.0, 0.0));
+    assert!(!window_minimized(None, 780.0, 750.0));
+}
+
+#[test]
+fn chat_names_use_the_senders_color_tag() {
+    let tag = PlayerColorTag::from_hex("ff8800").unwrap();
+    let color = chat_sender_color(true, Some(tag));
+    assert_eq!((color.r, color.g, color.b), tag.to_rgb());
+    assert_eq!((color.r, color.g, color.b), (1.0, 136.0 / 255.0, 0.0));
+    
+    // Without a tag, players and spectators keep their own colors
+    assert_ne!(chat_sender_color(false, None), chat_sender_color(true, None));
+    assert_eq!(PlayerColorTag::from_hex("#12345"), None);
+}
+
+// White to move with a pawn one step from promoting
+fn pawn_on_the_seventh() -> GameState {
+    GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("a7", PieceType::Pawn, Color::White)
+        .place("h6", PieceType::King, Color::Black)
+        .build()
+}
+
+fn promote_a7_pawn(auto_queen: bool, force_dialog: bool) -> (GameState, bool) {
+    let mut game = pawn_on_the_seventh();
+    assert!(game.make_move(square_from_uci("a7").unwrap(), square_from_uci("a8").unwrap()).is_some());
+    let promoted = auto_promote(&mut game, auto_queen, force_dialog);
+    (game, promoted)
+}
+
+#[test]
+fn auto_queen_promotes_without_the_dialog() {
+    let (game, promoted) = promote_a7_pawn(true, false);
+    assert!(promoted);
+    assert!(game.promotion_pending.is_none());
+    assert_eq!(game.board[0][0].map(|piece| (piece.piece_type, piece.color)), Some((PieceType::Queen, Color::White)));
+    assert_eq!(game.current_turn, Color::Black);
+}
+
+#[test]
+fn the_dialog_still_opens_when_asked_for_or_auto_queen_is_off() {
+    for (auto_queen, force_dialog) in [(true, true), (false, false)] {
+        let (game, promoted) = promote_a7_pawn(auto_queen, force_dialog);
+        assert!(!promoted);
+        assert!(game.promotion_pending.is_some());
+        assert_eq!(game.current_turn, Color::White);
+    }
+}
+
+#[test]
+fn c_toggles_the_square_coordinates() {
+    let mut gui = ChessGui::with_text_pieces().unwrap();
+    assert!(gui.shows_square_coordinates());
+    
+    gui.handle_key_press('c').unwrap();
+    assert!(!gui.shows_square_coordinates());
+    gui.handle_key_press('C').unwrap();
+    assert!(gui.shows_square_coordinates());
+    
+    // Spectators get the same hotkey
+    gui.set_spectator_mode("game-1".to_string());
+    gui.handle_key_press('c').unwrap();
+    assert!(!gui.shows_square_coordinates());
+    gui.toggle_square_coordinates();
+    assert!(gui.shows_square_coordinates());
+}
+
+// Window position of a square's centre with the given side at the bottom
+fn square_center(name: &str, view: Color) -> (f32, f32) {
+    let (rank, file) = perspective_square(square_from_uci(name).unwrap(), view);
+    (BOARD_OFFSET_X + (file as f32 + 0.5) * SQUARE_SIZE, BOARD_OFFSET_Y + (rank as f32 + 0.5) * SQUARE_SIZE)
+}
+
+fn click(gui: &mut ChessGui, name: &str, view: Color) -> Option<MoveInfo> {
+    let (x, y) = square_center(name, view);
+    let pressed = gui.handle_mouse_down(MouseButton::Left, x, y).unwrap();
+    let released = gui.handle_mouse_up(MouseButton::Left, x, y).unwrap();
+    pressed.or(released)
+}
+
+#[test]
+fn pieces_can_be_previewed_but_not_moved_on_the_opponents_turn() {
+    // Playing Black over the network, so White moves first and the board is seen from Black's side
+    let mut gui = ChessGui::with_text_pieces().unwrap();
+    gui.set_player_color(false);
+    
+    assert!(click(&mut gui, "g8", Color::Black).is_none());
+    let (selected, destinations) = gui.selection().expect("the knight should be picked up to preview");
+    assert_eq!(selected, square_from_uci("g8").unwrap());
+    assert_eq!(destinations, square_bit(square_from_uci("f6").unwrap()) | square_bit(square_from_uci("h6").unwrap()));
+    
+    // Clicking a previewed destination plays nothing
+    assert!(click(&mut gui, "f6", Color::Black).is_none());
+    
+    // The opponent's pieces aren't picked up at all
+    assert!(click(&mut gui, "e2", Color::Black).is_none());
+    assert!(gui.selection().is_none());
+}
+
+#[test]
+fn chat_input_stops_at_its_cap() {
+    let mut panel = SpectatorPanel::new(0.0, 0.0);
+    for _ in 0..250 {
+        panel.handle_key_input('a');
+    }
+    assert_eq!(panel.chat_input().chars().count(), 200);
+    assert_eq!(panel.remaining_chars(), 0);
+    
+    // Room made with backspace takes exactly one more character, counted in characters not bytes
+    panel.handle_key_input('\u{08}');
+    panel.handle_key_input('é');
+    panel.handle_key_input('b');
+    assert_eq!(panel.chat_input().chars().count(), 200);
+    assert!(panel.chat_input().ends_with('é'));
+}
+
+#[test]
+fn chat_sends_are_spaced_out() {
+    let mut panel = SpectatorPanel::new(0.0, 0.0);
+    for key in "hello".chars() {
+        panel.handle_key_input(key);
+    }
+    assert_eq!(panel.take_message().as_deref(), Some("hello"));
+    assert_eq!(panel.remaining_chars(), 200);
+    
+    // Sent again straight away, the message is held back and stays in the input
+    for key in "again".chars() {
+        panel.handle_key_input(key);
+    }
+    assert_eq!(panel.take_message(), None);
+    assert_eq!(panel.chat_input(), "again");
+}
+
+#[test]
+fn analysis_is_for_spectators_and_local_games_only() {
+    let gui = ChessGui::with_text_pieces().unwrap();
+    assert!(gui.analysis_allowed());
+    
+    let mut against_computer = ChessGui::with_text_pieces().unwrap();
+    against_computer.set_computer_opponent(2);
+    assert!(against_computer.analysis_allowed());
+    
+    // A seated network player gets no engine help
+    let mut player = ChessGui::with_text_pieces().unwrap();
+    player.set_player_color(true);
+    assert!(!player.analysis_allowed());
+    
+    let mut spectator = ChessGui::with_text_pieces().unwrap();
+    spectator.set_spectator_mode("game-1".to_string());
+    assert!(spectator.analysis_allowed());
+}
+
+#[test]
+fn reselecting_a_piece_reuses_its_destinations_until_the_position_changes() {
+    let mut gui = ChessGui::with_text_pieces().unwrap();
+    gui.set_player_color(false);
+    
+    // Clicked again and again with nothing played in between
+    for _ in 0..3 {
+        click(&mut gui, "g8", Color::Black);
+    }
+    assert_eq!(gui.destination_lookups(), 1);
+    let (_, before) = gui.selection().unwrap();
+    
+    // White's move changes the position, so the same knight is worked out again
+    let mut game = GameState::new();
+    assert!(game.apply_uci_move("e2e4"));
+    let board = game.board.map(|row| row.map(|square| square.map(|piece| (piece.piece_type, piece.color))));
+    gui.update_game_state(board, Color::Black, None, false).unwrap();
+    click(&mut gui, "g8", Color::Black);
+    assert_eq!(gui.destination_lookups(), 2);
+    assert_eq!(gui.selection(), Some((square_from_uci("g8").unwrap(), before)));
+}
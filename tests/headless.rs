@@ -0,0 +1,84 @@
+// The modes that don't need a window run with no display at all, and the window explains itself
+// when there's no display to open it on
+use chess::network::ChessClient;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The game binary with no X11 or Wayland display to connect to
+fn chess_without_display() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_chess"));
+    command.env_remove("DISPLAY").env_remove("WAYLAND_DISPLAY").stdin(Stdio::null());
+    command
+}
+
+fn run(args: &[&str]) -> (Output, String) {
+    let output = chess_without_display().args(args).output().expect("the binary should start");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    (output, stdout)
+}
+
+#[test]
+fn mate_finder_runs_without_a_display() {
+    let (output, stdout) = run(&["--find-mate", "1", "--fen", "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1"]);
+    assert!(output.status.success(), "exited with {:?}: {}", output.status, stdout);
+    assert!(stdout.contains("Mate in 1: 1. Ra8#"), "unexpected output: {}", stdout);
+    assert!(!stdout.contains("No display found"));
+}
+
+#[test]
+fn protocol_replay_runs_without_a_display() {
+    let path = std::env::temp_dir().join(format!("chess-headless-{}.jsonl", std::process::id()));
+    std::fs::write(&path, "").unwrap();
+    
+    let (output, stdout) = run(&["--replay-protocol", path.to_str().unwrap()]);
+    assert!(output.status.success(), "exited with {:?}: {}", output.status, stdout);
+    assert!(stdout.contains("Replayed 0 moves (0 rejected)"), "unexpected output: {}", stdout);
+    
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn server_runs_without_a_display() {
+    let mut server = chess_without_display().arg("--server").stdout(Stdio::piped()).spawn().expect("the binary should start");
+    
+    // Lines are read on another thread so a server that never gets going can't hang the test
+    let stdout = server.stdout.take().unwrap();
+    let (lines_tx, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if lines_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    
+    let mut seen = Vec::new();
+    let started = loop {
+        match lines.recv_timeout(STARTUP_TIMEOUT) {
+            Ok(line) if line.contains("waiting for connections") => break true,
+            Ok(line) => seen.push(line),
+            Err(_) => break false,
+        }
+    };
+    let connected = started && ChessClient::new("127.0.0.1:8080").is_ok();
+    
+    let _ = server.kill();
+    let _ = server.wait();
+    assert!(started, "the server never started listening: {:?}", seen);
+    assert!(connected, "the server didn't accept a connection");
+    assert!(!seen.iter().any(|line| line.contains("No display found")));
+}
+
+#[test]
+fn window_without_a_display_points_to_the_headless_modes() {
+    // Fails before asking for a name, so nothing waits on stdin
+    let (output, stdout) = run(&[]);
+    assert!(!output.status.success());
+    assert!(stdout.contains("No display found"), "unexpected output: {}", stdout);
+    assert!(stdout.contains("--server"), "the hint should name the headless modes: {}", stdout);
+}
@@ -0,0 +1,295 @@
+// Move generation checked against hand-built positions: every generated move must be one make_move
+// accepts, so nothing the GUI or the bots offer can silently fail
+use chess::board::{square_from_uci, GameState, GameStateBuilder};
+use chess::piece::{Color, Piece, PieceType};
+
+fn square(name: &str) -> (usize, usize) {
+    square_from_uci(name).unwrap()
+}
+
+#[test]
+fn no_en_passant_against_a_pawn_that_did_not_just_double_step() {
+    // Both pawns have sat side by side on the fifth rank for a while, so there's no target
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("e8", PieceType::King, Color::Black)
+        .place("e5", PieceType::Pawn, Color::White)
+        .place("d5", PieceType::Pawn, Color::Black)
+        .build();
+    
+    assert_eq!(game.legal_destinations(square("e5")), vec![square("e6")]);
+    
+    for (from, to) in game.get_all_legal_moves() {
+        let mut copy = game.clone();
+        assert!(copy.make_move(from, to).is_some(), "generated move {:?} -> {:?} was rejected", from, to);
+    }
+}
+
+#[test]
+fn en_passant_only_onto_the_target_square() {
+    // Black just played d7-d5; the f5 pawn has been there all along
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("e8", PieceType::King, Color::Black)
+        .place("e5", PieceType::Pawn, Color::White)
+        .place("d5", PieceType::Pawn, Color::Black)
+        .place("f5", PieceType::Pawn, Color::Black)
+        .en_passant("d6")
+        .build();
+    
+    let mut destinations = game.legal_destinations(square("e5"));
+    destinations.sort();
+    let mut expected = vec![square("d6"), square("e6")];
+    expected.sort();
+    assert_eq!(destinations, expected);
+    
+    assert!(game.make_move(square("e5"), square("d6")).is_some());
+    assert_eq!(game.board[square("d5").0][square("d5").1], None);
+}
+
+#[test]
+fn en_passant_target_lasts_one_ply_in_the_hash_too() {
+    let mut game = GameState::new();
+    assert!(game.apply_uci_move("e2e4"));
+    assert_eq!(game.en_passant_target, Some(square("e3")));
+    assert!(game.verify_hash());
+    
+    // Black's double push replaces White's target rather than adding to it
+    assert!(game.apply_uci_move("d7d5"));
+    assert_eq!(game.en_passant_target, Some(square("d6")));
+    assert!(game.verify_hash());
+    let same_position = GameState::from_fen("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2").unwrap();
+    assert_eq!(game.zobrist_hash(), same_position.zobrist_hash(), "a stale en passant key is left in the hash");
+    
+    // Once a move has gone by, the capture is gone for good
+    assert!(game.apply_uci_move("e4e5"));
+    assert!(game.apply_uci_move("f7f5"));
+    assert!(game.apply_uci_move("a2a3"));
+    assert!(game.apply_uci_move("h7h6"));
+    assert_eq!(game.en_passant_target, None);
+    assert!(game.verify_hash());
+    assert!(!game.legal_destinations(square("e5")).contains(&square("f6")));
+    assert!(game.clone().make_move(square("e5"), square("f6")).is_none());
+    
+    let same_position = GameState::from_fen("rnbqkbnr/ppp1p1p1/7p/3pPp2/8/P7/1PPP1PPP/RNBQKBNR w KQkq - 0 5").unwrap();
+    assert_eq!(game.zobrist_hash(), same_position.zobrist_hash());
+}
+
+// Leaf counts from the perft tables on the Chess Programming Wiki
+const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+#[test]
+fn perft_of_the_start_position() {
+    let mut game = GameState::from_fen(START).unwrap();
+    for (depth, nodes) in [(1, 20), (2, 400), (3, 8_902), (4, 197_281)] {
+        assert_eq!(game.perft(depth), nodes, "perft({})", depth);
+    }
+    assert_eq!(game.to_fen(), START, "perft must leave the position as it found it");
+}
+
+#[test]
+fn perft_of_kiwipete() {
+    // Castling both ways, en passant, promotions and pins all turn up within three plies
+    let mut game = GameState::from_fen(KIWIPETE).unwrap();
+    for (depth, nodes) in [(1, 48), (2, 2_039), (3, 97_862)] {
+        assert_eq!(game.perft(depth), nodes, "perft({})", depth);
+    }
+    assert_eq!(game.to_fen(), KIWIPETE);
+}
+
+#[test]
+fn perft_divide_adds_up_to_perft() {
+    let mut game = GameState::from_fen(KIWIPETE).unwrap();
+    let divide = game.perft_divide(2);
+    assert_eq!(divide.len(), 48);
+    assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2_039);
+    
+    // Both castles are among White's moves
+    for castle in ["e1g1", "e1c1"] {
+        assert!(divide.iter().any(|(uci, _)| uci == castle), "{} is missing", castle);
+    }
+}
+
+// Whether White's king castling onto king_to is generated and accepted, checked both ways so they
+// can't disagree
+fn can_castle(game: &GameState, king_to: &str) -> bool {
+    let king = square("e1");
+    let generated = game.clone().legal_destinations(king).contains(&square(king_to));
+    let accepted = game.clone().make_move(king, square(king_to)).is_some();
+    assert_eq!(generated, accepted, "generation and make_move disagree about castling to {}", king_to);
+    accepted
+}
+
+#[test]
+fn castling_is_allowed_with_a_clear_safe_path() {
+    let game = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    assert!(can_castle(&game, "g1"));
+    assert!(can_castle(&game, "c1"));
+}
+
+#[test]
+fn castling_out_of_through_or_into_check_is_refused() {
+    // A rook on e8 checks the king, one on f8 watches f1, one on d8 watches d1, one on g8 watches g1
+    for (fen, kingside, queenside) in [
+        ("4r1k1/8/8/8/8/8/8/R3K2R w KQ - 0 1", false, false),
+        ("5rk1/8/8/8/8/8/8/R3K2R w KQ - 0 1", false, true),
+        ("3r2k1/8/8/8/8/8/8/R3K2R w KQ - 0 1", true, false),
+        ("6rk/8/8/8/8/8/8/R3K2R w KQ - 0 1", false, true),
+        // A bishop on c4 covers f1, one on a4 covers d1
+        ("6k1/8/8/8/2b5/8/8/R3K2R w KQ - 0 1", false, true),
+        ("6k1/8/8/8/b7/8/8/R3K2R w KQ - 0 1", true, false),
+    ] {
+        let game = GameState::from_fen(fen).unwrap();
+        assert_eq!(can_castle(&game, "g1"), kingside, "kingside in {}", fen);
+        assert_eq!(can_castle(&game, "c1"), queenside, "queenside in {}", fen);
+    }
+    
+    // Only the king's path matters: b1 under attack doesn't stop the long castle
+    let game = GameState::from_fen("1r4k1/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+    assert!(can_castle(&game, "c1"));
+}
+
+#[test]
+fn a_captured_rook_takes_its_castling_right_with_it() {
+    // Black's bishop takes the h1 rook
+    let mut game = GameState::from_fen("r3k2r/8/8/8/8/8/6b1/R3K2R b KQkq - 0 1").unwrap();
+    assert!(game.apply_uci_move("g2h1"));
+    assert!(!game.white_can_castle_kingside);
+    assert!(game.white_can_castle_queenside);
+    assert!(game.to_fen().contains(" Qkq "));
+    
+    // A rook that comes back to h1 doesn't bring the right back
+    let mut game = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K1R1 w Qkq - 0 1").unwrap();
+    assert!(game.apply_uci_move("g1h1"));
+    assert!(game.apply_uci_move("a8b8"));
+    assert!(!can_castle(&game, "g1"));
+    assert!(can_castle(&game, "c1"));
+}
+
+#[test]
+fn a_king_that_has_moved_cannot_castle() {
+    let mut game = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    for uci in ["e1f1", "e8d8", "f1e1", "d8e8"] {
+        assert!(game.apply_uci_move(uci));
+    }
+    
+    // Both kings are home again, but neither may castle
+    assert!(!can_castle(&game, "g1"));
+    assert!(!can_castle(&game, "c1"));
+    assert!(game.to_fen().contains(" - "));
+    
+    // Moving a rook only gives up its own side
+    let mut game = GameState::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    assert!(game.apply_uci_move("a1b1"));
+    assert!(game.apply_uci_move("h8h7"));
+    assert!(game.white_can_castle_kingside && !game.white_can_castle_queenside);
+    assert!(game.black_can_castle_queenside && !game.black_can_castle_kingside);
+}
+
+#[test]
+fn start_position_has_twenty_uci_moves() {
+    let mut game = GameState::new();
+    let moves = game.legal_moves_uci();
+    assert_eq!(moves.len(), 20);
+    for uci in ["e2e4", "e2e3", "g1f3", "b1a3"] {
+        assert!(moves.contains(&uci.to_string()), "{} missing from {:?}", uci, moves);
+    }
+}
+
+#[test]
+fn promotions_are_listed_once_per_piece() {
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("b7", PieceType::Pawn, Color::White)
+        .place("h8", PieceType::King, Color::Black)
+        .build();
+    let moves = game.legal_moves_uci();
+    
+    let promotions: Vec<&String> = moves.iter().filter(|uci| uci.starts_with("b7")).collect();
+    assert_eq!(promotions, vec!["b7b8q", "b7b8r", "b7b8b", "b7b8n"]);
+    
+    // Each one reads back as the move it names
+    for uci in promotions {
+        let mut copy = game.clone();
+        assert!(copy.apply_uci_move(uci), "{} should be legal", uci);
+    }
+    
+    // A variant's narrower set is all that's offered
+    game.promotion_options = vec![PieceType::Queen, PieceType::Knight];
+    let moves = game.legal_moves_uci();
+    let promotions: Vec<&String> = moves.iter().filter(|uci| uci.starts_with("b7")).collect();
+    assert_eq!(promotions, vec!["b7b8q", "b7b8n"]);
+}
+
+// The squares whose bits are set, bit index rank * 8 + file
+fn squares_in(bitboard: u64) -> Vec<(usize, usize)> {
+    (0..64).filter(|bit| bitboard & (1 << bit) != 0).map(|bit| (bit / 8, bit % 8)).collect()
+}
+
+#[test]
+fn destination_bitboard_matches_the_destination_list() {
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("d4", PieceType::Knight, Color::White)
+        .place("c6", PieceType::Pawn, Color::White)
+        .place("e6", PieceType::Pawn, Color::Black)
+        .place("h8", PieceType::King, Color::Black)
+        .build();
+    
+    // A centre knight with one square blocked by its own pawn and one holding a capture
+    let bitboard = game.legal_destinations_bitboard(square("d4"));
+    assert_eq!(bitboard.count_ones(), 7);
+    assert_eq!(bitboard & (1 << (square("c6").0 * 8 + square("c6").1)), 0);
+    
+    let mut expected = game.legal_destinations(square("d4"));
+    expected.sort();
+    assert_eq!(squares_in(bitboard), expected);
+    
+    // Empty squares have nowhere to go
+    assert_eq!(game.legal_destinations_bitboard(square("a1")), 0);
+}
+
+#[test]
+fn only_bishops_rooks_and_queens_slide() {
+    for piece_type in [PieceType::Bishop, PieceType::Rook, PieceType::Queen] {
+        assert!(piece_type.is_slider(), "{:?} should slide", piece_type);
+    }
+    for piece_type in [PieceType::Pawn, PieceType::Knight, PieceType::King] {
+        assert!(!piece_type.is_slider(), "{:?} should not slide", piece_type);
+    }
+}
+
+#[test]
+fn queen_moves_are_rook_moves_plus_bishop_moves() {
+    // Friendly and enemy pieces on several rays, so both stopping rules are exercised
+    let game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("e8", PieceType::King, Color::Black)
+        .place("d4", PieceType::Queen, Color::White)
+        .place("d7", PieceType::Pawn, Color::Black)
+        .place("b4", PieceType::Knight, Color::White)
+        .place("f6", PieceType::Bishop, Color::Black)
+        .place("b2", PieceType::Pawn, Color::White)
+        .place("g4", PieceType::Rook, Color::Black)
+        .build();
+    
+    let from = square("d4");
+    let moves_as = |piece_type: PieceType| {
+        let mut moves = Piece::new(piece_type, Color::White).get_possible_moves(from, &game.board, None);
+        moves.sort();
+        moves
+    };
+    
+    let rook = moves_as(PieceType::Rook);
+    let bishop = moves_as(PieceType::Bishop);
+    let mut union: Vec<_> = rook.iter().chain(bishop.iter()).copied().collect();
+    union.sort();
+    union.dedup();
+    
+    assert_eq!(rook.len() + bishop.len(), union.len(), "rook and bishop rays should never overlap");
+    assert_eq!(moves_as(PieceType::Queen), union);
+    assert!(union.contains(&square("d7")) && union.contains(&square("f6")), "enemy pieces can be captured");
+    assert!(!union.contains(&square("b4")) && !union.contains(&square("b2")), "friendly pieces block");
+    assert!(!union.contains(&square("d8")) && !union.contains(&square("h4")), "nothing slides past a blocker");
+}
@@ -0,0 +1,206 @@
+// make_move guards against malformed input directly, so a crafted call from a client can't move a
+// piece onto itself or onto its own side, and apply_move_checked says which rule a move broke. The
+// move predicates classify a move without making it
+use chess::board::{square_from_uci, GameState, GameStateBuilder, MoveError, MoveOutcome};
+use chess::piece::{Color, PieceType};
+
+fn square(name: &str) -> (usize, usize) {
+    square_from_uci(name).unwrap()
+}
+
+#[test]
+fn moving_a_piece_onto_its_own_square_is_refused() {
+    let mut game = GameState::new();
+    let before = game.board;
+    
+    assert!(game.make_move(square("e1"), square("e1")).is_none());
+    assert!(game.make_move(square("g1"), square("g1")).is_none());
+    assert_eq!(game.board, before);
+    assert_eq!(game.current_turn, Color::White);
+}
+
+#[test]
+fn capturing_a_friendly_piece_is_refused() {
+    let mut game = GameState::new();
+    let before = game.board;
+    
+    // Queen onto her own pawn, and a knight onto a square its own pawn holds
+    assert!(game.make_move(square("d1"), square("d2")).is_none());
+    assert!(game.make_move(square("g1"), square("e2")).is_none());
+    assert_eq!(game.board, before);
+    
+    // A rook with a clear line still can't take its own king
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("a1", PieceType::Rook, Color::White)
+        .place("e8", PieceType::King, Color::Black)
+        .build();
+    assert!(game.make_move(square("a1"), square("e1")).is_none());
+    assert!(game.make_move(square("a1"), square("d1")).is_some());
+}
+
+#[test]
+fn each_refusal_names_its_reason() {
+    let mut game = GameState::new();
+    assert_eq!(game.apply_move_checked((8, 4), square("e4")), Err(MoveError::OutOfBounds));
+    assert_eq!(game.apply_move_checked(square("e2"), (4, 8)), Err(MoveError::OutOfBounds));
+    assert_eq!(game.apply_move_checked(square("e4"), square("e5")), Err(MoveError::NoPieceAtSource));
+    assert_eq!(game.apply_move_checked(square("e7"), square("e5")), Err(MoveError::WrongTurn));
+    assert_eq!(game.apply_move_checked(square("e2"), square("e5")), Err(MoveError::IllegalDestination));
+    assert_eq!(game.apply_move_checked(square("e1"), square("g1")), Err(MoveError::IllegalDestination));
+    assert_eq!(game.apply_move_checked(square("e2"), square("e4")), Ok(MoveOutcome::Moved));
+}
+
+#[test]
+fn leaving_the_king_in_check_is_its_own_error() {
+    // The e-file bishop is pinned against its king
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("e2", PieceType::Bishop, Color::White)
+        .place("e8", PieceType::Rook, Color::Black)
+        .place("a8", PieceType::King, Color::Black)
+        .build();
+    assert_eq!(game.apply_move_checked(square("e2"), square("d3")), Err(MoveError::LeavesKingInCheck));
+    
+    // So is stepping the king into an attacked square
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("d8", PieceType::Rook, Color::Black)
+        .place("a8", PieceType::King, Color::Black)
+        .build();
+    assert_eq!(game.apply_move_checked(square("e1"), square("d1")), Err(MoveError::LeavesKingInCheck));
+    assert_eq!(game.apply_move_checked(square("e1"), square("f1")), Ok(MoveOutcome::Moved));
+}
+
+#[test]
+fn nothing_moves_until_a_pending_promotion_is_chosen() {
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("a7", PieceType::Pawn, Color::White)
+        .place("h8", PieceType::King, Color::Black)
+        .build();
+    assert_eq!(game.apply_move_checked(square("a7"), square("a8")), Ok(MoveOutcome::PromotionRequired));
+    assert_eq!(game.apply_move_checked(square("h8"), square("g8")), Err(MoveError::PromotionPending));
+    
+    assert!(game.promote_pawn(PieceType::Queen));
+    assert_eq!(game.apply_move_checked(square("h8"), square("h7")), Ok(MoveOutcome::Moved));
+}
+
+#[test]
+fn captures_are_classified_without_moving() {
+    let mut game = GameState::new();
+    for uci in ["e2e4", "d7d5"] {
+        assert!(game.apply_uci_move(uci));
+    }
+    let before = game.board;
+    
+    assert!(game.is_capture(square("e4"), square("d5")));
+    assert!(!game.is_capture(square("e4"), square("e5")));
+    // A friendly piece on the destination isn't a capture
+    assert!(!game.is_capture(square("d1"), square("d2")));
+    assert_eq!(game.board, before);
+}
+
+#[test]
+fn en_passant_is_a_capture_onto_an_empty_square() {
+    let mut game = GameState::new();
+    for uci in ["e2e4", "a7a6", "e4e5", "d7d5"] {
+        assert!(game.apply_uci_move(uci));
+    }
+    
+    assert!(game.board[square("d6").0][square("d6").1].is_none());
+    assert!(game.is_en_passant(square("e5"), square("d6")));
+    assert!(game.is_capture(square("e5"), square("d6")));
+    
+    // The straight push and the other diagonal are neither
+    assert!(!game.is_en_passant(square("e5"), square("e6")));
+    assert!(!game.is_en_passant(square("e5"), square("f6")));
+    assert!(!game.is_capture(square("e5"), square("f6")));
+}
+
+#[test]
+fn only_a_two_file_king_step_is_a_castle() {
+    let game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("a1", PieceType::Rook, Color::White)
+        .place("h1", PieceType::Rook, Color::White)
+        .place("e8", PieceType::King, Color::Black)
+        .castling("KQ")
+        .build();
+    
+    assert!(game.is_castle(square("e1"), square("g1")));
+    assert!(game.is_castle(square("e1"), square("c1")));
+    assert!(!game.is_castle(square("e1"), square("f1")));
+    // A rook sliding the same two files is just a rook move
+    assert!(!game.is_castle(square("h1"), square("f1")));
+}
+
+#[test]
+fn promotion_is_limited_to_the_allowed_pieces() {
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("a7", PieceType::Pawn, Color::White)
+        .place("h6", PieceType::King, Color::Black)
+        .build();
+    assert_eq!(game.promotion_options, vec![PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight]);
+    
+    // A variant that only allows a queen or a knight
+    game.promotion_options = vec![PieceType::Queen, PieceType::Knight];
+    assert_eq!(game.apply_move_checked(square("a7"), square("a8")), Ok(MoveOutcome::PromotionRequired));
+    for refused in [PieceType::King, PieceType::Pawn, PieceType::Rook, PieceType::Bishop] {
+        assert!(!game.promote_pawn(refused), "{:?} should be refused", refused);
+        assert!(game.promotion_pending.is_some());
+    }
+    
+    assert!(game.promote_pawn(PieceType::Knight));
+    assert_eq!(game.board[0][0].map(|piece| piece.piece_type), Some(PieceType::Knight));
+    assert_eq!(game.current_turn, Color::Black);
+}
+
+#[test]
+fn a_uci_promotion_outside_the_allowed_pieces_leaves_the_position_alone() {
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("a7", PieceType::Pawn, Color::White)
+        .place("h6", PieceType::King, Color::Black)
+        .build();
+    game.promotion_options = vec![PieceType::Queen, PieceType::Knight];
+    let before = game.to_fen();
+    
+    assert!(!game.apply_uci_move("a7a8r"));
+    assert_eq!(game.to_fen(), before);
+    assert!(game.promotion_pending.is_none());
+    
+    assert!(game.apply_uci_move("a7a8n"));
+    assert_eq!(game.board[0][0].map(|piece| piece.piece_type), Some(PieceType::Knight));
+}
+
+#[test]
+fn a_pawn_promoting_by_capture_on_the_back_rank() {
+    let mut game = GameStateBuilder::new()
+        .place("e1", PieceType::King, Color::White)
+        .place("b7", PieceType::Pawn, Color::White)
+        .place("e8", PieceType::King, Color::Black)
+        .place("a8", PieceType::Rook, Color::Black)
+        .place("h8", PieceType::Rook, Color::Black)
+        .castling("kq")
+        .build();
+    
+    assert!(game.is_capture(square("b7"), square("a8")));
+    assert_eq!(game.apply_move_checked(square("b7"), square("a8")), Ok(MoveOutcome::PromotionRequired));
+    
+    // The rook is gone and the pawn waits on its square, with White still to choose
+    assert_eq!(game.board[0][0].map(|piece| (piece.piece_type, piece.color)), Some((PieceType::Pawn, Color::White)));
+    assert!(game.board[1][1].is_none());
+    assert_eq!(game.current_turn, Color::White);
+    assert!(game.verify_hash());
+    
+    // The captured corner rook takes Black's queenside castling with it, the other rook keeps its own
+    assert!(!game.black_can_castle_queenside);
+    assert!(game.black_can_castle_kingside);
+    
+    assert!(game.promote_pawn(PieceType::Queen));
+    assert_eq!(game.board[0][0].map(|piece| (piece.piece_type, piece.color)), Some((PieceType::Queen, Color::White)));
+    assert_eq!(game.current_turn, Color::Black);
+    assert!(game.verify_hash());
+}
@@ -0,0 +1,154 @@
+// What ChessClient reports when the other end misbehaves, against a bare socket standing in for a server
+use chess::network::{ChessClient, ChessNetError, ClientRole, NetworkMessage, ReconnectPolicy, ReconnectState};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[test]
+fn unreadable_message_is_a_parse_error() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    
+    let mut client = ChessClient::new(&address).expect("client should connect");
+    let (mut server_side, _) = listener.accept().unwrap();
+    server_side.write_all(b"this is not a message\n").unwrap();
+    
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    let error = loop {
+        match client.receive_message() {
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(5)),
+            Ok(other) => panic!("expected a parse error, got {:?}", other),
+            Err(e) => break e,
+        }
+    };
+    
+    assert!(matches!(error, ChessNetError::Parse(_)), "expected a parse error, got {:?}", error);
+    assert!(!error.is_disconnect());
+    assert!(client.is_connected());
+}
+
+// A client whose connection has dropped, pointing at the given server address
+fn disconnected_client(server_address: &str) -> ChessClient {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let mut client = ChessClient::with_role(stream, ClientRole::Spectator, server_address);
+    client.stream = None;
+    client.set_reconnect_policy(ReconnectPolicy {
+        max_attempts: 3,
+        base_backoff: Duration::from_millis(10),
+        max_window: Duration::from_secs(30),
+    });
+    client
+}
+
+// Nothing listens on a port that was just freed, so connecting to it is refused
+fn unreachable_address() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+#[test]
+fn reconnecting_to_an_unreachable_server_never_blocks_and_gives_up() {
+    let mut client = disconnected_client(&unreachable_address());
+    
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    let error = loop {
+        assert!(Instant::now() < deadline, "reconnect never gave up");
+        
+        let call_started = Instant::now();
+        let outcome = client.reconnect();
+        assert!(call_started.elapsed() < Duration::from_millis(100), "reconnect blocked for {:?}", call_started.elapsed());
+        
+        match outcome {
+            Ok(true) => panic!("nothing should accept the connection"),
+            Ok(false) => thread::sleep(Duration::from_millis(5)),
+            Err(e) => break e,
+        }
+    };
+    
+    assert!(matches!(error, ChessNetError::Io(_)), "unexpected error {:?}", error);
+    assert_eq!(client.reconnect_state(), ReconnectState::Exhausted);
+    assert!(!client.is_connected());
+    
+    // Exhausted stays exhausted until someone resets it
+    assert!(client.reconnect().is_err());
+    client.reset_reconnect();
+    assert_eq!(client.reconnect_state(), ReconnectState::Idle);
+}
+
+#[test]
+fn reconnecting_announces_the_client_once_the_server_answers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = disconnected_client(&listener.local_addr().unwrap().to_string());
+    
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    while !client.reconnect().expect("the server is up") {
+        assert!(Instant::now() < deadline, "reconnect never finished");
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert!(client.is_connected());
+    assert_eq!(client.reconnect_state(), ReconnectState::Idle);
+    
+    let (server_side, _) = listener.accept().unwrap();
+    let mut line = String::new();
+    BufReader::new(server_side).read_line(&mut line).unwrap();
+    let message: NetworkMessage = serde_json::from_str(&line).unwrap();
+    assert!(matches!(message, NetworkMessage::ConnectionStatus { connected: true, .. }));
+}
+
+#[test]
+fn read_timeout_with_nothing_sent_is_no_message() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = ChessClient::new(&listener.local_addr().unwrap().to_string()).expect("client should connect");
+    let (mut server_side, _) = listener.accept().unwrap();
+    
+    // The read waits out the timeout instead of returning at once, then reports that nothing came
+    let timeout = Duration::from_millis(100);
+    client.set_read_timeout(Some(timeout)).unwrap();
+    let started = Instant::now();
+    assert!(matches!(client.receive_message(), Ok(None)));
+    assert!(started.elapsed() >= timeout / 2, "the read returned after {:?}", started.elapsed());
+    assert!(client.is_connected());
+    
+    // A message arriving within the timeout wakes the read straight away
+    let message = serde_json::to_string(&NetworkMessage::RequestGameList).unwrap();
+    server_side.write_all(format!("{}\n", message).as_bytes()).unwrap();
+    client.set_read_timeout(Some(MESSAGE_TIMEOUT)).unwrap();
+    let started = Instant::now();
+    assert!(matches!(client.receive_message(), Ok(Some(NetworkMessage::RequestGameList))));
+    assert!(started.elapsed() < MESSAGE_TIMEOUT);
+}
+
+#[test]
+fn unresolvable_host_is_a_lookup_error() {
+    let error = ChessClient::new("no-such-host.invalid").err().expect("an .invalid host should not resolve");
+    
+    // A bare host gets the default port before it is looked up
+    match error {
+        ChessNetError::Io(e) => {
+            assert_eq!(e.kind(), ErrorKind::NotFound);
+            let message = e.to_string();
+            assert!(message.contains("Could not resolve no-such-host.invalid:8080"), "unexpected message: {}", message);
+        }
+        other => panic!("expected an I/O error, got {:?}", other),
+    }
+}
+
+#[test]
+fn closed_port_is_a_connection_error() {
+    // Take a free port and give it straight back, so nothing is listening on it
+    let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    
+    let error = ChessClient::new(&address).err().expect("nothing should be listening");
+    match error {
+        ChessNetError::Io(e) => {
+            assert_ne!(e.kind(), ErrorKind::NotFound, "a refused connection is not a lookup failure");
+            let message = e.to_string();
+            assert!(message.contains(&format!("Could not connect to {}", address)), "unexpected message: {}", message);
+        }
+        other => panic!("expected an I/O error, got {:?}", other),
+    }
+}
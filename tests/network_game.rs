@@ -0,0 +1,1049 @@
+// Plays a whole game through a real server: two clients connect over TCP, create and join a game,
+// and play Fool's mate. Every wait has a deadline instead of a fixed sleep, so a slow machine only
+// makes the test take longer
+mod common;
+
+use chess::board::{square_from_uci, GameState};
+use chess::bot::HOUSE_BOT_NAME;
+use chess::network::{ChessClient, GameResult, GameSummary, PlayerColorTag, NetworkMessage, TerminationReason, TimeControl, TimeMode};
+use chess::gui::{ChessGui, BOARD_OFFSET_X, BOARD_OFFSET_Y, SQUARE_SIZE};
+use chess::piece::{Color, PieceType};
+use ggez::event::MouseButton;
+use common::{create_game, expect_message, start_server, start_server_configured, MESSAGE_TIMEOUT};
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The board both players see after a move must have the moved piece on its destination
+fn expect_state_after(client: &mut ChessClient, to: (u8, u8), piece: (PieceType, Color), next_turn: Color) {
+    let state = expect_message(client, "GameState after the move", |message| {
+        matches!(message, NetworkMessage::GameState { current_turn, .. } if *current_turn == next_turn)
+    });
+    match state {
+        NetworkMessage::GameState { board, .. } => {
+            assert_eq!(board[to.0 as usize][to.1 as usize], Some(piece));
+        }
+        _ => unreachable!(),
+    }
+}
+
+// What Alice asks for when she creates the game
+#[derive(Default)]
+struct NewGame {
+    time_mode: TimeMode,
+    time_control: Option<TimeControl>,
+    white_tag: Option<PlayerColorTag>,
+    takeover_token: Option<String>,
+}
+
+// A game with both players seated, and what black needs to come back to it
+struct SeatedGame {
+    white: ChessClient,
+    black: ChessClient,
+    game_id: String,
+    black_session: String,
+}
+
+// Create a game as Alice, join it as Bob and answer both GameStarts, returning (white, black)
+fn start_game(address: &str) -> (ChessClient, ChessClient) {
+    start_timed_game(address, None)
+}
+
+fn start_timed_game(address: &str, time_control: Option<TimeControl>) -> (ChessClient, ChessClient) {
+    let SeatedGame { white, black, .. } = start_game_with(address, &NewGame { time_control, ..NewGame::default() });
+    (white, black)
+}
+
+fn start_game_with(address: &str, game: &NewGame) -> SeatedGame {
+    let mut seated = seat_players(address, game);
+    seated.white.send_ready().unwrap();
+    seated.black.send_ready().unwrap();
+    seated
+}
+
+// Both players seated and sent GameStart, with neither having answered it yet
+fn seat_players(address: &str, game: &NewGame) -> SeatedGame {
+    let mut white = ChessClient::new(address).expect("white should connect");
+    white.send_message(NetworkMessage::CreateGame {
+        player_name: "Alice".to_string(),
+        takeover_token: game.takeover_token.clone(),
+        time_mode: game.time_mode,
+        player_color_tag: game.white_tag,
+        time_control: game.time_control,
+    }).unwrap();
+    let game_id = match expect_message(&mut white, "GameCreated", |message| matches!(message, NetworkMessage::GameCreated { .. })) {
+        NetworkMessage::GameCreated { game_id } => game_id,
+        _ => unreachable!(),
+    };
+    
+    let mut black = ChessClient::new(address).expect("black should connect");
+    black.send_message(NetworkMessage::JoinGame {
+        game_id: game_id.clone(),
+        player_name: "Bob".to_string(),
+        player_color_tag: None,
+    }).unwrap();
+    
+    let mut sessions = Vec::new();
+    for (client, is_white, opponent) in [(&mut white, true, "Bob"), (&mut black, false, "Alice")] {
+        match expect_message(client, "GameStart", |message| matches!(message, NetworkMessage::GameStart { .. })) {
+            NetworkMessage::GameStart { is_white: start_is_white, game_id: start_game_id, opponent_name, white_name, black_name, time_control, session_token, .. } => {
+                assert_eq!(start_is_white, is_white);
+                assert_eq!(start_game_id, game_id);
+                assert_eq!(opponent_name, opponent);
+                assert_eq!((white_name.as_str(), black_name.as_str()), ("Alice", "Bob"));
+                assert_eq!(time_control, game.time_control);
+                sessions.push(session_token.expect("a player should get a session token"));
+            }
+            _ => unreachable!(),
+        }
+    }
+    let black_session = sessions.pop().unwrap();
+    let white_session = sessions.pop().unwrap();
+    assert_ne!(white_session, black_session, "both players were given the same session");
+    
+    SeatedGame { white, black, game_id, black_session }
+}
+
+// Join the only game on the server as a spectator, returning once its board has arrived
+fn spectate_first_game(address: &str, name: &str) -> ChessClient {
+    let mut spectator = ChessClient::new(address).expect("spectator should connect");
+    spectator.send_message(NetworkMessage::RequestGameList).unwrap();
+    let game_id = match expect_message(&mut spectator, "GameList", |message| matches!(message, NetworkMessage::GameList { .. })) {
+        NetworkMessage::GameList { available_games } => available_games[0].game_id.clone(),
+        _ => unreachable!(),
+    };
+    spectator.spectate_game(game_id, name.to_string()).unwrap();
+    expect_message(&mut spectator, "GameState on joining", |message| matches!(message, NetworkMessage::GameState { .. }));
+    spectator
+}
+
+#[test]
+fn fools_mate_over_the_network() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address);
+    let mut spectator = spectate_first_game(&address, "Carol");
+    
+    // 1. f3 e5 2. g4 Qh4#, with rank 0 as the 8th rank
+    let moves = [
+        (Color::White, (6, 5), (5, 5), PieceType::Pawn),
+        (Color::Black, (1, 4), (3, 4), PieceType::Pawn),
+        (Color::White, (6, 6), (4, 6), PieceType::Pawn),
+        (Color::Black, (0, 3), (4, 7), PieceType::Queen),
+    ];
+    for (mover, from, to, piece_type) in moves {
+        let (moving, waiting) = match mover {
+            Color::White => (&mut white, &mut black),
+            Color::Black => (&mut black, &mut white),
+        };
+        
+        moving.send_move(from, to, None).unwrap();
+        let relayed = expect_message(waiting, "the opponent's move", |message| matches!(message, NetworkMessage::Move { .. }));
+        assert!(matches!(relayed, NetworkMessage::Move { from: f, to: t, .. } if f == from && t == to));
+        
+        for client in [&mut *moving, &mut *waiting] {
+            expect_state_after(client, to, (piece_type, mover), mover.opposite());
+        }
+    }
+    
+    for client in [&mut white, &mut black, &mut spectator] {
+        match expect_message(client, "GameEnd", |message| matches!(message, NetworkMessage::GameEnd { .. })) {
+            NetworkMessage::GameEnd { result, termination, .. } => {
+                assert_eq!(result, Some(GameResult::BlackWins));
+                assert_eq!(termination, Some(TerminationReason::Checkmate));
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+// Every refused move is answered with MoveRejected and the real board, and the turn stays put
+fn expect_rejection(client: &mut ChessClient, next_turn: Color) {
+    expect_message(client, "MoveRejected", |message| matches!(message, NetworkMessage::MoveRejected { .. }));
+    expect_message(client, "GameState after the rejection", |message| {
+        matches!(message, NetworkMessage::GameState { current_turn, .. } if *current_turn == next_turn)
+    });
+}
+
+#[test]
+fn server_refuses_illegal_and_out_of_turn_moves() {
+    let (mut white, mut black) = start_game(&start_server());
+    
+    // Black tries to open before White has moved
+    black.send_move((1, 4), (3, 4), None).unwrap();
+    expect_rejection(&mut black, Color::White);
+    
+    // A rook can't jump its own pawn
+    white.send_move((7, 0), (4, 0), None).unwrap();
+    expect_rejection(&mut white, Color::White);
+    
+    // The game carries on normally afterwards
+    white.send_move((6, 4), (4, 4), None).unwrap();
+    let relayed = expect_message(&mut black, "White's move", |message| matches!(message, NetworkMessage::Move { .. }));
+    assert!(matches!(relayed, NetworkMessage::Move { from: (6, 4), to: (4, 4), .. }));
+    expect_state_after(&mut black, (4, 4), (PieceType::Pawn, Color::White), Color::Black);
+}
+
+#[test]
+fn a_client_cannot_end_the_game_itself() {
+    let (mut white, mut black) = start_game(&start_server());
+    
+    white.send_message(NetworkMessage::GameEnd {
+        reason: "I say draw".to_string(),
+        result: Some(GameResult::Draw),
+        termination: None,
+    }).unwrap();
+    
+    // The claim goes nowhere and the game carries on
+    white.send_move((6, 4), (4, 4), None).unwrap();
+    expect_message(&mut black, "e2e4", |message| {
+        assert!(!matches!(message, NetworkMessage::GameEnd { .. }), "white's GameEnd was relayed");
+        matches!(message, NetworkMessage::Move { .. })
+    });
+    expect_state_after(&mut black, (4, 4), (PieceType::Pawn, Color::White), Color::Black);
+}
+
+// Everyone at the board hears how the game ended and who won
+fn expect_game_end(client: &mut ChessClient, expected: GameResult, reason: TerminationReason) {
+    match expect_message(client, "GameEnd", |message| matches!(message, NetworkMessage::GameEnd { .. })) {
+        NetworkMessage::GameEnd { result, termination, .. } => {
+            assert_eq!(result, Some(expected));
+            assert_eq!(termination, Some(reason));
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn resigning_while_not_on_move_ends_the_game() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address);
+    let mut spectator = spectate_first_game(&address, "Carol");
+    
+    // Black gives up while White is still thinking about the first move
+    black.resign().unwrap();
+    for client in [&mut white, &mut black, &mut spectator] {
+        expect_game_end(client, GameResult::WhiteWins, TerminationReason::Resignation);
+    }
+}
+
+#[test]
+fn a_stray_ready_does_not_hold_up_the_waiting_player() {
+    let (mut white, mut black) = start_game(&start_server());
+
+    // A second Ready, as a client answering a repeated GameStart would send, sits ahead of the resign
+    black.send_ready().unwrap();
+    black.resign().unwrap();
+    for client in [&mut white, &mut black] {
+        expect_game_end(client, GameResult::WhiteWins, TerminationReason::Resignation);
+    }
+}
+
+#[test]
+fn resigning_after_declining_a_draw_out_of_turn_ends_the_game() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address);
+    let mut spectator = spectate_first_game(&address, "Carol");
+    
+    // Black answers White's offer and gives up, all before White has moved
+    white.offer_draw().unwrap();
+    expect_message(&mut black, "DrawOffered", |message| matches!(message, NetworkMessage::DrawOffered));
+    black.decline_draw().unwrap();
+    black.resign().unwrap();
+    expect_message(&mut white, "DeclineDraw", |message| matches!(message, NetworkMessage::DeclineDraw));
+    for client in [&mut white, &mut black, &mut spectator] {
+        expect_game_end(client, GameResult::WhiteWins, TerminationReason::Resignation);
+    }
+}
+
+#[test]
+fn resigning_on_move_gives_the_win_to_the_opponent() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address);
+    let mut spectator = spectate_first_game(&address, "Carol");
+    
+    white.resign().unwrap();
+    for client in [&mut white, &mut black, &mut spectator] {
+        expect_game_end(client, GameResult::BlackWins, TerminationReason::Resignation);
+    }
+}
+
+#[test]
+fn both_players_start_with_the_agreed_clock() {
+    // start_game_with checks each GameStart carries the opponent's name and this clock
+    let time_control = TimeControl::from_notation("5+3").unwrap();
+    let (mut white, mut black) = start_timed_game(&start_server(), Some(time_control));
+    
+    // The clocks the server runs start from the same settings
+    for client in [&mut white, &mut black] {
+        match expect_message(client, "ClockUpdate", |message| matches!(message, NetworkMessage::ClockUpdate { .. })) {
+            NetworkMessage::ClockUpdate { white_ms, black_ms } => {
+                let initial_ms = time_control.initial.as_millis() as u64;
+                assert!(white_ms <= initial_ms && white_ms > initial_ms - 5_000);
+                assert_eq!(black_ms, initial_ms);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn running_out_of_time_gives_the_win_to_the_opponent() {
+    let time_control = TimeControl { initial: Duration::from_millis(300), increment: Duration::ZERO };
+    let (mut white, mut black) = start_timed_game(&start_server(), Some(time_control));
+    
+    // White never moves and flags
+    for client in [&mut white, &mut black] {
+        expect_game_end(client, GameResult::BlackWins, TerminationReason::Timeout);
+    }
+}
+
+#[test]
+fn the_server_flags_on_its_own_clock_whatever_a_client_claims() {
+    let time_control = TimeControl { initial: Duration::from_secs(1), increment: Duration::ZERO };
+    let (mut white, mut black) = start_timed_game(&start_server(), Some(time_control));
+    
+    play_line(&mut white, &mut black, &["e2e4"]);
+    let black_to_move = Instant::now();
+    
+    // Black claims plenty of time left, then sits on the move
+    black.send_message(NetworkMessage::ClockUpdate { white_ms: 0, black_ms: 3_600_000 }).unwrap();
+    for client in [&mut white, &mut black] {
+        expect_game_end(client, GameResult::WhiteWins, TerminationReason::Timeout);
+    }
+    
+    // Flagged by the server's measure of black's thinking time, no sooner
+    assert!(black_to_move.elapsed() >= Duration::from_millis(900), "flagged after only {:?}", black_to_move.elapsed());
+}
+
+// Play a line of UCI moves, waiting for each one to reach the opponent before the next
+fn play_line(white: &mut ChessClient, black: &mut ChessClient, line: &[&str]) {
+    for (i, uci) in line.iter().enumerate() {
+        let (moving, waiting) = if i % 2 == 0 { (&mut *white, &mut *black) } else { (&mut *black, &mut *white) };
+        let from = square_from_uci(&uci[0..2]).unwrap();
+        let to = square_from_uci(&uci[2..4]).unwrap();
+        let (from, to) = ((from.0 as u8, from.1 as u8), (to.0 as u8, to.1 as u8));
+        
+        moving.send_move(from, to, None).unwrap();
+        expect_message(waiting, uci, |message| matches!(message, NetworkMessage::Move { from: f, to: t, .. } if *f == from && *t == to));
+    }
+}
+
+#[test]
+fn draw_offers_wait_out_a_cooldown_after_being_declined() {
+    let (mut white, mut black) = start_game(&start_server());
+    
+    // Offered and declined in move 1
+    white.offer_draw().unwrap();
+    expect_message(&mut black, "DrawOffered", |message| matches!(message, NetworkMessage::DrawOffered));
+    play_line(&mut white, &mut black, &["e2e4"]);
+    black.decline_draw().unwrap();
+    expect_message(&mut white, "DeclineDraw", |message| matches!(message, NetworkMessage::DeclineDraw));
+    play_line(&mut black, &mut white, &["e7e5"]);
+    
+    // Offering again in move 2 only earns the offerer a notice
+    white.offer_draw().unwrap();
+    expect_message(&mut white, "cooldown notice", |message| {
+        matches!(message, NetworkMessage::ChatMessage { sender, .. } if sender == "System")
+    });
+    white.send_move((7, 6), (5, 5), None).unwrap();
+    expect_message(&mut black, "g1f3", |message| {
+        assert!(!matches!(message, NetworkMessage::DrawOffered), "offer within the cooldown reached black");
+        matches!(message, NetworkMessage::Move { from: (7, 6), to: (5, 5), .. })
+    });
+    play_line(&mut black, &mut white, &["g8f6", "b1c3", "b8c6"]);
+    
+    // Three moves after the decline the offer goes through
+    white.offer_draw().unwrap();
+    expect_message(&mut black, "DrawOffered", |message| matches!(message, NetworkMessage::DrawOffered));
+}
+
+#[test]
+fn an_agreed_draw_is_announced_to_spectators_too() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address);
+    let mut spectator = spectate_first_game(&address, "Carol");
+
+    white.offer_draw().unwrap();
+    expect_message(&mut black, "DrawOffered", |message| matches!(message, NetworkMessage::DrawOffered));
+    play_line(&mut white, &mut black, &["e2e4"]);
+    black.accept_draw().unwrap();
+    for client in [&mut white, &mut black, &mut spectator] {
+        expect_game_end(client, GameResult::Draw, TerminationReason::DrawAgreed);
+    }
+}
+
+#[test]
+fn accepting_a_draw_nobody_offered_is_refused() {
+    let (mut white, mut black) = start_game(&start_server());
+
+    // White is on move and accepts an offer black never made
+    white.accept_draw().unwrap();
+    expect_message(&mut white, "refusal notice", |message| {
+        assert!(!matches!(message, NetworkMessage::GameEnd { .. }), "an unoffered draw ended the game");
+        matches!(message, NetworkMessage::ChatMessage { sender, .. } if sender == "System")
+    });
+
+    // The game carries on, and black never hears of a draw
+    white.send_move((6, 4), (4, 4), None).unwrap();
+    expect_message(&mut black, "e2e4", |message| {
+        assert!(!matches!(message, NetworkMessage::GameEnd { .. } | NetworkMessage::AcceptDraw), "black was told the game was drawn");
+        matches!(message, NetworkMessage::Move { .. })
+    });
+    expect_state_after(&mut black, (4, 4), (PieceType::Pawn, Color::White), Color::Black);
+}
+
+#[test]
+fn house_bot_joins_a_waiting_game_and_answers_moves() {
+    let interval = Duration::from_millis(200);
+    let address = start_server_configured(|server| server.set_heartbeat_interval(interval));
+    let bot_address = address.clone();
+    thread::spawn(move || chess::bot::run_house_bot(&bot_address, interval));
+    
+    let (mut human, _) = create_game(&address, "Alice");
+    human.set_heartbeat_interval(interval);
+    
+    match expect_message(&mut human, "GameStart", |message| matches!(message, NetworkMessage::GameStart { .. })) {
+        NetworkMessage::GameStart { is_white, opponent_name, .. } => {
+            assert!(is_white);
+            assert_eq!(opponent_name, HOUSE_BOT_NAME);
+        }
+        _ => unreachable!(),
+    }
+    human.send_ready().unwrap();
+    
+    // Our own move comes back first, then the bot's reply from Black's side of the board
+    let mut game = GameState::new();
+    for uci in ["e2e4", "g1f3"] {
+        let (from, to) = (square_from_uci(&uci[..2]).unwrap(), square_from_uci(&uci[2..]).unwrap());
+        assert!(game.make_move(from, to).is_some());
+        human.send_move((from.0 as u8, from.1 as u8), (to.0 as u8, to.1 as u8), None).unwrap();
+        let reply = expect_message(&mut human, "the bot's move", |message| {
+            matches!(message, NetworkMessage::Move { from: f, .. } if *f != (from.0 as u8, from.1 as u8))
+        });
+        match reply {
+            NetworkMessage::Move { from, to, .. } => {
+                assert!(game.make_move((from.0 as usize, from.1 as usize), (to.0 as usize, to.1 as usize)).is_some(), "bot played an illegal move");
+            }
+            _ => unreachable!(),
+        }
+        
+        // The bot keeps up the server's heartbeat while it waits, so it isn't taken for gone
+        let waited = Instant::now();
+        while waited.elapsed() < interval * 4 {
+            let message = human.receive_message().unwrap();
+            assert!(!matches!(message, Some(NetworkMessage::OpponentDisconnected { .. })), "the bot was dropped for silence");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
+
+#[test]
+fn a_player_who_drops_before_choosing_a_promotion_gets_a_queen() {
+    let (mut white, mut black) = start_game(&start_server());
+    play_line(&mut white, &mut black, &["a2a4", "b7b5", "a4b5", "a7a6", "b5a6", "c8b7", "a6b7", "g8f6"]);
+    
+    // White takes the rook on a8 without naming a piece, then goes away
+    white.send_move((1, 1), (0, 0), None).unwrap();
+    expect_message(&mut white, "pending promotion", |message| {
+        matches!(message, NetworkMessage::GameState { promotion_pending: Some(_), .. })
+    });
+    drop(white);
+    
+    let promoted = expect_message(&mut black, "the promotion", |message| matches!(message, NetworkMessage::Move { to: (0, 0), .. }));
+    assert!(matches!(promoted, NetworkMessage::Move { from: (1, 1), to: (0, 0), promotion: Some('Q'), .. }), "unexpected {:?}", promoted);
+    expect_state_after(&mut black, (0, 0), (PieceType::Queen, Color::White), Color::Black);
+    
+    // Black can carry on
+    black.send_move((1, 4), (3, 4), None).unwrap();
+    expect_ack(&mut black, 5);
+}
+
+#[test]
+fn a_player_who_goes_silent_is_declared_disconnected() {
+    let interval = Duration::from_millis(200);
+    let (mut white, black) = start_game(&start_server_configured(|server| server.set_heartbeat_interval(interval)));
+    
+    // White keeps its heartbeats coming while black's connection stays open but says nothing
+    white.set_heartbeat_interval(interval);
+    let silent_since = Instant::now();
+    match expect_message(&mut white, "OpponentDisconnected", |message| matches!(message, NetworkMessage::OpponentDisconnected { .. })) {
+        NetworkMessage::OpponentDisconnected { grace_secs } => assert!(grace_secs > 0),
+        _ => unreachable!(),
+    }
+    
+    // Dropped after two and a half intervals of silence, give or take a poll
+    assert!(silent_since.elapsed() < interval * 10, "took {:?} to notice", silent_since.elapsed());
+    drop(black);
+}
+
+#[test]
+fn instant_takeback_undoes_a_move_the_opponent_hasnt_answered() {
+    let (mut white, mut black) = start_game(&start_server_configured(|server| server.set_instant_takeback(true)));
+    play_line(&mut white, &mut black, &["e2e4"]);
+    
+    white.request_takeback().unwrap();
+    for client in [&mut white, &mut black] {
+        let taken_back = expect_message(client, "MoveTakenBack", |message| matches!(message, NetworkMessage::MoveTakenBack { .. }));
+        assert!(matches!(taken_back, NetworkMessage::MoveTakenBack { color: Color::White }));
+        expect_state_after(client, (6, 4), (PieceType::Pawn, Color::White), Color::White);
+    }
+    
+    // Once black has replied it's too late
+    play_line(&mut white, &mut black, &["d2d4", "d7d5"]);
+    white.request_takeback().unwrap();
+    expect_message(&mut white, "TakebackRefused", |message| matches!(message, NetworkMessage::TakebackRefused { .. }));
+    white.send_move((7, 6), (5, 5), None).unwrap();
+    expect_message(&mut black, "g1f3", |message| {
+        assert!(!matches!(message, NetworkMessage::MoveTakenBack { .. }), "a move was taken back after the reply");
+        matches!(message, NetworkMessage::Move { .. })
+    });
+}
+
+#[test]
+fn takebacks_are_refused_unless_the_server_allows_them() {
+    let (mut white, mut black) = start_game(&start_server());
+    play_line(&mut white, &mut black, &["e2e4"]);
+    
+    white.request_takeback().unwrap();
+    expect_message(&mut white, "TakebackRefused", |message| matches!(message, NetworkMessage::TakebackRefused { .. }));
+}
+
+#[test]
+fn a_substitute_with_the_token_takes_over_a_vacated_seat() {
+    let address = start_server();
+    let SeatedGame { mut white, mut black, game_id, .. } = start_game_with(&address, &NewGame { takeover_token: Some("sub-in".to_string()), ..NewGame::default() });
+    play_line(&mut white, &mut black, &["e2e4", "e7e5"]);
+    
+    // Bob leaves, and the seat can't be taken without the right token
+    drop(black);
+    expect_message(&mut white, "OpponentDisconnected", |message| matches!(message, NetworkMessage::OpponentDisconnected { .. }));
+    let mut intruder = ChessClient::new(&address).expect("intruder should connect");
+    intruder.take_over_slot(game_id.clone(), Color::Black, "guess".to_string(), "Mallory".to_string()).unwrap();
+    expect_message(&mut intruder, "JoinRejected", |message| matches!(message, NetworkMessage::JoinRejected { .. }));
+    
+    let mut substitute = ChessClient::new(&address).expect("substitute should connect");
+    substitute.take_over_slot(game_id, Color::Black, "sub-in".to_string(), "Carol".to_string()).unwrap();
+    match expect_message(&mut substitute, "GameStart", |message| matches!(message, NetworkMessage::GameStart { .. })) {
+        NetworkMessage::GameStart { is_white, opponent_name, black_name, .. } => {
+            assert!(!is_white);
+            assert_eq!(opponent_name, "Alice");
+            assert_eq!(black_name, "Carol");
+        }
+        _ => unreachable!(),
+    }
+    expect_state_after(&mut substitute, (3, 4), (PieceType::Pawn, Color::Black), Color::White);
+    
+    // Alice hears the new opponent's name, and the game carries on with Carol
+    match expect_message(&mut white, "GameStart", |message| matches!(message, NetworkMessage::GameStart { .. })) {
+        NetworkMessage::GameStart { opponent_name, .. } => assert_eq!(opponent_name, "Carol"),
+        _ => unreachable!(),
+    }
+    substitute.send_ready().unwrap();
+    play_line(&mut white, &mut substitute, &["g1f3", "b8c6"]);
+}
+
+#[test]
+fn stalemate_is_a_draw() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address);
+    let mut spectator = spectate_first_game(&address, "Carol");
+    
+    // Sam Loyd's ten-move stalemate, ending 10. Qe6 with Black to move and nowhere to go
+    play_line(&mut white, &mut black, &[
+        "e2e3", "a7a5", "d1h5", "a8a6", "h5a5", "h7h5", "h2h4", "a6h6", "a5c7", "f7f6",
+        "c7d7", "e8f7", "d7b7", "d8d3", "b7b8", "d3h7", "b8c8", "f7g6", "c8e6",
+    ]);
+    for client in [&mut white, &mut black, &mut spectator] {
+        expect_game_end(client, GameResult::Draw, TerminationReason::Stalemate);
+    }
+}
+
+#[test]
+fn server_sends_thumbnails_for_the_game_list() {
+    let address = start_server();
+    let (mut white, _black) = start_game(&address);
+    white.send_move((6, 4), (4, 4), None).unwrap();
+    expect_state_after(&mut white, (4, 4), (PieceType::Pawn, Color::White), Color::Black);
+    
+    // A lobby client asks for a game it isn't in, and for one that doesn't exist
+    let mut lobby = ChessClient::new(&address).expect("lobby client should connect");
+    lobby.send_message(NetworkMessage::RequestGameList).unwrap();
+    let game_id = match expect_message(&mut lobby, "GameList", |message| matches!(message, NetworkMessage::GameList { .. })) {
+        NetworkMessage::GameList { available_games } => available_games[0].game_id.clone(),
+        _ => unreachable!(),
+    };
+    
+    lobby.request_thumbnail(&game_id).unwrap();
+    match expect_message(&mut lobby, "Thumbnail", |message| matches!(message, NetworkMessage::Thumbnail { .. })) {
+        NetworkMessage::Thumbnail { game_id: thumbnail_id, png } => {
+            assert_eq!(thumbnail_id, game_id);
+            let image = image::load_from_memory(&png.expect("the game exists")).unwrap();
+            assert_eq!((image.width(), image.height()), (128, 128));
+        }
+        _ => unreachable!(),
+    }
+    
+    lobby.request_thumbnail("no-such-game").unwrap();
+    let missing = expect_message(&mut lobby, "Thumbnail", |message| matches!(message, NetworkMessage::Thumbnail { .. }));
+    assert!(matches!(missing, NetworkMessage::Thumbnail { png: None, .. }));
+}
+
+#[test]
+fn moves_are_broadcast_to_the_opponent_and_spectators() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address);
+    
+    let mut spectator = spectate_first_game(&address, "Carol");
+    // Once the players hear about Carol the spectator is in the game and sees every move from here on
+    expect_message(&mut white, "SpectatorJoined", |message| matches!(message, NetworkMessage::SpectatorJoined { .. }));
+    
+    white.send_move((6, 4), (4, 4), None).unwrap();
+    for client in [&mut black, &mut spectator] {
+        let moved = expect_message(client, "Move", |message| matches!(message, NetworkMessage::Move { .. }));
+        assert!(matches!(moved, NetworkMessage::Move { from: (6, 4), to: (4, 4), promotion: None, .. }), "unexpected {:?}", moved);
+        expect_state_after(client, (4, 4), (PieceType::Pawn, Color::White), Color::Black);
+    }
+}
+
+#[test]
+fn moves_after_the_game_is_over_are_refused() {
+    let (mut white, mut black) = start_game(&start_server());
+    play_line(&mut white, &mut black, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+    for client in [&mut white, &mut black] {
+        expect_game_end(client, GameResult::BlackWins, TerminationReason::Checkmate);
+    }
+    
+    // White plays on as if nothing happened, and gets the final board back untouched
+    white.send_move((6, 0), (5, 0), None).unwrap();
+    expect_message(&mut white, "MoveRejected", |message| matches!(message, NetworkMessage::MoveRejected { .. }));
+    match expect_message(&mut white, "GameState after the rejection", |message| matches!(message, NetworkMessage::GameState { .. })) {
+        NetworkMessage::GameState { board, game_over, current_turn, .. } => {
+            assert!(game_over);
+            assert_eq!(current_turn, Color::White);
+            assert_eq!(board[6][0], Some((PieceType::Pawn, Color::White)));
+            assert_eq!(board[5][0], None);
+            assert_eq!(board[4][7], Some((PieceType::Queen, Color::Black)));
+        }
+        _ => unreachable!(),
+    }
+    
+    // Black's duplicate of the mating move is refused the same way
+    black.send_move((0, 3), (4, 7), None).unwrap();
+    expect_rejection(&mut black, Color::White);
+}
+
+// Ask for a player's history until the server has recorded as many games as expected
+fn expect_history(address: &str, name: &str, games: usize) -> Vec<GameSummary> {
+    let mut client = ChessClient::new(address).expect("history client should connect");
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    loop {
+        client.send_message(NetworkMessage::PlayerHistory { name: name.to_string() }).unwrap();
+        let history = match expect_message(&mut client, "HistoryResult", |message| matches!(message, NetworkMessage::HistoryResult { .. })) {
+            NetworkMessage::HistoryResult { games } => games,
+            _ => unreachable!(),
+        };
+        if history.len() >= games || Instant::now() > deadline {
+            return history;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn finished_games_are_saved_as_pgn_next_to_the_history() {
+    let history_file = std::env::temp_dir().join(format!("chess-history-{}.jsonl", uuid::Uuid::new_v4()));
+    let saved_to = history_file.clone();
+    let address = start_server_configured(|server| server.set_history_file(saved_to));
+    
+    // Alice loses to Fool's mate, then wins the next game when Bob resigns after 1. e4
+    let mate = ["f2f3", "e7e5", "g2g4", "d8h4"];
+    let (mut white, mut black) = start_game(&address);
+    play_line(&mut white, &mut black, &mate);
+    expect_game_end(&mut white, GameResult::BlackWins, TerminationReason::Checkmate);
+    
+    let (mut white, mut black) = start_game(&address);
+    play_line(&mut white, &mut black, &["e2e4"]);
+    black.resign().unwrap();
+    expect_game_end(&mut white, GameResult::WhiteWins, TerminationReason::Resignation);
+    
+    let history = expect_history(&address, "Alice", 2);
+    assert_eq!(history.len(), 2);
+    assert!(expect_history(&address, "Carol", 0).is_empty());
+    
+    for summary in &history {
+        let moves = if summary.termination == Some(TerminationReason::Checkmate) { &mate[..] } else { &["e2e4"][..] };
+        let path = summary.pgn_path.as_ref().expect("every finished game should have a PGN");
+        let pgn = fs::read_to_string(path).expect("the PGN file should exist");
+        let game = GameState::from_pgn(&pgn).expect("the saved PGN should read back");
+        
+        // The PGN and the history entry tell the same story
+        assert_eq!(game.move_log_uci(), moves);
+        assert_eq!(game.ending(), Some((summary.termination.unwrap(), summary.result)));
+    }
+    
+    let pgn_dir = history[0].pgn_path.as_ref().map(|path| PathBuf::from(path).parent().unwrap().to_path_buf()).unwrap();
+    let _ = fs::remove_dir_all(pgn_dir);
+    let _ = fs::remove_file(&history_file);
+}
+
+#[test]
+fn chat_carries_the_color_tag_the_sender_joined_with() {
+    let tag = PlayerColorTag::from_hex("#ff8800").unwrap();
+    let SeatedGame { mut white, mut black, .. } = start_game_with(&start_server(), &NewGame { white_tag: Some(tag), ..NewGame::default() });
+    
+    // Whatever tag the message claims, the server fills in the one Alice joined with
+    white.send_message(NetworkMessage::ChatMessage {
+        sender: "Alice".to_string(),
+        message: "good luck".to_string(),
+        is_spectator: false,
+        player_color_tag: PlayerColorTag::from_hex("#000000"),
+    }).unwrap();
+    match expect_message(&mut black, "Alice's chat", |message| matches!(message, NetworkMessage::ChatMessage { .. })) {
+        NetworkMessage::ChatMessage { sender, message, player_color_tag, .. } => {
+            assert_eq!((sender.as_str(), message.as_str()), ("Alice", "good luck"));
+            assert_eq!(player_color_tag, Some(tag));
+        }
+        _ => unreachable!(),
+    }
+    
+    // Bob picked no tag, so his messages come without one
+    black.send_chat_message("you too".to_string(), "Bob".to_string()).unwrap();
+    let reply = expect_message(&mut white, "Bob's chat", |message| matches!(message, NetworkMessage::ChatMessage { sender, .. } if sender == "Bob"));
+    assert!(matches!(reply, NetworkMessage::ChatMessage { player_color_tag: None, .. }));
+}
+
+// The mover hears MoveAck, or MoveRejected, carrying the sequence number its move went out with
+fn expect_ack(client: &mut ChessClient, move_seq: u64) {
+    let ack = expect_message(client, "MoveAck", |message| matches!(message, NetworkMessage::MoveAck { .. } | NetworkMessage::MoveRejected { .. }));
+    assert!(matches!(ack, NetworkMessage::MoveAck { move_seq: acked } if acked == move_seq), "expected an ack for move {}, got {:?}", move_seq, ack);
+    assert!(!client.has_unacked_move());
+}
+
+#[test]
+fn accepted_moves_are_acknowledged_in_sequence() {
+    let (mut white, mut black) = start_game(&start_server());
+    
+    // Each client numbers its own moves from 1
+    for (i, uci) in ["e2e4", "e7e5", "g1f3", "b8c6"].iter().enumerate() {
+        let (moving, waiting) = if i % 2 == 0 { (&mut white, &mut black) } else { (&mut black, &mut white) };
+        let from = square_from_uci(&uci[0..2]).unwrap();
+        let to = square_from_uci(&uci[2..4]).unwrap();
+        
+        moving.send_move((from.0 as u8, from.1 as u8), (to.0 as u8, to.1 as u8), None).unwrap();
+        assert!(moving.has_unacked_move());
+        expect_ack(moving, i as u64 / 2 + 1);
+        expect_message(waiting, uci, |message| matches!(message, NetworkMessage::Move { .. }));
+    }
+    
+    // A refused move is answered with its own sequence number, and the next one moves on
+    white.send_move((6, 0), (3, 0), None).unwrap();
+    match expect_message(&mut white, "MoveRejected", |message| matches!(message, NetworkMessage::MoveRejected { .. })) {
+        NetworkMessage::MoveRejected { move_seq, .. } => assert_eq!(move_seq, Some(3)),
+        _ => unreachable!(),
+    }
+    assert!(!white.has_unacked_move());
+    
+    white.send_move((7, 5), (4, 2), None).unwrap();
+    expect_ack(&mut white, 4);
+}
+
+#[test]
+fn moves_wait_until_both_players_are_ready() {
+    let SeatedGame { mut white, mut black, .. } = seat_players(&start_server(), &NewGame::default());
+    
+    // White is ready and moves at once, before black has answered GameStart
+    white.send_ready().unwrap();
+    white.send_move((6, 4), (4, 4), None).unwrap();
+    
+    let deadline = Instant::now() + Duration::from_millis(500);
+    while Instant::now() < deadline {
+        match black.receive_message() {
+            Ok(Some(NetworkMessage::Move { .. })) => panic!("the move was played before black was ready"),
+            Ok(_) => thread::sleep(Duration::from_millis(5)),
+            Err(e) => panic!("black lost the connection: {}", e),
+        }
+    }
+    
+    // The held move goes through once black confirms
+    black.send_ready().unwrap();
+    let relayed = expect_message(&mut black, "white's held move", |message| matches!(message, NetworkMessage::Move { .. }));
+    assert!(matches!(relayed, NetworkMessage::Move { from: (6, 4), to: (4, 4), .. }));
+    expect_state_after(&mut black, (4, 4), (PieceType::Pawn, Color::White), Color::Black);
+}
+
+// Fail if a LegalMoves list reaches this client within a short window
+fn expect_no_legal_moves(client: &mut ChessClient, who: &str) {
+    let deadline = Instant::now() + Duration::from_millis(300);
+    while Instant::now() < deadline {
+        match client.receive_message() {
+            Ok(Some(NetworkMessage::LegalMoves { .. })) => panic!("{} was sent a move list it didn't ask for", who),
+            Ok(_) => thread::sleep(Duration::from_millis(5)),
+            Err(e) => panic!("{} lost the connection: {}", who, e),
+        }
+    }
+}
+
+#[test]
+fn legal_moves_go_only_to_the_player_who_asked() {
+    let address = start_server();
+    let (mut white, mut black) = start_game(&address);
+    
+    let mut spectator = spectate_first_game(&address, "Carol");
+    expect_message(&mut white, "SpectatorJoined", |message| matches!(message, NetworkMessage::SpectatorJoined { .. }));
+    
+    let mut opening = GameState::new().legal_moves_uci();
+    opening.sort();
+    assert_eq!(opening.len(), 20);
+    
+    white.request_legal_moves().unwrap();
+    match expect_message(&mut white, "LegalMoves", |message| matches!(message, NetworkMessage::LegalMoves { .. })) {
+        NetworkMessage::LegalMoves { mut moves } => {
+            moves.sort();
+            assert_eq!(moves, opening);
+        }
+        _ => unreachable!(),
+    }
+    expect_no_legal_moves(&mut black, "black");
+    expect_no_legal_moves(&mut spectator, "the spectator");
+    
+    // Asking out of turn gets an empty list rather than the opponent's moves, and only the asker gets it
+    black.request_legal_moves().unwrap();
+    match expect_message(&mut black, "LegalMoves", |message| matches!(message, NetworkMessage::LegalMoves { .. })) {
+        NetworkMessage::LegalMoves { moves } => assert!(moves.is_empty(), "black was sent white's moves: {:?}", moves),
+        _ => unreachable!(),
+    }
+    expect_no_legal_moves(&mut white, "white");
+    
+    // Spectators aren't answered at all
+    spectator.request_legal_moves().unwrap();
+    expect_no_legal_moves(&mut spectator, "the spectator");
+}
+
+// Black's view of a game where white has walked away on move one, waiting past the live inactivity window
+fn abandoned_by_white(time_mode: TimeMode) -> Option<NetworkMessage> {
+    let correspondence_dir = std::env::temp_dir().join(format!("chess-correspondence-{}", uuid::Uuid::new_v4()));
+    let dir = correspondence_dir.clone();
+    let address = start_server_configured(move |server| {
+        server.set_max_inactive_time(Duration::from_millis(300));
+        // Long enough that a live forfeit can only come from the inactivity window
+        server.set_reconnect_grace(Duration::from_secs(3600));
+        server.set_correspondence_hours(1);
+        server.set_correspondence_dir(dir);
+    });
+    let SeatedGame { white, mut black, .. } = start_game_with(&address, &NewGame { time_mode, ..NewGame::default() });
+    expect_message(&mut black, "the opening GameState", |message| matches!(message, NetworkMessage::GameState { .. }));
+    drop(white);
+    
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let mut ended = None;
+    while ended.is_none() && Instant::now() < deadline {
+        match black.receive_message() {
+            Ok(Some(message @ NetworkMessage::GameEnd { .. })) => ended = Some(message),
+            Ok(_) => thread::sleep(Duration::from_millis(5)),
+            Err(e) => panic!("black lost the connection: {}", e),
+        }
+    }
+    let _ = fs::remove_dir_all(&correspondence_dir);
+    ended
+}
+
+#[test]
+fn live_game_is_forfeited_after_the_inactivity_window() {
+    let ended = abandoned_by_white(TimeMode::Live).expect("white should have forfeited");
+    assert!(matches!(ended, NetworkMessage::GameEnd { result: Some(GameResult::BlackWins), .. }), "unexpected {:?}", ended);
+}
+
+#[test]
+fn correspondence_game_outlasts_the_live_inactivity_window() {
+    assert!(abandoned_by_white(TimeMode::Correspondence).is_none(), "a correspondence game was forfeited on the live timer");
+}
+
+#[test]
+fn a_correspondence_player_rejoins_with_their_token_after_a_restart() {
+    let correspondence_dir = std::env::temp_dir().join(format!("chess-correspondence-{}", uuid::Uuid::new_v4()));
+    let dir = correspondence_dir.clone();
+    let address = start_server_configured(move |server| server.set_correspondence_dir(dir));
+    let SeatedGame { mut white, mut black, game_id, black_session, .. } =
+        start_game_with(&address, &NewGame { time_mode: TimeMode::Correspondence, ..NewGame::default() });
+    play_line(&mut white, &mut black, &["e2e4", "e7e5"]);
+    
+    // The snapshot is written once the move has gone out, so wait for it to be on disk
+    let snapshot = correspondence_dir.join(format!("{}.json", game_id));
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    while !fs::read_to_string(&snapshot).is_ok_and(|text| text.contains("e7e5")) {
+        assert!(Instant::now() < deadline, "the game was never saved after e7e5");
+        thread::sleep(Duration::from_millis(5));
+    }
+    
+    // A second server reading the same folder stands in for the first one restarting
+    let dir = correspondence_dir.clone();
+    let restarted = start_server_configured(move |server| server.set_correspondence_dir(dir));
+    let mut black = ChessClient::new(&restarted).expect("black should connect to the restarted server");
+    black.send_message(NetworkMessage::Rejoin { game_id, session_token: black_session }).unwrap();
+    match expect_message(&mut black, "GameStart", |message| matches!(message, NetworkMessage::GameStart { .. } | NetworkMessage::JoinRejected { .. })) {
+        NetworkMessage::GameStart { is_white, opponent_name, white_name, black_name, .. } => {
+            assert!(!is_white);
+            assert_eq!(opponent_name, "Alice");
+            assert_eq!((white_name.as_str(), black_name.as_str()), ("Alice", "Bob"));
+        }
+        other => panic!("rejoining the resumed game failed: {:?}", other),
+    }
+    match expect_message(&mut black, "GameState", |message| matches!(message, NetworkMessage::GameState { .. })) {
+        NetworkMessage::GameState { moves, current_turn, .. } => {
+            assert_eq!(moves, vec!["e2e4", "e7e5"]);
+            assert_eq!(current_turn, Color::White);
+        }
+        _ => unreachable!(),
+    }
+    let _ = fs::remove_dir_all(&correspondence_dir);
+}
+
+#[test]
+fn a_player_who_rejoins_within_the_grace_period_carries_on() {
+    let address = start_server_configured(|server| server.set_reconnect_grace(Duration::from_secs(30)));
+    let SeatedGame { mut white, mut black, game_id, black_session, .. } = start_game_with(&address, &NewGame::default());
+    play_line(&mut white, &mut black, &["e2e4", "e7e5"]);
+    
+    drop(black);
+    match expect_message(&mut white, "OpponentDisconnected", |message| matches!(message, NetworkMessage::OpponentDisconnected { .. })) {
+        NetworkMessage::OpponentDisconnected { grace_secs } => assert_eq!(grace_secs, 30),
+        _ => unreachable!(),
+    }
+    
+    // A wrong token doesn't get the seat
+    let mut impostor = ChessClient::new(&address).expect("impostor should connect");
+    impostor.send_message(NetworkMessage::Rejoin { game_id: game_id.clone(), session_token: "guess".to_string() }).unwrap();
+    expect_message(&mut impostor, "JoinRejected", |message| matches!(message, NetworkMessage::JoinRejected { .. }));
+    
+    let mut black = ChessClient::new(&address).expect("black should reconnect");
+    black.send_message(NetworkMessage::Rejoin { game_id, session_token: black_session }).unwrap();
+    match expect_message(&mut black, "GameStart", |message| matches!(message, NetworkMessage::GameStart { .. })) {
+        NetworkMessage::GameStart { is_white, opponent_name, .. } => {
+            assert!(!is_white);
+            assert_eq!(opponent_name, "Alice");
+        }
+        _ => unreachable!(),
+    }
+    expect_state_after(&mut black, (3, 4), (PieceType::Pawn, Color::Black), Color::White);
+    expect_message(&mut white, "OpponentReconnected", |message| matches!(message, NetworkMessage::OpponentReconnected));
+    
+    play_line(&mut white, &mut black, &["g1f3", "b8c6"]);
+}
+
+#[test]
+fn a_player_who_stays_away_past_the_grace_period_forfeits() {
+    let address = start_server_configured(|server| server.set_reconnect_grace(Duration::from_secs(1)));
+    let (mut white, mut black) = start_game(&address);
+    play_line(&mut white, &mut black, &["e2e4", "e7e5"]);
+    
+    drop(black);
+    let disconnected_at = Instant::now();
+    expect_message(&mut white, "OpponentDisconnected", |message| matches!(message, NetworkMessage::OpponentDisconnected { grace_secs: 1 }));
+    let ended = expect_message(&mut white, "GameEnd", |message| matches!(message, NetworkMessage::GameEnd { .. }));
+    assert!(matches!(ended, NetworkMessage::GameEnd { result: Some(GameResult::WhiteWins), .. }), "unexpected {:?}", ended);
+    assert!(disconnected_at.elapsed() >= Duration::from_secs(1), "forfeited before the grace period was up");
+}
+
+// Window position of a square's centre, seen from White's side
+fn square_center(name: &str) -> (f32, f32) {
+    let (rank, file) = square_from_uci(name).unwrap();
+    (BOARD_OFFSET_X + (file as f32 + 0.5) * SQUARE_SIZE, BOARD_OFFSET_Y + (rank as f32 + 0.5) * SQUARE_SIZE)
+}
+
+fn click_at(gui: &mut ChessGui, (x, y): (f32, f32)) {
+    gui.handle_mouse_down(MouseButton::Left, x, y).unwrap();
+    gui.handle_mouse_up(MouseButton::Left, x, y).unwrap();
+}
+
+// Let the GUI read from the server until the check passes
+fn pump_gui_until(gui: &mut ChessGui, what: &str, mut done: impl FnMut(&ChessGui) -> bool) {
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    while !done(gui) {
+        assert!(Instant::now() < deadline, "the GUI never saw {}", what);
+        gui.handle_network_messages().unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+// A game the GUI hosts as White, with Bob joined over his own connection and both sides started
+fn gui_game_against_bob(address: &str) -> (ChessGui, ChessClient) {
+    let mut gui = ChessGui::with_text_pieces().unwrap();
+    gui.init_network(address, "Alice".to_string()).unwrap();
+    gui.create_game().unwrap();
+    
+    // Bob finds the GUI's game in the list and joins it from the same connection
+    let mut black = ChessClient::new(address).expect("black should connect");
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    let game_id = loop {
+        black.send_message(NetworkMessage::RequestGameList).unwrap();
+        if let NetworkMessage::GameList { available_games } = expect_message(&mut black, "GameList", |message| matches!(message, NetworkMessage::GameList { .. })) {
+            if let Some(game) = available_games.first() {
+                break game.game_id.clone();
+            }
+        }
+        assert!(Instant::now() < deadline, "the GUI's game was never listed");
+        thread::sleep(Duration::from_millis(20));
+    };
+    black.send_message(NetworkMessage::JoinGame { game_id, player_name: "Bob".to_string(), player_color_tag: None }).unwrap();
+    expect_message(&mut black, "GameStart", |message| matches!(message, NetworkMessage::GameStart { .. }));
+    black.send_ready().unwrap();
+    pump_gui_until(&mut gui, "GameStart", |gui| gui.network_log().iter().any(|line| line.contains("Game started")));
+    (gui, black)
+}
+
+#[test]
+fn a_promotion_chosen_in_the_dialog_reaches_the_server_as_one_move() {
+    let address = start_server();
+    let (mut gui, mut black) = gui_game_against_bob(&address);
+    
+    // White's h-pawn runs up the board while black shuffles the a-pawn
+    for (white_move, black_move) in [("h2h4", "g7g5"), ("h4g5", "a7a6"), ("g5g6", "a6a5"), ("g6h7", "a5a4")] {
+        click_at(&mut gui, square_center(&white_move[..2]));
+        click_at(&mut gui, square_center(&white_move[2..]));
+        let (from, to) = (square_from_uci(&white_move[..2]).unwrap(), square_from_uci(&white_move[2..]).unwrap());
+        expect_message(&mut black, white_move, |message| {
+            matches!(message, NetworkMessage::Move { from: f, to: t, .. } if *f == (from.0 as u8, from.1 as u8) && *t == (to.0 as u8, to.1 as u8))
+        });
+        
+        let (from, to) = (square_from_uci(&black_move[..2]).unwrap(), square_from_uci(&black_move[2..]).unwrap());
+        black.send_move((from.0 as u8, from.1 as u8), (to.0 as u8, to.1 as u8), None).unwrap();
+        pump_gui_until(&mut gui, black_move, |gui| {
+            let game = gui.game_state();
+            game.current_turn == Color::White && matches!(game.board[to.0][to.1], Some(piece) if piece.color == Color::Black)
+        });
+    }
+    
+    // Taking the knight on g8 opens the dialog, and the knight is fourth down from the promotion square
+    click_at(&mut gui, square_center("h7"));
+    click_at(&mut gui, square_center("g8"));
+    assert!(gui.game_state().promotion_pending.is_some(), "the promotion dialog should be open");
+    click_at(&mut gui, square_center("g5"));
+    
+    let promoted = expect_message(&mut black, "the promotion", |message| matches!(message, NetworkMessage::Move { to: (0, 6), .. }));
+    assert!(matches!(promoted, NetworkMessage::Move { from: (1, 7), to: (0, 6), promotion: Some('N'), .. }), "unexpected {:?}", promoted);
+    expect_state_after(&mut black, (0, 6), (PieceType::Knight, Color::White), Color::Black);
+    
+    // The server took the move as sent, so the GUI never hears of a rejection
+    pump_gui_until(&mut gui, "black to move", |gui| gui.game_state().current_turn == Color::Black);
+    let settle = Instant::now() + Duration::from_millis(300);
+    while Instant::now() < settle {
+        gui.handle_network_messages().unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert!(!gui.network_log().iter().any(|line| line.contains("rejected")), "the server refused part of the promotion: {:?}", gui.network_log());
+}
+
+#[test]
+fn resigning_in_the_gui_ends_the_game_there_before_the_server_answers() {
+    let address = start_server();
+    let (mut gui, mut black) = gui_game_against_bob(&address);
+    
+    gui.resign().unwrap();
+    assert_eq!(gui.ending(), (true, Some(TerminationReason::Resignation)));
+    
+    // The server's GameEnd then settles it the same way for both sides
+    expect_game_end(&mut black, GameResult::BlackWins, TerminationReason::Resignation);
+    pump_gui_until(&mut gui, "GameEnd", |gui| gui.network_log().iter().any(|line| line.contains("Game ended")));
+    assert_eq!(gui.ending(), (true, Some(TerminationReason::Resignation)));
+}
@@ -0,0 +1,61 @@
+// The GUI keeps the network events it prints in an on-screen log, fed here from a bare socket standing in
+// for a server
+use chess::gui::ChessGui;
+use chess::network::NetworkMessage;
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn connected_gui() -> (ChessGui, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut gui = ChessGui::with_text_pieces().unwrap();
+    gui.init_network(&listener.local_addr().unwrap().to_string(), "Alice".to_string()).unwrap();
+    let (server_side, _) = listener.accept().unwrap();
+    (gui, server_side)
+}
+
+fn send(server_side: &mut TcpStream, message: &NetworkMessage) {
+    let line = format!("{}\n", serde_json::to_string(message).unwrap());
+    server_side.write_all(line.as_bytes()).unwrap();
+}
+
+// Let the GUI read until its log holds a line containing the given text
+fn wait_for_log_line(gui: &mut ChessGui, text: &str) {
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    while !gui.network_log().iter().any(|line| line.contains(text)) {
+        assert!(Instant::now() < deadline, "no log line with {:?} in {:?}", text, gui.network_log());
+        gui.handle_network_messages().unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn received_messages_are_logged() {
+    let (mut gui, mut server_side) = connected_gui();
+    assert!(gui.network_log().is_empty());
+    
+    send(&mut server_side, &NetworkMessage::GameCreated { game_id: "game-1".to_string() });
+    wait_for_log_line(&mut gui, "Game created with ID: game-1");
+    
+    send(&mut server_side, &NetworkMessage::GameList { available_games: Vec::new() });
+    wait_for_log_line(&mut gui, "Received game list with 0 games");
+}
+
+#[test]
+fn the_log_keeps_only_the_latest_fifty_lines() {
+    let (mut gui, mut server_side) = connected_gui();
+    
+    for i in 0..40 {
+        send(&mut server_side, &NetworkMessage::GameCreated { game_id: format!("game-{}", i) });
+    }
+    wait_for_log_line(&mut gui, "game-39");
+    
+    // Two lines per game created, the oldest dropped first
+    let log = gui.network_log();
+    assert_eq!(log.len(), 50);
+    assert_eq!(log.front().map(String::as_str), Some("Game created with ID: game-15"));
+    assert_eq!(log.back().map(String::as_str), Some("Waiting for an opponent to join..."));
+}
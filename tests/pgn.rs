@@ -0,0 +1,105 @@
+// PGN export and import, including the tags that say how a game ended
+use chess::board::{pgn_tag, GameResult, GameState, TerminationReason};
+
+fn play(game: &mut GameState, moves: &[&str]) {
+    for uci in moves {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    }
+}
+
+#[test]
+fn checkmate_is_written_as_the_termination() {
+    let mut game = GameState::new();
+    play(&mut game, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+    
+    let pgn = game.to_pgn();
+    assert_eq!(pgn_tag(&pgn, "Result").as_deref(), Some("0-1"));
+    assert_eq!(pgn_tag(&pgn, "Termination").as_deref(), Some("Checkmate"));
+    assert!(pgn.trim_end().ends_with("1. f3 e5 2. g4 Qh4# 0-1"));
+}
+
+#[test]
+fn resignation_and_timeout_come_from_the_recorded_ending() {
+    let mut resigned = GameState::new();
+    play(&mut resigned, &["e2e4", "e7e5"]);
+    resigned.set_ending(TerminationReason::Resignation, GameResult::BlackWins);
+    let pgn = resigned.to_pgn();
+    assert_eq!(pgn_tag(&pgn, "Result").as_deref(), Some("0-1"));
+    assert_eq!(pgn_tag(&pgn, "Termination").as_deref(), Some("Resignation"));
+    assert!(pgn.trim_end().ends_with("1. e4 e5 0-1"));
+    
+    let mut flagged = GameState::new();
+    play(&mut flagged, &["d2d4"]);
+    flagged.set_ending(TerminationReason::Timeout, GameResult::WhiteWins);
+    let pgn = flagged.to_pgn();
+    assert_eq!(pgn_tag(&pgn, "Result").as_deref(), Some("1-0"));
+    assert_eq!(pgn_tag(&pgn, "Termination").as_deref(), Some("Time forfeit"));
+}
+
+#[test]
+fn agreed_draws_and_stalemates_are_told_apart() {
+    let mut agreed = GameState::new();
+    play(&mut agreed, &["e2e4", "e7e5"]);
+    agreed.set_ending(TerminationReason::DrawAgreed, GameResult::Draw);
+    let pgn = agreed.to_pgn();
+    assert_eq!(pgn_tag(&pgn, "Result").as_deref(), Some("1/2-1/2"));
+    assert_eq!(pgn_tag(&pgn, "Termination").as_deref(), Some("Draw agreed"));
+    
+    // Black's king on h8 has no move and isn't in check
+    let stalemate = GameState::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    let pgn = stalemate.to_pgn();
+    assert_eq!(pgn_tag(&pgn, "Result").as_deref(), Some("1/2-1/2"));
+    assert_eq!(pgn_tag(&pgn, "Termination").as_deref(), Some("Stalemate"));
+}
+
+#[test]
+fn unfinished_games_have_no_termination() {
+    let mut game = GameState::new();
+    play(&mut game, &["e2e4"]);
+    let pgn = game.to_pgn();
+    assert_eq!(pgn_tag(&pgn, "Result").as_deref(), Some("*"));
+    assert_eq!(pgn_tag(&pgn, "Termination"), None);
+}
+
+#[test]
+fn pgn_survives_a_round_trip() {
+    let mut game = GameState::new();
+    // Castling, captures and a check
+    play(&mut game, &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "e1g1", "f6e4", "b1c3", "e4c3", "d2c3", "f8c5", "c4f7"]);
+    game.set_ending(TerminationReason::Resignation, GameResult::WhiteWins);
+    let pgn = game.to_pgn();
+    
+    let restored = GameState::from_pgn(&pgn).expect("exported PGN should read back");
+    assert_eq!(restored.to_fen(), game.to_fen());
+    assert_eq!(restored.move_log_san(), game.move_log_san());
+    assert_eq!(restored.ending(), Some((TerminationReason::Resignation, GameResult::WhiteWins)));
+    assert_eq!(restored.to_pgn(), pgn);
+}
+
+#[test]
+fn games_from_a_position_keep_their_fen_through_a_round_trip() {
+    let start = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+    let mut game = GameState::from_fen(start).unwrap();
+    play(&mut game, &["e1c1", "e8g8"]);
+    let pgn = game.to_pgn();
+    assert_eq!(pgn_tag(&pgn, "FEN").as_deref(), Some(start));
+    assert!(pgn.contains("1. O-O-O O-O *"));
+    
+    let restored = GameState::from_pgn(&pgn).unwrap();
+    assert_eq!(restored.to_fen(), game.to_fen());
+}
+
+#[test]
+fn result_and_termination_tags_read_back() {
+    for result in [GameResult::WhiteWins, GameResult::BlackWins, GameResult::Draw] {
+        assert_eq!(GameResult::from_pgn(&result.to_string()), Some(result));
+    }
+    assert_eq!(GameResult::from_pgn("*"), None);
+    
+    assert_eq!(TerminationReason::from_pgn("Time forfeit"), Some(TerminationReason::Timeout));
+    assert_eq!(TerminationReason::from_pgn("Draw agreed"), Some(TerminationReason::DrawAgreed));
+    assert_eq!(TerminationReason::from_pgn("Abandoned"), None);
+    
+    // The protocol still names them the same
+    assert_eq!(chess::network::GameResult::Draw, GameResult::Draw);
+}
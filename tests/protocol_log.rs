@@ -0,0 +1,91 @@
+// A game recorded by a client and by the server replays through the rules engine to the position
+// the game actually reached
+mod common;
+
+use chess::board::{square_from_uci, GameState};
+use chess::network::{ChessClient, NetworkMessage};
+use chess::protocol_log::{replay, Direction, ProtocolRecorder};
+use common::{create_game, expect_message, start_server_configured};
+use std::fs;
+use std::path::PathBuf;
+
+const SCHOLARS_MATE: [&str; 7] = ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"];
+
+fn log_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("chess-protocol-{}-{}.jsonl", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn wire(uci: &str) -> (u8, u8) {
+    let (rank, file) = square_from_uci(uci).unwrap();
+    (rank as u8, file as u8)
+}
+
+fn played(line: &[&str]) -> GameState {
+    let mut game = GameState::new();
+    for uci in line {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    }
+    game
+}
+
+#[test]
+fn recorded_game_replays_to_the_same_position() {
+    let server_log = log_path("server");
+    let client_log = log_path("client");
+    
+    let recorder = ProtocolRecorder::open(&server_log).unwrap();
+    let address = start_server_configured(|server| server.set_protocol_recorder(recorder));
+    let (mut white, game_id) = create_game(&address, "Alice");
+    
+    let mut black = ChessClient::new(&address).unwrap();
+    black.set_recorder(Some(ProtocolRecorder::open(&client_log).unwrap()));
+    black.send_message(NetworkMessage::JoinGame { game_id, player_name: "Bob".to_string(), player_color_tag: None }).unwrap();
+    for client in [&mut white, &mut black] {
+        expect_message(client, "GameStart", |message| matches!(message, NetworkMessage::GameStart { .. }));
+        client.send_ready().unwrap();
+    }
+    
+    // Each move is echoed to both players, so black seeing it means the server has applied it
+    for (ply, uci) in SCHOLARS_MATE.iter().enumerate() {
+        let mover = if ply % 2 == 0 { &mut white } else { &mut black };
+        let (from, to) = (wire(&uci[..2]), wire(&uci[2..]));
+        mover.send_move(from, to, None).unwrap();
+        expect_message(&mut black, uci, |message| matches!(message, NetworkMessage::Move { to: seen, .. } if *seen == to));
+    }
+    expect_message(&mut black, "GameEnd", |message| matches!(message, NetworkMessage::GameEnd { .. }));
+    
+    let expected = played(&SCHOLARS_MATE);
+    for log in [&client_log, &server_log] {
+        let summary = replay(log).expect("the log should replay");
+        assert_eq!(summary.rejected, 0, "{} had moves the engine refused", log.display());
+        assert_eq!(summary.game_state.to_fen(), expected.to_fen(), "{} replayed to a different position", log.display());
+        assert_eq!(summary.moves.last().map(String::as_str), Some("4. Qxf7#"));
+    }
+    
+    let _ = fs::remove_file(&server_log);
+    let _ = fs::remove_file(&client_log);
+}
+
+#[test]
+fn replay_undoes_takebacks_and_counts_refused_moves() {
+    let path = log_path("takeback");
+    let recorder = ProtocolRecorder::open(&path).unwrap();
+    let received = |uci: &str| NetworkMessage::Move { from: wire(&uci[..2]), to: wire(&uci[2..]), promotion: None, move_seq: None };
+    
+    recorder.record(Direction::Received, &received("e2e4"));
+    recorder.record(Direction::Received, &received("e7e5"));
+    recorder.record(Direction::Received, &NetworkMessage::MoveTakenBack { color: chess::piece::Color::Black });
+    recorder.record(Direction::Received, &received("e7e6"));
+    recorder.record(Direction::Received, &received("e4e6")); // Not a legal move
+    // What this side sent is only in the log for reference
+    recorder.record(Direction::Sent, &received("d2d4"));
+    
+    let summary = replay(&path).unwrap();
+    assert_eq!(summary.rejected, 1);
+    assert_eq!(summary.moves, vec!["1. e4", "e6"]);
+    assert_eq!(summary.game_state.to_fen(), played(&["e2e4", "e7e6"]).to_fen());
+    
+    let _ = fs::remove_file(&path);
+}
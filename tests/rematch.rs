@@ -0,0 +1,63 @@
+// A rematch offer reaches the player whenever it arrives after the game, fed here from a bare socket
+// standing in for a server
+use chess::gui::ChessGui;
+use chess::network::{GameResult, NetworkMessage, TerminationReason, WIRE_BOARD_SIZE};
+use chess::piece::Color;
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// White in a game on a fake server
+fn gui_in_a_game() -> (ChessGui, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut gui = ChessGui::with_text_pieces().unwrap();
+    gui.init_network(&listener.local_addr().unwrap().to_string(), "Alice".to_string()).unwrap();
+    gui.set_player_color(true);
+    let (server_side, _) = listener.accept().unwrap();
+    (gui, server_side)
+}
+
+fn send(server_side: &mut TcpStream, message: &NetworkMessage) {
+    let line = format!("{}\n", serde_json::to_string(message).unwrap());
+    server_side.write_all(line.as_bytes()).unwrap();
+}
+
+fn wait_for_offer(gui: &mut ChessGui) {
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    while !gui.has_rematch_offer() {
+        assert!(Instant::now() < deadline, "the rematch offer never showed");
+        gui.handle_network_messages().unwrap();
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn an_offer_after_the_end_screen_is_left_still_shows() {
+    let (mut gui, mut server_side) = gui_in_a_game();
+    send(&mut server_side, &NetworkMessage::GameEnd {
+        reason: "Black resigned".to_string(),
+        result: Some(GameResult::WhiteWins),
+        termination: Some(TerminationReason::Resignation),
+    });
+    
+    // A fresh board with the game running again stands in for having left the result screen
+    send(&mut server_side, &NetworkMessage::GameState {
+        board: [[None; WIRE_BOARD_SIZE]; WIRE_BOARD_SIZE],
+        current_turn: Color::White,
+        promotion_pending: None,
+        game_over: false,
+        moves: Vec::new(),
+    });
+    send(&mut server_side, &NetworkMessage::RequestRematch);
+    wait_for_offer(&mut gui);
+}
+
+#[test]
+fn an_offer_that_outruns_the_game_end_still_shows() {
+    let (mut gui, mut server_side) = gui_in_a_game();
+    send(&mut server_side, &NetworkMessage::RequestRematch);
+    wait_for_offer(&mut gui);
+}
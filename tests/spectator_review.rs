@@ -0,0 +1,82 @@
+// A spectator stepping back through the game while new positions keep arriving, driven without a window
+use chess::board::GameState;
+use chess::gui::ChessGui;
+use chess::network::WIRE_BOARD_SIZE;
+use chess::piece::{Color, PieceType};
+
+type WireBoard = [[Option<(PieceType, Color)>; WIRE_BOARD_SIZE]; WIRE_BOARD_SIZE];
+
+fn wire_board(game: &GameState) -> WireBoard {
+    let mut board = [[None; WIRE_BOARD_SIZE]; WIRE_BOARD_SIZE];
+    for (rank, row) in game.board.iter().enumerate() {
+        for (file, square) in row.iter().enumerate() {
+            board[rank][file] = square.map(|piece| (piece.piece_type, piece.color));
+        }
+    }
+    board
+}
+
+// A spectator who has seen the start position and then each move of the line as a snapshot
+fn spectator_after(line: &[&str]) -> (ChessGui, GameState) {
+    let mut gui = ChessGui::with_text_pieces().unwrap();
+    gui.set_spectator_mode("game-1".to_string());
+    
+    let mut game = GameState::new();
+    gui.update_game_state(wire_board(&game), game.current_turn, None, false).unwrap();
+    for uci in line {
+        push(&mut gui, &mut game, uci);
+    }
+    (gui, game)
+}
+
+fn push(gui: &mut ChessGui, game: &mut GameState, uci: &str) {
+    assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+    gui.update_game_state(wire_board(game), game.current_turn, None, false).unwrap();
+}
+
+#[test]
+fn new_positions_are_followed_until_the_spectator_steps_back() {
+    let (mut gui, mut game) = spectator_after(&["e2e4", "e7e5"]);
+    assert_eq!(gui.review_position(), (2, 3));
+    assert!(gui.is_following_live());
+    
+    gui.review_step_back();
+    assert_eq!(gui.review_position(), (1, 3));
+    assert!(!gui.is_following_live());
+    
+    // The game carries on, and the reviewed position stays put
+    push(&mut gui, &mut game, "g1f3");
+    assert_eq!(gui.review_position(), (1, 4));
+    assert!(!gui.is_following_live());
+}
+
+#[test]
+fn jumping_to_live_shows_the_latest_ply_and_follows_again() {
+    let (mut gui, mut game) = spectator_after(&["e2e4", "e7e5", "g1f3"]);
+    gui.review_step_back();
+    gui.review_step_back();
+    push(&mut gui, &mut game, "b8c6");
+    assert_eq!(gui.review_position(), (1, 5));
+    
+    gui.jump_to_live();
+    assert_eq!(gui.review_position(), (4, 5));
+    assert!(gui.is_following_live());
+    
+    push(&mut gui, &mut game, "f1b5");
+    assert_eq!(gui.review_position(), (5, 6));
+    assert!(gui.is_following_live());
+}
+
+#[test]
+fn stepping_forward_to_the_latest_ply_follows_again() {
+    let (mut gui, mut game) = spectator_after(&["e2e4"]);
+    gui.review_step_back();
+    assert!(!gui.is_following_live());
+    
+    gui.review_step_forward();
+    assert_eq!(gui.review_position(), (1, 2));
+    assert!(gui.is_following_live());
+    
+    push(&mut gui, &mut game, "c7c5");
+    assert_eq!(gui.review_position(), (2, 3));
+}
@@ -0,0 +1,20 @@
+// Text-piece mode builds the GUI with no image loading, so it needs no window
+use chess::embedded_assets::EmbeddedAssets;
+use chess::gui::ChessGui;
+
+#[test]
+fn text_piece_gui_builds_without_images() {
+    let gui = ChessGui::with_text_pieces().expect("text-piece mode should build without a context");
+    assert!(gui.uses_text_pieces());
+}
+
+#[test]
+fn text_pieces_still_fill_a_square() {
+    let assets = EmbeddedAssets::text_pieces();
+    assert!(assets.uses_text_pieces());
+    
+    // Twice the square gives twice the scale, the same as images would
+    let scale = assets.piece_scale(60.0);
+    assert!(scale > 0.0);
+    assert_eq!(assets.piece_scale(120.0), scale * 2.0);
+}
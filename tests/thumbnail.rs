@@ -0,0 +1,32 @@
+// Game list thumbnails, decoded back from the PNG the server sends
+use chess::board::{GameState, BOARD_SIZE};
+use chess::thumbnail::{render_thumbnail_png, DEFAULT_THUMBNAIL_SQUARE_SIZE};
+use image::Rgba;
+
+const LIGHT_SQUARE: Rgba<u8> = Rgba([230, 230, 204, 255]);
+const DARK_SQUARE: Rgba<u8> = Rgba([128, 128, 102, 255]);
+
+#[test]
+fn start_position_thumbnail_decodes_to_the_board() {
+    let png = render_thumbnail_png(&GameState::new(), DEFAULT_THUMBNAIL_SQUARE_SIZE).unwrap();
+    let image = image::load_from_memory(&png).expect("thumbnail should be a valid PNG").to_rgba8();
+    
+    let side = DEFAULT_THUMBNAIL_SQUARE_SIZE * BOARD_SIZE as u32;
+    assert_eq!(image.dimensions(), (side, side));
+    
+    // The empty middle ranks show the bare squares, light on a8's colour and alternating from there
+    for rank in 2..6 {
+        for file in 0..BOARD_SIZE {
+            let centre = |n: usize| n as u32 * DEFAULT_THUMBNAIL_SQUARE_SIZE + DEFAULT_THUMBNAIL_SQUARE_SIZE / 2;
+            let expected = if (rank + file) % 2 == 0 { LIGHT_SQUARE } else { DARK_SQUARE };
+            assert_eq!(*image.get_pixel(centre(file), centre(rank)), expected, "square at rank {} file {}", rank, file);
+        }
+    }
+}
+
+#[test]
+fn thumbnail_scales_with_the_square_size() {
+    let png = render_thumbnail_png(&GameState::new(), 4).unwrap();
+    let image = image::load_from_memory(&png).unwrap();
+    assert_eq!((image.width(), image.height()), (32, 32));
+}
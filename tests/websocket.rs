@@ -0,0 +1,201 @@
+// WebSocket framing: fragmented messages are put back together, control frames may come between the
+// fragments, and a browser that doesn't mask its frames is closed with a protocol error
+#![cfg(feature = "websocket")]
+
+use chess::network::NetworkMessage;
+use chess::server::ChessServer;
+use chess::websocket::{
+    encode_frame, read_frame, run_websocket_bridge, MessageReader, CLOSE_PROTOCOL_ERROR,
+    OPCODE_CLOSE, OPCODE_CONTINUATION, OPCODE_PING, OPCODE_TEXT,
+};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A frame the way a browser sends it, masked
+fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = [0x37, 0xFA, 0x21, 0x3D];
+    let mut frame = vec![if fin { 0x80 | opcode } else { opcode }];
+
+    if payload.len() < 126 {
+        frame.push(0x80 | payload.len() as u8);
+    } else {
+        frame.push(0x80 | 126);
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    }
+
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+    frame
+}
+
+// The same frame without the mask, which only a server may send
+fn unmasked_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    encode_frame(opcode, payload)
+}
+
+// Reads a server frame, which is never masked, so read_frame can't be used
+fn read_server_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).expect("bridge should send a frame");
+
+    let mut length = (header[1] & 0x7F) as usize;
+    if length == 126 {
+        let mut extended = [0u8; 2];
+        stream.read_exact(&mut extended).unwrap();
+        length = u16::from_be_bytes(extended) as usize;
+    } else if length == 127 {
+        let mut extended = [0u8; 8];
+        stream.read_exact(&mut extended).unwrap();
+        length = u64::from_be_bytes(extended) as usize;
+    }
+
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload).unwrap();
+    (header[0] & 0x0F, payload)
+}
+
+#[test]
+fn fragments_are_joined_into_one_message() {
+    let mut bytes = client_frame(false, OPCODE_TEXT, b"{\"Request");
+    bytes.extend(client_frame(false, OPCODE_CONTINUATION, b"Game"));
+    bytes.extend(client_frame(true, OPCODE_CONTINUATION, b"List\"}"));
+
+    let mut reader = MessageReader::new();
+    let message = reader.read(&mut bytes.as_slice()).expect("fragments should make one message");
+    assert!(message.fin);
+    assert_eq!(message.opcode, OPCODE_TEXT);
+    assert_eq!(message.payload, b"{\"RequestGameList\"}");
+}
+
+#[test]
+fn control_frames_can_come_between_fragments() {
+    let mut bytes = client_frame(false, OPCODE_TEXT, b"hello ");
+    bytes.extend(client_frame(true, OPCODE_PING, b"are you there"));
+    bytes.extend(client_frame(true, OPCODE_CONTINUATION, b"world"));
+    let mut bytes = bytes.as_slice();
+
+    let mut reader = MessageReader::new();
+    let ping = reader.read(&mut bytes).unwrap();
+    assert_eq!(ping.opcode, OPCODE_PING);
+    assert_eq!(ping.payload, b"are you there");
+
+    let message = reader.read(&mut bytes).unwrap();
+    assert_eq!(message.opcode, OPCODE_TEXT);
+    assert_eq!(message.payload, b"hello world");
+}
+
+#[test]
+fn broken_fragmentation_is_a_protocol_error() {
+    // A continuation with nothing to continue
+    let bytes = client_frame(true, OPCODE_CONTINUATION, b"stray");
+    let error = MessageReader::new().read(&mut bytes.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+    // A new message while the last one is still missing its end
+    let mut bytes = client_frame(false, OPCODE_TEXT, b"first");
+    bytes.extend(client_frame(true, OPCODE_TEXT, b"second"));
+    let error = MessageReader::new().read(&mut bytes.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+    // Control frames can't be fragmented
+    let bytes = client_frame(false, OPCODE_PING, b"ping");
+    let error = MessageReader::new().read(&mut bytes.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn unmasked_client_frames_are_rejected() {
+    let bytes = unmasked_frame(OPCODE_TEXT, b"{\"RequestGameList\"}");
+    let error = read_frame(&mut bytes.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+    let bytes = client_frame(true, OPCODE_TEXT, b"{\"RequestGameList\"}");
+    let frame = read_frame(&mut bytes.as_slice()).expect("masked frames should be read");
+    assert_eq!(frame.payload, b"{\"RequestGameList\"}");
+}
+
+#[test]
+fn long_payloads_use_the_extended_length() {
+    let payload = vec![b'x'; 300];
+    let bytes = client_frame(true, OPCODE_TEXT, &payload);
+    let frame = read_frame(&mut bytes.as_slice()).unwrap();
+    assert_eq!(frame.payload, payload);
+}
+
+// A bridge in front of a real server, on a port nothing else holds
+fn start_bridge() -> TcpStream {
+    let mut server = ChessServer::new(0).expect("server should bind a free port");
+    let server_port = server.local_port().expect("server should report its port");
+    server.set_history_file(std::env::temp_dir().join(format!("chess-websocket-{}.jsonl", server_port)));
+    thread::spawn(move || server.run());
+
+    let ws_port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    thread::spawn(move || run_websocket_bridge(ws_port, format!("127.0.0.1:{}", server_port)));
+
+    let deadline = Instant::now() + MESSAGE_TIMEOUT;
+    let mut stream = loop {
+        match TcpStream::connect(("127.0.0.1", ws_port)) {
+            Ok(stream) => break stream,
+            Err(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+            Err(e) => panic!("bridge never started listening: {}", e),
+        }
+    };
+    stream.set_read_timeout(Some(MESSAGE_TIMEOUT)).unwrap();
+
+    stream.write_all(b"GET / HTTP/1.1\r\n\
+        Host: localhost\r\n\
+        Upgrade: websocket\r\n\
+        Connection: Upgrade\r\n\
+        Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+        Sec-WebSocket-Version: 13\r\n\r\n").unwrap();
+
+    let mut response = Vec::new();
+    let mut byte = [0u8; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).expect("bridge should answer the handshake");
+        response.push(byte[0]);
+    }
+    assert!(response.starts_with(b"HTTP/1.1 101"));
+    stream
+}
+
+#[test]
+fn bridge_forwards_a_fragmented_message() {
+    let mut stream = start_bridge();
+    let request = serde_json::to_string(&NetworkMessage::RequestGameList).unwrap();
+    let (start, end) = request.as_bytes().split_at(request.len() / 2);
+    stream.write_all(&client_frame(false, OPCODE_TEXT, start)).unwrap();
+    stream.write_all(&client_frame(true, OPCODE_CONTINUATION, end)).unwrap();
+
+    // The server's reply only comes if the two halves reached it as one line
+    loop {
+        let (opcode, payload) = read_server_frame(&mut stream);
+        assert_eq!(opcode, OPCODE_TEXT, "bridge closed before the game list came back");
+        let message: NetworkMessage = serde_json::from_slice(&payload).unwrap();
+        if matches!(message, NetworkMessage::GameList { .. }) {
+            break;
+        }
+    }
+}
+
+#[test]
+fn bridge_closes_unmasked_connections_with_a_protocol_error() {
+    let mut stream = start_bridge();
+    let request = serde_json::to_string(&NetworkMessage::RequestGameList).unwrap();
+    stream.write_all(&unmasked_frame(OPCODE_TEXT, request.as_bytes())).unwrap();
+
+    loop {
+        let (opcode, payload) = read_server_frame(&mut stream);
+        if opcode == OPCODE_CLOSE {
+            assert_eq!(u16::from_be_bytes([payload[0], payload[1]]), CLOSE_PROTOCOL_ERROR);
+            break;
+        }
+        // Anything the server sent on connecting may come first, but never a GameList
+        let message: NetworkMessage = serde_json::from_slice(&payload).unwrap();
+        assert!(!matches!(message, NetworkMessage::GameList { .. }), "unmasked request was forwarded");
+    }
+}
@@ -0,0 +1,78 @@
+// Zobrist hashing goes through ZobristKeys' index helpers everywhere, so the hash kept up move by move
+// must match the one computed from scratch
+use chess::board::{square_from_uci, square_index, GameState, BOARD_SIZE};
+use chess::piece::{Color, PieceType};
+use chess::zobrist::{ZobristKeys, ZOBRIST};
+use std::collections::HashSet;
+
+#[test]
+fn every_piece_and_color_has_its_own_index() {
+    let pieces = [PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen, PieceType::King];
+    let indices: HashSet<usize> = pieces.iter().map(|&piece_type| ZobristKeys::get_piece_index(piece_type)).collect();
+    assert_eq!(indices, (0..6).collect());
+    
+    let colors: HashSet<usize> = [Color::White, Color::Black].iter().map(|&color| ZobristKeys::get_color_index(color)).collect();
+    assert_eq!(colors, (0..2).collect());
+}
+
+#[test]
+fn squares_are_numbered_from_a8() {
+    // Rank 0 is the eighth rank, so a1 sits at the start of the last row
+    assert_eq!(square_index(7, 0), 56);
+    assert_eq!(square_index(0, 7), 7);
+    assert_eq!(square_index(4, 4), 36);
+    
+    for (name, index) in [("a1", 56), ("h8", 7), ("e4", 36)] {
+        let (rank, file) = square_from_uci(name).unwrap();
+        assert_eq!(square_index(rank, file), index, "{}", name);
+    }
+}
+
+#[test]
+fn start_position_hash_is_built_from_the_keys() {
+    let game = GameState::new();
+    let mut expected = 0;
+    for rank in 0..BOARD_SIZE {
+        for file in 0..BOARD_SIZE {
+            if let Some(piece) = game.board[rank][file] {
+                let color = ZobristKeys::get_color_index(piece.color);
+                let piece_type = ZobristKeys::get_piece_index(piece.piece_type);
+                expected ^= ZOBRIST.piece_keys[color][piece_type][square_index(rank, file)];
+            }
+        }
+    }
+    for key in ZOBRIST.castling_keys {
+        expected ^= key;
+    }
+    
+    assert_eq!(game.zobrist_hash(), expected);
+}
+
+#[test]
+fn incremental_hash_matches_a_fresh_one_through_every_kind_of_move() {
+    // Castling both ways, a capture, en passant and a promotion with capture
+    let mut game = GameState::new();
+    let line = [
+        "e2e4", "d7d5", "e4e5", "f7f5", "e5f6", "g8h6", "g1f3", "c8e6", "f1c4", "b8c6",
+        "e1g1", "d8d7", "f6g7", "e8c8", "g7h8q",
+    ];
+    for uci in line {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+        let fresh = GameState::from_fen(&game.to_fen()).unwrap();
+        assert_eq!(game.zobrist_hash(), fresh.zobrist_hash(), "hash drifted after {}", uci);
+    }
+}
+
+#[test]
+fn accessor_matches_a_full_recalculation() {
+    let mut game = GameState::new();
+    assert_eq!(game.zobrist_hash(), game.calculate_zobrist_hash());
+    
+    for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+        assert!(game.apply_uci_move(uci), "{} should be legal", uci);
+        assert_eq!(game.zobrist_hash(), game.calculate_zobrist_hash(), "hash drifted after {}", uci);
+    }
+    
+    // Back where it started, so the same key as a fresh game within this run
+    assert_eq!(game.zobrist_hash(), GameState::new().zobrist_hash());
+}